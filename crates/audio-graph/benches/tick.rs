@@ -0,0 +1,94 @@
+//! Measures the per-block overhead of processing a large graph whose nodes
+//! do almost no work. Run with `cargo bench -p audio-graph`.
+
+use std::time::{Duration, Instant};
+
+use audio_blocks::{AudioBlock, AudioBlockMut};
+use audio_graph::{
+    GraphConnection, GraphNodeDesc, GraphOutputNode, GraphPlugin, GraphProcessContext,
+    GraphProcessor, GraphWorker, graph_connect_audio, graph_set_processor,
+};
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+
+const NUM_CHAINS: u16 = 100;
+const CHAIN_LENGTH: usize = 5;
+const NUM_FRAMES: usize = 64;
+const NUM_TICKS: u32 = 10_000;
+
+/// Copies its first input, or outputs silence if it has none.
+#[derive(Debug)]
+struct PassThrough;
+
+impl GraphProcessor for PassThrough {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        let output = ctx.out_audio_buffers.channel_mut(0);
+        output.fill(0.0);
+
+        if let Some(src) = ctx.node.desc.inputs.first()
+            && let Some(input) = ctx.graph.input_audio_buffers(ctx.node, *src)
+        {
+            output.copy_from_slice(&input.channel(0)[..output.len()]);
+        }
+    }
+}
+
+/// `NUM_CHAINS` chains of `CHAIN_LENGTH` nodes, all feeding the output node.
+fn build_worker() -> GraphWorker {
+    let mut app = App::new();
+    app.add_plugins(GraphPlugin);
+    let world = app.world_mut();
+
+    let output = world
+        .spawn((
+            GraphNodeDesc::default().audio(NUM_CHAINS, 1),
+            GraphOutputNode,
+        ))
+        .id();
+    graph_set_processor(world, output, Box::new(PassThrough));
+
+    for chain in 0..NUM_CHAINS {
+        let mut previous: Option<Entity> = None;
+        for _ in 0..CHAIN_LENGTH {
+            let node = world.spawn(GraphNodeDesc::default().audio(1, 1)).id();
+            graph_set_processor(world, node, Box::new(PassThrough));
+            if let Some(previous) = previous {
+                graph_connect_audio(world, node, GraphConnection::new(0, previous, 0)).unwrap();
+            }
+            previous = Some(node);
+        }
+        graph_connect_audio(
+            world,
+            output,
+            GraphConnection::new(chain, previous.unwrap(), 0),
+        )
+        .unwrap();
+    }
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(1, 48_000, NUM_FRAMES);
+    worker
+}
+
+fn main() {
+    let mut worker = build_worker();
+    let mut data = [0.0; NUM_FRAMES];
+
+    // Let the buffers settle before measuring
+    for _ in 0..100 {
+        worker.tick(&mut data, Duration::default());
+    }
+
+    let start = Instant::now();
+    for _ in 0..NUM_TICKS {
+        worker.tick(&mut data, Duration::default());
+    }
+    let per_tick = start.elapsed() / NUM_TICKS;
+
+    println!(
+        "{} nodes: {per_tick:?} per {NUM_FRAMES} frame tick",
+        NUM_CHAINS as usize * CHAIN_LENGTH + 1
+    );
+}
@@ -1,55 +1,354 @@
 use bevy_ecs::prelude::*;
 
-use audio_blocks::{AudioBlock, AudioBlockInterleavedViewMut, AudioBlockMut, AudioBlockOpsMut};
+use rtrb::{Consumer, Producer, RingBuffer};
 
 use crate::{
-    GraphNodeDesc, GraphProcessor,
+    GraphNodeDesc, GraphOutputMapping, GraphProcessor,
     node::{self, GraphOutputNode},
-    worker::{GraphState, GraphStateWriter},
+    schedule::{self, CompensationPlan},
+    transport::{GraphTransport, GraphTransportInfo},
+    worker::{
+        CompensationDelay, DEFAULT_MAX_BLOCK_FRAMES, GraphNode, GraphState, GraphStateWriter,
+        GraphThreadPool, GraphUpdate, NodeMap, ParallelSchedule, ProcessorMap,
+    },
 };
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
     ops::DerefMut,
-    sync::mpsc::{Receiver, Sender, channel},
+    sync::{
+        Arc,
+        atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
+/// The sample rate reported by `GraphController::sample_rate` until the worker
+/// has been configured.
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// The device channel count reported by `GraphController::num_channels` until
+/// the worker has been configured.
+const DEFAULT_NUM_CHANNELS: u16 = 2;
+
+/// How many messages can be waiting in each direction between the controller
+/// and the worker.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// How many nodes and processors the worker has room for to begin with. The
+/// controller sends it bigger maps before it runs out.
+const INITIAL_NODE_CAPACITY: usize = 64;
+
 pub struct GraphController {
-    sender: Sender<AudioGraphMessage>,
+    sender: Producer<AudioGraphMessage>,
+    /// Messages that didn't fit in the queue, sent in order once there's room.
+    pending: VecDeque<AudioGraphMessage>,
+    receiver: Consumer<WorkerMessage>,
+    sample_rate: Arc<AtomicU32>,
+    num_channels: Arc<AtomicU16>,
+    max_block_frames: Arc<AtomicUsize>,
+    transport_position: Arc<AtomicU64>,
+    /// What the worker has, so that everything it needs can be allocated here
+    /// rather than on the audio thread.
+    nodes: HashSet<Entity>,
+    node_capacity: usize,
+    processors: HashSet<Entity>,
+    processor_capacity: usize,
+    output: Option<Entity>,
+    /// Nodes routed straight to device channels, which are scheduled along
+    /// with the output node.
+    routed: Vec<Entity>,
+    latencies: HashMap<Entity, usize>,
+    compensation: HashMap<Entity, CompensationPlan>,
+    /// Set when a latency has changed, so the compensation needs planning
+    /// again.
+    latency_changed: bool,
 }
 
 /// This is the part of the audio graph that does audio processing, so it lives
 /// on the audio thread.
 pub struct GraphWorker {
-    receiver: Receiver<AudioGraphMessage>,
+    receiver: Consumer<AudioGraphMessage>,
+    sender: Producer<WorkerMessage>,
     state_writer: GraphStateWriter,
     num_channels: u16,
+    shared_num_channels: Arc<AtomicU16>,
     sample_rate: u32,
+    shared_sample_rate: Arc<AtomicU32>,
+    max_block_frames: usize,
+    shared_max_block_frames: Arc<AtomicUsize>,
+    /// Set to split blocks at the boundaries processors report.
+    split_at_boundaries: bool,
+    transport: GraphTransportInfo,
+    // The bits of an f64, since there's no AtomicF64.
+    shared_transport_position: Arc<AtomicU64>,
     pub(crate) graph: GraphState,
     output: Option<Entity>,
+    output_mapping: Box<GraphOutputMapping>,
+    thread_pool: Option<GraphThreadPool>,
 }
 
 enum AudioGraphMessage {
     SetProcessor(Entity, Box<dyn GraphProcessor>),
-    UpdateGraph {
-        changed: Vec<(Entity, GraphNodeDesc)>,
-        removed: Vec<Entity>,
-        output_node: Option<Entity>,
-    },
+    UpdateGraph(Box<GraphUpdate>),
+    /// Bigger, empty maps for the worker to move its nodes or processors into.
+    ReserveNodes(NodeMap),
+    ReserveProcessors(ProcessorMap),
+    SetOutputMapping(Box<GraphOutputMapping>),
+    SetTransport(GraphTransport),
+    Seek(f64),
+}
+
+/// Sent from the worker back to the controller. Apart from latency changes,
+/// these are things the worker has finished with, sent back so that they're
+/// freed on the main thread rather than the audio thread.
+enum WorkerMessage {
+    Latency(Entity, usize),
+    Processor(Box<dyn GraphProcessor>),
+    Update(Box<GraphUpdate>),
+    Nodes(NodeMap),
+    Processors(ProcessorMap),
+    OutputMapping(Box<GraphOutputMapping>),
 }
 
 impl GraphController {
     pub fn new(state_writer: GraphStateWriter) -> (GraphController, GraphWorker) {
-        let (sender, receiver) = channel();
+        let (sender, worker_receiver) = RingBuffer::new(QUEUE_CAPACITY);
+        let (worker_sender, receiver) = RingBuffer::new(QUEUE_CAPACITY);
+        let sample_rate = Arc::new(AtomicU32::new(DEFAULT_SAMPLE_RATE));
+        let num_channels = Arc::new(AtomicU16::new(DEFAULT_NUM_CHANNELS));
+        let max_block_frames = Arc::new(AtomicUsize::new(DEFAULT_MAX_BLOCK_FRAMES));
+        let transport_position = Arc::new(AtomicU64::new(0.0f64.to_bits()));
+
+        let audio_graph = GraphController {
+            sender,
+            pending: VecDeque::new(),
+            receiver,
+            sample_rate: sample_rate.clone(),
+            num_channels: num_channels.clone(),
+            max_block_frames: max_block_frames.clone(),
+            transport_position: transport_position.clone(),
+            nodes: HashSet::new(),
+            node_capacity: INITIAL_NODE_CAPACITY,
+            processors: HashSet::new(),
+            processor_capacity: INITIAL_NODE_CAPACITY,
+            output: None,
+            routed: Vec::new(),
+            latencies: HashMap::new(),
+            compensation: HashMap::new(),
+            latency_changed: false,
+        };
+
+        (
+            audio_graph,
+            GraphWorker::new(
+                worker_receiver,
+                worker_sender,
+                state_writer,
+                sample_rate,
+                num_channels,
+                max_block_frames,
+                transport_position,
+            ),
+        )
+    }
+
+    /// The sample rate the worker was most recently configured with. New
+    /// processors should be created for this rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// The number of device channels the worker was most recently configured
+    /// with, which `GraphOutputRoute`s can send audio to.
+    pub fn num_channels(&self) -> u16 {
+        self.num_channels.load(Ordering::Relaxed)
+    }
+
+    /// The most frames the worker processes at once, as it was most recently
+    /// configured. Node buffers are allocated this big, and new processors
+    /// should be prepared for blocks this long.
+    pub fn max_block_frames(&self) -> usize {
+        self.max_block_frames.load(Ordering::Relaxed)
+    }
+
+    /// The song position, in beats, at the end of the most recently processed
+    /// block.
+    pub fn transport_position(&self) -> f64 {
+        f64::from_bits(self.transport_position.load(Ordering::Relaxed))
+    }
+
+    /// Whether anything sent to the worker is still waiting for it to pick it
+    /// up.
+    pub fn has_pending_updates(&self) -> bool {
+        !self.pending.is_empty() || self.sender.slots() < QUEUE_CAPACITY
+    }
+
+    pub fn set_processor(&mut self, entity: Entity, processor: Box<dyn GraphProcessor>) {
+        if self.processors.insert(entity) && self.processors.len() > self.processor_capacity {
+            self.processor_capacity = self.processors.len() * 2;
+            self.send(AudioGraphMessage::ReserveProcessors(
+                HashMap::with_capacity(self.processor_capacity),
+            ));
+        }
+
+        self.set_latency(entity, processor.latency());
+        self.send(AudioGraphMessage::SetProcessor(entity, processor));
+    }
+
+    fn set_output_mapping(&mut self, mapping: &GraphOutputMapping) {
+        self.send(AudioGraphMessage::SetOutputMapping(Box::new(
+            mapping.clone(),
+        )));
+    }
+
+    pub(crate) fn set_transport(&mut self, transport: &GraphTransport) {
+        self.send(AudioGraphMessage::SetTransport(transport.clone()));
+    }
+
+    pub(crate) fn seek(&mut self, position: f64) {
+        self.send(AudioGraphMessage::Seek(position));
+    }
+
+    fn send(&mut self, message: AudioGraphMessage) {
+        self.send_pending();
+
+        if !self.pending.is_empty() {
+            self.pending.push_back(message);
+        } else if let Err(rtrb::PushError::Full(message)) = self.sender.push(message) {
+            self.pending.push_back(message);
+        }
+    }
+
+    fn send_pending(&mut self) {
+        while let Some(message) = self.pending.pop_front() {
+            if let Err(rtrb::PushError::Full(message)) = self.sender.push(message) {
+                self.pending.push_front(message);
+                break;
+            }
+        }
+    }
+
+    /// Handles latency changes from the worker, and frees everything it has
+    /// finished with.
+    fn receive(&mut self) {
+        while let Ok(message) = self.receiver.pop() {
+            match message {
+                WorkerMessage::Latency(entity, latency) => self.set_latency(entity, latency),
+                WorkerMessage::Processor(processor) => drop(processor),
+                WorkerMessage::Update(update) => drop(update),
+                WorkerMessage::Nodes(nodes) => drop(nodes),
+                WorkerMessage::Processors(processors) => drop(processors),
+                WorkerMessage::OutputMapping(mapping) => drop(mapping),
+            }
+        }
+    }
+
+    fn set_latency(&mut self, entity: Entity, latency: usize) {
+        if self.latencies.insert(entity, latency) != Some(latency) {
+            self.latency_changed = true;
+        }
+    }
+
+    fn needs_update(
+        &self,
+        nodes: &[GraphNode],
+        removed: &[Entity],
+        output: Option<Entity>,
+        routed: &[Entity],
+    ) -> bool {
+        !nodes.is_empty()
+            || !removed.is_empty()
+            || output != self.output
+            || routed != self.routed
+            || self.latency_changed
+    }
+
+    fn update_graph(
+        &mut self,
+        descs: &HashMap<Entity, &GraphNodeDesc>,
+        nodes: Vec<GraphNode>,
+        removed: Vec<Entity>,
+        output: Option<Entity>,
+        routed: Vec<Entity>,
+    ) {
+        for entity in &removed {
+            self.nodes.remove(entity);
+            self.processors.remove(entity);
+            self.latencies.remove(entity);
+            self.compensation.remove(entity);
+        }
+        for node in &nodes {
+            self.nodes.insert(node.entity);
+        }
+
+        if self.nodes.len() > self.node_capacity {
+            self.node_capacity = self.nodes.len() * 2;
+            self.send(AudioGraphMessage::ReserveNodes(HashMap::with_capacity(
+                self.node_capacity,
+            )));
+        }
 
-        let audio_graph = GraphController { sender };
+        let targets: Vec<_> = output.iter().chain(&routed).copied().collect();
+        let schedule = if targets.is_empty() {
+            Vec::new()
+        } else {
+            schedule::build_schedule(descs, &targets)
+        };
+        let (dependents, num_inputs) = schedule::build_dependencies(&schedule, descs);
+        let latencies = schedule
+            .iter()
+            .map(|entity| self.latencies.get(entity).copied().unwrap_or(0))
+            .collect();
+        let compensation = self.update_compensation(&schedule, descs);
+
+        self.output = output;
+        self.routed = routed;
+        self.latency_changed = false;
 
-        (audio_graph, GraphWorker::new(receiver, state_writer))
+        self.send(AudioGraphMessage::UpdateGraph(Box::new(GraphUpdate::new(
+            nodes,
+            removed,
+            output,
+            schedule,
+            ParallelSchedule::new(dependents, num_inputs),
+            latencies,
+            compensation,
+        ))));
     }
 
-    pub fn set_processor(&self, entity: Entity, processor: Box<dyn GraphProcessor>) {
-        let _ = self
-            .sender
-            .send(AudioGraphMessage::SetProcessor(entity, processor));
+    /// Plans the latency compensation for `schedule`, and builds the delays
+    /// for the nodes whose plan has changed.
+    fn update_compensation(
+        &mut self,
+        schedule: &[Entity],
+        descs: &HashMap<Entity, &GraphNodeDesc>,
+    ) -> Vec<(Entity, Vec<CompensationDelay>)> {
+        let plan = schedule::plan_compensation(schedule, descs, &self.latencies);
+        let max_block_frames = self.max_block_frames();
+
+        // Nodes that no longer need any delays
+        let mut changed: Vec<_> = self
+            .compensation
+            .keys()
+            .filter(|entity| !plan.contains_key(entity))
+            .map(|entity| (*entity, Vec::new()))
+            .collect();
+
+        for (entity, delays) in &plan {
+            if self.compensation.get(entity) != Some(delays) {
+                let delays = delays
+                    .iter()
+                    .map(|(src, delay, num_channels)| {
+                        CompensationDelay::new(*src, *delay, *num_channels, max_block_frames)
+                    })
+                    .collect();
+                changed.push((*entity, delays));
+            }
+        }
+
+        self.compensation = plan;
+        changed
     }
 }
 
@@ -68,7 +367,12 @@ pub(crate) fn pre_update_system(
     }
 
     for (entity, mut node) in nodes {
-        if node.inputs.iter().any(|input| removed.contains(input)) {
+        if node
+            .inputs
+            .iter()
+            .chain(&node.feedback_inputs)
+            .any(|input| removed.contains(input))
+        {
             for n in removed.iter() {
                 if node.deref_mut().disconnect_node(n) {
                     println!("** {:?} removed input from {:?}", entity, n);
@@ -79,110 +383,274 @@ pub(crate) fn pre_update_system(
 }
 
 pub(crate) fn update_system(
-    audio_graph: NonSendMut<GraphController>,
-    mut changed_nodes: Query<(Entity, Ref<node::GraphNodeDesc>, Option<&Name>)>,
+    mut audio_graph: NonSendMut<GraphController>,
+    nodes: Query<(Entity, Ref<node::GraphNodeDesc>, Option<&Name>)>,
     mut removed_nodes: RemovedComponents<node::GraphNodeDesc>,
     output_node: Option<Single<(Entity, &GraphOutputNode)>>,
+    output_mapping: Res<GraphOutputMapping>,
 ) {
-    let removed = Vec::from_iter(removed_nodes.read());
+    audio_graph.receive();
+
+    // A node whose description was removed and added back again is changed,
+    // rather than removed along with its processor
+    let removed = Vec::from_iter(
+        removed_nodes
+            .read()
+            .filter(|entity| !nodes.contains(*entity)),
+    );
 
     let mut changed = Vec::default();
+    let max_block_frames = audio_graph.max_block_frames();
 
-    for (entity, node, name) in &mut changed_nodes {
+    for (entity, node, name) in &nodes {
         if node.is_changed() {
             println!("{:?} ({:?}) is changed", entity, name);
-            changed.push((entity, (*node).clone()));
+            changed.push(GraphNode::new(entity, (*node).clone(), max_block_frames));
         }
     }
 
     let output_node = output_node.map(|s| s.0);
 
-    let _ = audio_graph.sender.send(AudioGraphMessage::UpdateGraph {
-        changed,
-        removed,
-        output_node,
-    });
+    let mut routed: Vec<_> = output_mapping
+        .routes
+        .iter()
+        .map(|route| route.src)
+        .collect();
+    routed.sort();
+    routed.dedup();
+
+    if audio_graph.needs_update(&changed, &removed, output_node, &routed) {
+        let descs = nodes
+            .iter()
+            .map(|(entity, node, _)| (entity, node.into_inner()))
+            .collect();
+        audio_graph.update_graph(&descs, changed, removed, output_node, routed);
+    }
+
+    // Sent after the update, so that routed nodes are scheduled by the time
+    // they're mixed
+    if output_mapping.is_changed() {
+        audio_graph.set_output_mapping(&output_mapping);
+    }
+
+    audio_graph.send_pending();
 }
 
 impl GraphWorker {
-    fn new(receiver: Receiver<AudioGraphMessage>, state_writer: GraphStateWriter) -> Self {
+    fn new(
+        receiver: Consumer<AudioGraphMessage>,
+        sender: Producer<WorkerMessage>,
+        state_writer: GraphStateWriter,
+        shared_sample_rate: Arc<AtomicU32>,
+        shared_num_channels: Arc<AtomicU16>,
+        shared_max_block_frames: Arc<AtomicUsize>,
+        shared_transport_position: Arc<AtomicU64>,
+    ) -> Self {
         Self {
             receiver,
+            sender,
             state_writer,
-            graph: Default::default(),
+            graph: GraphState::with_capacity(INITIAL_NODE_CAPACITY),
             output: None,
+            output_mapping: Box::default(),
+            thread_pool: None,
             num_channels: 0,
+            shared_num_channels,
             sample_rate: 0,
+            shared_sample_rate,
+            max_block_frames: DEFAULT_MAX_BLOCK_FRAMES,
+            shared_max_block_frames,
+            split_at_boundaries: false,
+            transport: Default::default(),
+            shared_transport_position,
         }
     }
 
-    pub fn configure(&mut self, channels: u16, sample_rate: u32) {
+    /// Must not be called while `tick` could be running. Blocks of more than
+    /// `max_block_frames` frames are processed in pieces, and every node's
+    /// buffers are reallocated if it changes. If the sample rate or the
+    /// maximum block size changes then every processor is reconfigured.
+    pub fn configure(&mut self, channels: u16, sample_rate: u32, max_block_frames: usize) {
+        assert!(max_block_frames > 0);
+
+        // Stored first so that nodes the controller builds from now on are
+        // the new size, and the ones already on their way are resized below
+        self.shared_max_block_frames
+            .store(max_block_frames, Ordering::Relaxed);
+        self.process_messages();
+
         self.num_channels = channels;
-        self.sample_rate = sample_rate;
+
+        let resized = self.max_block_frames != max_block_frames;
+        if resized {
+            self.max_block_frames = max_block_frames;
+            self.graph.resize(max_block_frames);
+        }
+
+        if resized || self.sample_rate != sample_rate {
+            self.sample_rate = sample_rate;
+            self.graph
+                .processors
+                .configure(sample_rate, max_block_frames);
+        }
+
+        self.shared_sample_rate
+            .store(sample_rate, Ordering::Relaxed);
+        self.shared_num_channels.store(channels, Ordering::Relaxed);
+    }
+
+    /// Splits blocks wherever a processor reports an event or automation
+    /// boundary through `GraphProcessor::next_boundary`, so that processors
+    /// that only read them once per block still respond on time. Off by
+    /// default, as every node then processes smaller blocks.
+    pub fn set_split_at_boundaries(&mut self, split: bool) {
+        self.split_at_boundaries = split;
     }
 
+    /// Processes independent branches of the graph on `num_threads` threads
+    /// as well as the audio thread. With no threads, which is the default,
+    /// everything is processed on the audio thread. Must not be called while
+    /// `tick` could be running. The threads' real-time priority is sized for
+    /// the sample rate and block size the worker is configured with, or the
+    /// defaults if it hasn't been yet.
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        let sample_rate = match self.sample_rate {
+            0 => DEFAULT_SAMPLE_RATE,
+            sample_rate => sample_rate,
+        };
+        self.thread_pool = (num_threads > 0)
+            .then(|| GraphThreadPool::new(num_threads, sample_rate, self.max_block_frames));
+    }
+
+    /// Fills `data` with the graph's output. This is called on the audio
+    /// thread, so it never allocates, frees or locks.
     pub fn tick(&mut self, data: &mut [f32], timestamp: Duration) {
-        for message in self.receiver.try_iter() {
-            match message {
-                AudioGraphMessage::UpdateGraph {
-                    changed,
-                    removed,
-                    output_node,
-                } => {
-                    self.graph.update(changed, removed);
-                    self.output = output_node;
-                }
-                AudioGraphMessage::SetProcessor(entity, processor) => {
-                    self.graph.processors.borrow_mut().set(entity, processor);
-                }
-            }
-        }
+        #[cfg(any(test, feature = "rt-guard"))]
+        let _guard = crate::rt_guard::RealtimeGuard::enter();
+
+        self.process_messages();
+
+        let sender = &mut self.sender;
+        self.graph.report_latencies(|entity, latency| {
+            sender.push(WorkerMessage::Latency(entity, latency)).is_ok()
+        });
 
         self.state_writer.swap_buffers();
 
+        let num_channels = self.num_channels as usize;
+        let max_block_frames = self.max_block_frames;
+        for (index, chunk) in data.chunks_mut(max_block_frames * num_channels).enumerate() {
+            self.tick_block(
+                chunk,
+                timestamp + self.frames_to_duration(index * max_block_frames),
+            );
+        }
+
+        self.publish_transport_position();
+    }
+
+    /// Processes `data` in one go, or in pieces split at the processors'
+    /// boundaries if that's been asked for.
+    fn tick_block(&mut self, data: &mut [f32], timestamp: Duration) {
+        let num_channels = self.num_channels as usize;
+        let num_frames = data.len() / num_channels;
+
+        let mut start = 0;
+        while start < num_frames {
+            let remaining = num_frames - start;
+            let length = if self.split_at_boundaries {
+                self.graph
+                    .next_boundary(&self.transport, self.sample_rate, remaining)
+                    .unwrap_or(remaining)
+            } else {
+                remaining
+            };
+
+            let end = start + length;
+            self.process_block(
+                &mut data[start * num_channels..end * num_channels],
+                timestamp + self.frames_to_duration(start),
+            );
+            start = end;
+        }
+    }
+
+    fn frames_to_duration(&self, num_frames: usize) -> Duration {
+        Duration::from_secs_f64(num_frames as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    fn process_block(&mut self, data: &mut [f32], timestamp: Duration) {
         let num_frames = data.len() / self.num_channels as usize;
-        let mut block = AudioBlockInterleavedViewMut::from_slice(data, self.num_channels);
 
-        if let Some(output) = self.output {
-            self.graph.process(
-                output,
+        match &self.thread_pool {
+            Some(pool) if self.graph.schedule_len() > 1 => self.graph.process_parallel(
+                pool,
                 num_frames,
                 self.sample_rate,
                 &timestamp,
+                &self.transport,
                 &mut self.state_writer,
-            );
+            ),
+            _ => self.graph.process(
+                num_frames,
+                self.sample_rate,
+                &timestamp,
+                &self.transport,
+                &mut self.state_writer,
+            ),
+        }
+
+        self.output_mapping
+            .mix(&self.graph, self.output, data, self.num_channels);
+
+        self.transport.advance(num_frames, self.sample_rate);
+    }
 
-            if let Some(output_node) = self.graph.get_node(output) {
-                let output_buffers = output_node.output_audio_buffers.get();
-
-                if output_buffers.num_channels() == block.num_channels() {
-                    let output_buffers = output_buffers.frames_iter();
-                    let frames_dest = block.frames_iter_mut();
-
-                    for (output_channel, dest_channel) in output_buffers.zip(frames_dest) {
-                        for (output_frame, dest_frame) in output_channel.zip(dest_channel) {
-                            *dest_frame = *output_frame;
-                        }
-                    }
-                } else if output_buffers.num_channels() == 1 {
-                    let frames_dest = block.channels_iter_mut();
-                    for dest_channel in frames_dest {
-                        for (output_frame, dest_frame) in
-                            output_buffers.channel_iter(0).zip(dest_channel)
-                        {
-                            *dest_frame = *output_frame;
-                        }
-                    }
-                } else {
-                    panic!(
-                        "Don't know how to convert from {} channels to {} channels",
-                        output_buffers.num_channels(),
-                        block.num_channels()
-                    );
+    fn publish_transport_position(&self) {
+        self.shared_transport_position
+            .store(self.transport.position.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Applies the controller's messages, and sends back whatever they
+    /// replace to be freed on the main thread.
+    fn process_messages(&mut self) {
+        while let Ok(message) = self.receiver.pop() {
+            let retired = match message {
+                AudioGraphMessage::UpdateGraph(mut update) => {
+                    self.graph.update(&mut update);
+                    self.output = update.output;
+                    Some(WorkerMessage::Update(update))
+                }
+                AudioGraphMessage::SetProcessor(entity, processor) => self
+                    .graph
+                    .processors
+                    .set(entity, processor)
+                    .map(WorkerMessage::Processor),
+                AudioGraphMessage::ReserveNodes(nodes) => {
+                    Some(WorkerMessage::Nodes(self.graph.reserve_nodes(nodes)))
+                }
+                AudioGraphMessage::ReserveProcessors(processors) => Some(
+                    WorkerMessage::Processors(self.graph.processors.reserve(processors)),
+                ),
+                AudioGraphMessage::SetOutputMapping(mapping) => Some(WorkerMessage::OutputMapping(
+                    mem::replace(&mut self.output_mapping, mapping),
+                )),
+                AudioGraphMessage::SetTransport(transport) => {
+                    self.transport = GraphTransportInfo::new(&transport, self.transport.position);
+                    None
                 }
+                AudioGraphMessage::Seek(position) => {
+                    self.transport.position = position;
+                    self.publish_transport_position();
+                    None
+                }
+            };
+
+            // If the queue is full the message is dropped here, which
+            // frees it on this thread as a last resort
+            if let Some(retired) = retired {
+                let _ = self.sender.push(retired);
             }
-        } else {
-            block.fill_with(0.0);
         }
     }
 }
@@ -0,0 +1,195 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use bevy_reflect::Reflect;
+
+use crate::GraphMixMatrix;
+use GraphSpeaker::*;
+
+/// How the channels of a set of audio ports are arranged. Surround layouts
+/// use the usual WAVE channel order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub enum GraphChannelLayout {
+    Mono,
+    Stereo,
+    /// Front left, front right, back left, back right.
+    Quad,
+    /// Front left, front right, centre, LFE, back left, back right.
+    Surround51,
+    /// 5.1 followed by side left and side right.
+    Surround71,
+    /// Ambisonics of the given order, in ACN channel order.
+    Ambisonic(u8),
+    /// Channels with no particular arrangement.
+    Discrete(u16),
+}
+
+impl Default for GraphChannelLayout {
+    fn default() -> Self {
+        Self::Discrete(0)
+    }
+}
+
+/// Where a channel of a speaker layout is meant to be played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub enum GraphSpeaker {
+    FrontLeft,
+    FrontRight,
+    FrontCentre,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    SideLeft,
+    SideRight,
+}
+
+impl GraphChannelLayout {
+    /// The usual layout for `num_channels` channels.
+    pub fn from_num_channels(num_channels: u16) -> Self {
+        match num_channels {
+            1 => Self::Mono,
+            2 => Self::Stereo,
+            4 => Self::Quad,
+            6 => Self::Surround51,
+            8 => Self::Surround71,
+            _ => Self::Discrete(num_channels),
+        }
+    }
+
+    pub fn num_channels(self) -> u16 {
+        match self {
+            Self::Ambisonic(order) => (order as u16 + 1).saturating_pow(2),
+            Self::Discrete(num_channels) => num_channels,
+            _ => self.speakers().map_or(0, |speakers| speakers.len() as u16),
+        }
+    }
+
+    /// The speaker each channel is meant for, unless the layout isn't a
+    /// speaker layout.
+    pub fn speakers(self) -> Option<&'static [GraphSpeaker]> {
+        match self {
+            Self::Mono => Some(&[FrontCentre]),
+            Self::Stereo => Some(&[FrontLeft, FrontRight]),
+            Self::Quad => Some(&[FrontLeft, FrontRight, BackLeft, BackRight]),
+            Self::Surround51 => Some(&[
+                FrontLeft,
+                FrontRight,
+                FrontCentre,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ]),
+            Self::Surround71 => Some(&[
+                FrontLeft,
+                FrontRight,
+                FrontCentre,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ]),
+            Self::Ambisonic(_) | Self::Discrete(_) => None,
+        }
+    }
+
+    /// The `(channel, dst_channel, gain)` connections from channels of this
+    /// layout to channels of `dst`. Between mono, stereo and 5.1 they're the
+    /// gains of `GraphMixMatrix::standard`, so that a mix in the graph sounds
+    /// the same as one at the device. Otherwise each speaker goes to the same
+    /// speaker, or is folded into the nearest ones `dst` has: a centre to both
+    /// fronts at -3 dB, or at unity if it's mono, the fronts to a centre at
+    /// half gain, and the surrounds to the other surrounds at unity or to the
+    /// fronts at -3 dB. The LFE is dropped if `dst` has none. Layouts without
+    /// speakers are connected channel by channel.
+    pub fn connections(self, dst: Self) -> Vec<(u16, u16, f32)> {
+        let (Some(speakers), Some(dst_speakers)) = (self.speakers(), dst.speakers()) else {
+            let num_channels = self.num_channels().min(dst.num_channels());
+            return (0..num_channels)
+                .map(|channel| (channel, channel, 1.0))
+                .collect();
+        };
+
+        if self.has_standard_mix() && dst.has_standard_mix() {
+            let matrix = GraphMixMatrix::standard(self.num_channels(), dst.num_channels());
+            let mut connections = Vec::new();
+            for channel in 0..matrix.num_inputs() {
+                for dst_channel in 0..matrix.num_outputs() {
+                    let gain = matrix.gain(channel, dst_channel);
+                    if gain > 0.0 {
+                        connections.push((channel, dst_channel, gain));
+                    }
+                }
+            }
+            return connections;
+        }
+
+        let dst_channel = |speaker| {
+            dst_speakers
+                .iter()
+                .position(|dst_speaker| *dst_speaker == speaker)
+                .map(|channel| channel as u16)
+        };
+
+        let mut connections = Vec::new();
+        for (channel, speaker) in speakers.iter().enumerate() {
+            let same: &[_] = std::slice::from_ref(speaker);
+            let mut groups =
+                std::iter::once((same, 1.0)).chain(speaker.fold_targets().iter().copied());
+            let found = groups.find_map(|(group, gain)| {
+                let dst_channels = group
+                    .iter()
+                    .map(|speaker| dst_channel(*speaker))
+                    .collect::<Option<Vec<_>>>()?;
+                Some((dst_channels, gain))
+            });
+            let Some((dst_channels, gain)) = found else {
+                continue;
+            };
+
+            // Mono keeps its level on both fronts, as in the standard mix
+            let gain = if self == Self::Mono { 1.0 } else { gain };
+            for dst_channel in dst_channels {
+                connections.push((channel as u16, dst_channel, gain));
+            }
+        }
+        connections
+    }
+
+    /// The layouts that `GraphMixMatrix::standard` has mixes for.
+    fn has_standard_mix(self) -> bool {
+        matches!(self, Self::Mono | Self::Stereo | Self::Surround51)
+    }
+}
+
+impl GraphSpeaker {
+    /// The groups of speakers to play this one's audio on when it's missing,
+    /// in order of preference, and the gain to play it at.
+    fn fold_targets(self) -> &'static [(&'static [GraphSpeaker], f32)] {
+        const SURROUND_TO_CENTRE: f32 = 0.5 * FRAC_1_SQRT_2;
+        match self {
+            FrontLeft | FrontRight => &[(&[FrontCentre], 0.5)],
+            FrontCentre => &[(&[FrontLeft, FrontRight], FRAC_1_SQRT_2)],
+            LowFrequency => &[],
+            BackLeft => &[
+                (&[SideLeft], 1.0),
+                (&[FrontLeft], FRAC_1_SQRT_2),
+                (&[FrontCentre], SURROUND_TO_CENTRE),
+            ],
+            BackRight => &[
+                (&[SideRight], 1.0),
+                (&[FrontRight], FRAC_1_SQRT_2),
+                (&[FrontCentre], SURROUND_TO_CENTRE),
+            ],
+            SideLeft => &[
+                (&[BackLeft], 1.0),
+                (&[FrontLeft], FRAC_1_SQRT_2),
+                (&[FrontCentre], SURROUND_TO_CENTRE),
+            ],
+            SideRight => &[
+                (&[BackRight], 1.0),
+                (&[FrontRight], FRAC_1_SQRT_2),
+                (&[FrontCentre], SURROUND_TO_CENTRE),
+            ],
+        }
+    }
+}
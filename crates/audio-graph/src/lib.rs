@@ -2,18 +2,32 @@ use bevy_app::prelude::*;
 
 mod audio_graph;
 mod events;
+mod layout;
 mod node;
+mod output;
+mod schedule;
+pub mod sync;
+mod transport;
 mod worker;
 
+#[cfg(any(test, feature = "rt-guard"))]
+pub mod rt_guard;
+
 pub use audio_graph::{GraphController, GraphWorker};
 pub use events::GraphEvent;
+pub use layout::{GraphChannelLayout, GraphSpeaker};
 pub use node::{
-    GraphConnection, GraphNodeDesc, GraphOutputNode, GraphPorts, graph_connect_audio,
-    graph_connect_event, graph_disconnect_event_input, graph_set_processor,
+    GraphConnection, GraphError, GraphNodeDesc, GraphOutputNode, GraphPorts, graph_connect_audio,
+    graph_connect_audio_layout, graph_connect_event, graph_disconnect_audio_input,
+    graph_disconnect_event_input, graph_set_processor,
+};
+pub use output::{GraphMixMatrix, GraphOutputMapping, GraphOutputRoute};
+pub use transport::{
+    GraphLoopRegion, GraphSeekEvent, GraphTimeSignature, GraphTransport, GraphTransportInfo,
 };
 pub use worker::{
-    GraphNode, GraphProcessContext, GraphProcessor, GraphState, GraphStateReader, GraphStateValue,
-    GraphStateWriter, graph_state_tracker,
+    DEFAULT_MAX_BLOCK_FRAMES, GraphNode, GraphProcessContext, GraphProcessor, GraphRef, GraphState,
+    GraphStateReader, GraphStateValue, GraphStateWriter, GraphThreadPool, graph_state_tracker,
 };
 
 pub struct GraphPlugin;
@@ -25,10 +39,17 @@ impl Plugin for GraphPlugin {
         app.insert_non_send(audio_graph)
             .insert_non_send(audio_graph_worker)
             .insert_non_send(state_reader)
+            .init_resource::<GraphTransport>()
+            .init_resource::<GraphOutputMapping>()
             .add_systems(
                 Update,
-                (audio_graph::pre_update_system, audio_graph::update_system),
-            );
+                (
+                    audio_graph::pre_update_system,
+                    audio_graph::update_system,
+                    transport::transport_system,
+                ),
+            )
+            .add_observer(transport::on_seek_event);
     }
 }
 
@@ -1,7 +1,9 @@
+use std::collections::HashSet;
+
 use bevy_ecs::{prelude::*, query::QueryEntityError};
 use bevy_reflect::Reflect;
 
-use crate::{GraphController, worker::GraphProcessor};
+use crate::{GraphChannelLayout, GraphController, worker::GraphProcessor};
 use thiserror::Error;
 
 #[derive(Component, Reflect)]
@@ -9,7 +11,12 @@ pub struct GraphOutputNode;
 
 #[derive(Component, Clone, Debug, Default, Reflect)]
 pub struct GraphNodeDesc {
+    /// The nodes this node reads from in the same block, which are processed
+    /// before it.
     pub inputs: Vec<Entity>,
+    /// The nodes this node reads from through feedback connections, which
+    /// don't affect the order nodes are processed in.
+    pub feedback_inputs: Vec<Entity>,
     pub audio_channels: GraphPorts,
     pub event_channels: GraphPorts,
     pub always_run: bool,
@@ -20,13 +27,26 @@ pub struct GraphPorts {
     pub connections: Vec<GraphConnection>,
     pub num_inputs: u16,
     pub num_outputs: u16,
+    /// How the input channels are arranged. Only used for audio.
+    pub input_layout: GraphChannelLayout,
+    /// How the output channels are arranged. Only used for audio.
+    pub output_layout: GraphChannelLayout,
 }
 
 impl GraphPorts {
     fn new(num_inputs: u16, num_outputs: u16) -> Self {
+        Self::with_layouts(
+            GraphChannelLayout::from_num_channels(num_inputs),
+            GraphChannelLayout::from_num_channels(num_outputs),
+        )
+    }
+
+    fn with_layouts(input_layout: GraphChannelLayout, output_layout: GraphChannelLayout) -> Self {
         Self {
-            num_inputs,
-            num_outputs,
+            num_inputs: input_layout.num_channels(),
+            num_outputs: output_layout.num_channels(),
+            input_layout,
+            output_layout,
             ..Default::default()
         }
     }
@@ -40,19 +60,39 @@ impl GraphPorts {
             return Err(GraphError::SrcPortOutOfBounds);
         }
 
-        if !self.connections.contains(&connection) {
-            self.connections.push(connection);
+        // A node reads everything from a source either in the same block or
+        // from the previous one
+        if self.connections.iter().any(|existing| {
+            existing.src == connection.src && existing.feedback != connection.feedback
+        }) {
+            return Err(GraphError::MixedFeedback);
+        }
+
+        // Connecting the same channels again only changes the gain
+        match self
+            .connections
+            .iter_mut()
+            .find(|existing| existing.with_gain(connection.gain) == connection)
+        {
+            Some(existing) => existing.gain = connection.gain,
+            None => self.connections.push(connection),
         }
 
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Reflect)]
 pub struct GraphConnection {
     pub channel: u16,
     pub src: Entity,
     pub src_channel: u16,
+    /// Set for connections that read what `src` output in the previous
+    /// block, which is how the graph allows loops.
+    pub feedback: bool,
+    /// What `src_channel` is scaled by before it's mixed into `channel`.
+    /// Below unity where `graph_connect_audio_layout` folds channels together.
+    pub gain: f32,
 }
 
 impl GraphConnection {
@@ -61,6 +101,22 @@ impl GraphConnection {
             channel,
             src,
             src_channel,
+            feedback: false,
+            gain: 1.0,
+        }
+    }
+
+    pub fn with_gain(self, gain: f32) -> Self {
+        Self { gain, ..self }
+    }
+
+    /// A connection that's one block behind `src`, so it can be used to
+    /// route a node's output back round to itself or to the nodes it depends
+    /// on.
+    pub fn new_feedback(channel: u16, src: Entity, src_channel: u16) -> Self {
+        Self {
+            feedback: true,
+            ..Self::new(channel, src, src_channel)
         }
     }
 }
@@ -73,6 +129,19 @@ impl GraphNodeDesc {
         }
     }
 
+    /// Like `audio`, but with the channels arranged in the given layouts
+    /// rather than the usual ones for their channel counts.
+    pub fn audio_layout(
+        self,
+        input_layout: GraphChannelLayout,
+        output_layout: GraphChannelLayout,
+    ) -> Self {
+        Self {
+            audio_channels: GraphPorts::with_layouts(input_layout, output_layout),
+            ..self
+        }
+    }
+
     pub fn event(self, num_event_input_channels: u16, num_event_output_channels: u16) -> Self {
         Self {
             event_channels: GraphPorts::new(num_event_input_channels, num_event_output_channels),
@@ -92,10 +161,16 @@ impl GraphNodeDesc {
         let event_channels = self.event_channels.connections.iter();
         let ports = audio_channels.chain(event_channels);
 
-        let mut nodes: Vec<_> = ports.map(|c| c.src).collect();
-        nodes.sort();
-        nodes.dedup();
-        self.inputs = nodes;
+        let (feedback, ordinary): (Vec<_>, Vec<_>) = ports.partition(|c| c.feedback);
+
+        let sources = |connections: Vec<&GraphConnection>| {
+            let mut nodes: Vec<_> = connections.iter().map(|c| c.src).collect();
+            nodes.sort();
+            nodes.dedup();
+            nodes
+        };
+        self.inputs = sources(ordinary);
+        self.feedback_inputs = sources(feedback);
     }
 
     pub(crate) fn disconnect_node(&mut self, node: &Entity) -> bool {
@@ -106,11 +181,11 @@ impl GraphNodeDesc {
             .connections
             .retain(|connection| connection.src != *node);
 
-        let before = self.inputs.len();
+        let before = self.inputs.len() + self.feedback_inputs.len();
 
         self.update_input_nodes();
 
-        before != self.inputs.len()
+        before != self.inputs.len() + self.feedback_inputs.len()
     }
 
     pub fn has_event_connected(&self, input_node: Entity) -> bool {
@@ -129,7 +204,7 @@ pub fn graph_set_processor(
     // It's hard to put dyn GraphProcessor's into components (they don't naturally
     // want to be sync), so this is working around that.
 
-    let audio_graph = world_mut.get_non_send_mut::<GraphController>().unwrap();
+    let mut audio_graph = world_mut.get_non_send_mut::<GraphController>().unwrap();
     audio_graph.set_processor(entity, processor);
 }
 
@@ -142,11 +217,41 @@ pub fn graph_connect_audio(
     Ok(())
 }
 
+/// Connects `src`'s audio outputs to `dst`'s audio inputs by matching up
+/// their channel layouts, as `GraphChannelLayout::connections` describes.
+pub fn graph_connect_audio_layout(
+    world: &mut World,
+    dst: Entity,
+    src: Entity,
+) -> Result<(), GraphError> {
+    let layouts = |entity| {
+        world
+            .get::<GraphNodeDesc>(entity)
+            .map(|node| {
+                let ports = &node.audio_channels;
+                (ports.input_layout, ports.output_layout)
+            })
+            .ok_or(GraphError::InvalidEntity(entity))
+    };
+    let (_, src_layout) = layouts(src)?;
+    let (dst_layout, _) = layouts(dst)?;
+
+    for (src_channel, channel, gain) in src_layout.connections(dst_layout) {
+        let connection = GraphConnection::new(channel, src, src_channel).with_gain(gain);
+        graph_connect_audio(world, dst, connection)?;
+    }
+    Ok(())
+}
+
 pub fn graph_connect_event(
     world: &mut World,
     dst: Entity,
     connection: GraphConnection,
 ) -> Result<(), GraphError> {
+    if connection.feedback {
+        return Err(GraphError::EventFeedback);
+    }
+
     connect_channels(world, dst, connection, |node| &mut node.event_channels)?;
     Ok(())
 }
@@ -160,6 +265,20 @@ fn connect_channels<F>(
 where
     F: Fn(&mut GraphNodeDesc) -> &mut GraphPorts,
 {
+    if connection.feedback && dst == connection.src {
+        let mut node = world
+            .get_mut::<GraphNodeDesc>(dst)
+            .ok_or(GraphError::InvalidEntity(dst))?;
+        let src = get_channels(&mut node).clone();
+        get_channels(&mut node).connect(&src, connection)?;
+        node.update_input_nodes();
+        return Ok(());
+    }
+
+    if !connection.feedback && dst != connection.src && depends_on(world, connection.src, dst) {
+        return Err(GraphError::Cycle);
+    }
+
     let mut nodes = world.query::<&mut GraphNodeDesc>();
 
     let [mut dst_node, mut src_node] =
@@ -177,6 +296,27 @@ where
     Ok(())
 }
 
+/// Whether `node` reads from `target`, directly or through other nodes, in
+/// the same block.
+fn depends_on(world: &mut World, node: Entity, target: Entity) -> bool {
+    let mut nodes = world.query::<&GraphNodeDesc>();
+    let mut visited = HashSet::new();
+    let mut stack = vec![node];
+
+    while let Some(node) = stack.pop() {
+        if node == target {
+            return true;
+        }
+        if visited.insert(node)
+            && let Ok(desc) = nodes.get(world, node)
+        {
+            stack.extend_from_slice(&desc.inputs);
+        }
+    }
+
+    false
+}
+
 pub fn graph_disconnect_event_input(
     world: &mut World,
     node: Entity,
@@ -186,6 +326,15 @@ pub fn graph_disconnect_event_input(
     Ok(())
 }
 
+pub fn graph_disconnect_audio_input(
+    world: &mut World,
+    node: Entity,
+    input_node: Entity,
+) -> Result<(), GraphError> {
+    disconnect_channel_from_node(world, node, input_node, |node| &mut node.audio_channels)?;
+    Ok(())
+}
+
 fn disconnect_channel_from_node<F>(
     world: &mut World,
     node: Entity,
@@ -201,6 +350,7 @@ where
     get_channels(&mut dest)
         .connections
         .retain(|connection| connection.src != input_node);
+    dest.update_input_nodes();
     Ok(())
 }
 
@@ -217,6 +367,15 @@ pub enum GraphError {
 
     #[error("src_channel out of bounds")]
     SrcPortOutOfBounds,
+
+    #[error("connection would create a cycle; use a feedback connection instead")]
+    Cycle,
+
+    #[error("a node can't read from the same source with and without feedback")]
+    MixedFeedback,
+
+    #[error("only audio connections can be feedback connections")]
+    EventFeedback,
 }
 
 #[cfg(test)]
@@ -257,7 +416,9 @@ mod test {
         graph_connect_audio(world, b, GraphConnection::new(1, a, 1)).unwrap();
         let mut n = get_node(world, b);
         assert_eq!(n.inputs, vec![a]);
-        n.audio_channels.connections.sort();
+        n.audio_channels
+            .connections
+            .sort_by(|a, b| a.partial_cmp(b).unwrap());
         assert_eq!(
             n.audio_channels.connections,
             vec![GraphConnection::new(0, a, 0), GraphConnection::new(1, a, 1)]
@@ -268,7 +429,9 @@ mod test {
         graph_connect_audio(world, b, GraphConnection::new(1, c, 0)).unwrap();
         let mut n = get_node(world, b);
         n.inputs.sort();
-        n.audio_channels.connections.sort();
+        n.audio_channels
+            .connections
+            .sort_by(|a, b| a.partial_cmp(b).unwrap());
         assert_eq!(n.inputs, vec![c, a]);
         assert_eq!(
             n.audio_channels.connections,
@@ -287,7 +450,9 @@ mod test {
         graph_connect_audio(world, b, GraphConnection::new(1, c, 0)).unwrap();
         let mut n = get_node(world, b);
         n.inputs.sort();
-        n.audio_channels.connections.sort();
+        n.audio_channels
+            .connections
+            .sort_by(|a, b| a.partial_cmp(b).unwrap());
         assert_eq!(n.inputs, vec![c, a]);
         assert_eq!(
             n.audio_channels.connections,
@@ -299,4 +464,40 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_disconnect_audio() {
+        let mut world = World::new();
+        let world = &mut world;
+        let a = world
+            .spawn(GraphNodeDesc::default().audio(0, 2).event(0, 1))
+            .id();
+        let b = world.spawn(GraphNodeDesc::default().audio(0, 2)).id();
+        let c = world
+            .spawn(GraphNodeDesc::default().audio(2, 0).event(1, 0))
+            .id();
+
+        graph_connect_audio(world, c, GraphConnection::new(0, a, 0)).unwrap();
+        graph_connect_audio(world, c, GraphConnection::new(1, b, 1)).unwrap();
+        graph_connect_event(world, c, GraphConnection::new(0, a, 0)).unwrap();
+
+        // Only the audio connections from the input node are removed
+        graph_disconnect_audio_input(world, c, a).unwrap();
+        let mut n = get_node(world, c);
+        n.inputs.sort();
+        let mut expected_inputs = vec![a, b];
+        expected_inputs.sort();
+        assert_eq!(n.inputs, expected_inputs);
+        assert_eq!(
+            n.audio_channels.connections,
+            vec![GraphConnection::new(1, b, 1)]
+        );
+        assert!(n.has_event_connected(a));
+
+        graph_disconnect_audio_input(world, c, b).unwrap();
+        graph_disconnect_event_input(world, c, a).unwrap();
+        let n = get_node(world, c);
+        assert!(n.audio_channels.connections.is_empty());
+        assert!(n.inputs.is_empty());
+    }
 }
@@ -0,0 +1,199 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use audio_blocks::AudioBlock;
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+
+use crate::GraphState;
+
+/// How the graph's output reaches the device's channels. The output node's
+/// channels are mixed down or up to the device's with `matrix`, or with a
+/// standard mix if there's no matrix or it doesn't fit, and `routes` add
+/// other nodes' outputs to device channels of their own. Changes are sent to
+/// the worker by `update_system`.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Reflect)]
+pub struct GraphOutputMapping {
+    pub matrix: Option<GraphMixMatrix>,
+    pub routes: Vec<GraphOutputRoute>,
+}
+
+impl GraphOutputMapping {
+    /// How much of the output node's `input` channel goes to the device's
+    /// `output` channel.
+    pub fn gain(&self, num_inputs: u16, num_outputs: u16, input: u16, output: u16) -> f32 {
+        match &self.matrix {
+            Some(matrix)
+                if matrix.num_inputs == num_inputs && matrix.num_outputs == num_outputs =>
+            {
+                matrix.gain(input, output)
+            }
+            _ => GraphMixMatrix::standard_gain(num_inputs, num_outputs, input, output),
+        }
+    }
+
+    /// Fills the interleaved `data` with the output node's audio and the
+    /// routed nodes' audio. Called on the audio thread.
+    pub(crate) fn mix(
+        &self,
+        graph: &GraphState,
+        output: Option<Entity>,
+        data: &mut [f32],
+        num_channels: u16,
+    ) {
+        data.fill(0.0);
+
+        if let Some(node) = output.and_then(|output| graph.get_node(output)) {
+            let buffers = node.output_audio_buffers.get();
+            let num_inputs = buffers.num_channels();
+            for channel in 0..num_channels {
+                for input in 0..num_inputs {
+                    let gain = self.gain(num_inputs, num_channels, input, channel);
+                    if gain != 0.0 {
+                        add_channel(data, num_channels, channel, buffers.channel(input), gain);
+                    }
+                }
+            }
+        }
+
+        for route in &self.routes {
+            if route.device_channel >= num_channels {
+                continue;
+            }
+            let Some(node) = graph.get_node(route.src) else {
+                continue;
+            };
+            let buffers = node.output_audio_buffers.get();
+            if route.src_channel < buffers.num_channels() {
+                add_channel(
+                    data,
+                    num_channels,
+                    route.device_channel,
+                    buffers.channel(route.src_channel),
+                    route.gain,
+                );
+            }
+        }
+    }
+}
+
+/// Adds `input` to one channel of the interleaved `data`.
+fn add_channel(data: &mut [f32], num_channels: u16, channel: u16, input: &[f32], gain: f32) {
+    let frames = data
+        .chunks_exact_mut(num_channels as usize)
+        .map(|frame| &mut frame[channel as usize]);
+    for (output, input) in frames.zip(input) {
+        *output += *input * gain;
+    }
+}
+
+/// Sends one channel of a node's output straight to a device channel, on top
+/// of whatever the output node puts there.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct GraphOutputRoute {
+    pub src: Entity,
+    pub src_channel: u16,
+    pub device_channel: u16,
+    pub gain: f32,
+}
+
+impl GraphOutputRoute {
+    pub fn new(src: Entity, src_channel: u16, device_channel: u16) -> Self {
+        Self {
+            src,
+            src_channel,
+            device_channel,
+            gain: 1.0,
+        }
+    }
+}
+
+/// The gain from each of `num_inputs` channels to each of `num_outputs`
+/// channels. Surround channels are in the order L, R, C, LFE, Ls, Rs.
+#[derive(Clone, Debug, PartialEq, Reflect)]
+pub struct GraphMixMatrix {
+    num_inputs: u16,
+    num_outputs: u16,
+    /// One row of input gains for each output.
+    gains: Vec<f32>,
+}
+
+impl GraphMixMatrix {
+    /// A matrix that mixes everything to silence.
+    pub fn new(num_inputs: u16, num_outputs: u16) -> Self {
+        Self {
+            num_inputs,
+            num_outputs,
+            gains: vec![0.0; num_inputs as usize * num_outputs as usize],
+        }
+    }
+
+    /// Sends each input to the output with the same index, dropping inputs
+    /// that there are no outputs for.
+    pub fn identity(num_inputs: u16, num_outputs: u16) -> Self {
+        let mut matrix = Self::new(num_inputs, num_outputs);
+        for channel in 0..num_inputs.min(num_outputs) {
+            matrix.set_gain(channel, channel, 1.0);
+        }
+        matrix
+    }
+
+    /// The mix used when there's no matrix: mono is copied to every channel
+    /// or to the centre of 5.1, stereo is averaged to mono, and 5.1 is folded
+    /// down with the centre and surrounds at -3 dB and the LFE dropped. Other
+    /// channel counts are mapped one to one.
+    pub fn standard(num_inputs: u16, num_outputs: u16) -> Self {
+        let mut matrix = Self::new(num_inputs, num_outputs);
+        for output in 0..num_outputs {
+            for input in 0..num_inputs {
+                let gain = Self::standard_gain(num_inputs, num_outputs, input, output);
+                matrix.set_gain(input, output, gain);
+            }
+        }
+        matrix
+    }
+
+    fn standard_gain(num_inputs: u16, num_outputs: u16, input: u16, output: u16) -> f32 {
+        let connected = |connected: bool| if connected { 1.0 } else { 0.0 };
+        match (num_inputs, num_outputs) {
+            (1, 6) => connected(output == 2),
+            (1, _) => 1.0,
+            (2, 1) => 0.5,
+            (6, 1) => [
+                0.5,
+                0.5,
+                FRAC_1_SQRT_2,
+                0.0,
+                0.5 * FRAC_1_SQRT_2,
+                0.5 * FRAC_1_SQRT_2,
+            ][input as usize],
+            (6, 2) => match (input, output) {
+                (0, 0) | (1, 1) => 1.0,
+                (2, _) | (4, 0) | (5, 1) => FRAC_1_SQRT_2,
+                _ => 0.0,
+            },
+            _ => connected(input == output),
+        }
+    }
+
+    pub fn num_inputs(&self) -> u16 {
+        self.num_inputs
+    }
+
+    pub fn num_outputs(&self) -> u16 {
+        self.num_outputs
+    }
+
+    pub fn gain(&self, input: u16, output: u16) -> f32 {
+        self.gains[self.index(input, output)]
+    }
+
+    pub fn set_gain(&mut self, input: u16, output: u16, gain: f32) {
+        let index = self.index(input, output);
+        self.gains[index] = gain;
+    }
+
+    fn index(&self, input: u16, output: u16) -> usize {
+        assert!(input < self.num_inputs && output < self.num_outputs);
+        output as usize * self.num_inputs as usize + input as usize
+    }
+}
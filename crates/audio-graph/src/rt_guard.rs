@@ -0,0 +1,102 @@
+//! Fails tests that allocate, free memory or take a lock on the audio thread.
+//! `GraphWorker::tick` enters a `RealtimeGuard` for as long as it runs, and
+//! allocations are counted by `GuardedAllocator`, which a test crate has to
+//! make its global allocator.
+//!
+//! Only the locks in `crate::sync` are counted. Taking a `std::sync` lock,
+//! or blocking on a channel, inside a guard goes unnoticed, so anything that
+//! the audio thread can reach has to use those locks instead, and only
+//! non-blocking channel calls.
+//!
+//! The graph's thread pool enters a guard of its own for each job, and
+//! passes on what it counts to the audio thread's guard.
+//!
+//! Built for this crate's tests, and for other crates' with the `rt-guard`
+//! feature.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    static REALTIME: Cell<bool> = const { Cell::new(false) };
+    static VIOLATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts a violation if the current thread is in a `RealtimeGuard`.
+pub(crate) fn check() {
+    // `try_with` as this can be called while the thread is being torn down
+    let _ = REALTIME.try_with(|realtime| {
+        if realtime.get() {
+            VIOLATIONS.with(|violations| violations.set(violations.get() + 1));
+        }
+    });
+}
+
+/// The system allocator, counting every call made while a `RealtimeGuard` is
+/// active:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: GuardedAllocator = GuardedAllocator;
+/// ```
+pub struct GuardedAllocator;
+
+unsafe impl GlobalAlloc for GuardedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        check();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        check();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        check();
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        check();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Counts allocations and locks on this thread until it's dropped, then
+/// panics if there were any.
+pub struct RealtimeGuard;
+
+impl RealtimeGuard {
+    pub fn enter() -> Self {
+        VIOLATIONS.with(|violations| violations.set(0));
+        REALTIME.with(|realtime| realtime.set(true));
+        RealtimeGuard
+    }
+
+    /// Stops counting, and returns how many violations there were rather
+    /// than panicking.
+    pub fn finish(self) -> usize {
+        std::mem::forget(self);
+        REALTIME.with(|realtime| realtime.set(false));
+        VIOLATIONS.with(|violations| violations.get())
+    }
+}
+
+/// Adds violations counted on another thread to this thread's count.
+pub(crate) fn add_violations(count: usize) {
+    VIOLATIONS.with(|violations| violations.set(violations.get() + count));
+}
+
+impl Drop for RealtimeGuard {
+    fn drop(&mut self) {
+        REALTIME.with(|realtime| realtime.set(false));
+        let violations = VIOLATIONS.with(|violations| violations.get());
+
+        if violations > 0 && !std::thread::panicking() {
+            panic!("{violations} allocations, frees or locks on the audio thread");
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy_ecs::entity::Entity;
+
+use crate::GraphNodeDesc;
+
+/// The delays a node needs on its inputs: `(src, delay, num_channels)`.
+pub(crate) type CompensationPlan = Vec<(Entity, usize, u16)>;
+
+/// The order to process the nodes `targets` depend on, with every node
+/// after its inputs.
+pub(crate) fn build_schedule(
+    descs: &HashMap<Entity, &GraphNodeDesc>,
+    targets: &[Entity],
+) -> Vec<Entity> {
+    let reachable = reachable_nodes(descs, targets);
+
+    let mut incoming: HashMap<Entity, usize> = HashMap::with_capacity(descs.len());
+
+    let mut outputs: HashMap<Entity, Vec<Entity>> = HashMap::with_capacity(descs.len());
+
+    let mut heap: BinaryHeap<Reverse<Entity>> = BinaryHeap::with_capacity(descs.len());
+    for node_entity in reachable.iter() {
+        let Some(desc) = descs.get(node_entity) else {
+            continue;
+        };
+        for input_entity in desc.inputs.iter() {
+            outputs.entry(*input_entity).or_default().push(*node_entity);
+        }
+        incoming.insert(*node_entity, desc.inputs.len());
+
+        if desc.inputs.is_empty() {
+            heap.push(Reverse(*node_entity));
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(descs.len());
+
+    while let Some(Reverse(node_entity)) = heap.pop() {
+        assert_eq!(incoming[&node_entity], 0);
+        ordered.push(node_entity);
+
+        if let Some(outputs) = outputs.get(&node_entity) {
+            for input in outputs {
+                *incoming.get_mut(input).unwrap() -= 1;
+                if incoming[input] == 0 {
+                    heap.push(Reverse(*input));
+                }
+            }
+        }
+    }
+
+    ordered
+}
+
+/// For each node in `schedule`, the indices of the nodes that read from it,
+/// and how many of the nodes in `schedule` it reads from itself.
+pub(crate) fn build_dependencies(
+    schedule: &[Entity],
+    descs: &HashMap<Entity, &GraphNodeDesc>,
+) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let indices: HashMap<Entity, usize> = schedule
+        .iter()
+        .enumerate()
+        .map(|(index, entity)| (*entity, index))
+        .collect();
+
+    let mut dependents = vec![Vec::new(); schedule.len()];
+    let mut num_inputs = vec![0; schedule.len()];
+
+    for (index, entity) in schedule.iter().enumerate() {
+        let Some(desc) = descs.get(entity) else {
+            continue;
+        };
+        for input in desc.inputs.iter().filter_map(|input| indices.get(input)) {
+            dependents[*input].push(index);
+            num_inputs[index] += 1;
+        }
+    }
+
+    (dependents, num_inputs)
+}
+
+pub(crate) fn reachable_nodes(
+    descs: &HashMap<Entity, &GraphNodeDesc>,
+    start_nodes: &[Entity],
+) -> HashSet<Entity> {
+    let mut reachable = HashSet::with_capacity(descs.len());
+    let mut stack = Vec::with_capacity(descs.len());
+
+    for (entity, desc) in descs.iter() {
+        if desc.always_run {
+            stack.push(*entity);
+        }
+    }
+
+    stack.extend_from_slice(start_nodes);
+    while let Some(node) = stack.pop() {
+        if !reachable.contains(&node) {
+            reachable.insert(node);
+            let Some(desc) = descs.get(&node) else {
+                continue;
+            };
+            // Feedback sources are needed too, for the next block
+            stack.extend_from_slice(desc.inputs.as_slice());
+            stack.extend_from_slice(desc.feedback_inputs.as_slice());
+        }
+    }
+
+    reachable
+}
+
+/// Works out how late each node's audio is, and the delays needed on the
+/// inputs of nodes whose inputs arrive at different times so that they line
+/// up. Nodes that need no delays are left out.
+pub(crate) fn plan_compensation(
+    schedule: &[Entity],
+    descs: &HashMap<Entity, &GraphNodeDesc>,
+    latencies: &HashMap<Entity, usize>,
+) -> HashMap<Entity, CompensationPlan> {
+    // The latency of each node's output, relative to the graph's sources
+    let mut totals: HashMap<Entity, usize> = HashMap::with_capacity(schedule.len());
+    let mut plan = HashMap::new();
+
+    for entity in schedule {
+        let Some(desc) = descs.get(entity) else {
+            continue;
+        };
+
+        let mut sources: Vec<Entity> = desc
+            .audio_channels
+            .connections
+            .iter()
+            .filter(|connection| !connection.feedback)
+            .map(|connection| connection.src)
+            .collect();
+        sources.sort();
+        sources.dedup();
+
+        let arrival = sources
+            .iter()
+            .filter_map(|src| totals.get(src))
+            .copied()
+            .max()
+            .unwrap_or(0);
+
+        let delays: CompensationPlan = sources
+            .iter()
+            .filter_map(|src| {
+                let delay = arrival - totals.get(src)?;
+                let num_channels = descs.get(src)?.audio_channels.num_outputs;
+                (delay > 0).then_some((*src, delay, num_channels))
+            })
+            .collect();
+
+        if !delays.is_empty() {
+            plan.insert(*entity, delays);
+        }
+
+        let latency = latencies.get(entity).copied().unwrap_or(0);
+        totals.insert(*entity, arrival + latency);
+    }
+
+    plan
+}
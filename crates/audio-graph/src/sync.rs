@@ -0,0 +1,53 @@
+//! Locks for state that the audio thread could end up touching, such as
+//! what plugins can call back into. They're the standard library's, except
+//! that in tests, taking one inside a `rt_guard::RealtimeGuard` fails them.
+//! These are the only locks the guard can see, so every lock on the audio
+//! thread's path has to be one of them.
+
+use std::sync::{self, LockResult, MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+
+#[derive(Debug, Default)]
+pub struct Mutex<T>(sync::Mutex<T>);
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self(sync::Mutex::new(value))
+    }
+
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        check();
+        self.0.lock()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.0.get_mut()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RwLock<T>(sync::RwLock<T>);
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self(sync::RwLock::new(value))
+    }
+
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        check();
+        self.0.read()
+    }
+
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        check();
+        self.0.write()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.0.get_mut()
+    }
+}
+
+fn check() {
+    #[cfg(any(test, feature = "rt-guard"))]
+    crate::rt_guard::check();
+}
@@ -1,6 +1,10 @@
 use std::{
     collections::VecDeque,
-    sync::{Arc, RwLock, RwLockReadGuard},
+    f32::consts::FRAC_1_SQRT_2,
+    sync::{
+        Arc, RwLock, RwLockReadGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
@@ -13,6 +17,9 @@ use crate::GraphEvent;
 
 use super::*;
 
+#[global_allocator]
+static ALLOCATOR: crate::rt_guard::GuardedAllocator = crate::rt_guard::GuardedAllocator;
+
 #[derive(Debug)]
 struct Constant(f32);
 impl GraphProcessor for Constant {
@@ -35,8 +42,7 @@ impl GraphProcessor for SumInputs {
             .desc
             .inputs
             .iter()
-            .map(|id| &ctx.graph.nodes[id])
-            .map(|node| node.output_audio_buffers.get());
+            .filter_map(|id| ctx.graph.input_audio_buffers(ctx.node, *id));
 
         for input in inputs {
             let input = input.channel(0);
@@ -64,7 +70,7 @@ struct Logger {
 impl Logger {
     fn new() -> Self {
         Self {
-            log: Arc::default(),
+            log: Arc::new(RwLock::new(Vec::with_capacity(64))),
         }
     }
 
@@ -105,7 +111,7 @@ fn single_node_process() {
     let mut data = [0.0, 0.0];
 
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     audio_graph_worker.tick(&mut data, Duration::default());
 
     assert_eq!([1.0, 1.0], data);
@@ -138,7 +144,7 @@ fn reachable_nodes() {
     app.update();
 
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -200,7 +206,7 @@ fn multiple_node_process_order() {
 
     app.update();
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -245,7 +251,7 @@ fn always_run_nodes() {
 
     app.update();
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -258,6 +264,42 @@ fn always_run_nodes() {
     assert!(!order.contains(&f));
 }
 
+#[test]
+fn schedule_follows_topology_changes() {
+    // a --> b, then c --> a --> b
+
+    let mut app = test_app();
+
+    let logger = Logger::new();
+
+    let w = app.world_mut();
+    let [a, c] = std::array::from_fn(|_| w.spawn(GraphNodeDesc::default().audio(1, 1)).id());
+    let b = w
+        .spawn((GraphNodeDesc::default().audio(1, 1), GraphOutputNode))
+        .id();
+
+    for e in [a, b, c] {
+        graph_set_processor(w, e, logger.make_processor());
+    }
+
+    let _ = graph_connect_audio(w, b, GraphConnection::new(0, a, 0));
+
+    app.update();
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.configure(1, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    let mut data = [0.0];
+
+    audio_graph_worker.tick(&mut data, Duration::default());
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!([a, b, a, b], logger.get()[..]);
+
+    let _ = graph_connect_audio(app.world_mut(), a, GraphConnection::new(0, c, 0));
+    app.update();
+
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!([c, a, b], logger.get()[4..]);
+}
+
 fn is_before(order: &Vec<Entity>, entity: Entity, is_before: Entity) -> bool {
     let entity_index = order.iter().find_position(|e| **e == entity).unwrap();
     let is_before_index = order.iter().find_position(|e| **e == is_before).unwrap();
@@ -298,7 +340,7 @@ fn node_processing() {
     app.update();
 
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -321,7 +363,7 @@ fn mono_node_stereo_output() {
     app.update();
 
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -393,7 +435,7 @@ fn events_output_to_single_input() {
         },
     ];
 
-    let events_sink: Arc<RwLock<VecDeque<GraphEvent>>> = Arc::default();
+    let events_sink = Arc::new(RwLock::new(VecDeque::with_capacity(events.len())));
 
     let source = w.spawn((node::GraphNodeDesc::default().event(0, 1),)).id();
     node::graph_set_processor(w, source, EventSource::make_processor(&events));
@@ -411,7 +453,7 @@ fn events_output_to_single_input() {
     app.update();
 
     let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
-    audio_graph_worker.configure(2, 1);
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
     let mut data = [0.0, 0.0];
     audio_graph_worker.tick(&mut data, Duration::default());
 
@@ -425,3 +467,923 @@ fn events_output_to_single_input() {
         events
     );
 }
+
+fn transport_test_app(transport: GraphTransport) -> (App, GraphWorker) {
+    let mut app = test_app();
+    app.insert_resource(transport);
+    app.update();
+
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    // At 60bpm and a sample rate of 1 each frame is one beat.
+    audio_graph_worker.configure(1, 1, DEFAULT_MAX_BLOCK_FRAMES);
+
+    (app, audio_graph_worker)
+}
+
+fn transport_position(app: &App) -> f64 {
+    app.world()
+        .non_send::<GraphController>()
+        .transport_position()
+}
+
+#[test]
+fn transport_only_advances_while_playing() {
+    let transport = GraphTransport {
+        tempo: 60.0,
+        ..Default::default()
+    };
+    let (mut app, mut audio_graph_worker) = transport_test_app(transport);
+
+    let mut data = [0.0; 4];
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!(0.0, transport_position(&app));
+
+    app.world_mut().resource_mut::<GraphTransport>().playing = true;
+    app.update();
+
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!(4.0, transport_position(&app));
+}
+
+#[test]
+fn transport_wraps_at_loop_end() {
+    let transport = GraphTransport {
+        playing: true,
+        tempo: 60.0,
+        loop_region: Some(GraphLoopRegion {
+            start: 1.0,
+            end: 3.0,
+        }),
+        ..Default::default()
+    };
+    let (app, mut audio_graph_worker) = transport_test_app(transport);
+
+    let mut data = [0.0; 4];
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!(2.0, transport_position(&app));
+}
+
+#[test]
+fn transport_seek() {
+    let transport = GraphTransport {
+        playing: true,
+        tempo: 60.0,
+        ..Default::default()
+    };
+    let (mut app, mut audio_graph_worker) = transport_test_app(transport);
+
+    app.world_mut().trigger(GraphSeekEvent(10.0));
+
+    let mut data = [0.0; 4];
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!(14.0, transport_position(&app));
+}
+
+/// Outputs a 1 on the first frame of every block.
+#[derive(Debug)]
+struct Impulse;
+
+impl GraphProcessor for Impulse {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        let output = ctx.out_audio_buffers.channel_mut(0);
+        output.fill(0.0);
+        output[0] = 1.0;
+    }
+}
+
+/// Delays its input by a latency that can be changed while it runs.
+#[derive(Debug)]
+struct Latent {
+    latency: Arc<AtomicUsize>,
+    line: VecDeque<f32>,
+}
+
+impl Latent {
+    fn make_processor(latency: &Arc<AtomicUsize>) -> Box<dyn GraphProcessor> {
+        Box::new(Latent {
+            latency: latency.clone(),
+            line: VecDeque::with_capacity(16),
+        })
+    }
+}
+
+impl GraphProcessor for Latent {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        self.line.resize(self.latency(), 0.0);
+
+        let input = ctx
+            .graph
+            .input_audio_buffers(ctx.node, ctx.node.desc.inputs[0])
+            .unwrap();
+        for (input, output) in input
+            .channel(0)
+            .iter()
+            .zip(ctx.out_audio_buffers.channel_mut(0).iter_mut())
+        {
+            self.line.push_back(*input);
+            *output = self.line.pop_front().unwrap();
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.latency.load(Ordering::Relaxed)
+    }
+}
+
+/// impulse --> latent --> sum
+///        \-------------/
+fn latency_test_app(latency: &Arc<AtomicUsize>) -> (App, GraphWorker) {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let impulse = w.spawn(node::GraphNodeDesc::default().audio(0, 1)).id();
+    graph_set_processor(w, impulse, Box::new(Impulse));
+
+    let latent = w.spawn(node::GraphNodeDesc::default().audio(1, 1)).id();
+    graph_set_processor(w, latent, Latent::make_processor(latency));
+
+    let sum = w
+        .spawn((
+            node::GraphNodeDesc::default().audio(2, 1),
+            node::GraphOutputNode,
+        ))
+        .id();
+    graph_set_processor(w, sum, Box::new(SumInputs));
+
+    let _ = node::graph_connect_audio(w, latent, GraphConnection::new(0, impulse, 0));
+    let _ = node::graph_connect_audio(w, sum, GraphConnection::new(0, latent, 0));
+    let _ = node::graph_connect_audio(w, sum, GraphConnection::new(1, impulse, 0));
+
+    app.update();
+
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.configure(1, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    (app, audio_graph_worker)
+}
+
+#[test]
+fn parallel_paths_are_delay_compensated() {
+    let latency = Arc::new(AtomicUsize::new(2));
+    let (_app, mut audio_graph_worker) = latency_test_app(&latency);
+
+    let mut data = [0.0; 4];
+    audio_graph_worker.tick(&mut data, Duration::default());
+
+    assert_eq!([0.0, 0.0, 2.0, 0.0], data);
+}
+
+#[test]
+fn compensation_follows_latency_changes() {
+    let latency = Arc::new(AtomicUsize::new(1));
+    let (mut app, mut audio_graph_worker) = latency_test_app(&latency);
+
+    let mut data = [0.0; 4];
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!([0.0, 2.0, 0.0, 0.0], data);
+
+    // The worker reports the change, and the main thread plans new delays
+    latency.store(3, Ordering::Relaxed);
+    audio_graph_worker.tick(&mut data, Duration::default());
+    app.update();
+
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!([0.0, 0.0, 0.0, 2.0], data);
+}
+
+/// Keeps count of how many of them are alive.
+#[derive(Debug)]
+struct Counted(Arc<AtomicUsize>);
+
+impl Counted {
+    fn make_processor(live: &Arc<AtomicUsize>) -> Box<dyn GraphProcessor> {
+        live.fetch_add(1, Ordering::Relaxed);
+        Box::new(Counted(live.clone()))
+    }
+}
+
+impl GraphProcessor for Counted {
+    fn process(&mut self, _ctx: GraphProcessContext) {}
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn despawned_node_processor_is_dropped_on_main_thread() {
+    let mut app = test_app();
+    let live = Arc::new(AtomicUsize::new(0));
+
+    let w = app.world_mut();
+    let node = w.spawn(GraphNodeDesc::default().audio(0, 1)).id();
+    graph_set_processor(w, node, Counted::make_processor(&live));
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(1, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, Box::new(SumInputs));
+    let _ = graph_connect_audio(w, output, GraphConnection::new(0, node, 0));
+
+    app.update();
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.configure(1, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    let mut data = [0.0];
+    audio_graph_worker.tick(&mut data, Duration::default());
+
+    app.world_mut().despawn(node);
+    app.update();
+
+    // The worker lets go of the processor without freeing it...
+    audio_graph_worker.tick(&mut data, Duration::default());
+    assert_eq!(1, live.load(Ordering::Relaxed));
+    assert!(audio_graph_worker.graph.processors.remove(node).is_none());
+
+    // ...and the main thread drops it
+    app.update();
+    assert_eq!(0, live.load(Ordering::Relaxed));
+}
+
+#[test]
+fn replaced_processor_is_dropped_on_main_thread() {
+    let mut app = test_app();
+    let live = Arc::new(AtomicUsize::new(0));
+
+    let w = app.world_mut();
+    let node = w
+        .spawn((GraphNodeDesc::default().audio(0, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, node, Counted::make_processor(&live));
+
+    app.update();
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.configure(1, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    let mut data = [0.0];
+
+    for _ in 0..3 {
+        graph_set_processor(app.world_mut(), node, Counted::make_processor(&live));
+        audio_graph_worker.tick(&mut data, Duration::default());
+        app.update();
+        assert_eq!(1, live.load(Ordering::Relaxed));
+    }
+}
+
+/// Adds a tone of its own to the sum of its inputs, so that every node's
+/// output depends on exactly what it was given.
+#[derive(Debug)]
+struct Mixer {
+    gain: f32,
+    phase: f32,
+}
+
+impl GraphProcessor for Mixer {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        let output = ctx.out_audio_buffers.channel_mut(0);
+        for (frame, out) in output.iter_mut().enumerate() {
+            *out = (self.phase + frame as f32 * self.gain).sin() * 0.1;
+        }
+        self.phase += ctx.num_frames as f32 * self.gain;
+
+        for src in &ctx.node.desc.inputs {
+            if let Some(input) = ctx.graph.input_audio_buffers(ctx.node, *src) {
+                for (input, out) in input.channel(0).iter().zip(output.iter_mut()) {
+                    *out += *input * self.gain;
+                }
+            }
+        }
+    }
+}
+
+/// Four chains of three mixers feeding the output, with the second chain
+/// also reading from the middle of the first.
+fn mixer_graph_worker(num_threads: usize) -> GraphWorker {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let mixer = |index: usize| -> Box<dyn GraphProcessor> {
+        Box::new(Mixer {
+            gain: 0.01 * (index + 1) as f32,
+            phase: 0.0,
+        })
+    };
+
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(4, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, mixer(0));
+
+    let mut chains = Vec::new();
+    for chain in 0..4 {
+        let mut previous: Option<Entity> = None;
+        let mut nodes = Vec::new();
+        for index in 0..3 {
+            let node = w.spawn(GraphNodeDesc::default().audio(2, 1)).id();
+            graph_set_processor(w, node, mixer(chain * 3 + index));
+            if let Some(previous) = previous {
+                graph_connect_audio(w, node, GraphConnection::new(0, previous, 0)).unwrap();
+            }
+            previous = Some(node);
+            nodes.push(node);
+        }
+        graph_connect_audio(
+            w,
+            output,
+            GraphConnection::new(chain as u16, previous.unwrap(), 0),
+        )
+        .unwrap();
+        chains.push(nodes);
+    }
+    graph_connect_audio(w, chains[1][2], GraphConnection::new(1, chains[0][1], 0)).unwrap();
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.set_num_threads(num_threads);
+    worker.configure(1, 48_000, DEFAULT_MAX_BLOCK_FRAMES);
+    worker
+}
+
+#[test]
+fn parallel_processing_matches_serial() {
+    let mut serial = mixer_graph_worker(0);
+    let mut parallel = mixer_graph_worker(3);
+
+    for _ in 0..20 {
+        let mut serial_data = [0.0; 256];
+        let mut parallel_data = [0.0; 256];
+        serial.tick(&mut serial_data, Duration::default());
+        parallel.tick(&mut parallel_data, Duration::default());
+
+        assert!(serial_data.iter().any(|sample| *sample != 0.0));
+        assert_eq!(
+            serial_data.map(f32::to_bits),
+            parallel_data.map(f32::to_bits)
+        );
+    }
+}
+
+/// Hands out tasks to the pool, counting how many times each one runs.
+#[derive(Debug)]
+struct TaskRunner {
+    runs: Arc<[AtomicUsize; 8]>,
+    handed_out: Arc<AtomicUsize>,
+}
+
+impl GraphProcessor for TaskRunner {
+    fn process(&mut self, _ctx: GraphProcessContext) {
+        let task = |index: u32| {
+            self.runs[index as usize].fetch_add(1, Ordering::Relaxed);
+        };
+
+        if GraphThreadPool::execute(8, &task) {
+            self.handed_out.fetch_add(1, Ordering::Relaxed);
+        } else {
+            (0..8).for_each(task);
+        }
+    }
+}
+
+#[test]
+fn processors_can_hand_tasks_to_the_thread_pool() {
+    let runs = Arc::new(std::array::from_fn(|_| AtomicUsize::new(0)));
+    let handed_out = Arc::new(AtomicUsize::new(0));
+
+    let mut app = test_app();
+    let w = app.world_mut();
+    let node = w.spawn(GraphNodeDesc::default().audio(0, 1)).id();
+    graph_set_processor(
+        w,
+        node,
+        Box::new(TaskRunner {
+            runs: runs.clone(),
+            handed_out: handed_out.clone(),
+        }),
+    );
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(1, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, Box::new(SumInputs));
+    graph_connect_audio(w, output, GraphConnection::new(0, node, 0)).unwrap();
+
+    app.update();
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.set_num_threads(2);
+    audio_graph_worker.configure(1, 48_000, DEFAULT_MAX_BLOCK_FRAMES);
+
+    let mut data = [0.0; 64];
+    for _ in 0..10 {
+        audio_graph_worker.tick(&mut data, Duration::default());
+    }
+
+    assert_eq!(10, handed_out.load(Ordering::Relaxed));
+    for run in runs.iter() {
+        assert_eq!(10, run.load(Ordering::Relaxed));
+    }
+
+    // Without a pool, the processor has to run the tasks itself
+    audio_graph_worker.set_num_threads(0);
+    audio_graph_worker.tick(&mut data, Duration::default());
+
+    assert_eq!(10, handed_out.load(Ordering::Relaxed));
+    for run in runs.iter() {
+        assert_eq!(11, run.load(Ordering::Relaxed));
+    }
+}
+
+/// Sums every audio connection into its one output channel, including
+/// feedback connections.
+#[derive(Debug)]
+struct SumConnections;
+
+impl GraphProcessor for SumConnections {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        ctx.out_audio_buffers.channel_mut(0).fill(0.0);
+
+        for connection in &ctx.node.desc.audio_channels.connections {
+            if let Some(input) = ctx.graph.input_audio_buffers(ctx.node, connection.src) {
+                for (input, output) in input
+                    .channel(connection.src_channel)
+                    .iter()
+                    .zip(ctx.out_audio_buffers.channel_iter_mut(0))
+                {
+                    *output += *input;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn connecting_a_cycle_is_an_error() {
+    // a --> b --> c
+    let mut app = test_app();
+    let w = app.world_mut();
+    let a = w.spawn(GraphNodeDesc::default().audio(1, 1)).id();
+    let b = w.spawn(GraphNodeDesc::default().audio(1, 1)).id();
+    let c = w.spawn(GraphNodeDesc::default().audio(1, 1)).id();
+    graph_connect_audio(w, b, GraphConnection::new(0, a, 0)).unwrap();
+    graph_connect_audio(w, c, GraphConnection::new(0, b, 0)).unwrap();
+
+    assert!(matches!(
+        graph_connect_audio(w, a, GraphConnection::new(0, c, 0)),
+        Err(GraphError::Cycle)
+    ));
+    assert!(matches!(
+        graph_connect_audio(w, b, GraphConnection::new(0, c, 0)),
+        Err(GraphError::Cycle)
+    ));
+    assert!(matches!(
+        graph_connect_audio(w, a, GraphConnection::new(0, a, 0)),
+        Err(GraphError::DestEqualsSrc)
+    ));
+    assert!(w.get::<GraphNodeDesc>(a).unwrap().inputs.is_empty());
+
+    // Parallel paths aren't cycles
+    graph_connect_audio(w, c, GraphConnection::new(0, a, 0)).unwrap();
+
+    // Feedback has to be asked for, and only works for audio
+    graph_connect_audio(w, a, GraphConnection::new_feedback(0, c, 0)).unwrap();
+    assert!(matches!(
+        graph_connect_audio(w, c, GraphConnection::new_feedback(0, b, 0)),
+        Err(GraphError::MixedFeedback)
+    ));
+    assert!(matches!(
+        graph_connect_event(w, a, GraphConnection::new_feedback(0, c, 0)),
+        Err(GraphError::EventFeedback)
+    ));
+
+    let desc = w.get::<GraphNodeDesc>(a).unwrap();
+    assert!(desc.inputs.is_empty());
+    assert_eq!(desc.feedback_inputs, vec![c]);
+}
+
+/// `source --> a --> b --> output`, with `b` fed back into `a`, so that each
+/// block `a` adds one to what `b` output in the block before.
+fn feedback_loop_worker(num_threads: usize) -> GraphWorker {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let source = w.spawn(GraphNodeDesc::default().audio(0, 1)).id();
+    graph_set_processor(w, source, Box::new(Constant(1.0)));
+    let a = w.spawn(GraphNodeDesc::default().audio(2, 1)).id();
+    graph_set_processor(w, a, Box::new(SumConnections));
+    let b = w.spawn(GraphNodeDesc::default().audio(1, 1)).id();
+    graph_set_processor(w, b, Box::new(SumConnections));
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(1, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, Box::new(SumConnections));
+
+    graph_connect_audio(w, a, GraphConnection::new(0, source, 0)).unwrap();
+    graph_connect_audio(w, b, GraphConnection::new(0, a, 0)).unwrap();
+    graph_connect_audio(w, output, GraphConnection::new(0, b, 0)).unwrap();
+    graph_connect_audio(w, a, GraphConnection::new_feedback(1, b, 0)).unwrap();
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.set_num_threads(num_threads);
+    worker.configure(1, 48_000, DEFAULT_MAX_BLOCK_FRAMES);
+    worker
+}
+
+#[test]
+fn feedback_connection_reads_previous_block() {
+    for num_threads in [0, 2] {
+        let mut worker = feedback_loop_worker(num_threads);
+
+        for block in 1..=4 {
+            let mut data = [0.0; 4];
+            worker.tick(&mut data, Duration::default());
+            assert_eq!([block as f32; 4], data, "{num_threads} threads");
+        }
+    }
+}
+
+#[test]
+fn node_can_feed_back_into_itself() {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let source = w.spawn(GraphNodeDesc::default().audio(0, 1)).id();
+    graph_set_processor(w, source, Box::new(Constant(0.5)));
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(2, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, Box::new(SumConnections));
+    graph_connect_audio(w, output, GraphConnection::new(0, source, 0)).unwrap();
+    graph_connect_audio(w, output, GraphConnection::new_feedback(1, output, 0)).unwrap();
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(1, 48_000, DEFAULT_MAX_BLOCK_FRAMES);
+
+    let mut data = [0.0; 2];
+    for expected in [0.5, 1.0, 1.5] {
+        worker.tick(&mut data, Duration::default());
+        assert_eq!([expected; 2], data);
+    }
+}
+
+/// Outputs its channel number, counting from one, on each channel.
+#[derive(Debug)]
+struct ChannelNumbers;
+
+impl GraphProcessor for ChannelNumbers {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        for (channel, output) in ctx.out_audio_buffers.channels_mut().enumerate() {
+            output.fill(channel as f32 + 1.0);
+        }
+    }
+}
+
+/// Ticks a graph whose output node has `num_outputs` channels through a
+/// device with `num_channels` channels, returning the first frame.
+fn tick_output_mapping(
+    num_outputs: u16,
+    num_channels: u16,
+    mapping: GraphOutputMapping,
+) -> Vec<f32> {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let output = w
+        .spawn((
+            GraphNodeDesc::default().audio(0, num_outputs),
+            GraphOutputNode,
+        ))
+        .id();
+    graph_set_processor(w, output, Box::new(ChannelNumbers));
+    w.insert_resource(mapping);
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(num_channels, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    let mut data = vec![0.0; num_channels as usize];
+    worker.tick(&mut data, Duration::default());
+    data
+}
+
+#[test]
+fn output_is_mixed_to_the_device_channels() {
+    let standard = GraphOutputMapping::default;
+
+    assert_eq!(vec![1.5], tick_output_mapping(2, 1, standard()));
+    assert_eq!(
+        vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        tick_output_mapping(1, 6, standard())
+    );
+    assert_eq!(
+        vec![1.0, 2.0, 0.0, 0.0, 0.0, 0.0],
+        tick_output_mapping(2, 6, standard())
+    );
+
+    // The centre and surrounds are folded into the sides at -3 dB
+    let stereo = tick_output_mapping(6, 2, standard());
+    let expected = [
+        1.0 + (3.0 + 5.0) * FRAC_1_SQRT_2,
+        2.0 + (3.0 + 6.0) * FRAC_1_SQRT_2,
+    ];
+    for (actual, expected) in stereo.into_iter().zip(expected) {
+        assert!((actual - expected).abs() < 1e-5);
+    }
+
+    // Channel counts without a standard mix are mapped one to one rather
+    // than panicking
+    assert_eq!(vec![1.0, 2.0], tick_output_mapping(3, 2, standard()));
+    assert_eq!(
+        vec![1.0, 2.0, 3.0, 0.0],
+        tick_output_mapping(3, 4, standard())
+    );
+}
+
+#[test]
+fn output_mapping_matrix_replaces_the_standard_mix() {
+    let mut swap = GraphMixMatrix::new(2, 2);
+    swap.set_gain(0, 1, 1.0);
+    swap.set_gain(1, 0, 1.0);
+    let mapping = GraphOutputMapping {
+        matrix: Some(swap),
+        ..Default::default()
+    };
+
+    assert_eq!(vec![2.0, 1.0], tick_output_mapping(2, 2, mapping.clone()));
+    // A matrix that doesn't fit the channel counts is ignored
+    assert_eq!(vec![1.5], tick_output_mapping(2, 1, mapping));
+}
+
+#[test]
+fn nodes_can_be_routed_to_device_channels() {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let output = w
+        .spawn((GraphNodeDesc::default().audio(0, 2), GraphOutputNode))
+        .id();
+    graph_set_processor(w, output, Box::new(Constant(1.0)));
+    // Not connected to the output node, so only processed for the route
+    let bus = w.spawn(GraphNodeDesc::default().audio(0, 2)).id();
+    graph_set_processor(w, bus, Box::new(ChannelNumbers));
+
+    let mut mapping = w.resource_mut::<GraphOutputMapping>();
+    mapping.routes.push(GraphOutputRoute::new(bus, 0, 2));
+    mapping.routes.push(GraphOutputRoute::new(bus, 1, 3));
+    // Routes to channels the device doesn't have are skipped
+    mapping.routes.push(GraphOutputRoute::new(bus, 1, 7));
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(4, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    let mut data = [0.0; 8];
+    worker.tick(&mut data, Duration::default());
+    assert_eq!([1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 2.0], data);
+
+    // Removing the routes silences the bus's device channels
+    app.world_mut()
+        .resource_mut::<GraphOutputMapping>()
+        .routes
+        .clear();
+    app.update();
+
+    worker.tick(&mut data, Duration::default());
+    assert_eq!([1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0], data);
+}
+
+#[test]
+fn channel_layouts_fold_to_the_nearest_speakers() {
+    use GraphChannelLayout::*;
+    const HALF: f32 = 0.5;
+    const MINUS_3DB: f32 = FRAC_1_SQRT_2;
+
+    assert_eq!(vec![(0, 0, 1.0), (1, 1, 1.0)], Stereo.connections(Stereo));
+    assert_eq!(vec![(0, 0, 1.0), (0, 1, 1.0)], Mono.connections(Stereo));
+    assert_eq!(vec![(0, 0, HALF), (1, 0, HALF)], Stereo.connections(Mono));
+    assert_eq!(vec![(0, 2, 1.0)], Mono.connections(Surround51));
+    // The LFE is dropped
+    assert_eq!(
+        vec![
+            (0, 0, 1.0),
+            (1, 1, 1.0),
+            (2, 0, MINUS_3DB),
+            (2, 1, MINUS_3DB),
+            (4, 0, MINUS_3DB),
+            (5, 1, MINUS_3DB)
+        ],
+        Surround51.connections(Stereo)
+    );
+    // The sides share the backs
+    assert_eq!(
+        vec![
+            (0, 0, 1.0),
+            (1, 1, 1.0),
+            (2, 0, MINUS_3DB),
+            (2, 1, MINUS_3DB),
+            (4, 2, 1.0),
+            (5, 3, 1.0),
+            (6, 2, 1.0),
+            (7, 3, 1.0)
+        ],
+        Surround71.connections(Quad)
+    );
+    // Mono keeps its level on the fronts
+    assert_eq!(vec![(0, 0, 1.0), (0, 1, 1.0)], Mono.connections(Quad));
+    assert_eq!(
+        vec![(0, 0, 1.0), (1, 1, 1.0)],
+        Discrete(3).connections(Stereo)
+    );
+    assert_eq!(
+        vec![(0, 0, 1.0), (1, 1, 1.0), (2, 2, 1.0), (3, 3, 1.0)],
+        Ambisonic(1).connections(Quad)
+    );
+}
+
+#[test]
+fn channel_layouts_fold_like_the_standard_mix() {
+    use GraphChannelLayout::*;
+
+    for src in [Mono, Stereo, Surround51] {
+        for dst in [Mono, Stereo, Surround51] {
+            let standard = GraphMixMatrix::standard(src.num_channels(), dst.num_channels());
+            let mut folded = GraphMixMatrix::new(src.num_channels(), dst.num_channels());
+            for (channel, dst_channel, gain) in src.connections(dst) {
+                folded.set_gain(channel, dst_channel, gain);
+            }
+            assert_eq!(standard, folded, "{src:?} to {dst:?}");
+        }
+    }
+}
+
+#[test]
+fn nodes_are_connected_by_layout() {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let src = w
+        .spawn(GraphNodeDesc::default().audio_layout(
+            GraphChannelLayout::Discrete(0),
+            GraphChannelLayout::Surround51,
+        ))
+        .id();
+    let dst = w.spawn(GraphNodeDesc::default().audio(2, 2)).id();
+    assert_eq!(
+        GraphChannelLayout::Stereo,
+        w.get::<GraphNodeDesc>(dst)
+            .unwrap()
+            .audio_channels
+            .input_layout
+    );
+
+    graph_connect_audio_layout(w, dst, src).unwrap();
+
+    let connections = &w
+        .get::<GraphNodeDesc>(dst)
+        .unwrap()
+        .audio_channels
+        .connections;
+    itertools::assert_equal(
+        [(0, 0), (1, 1), (0, 2), (1, 2), (0, 4), (1, 5)],
+        connections
+            .iter()
+            .map(|connection| (connection.channel, connection.src_channel)),
+    );
+    // The centre and surrounds are folded in at -3 dB
+    itertools::assert_equal(
+        [
+            1.0,
+            1.0,
+            FRAC_1_SQRT_2,
+            FRAC_1_SQRT_2,
+            FRAC_1_SQRT_2,
+            FRAC_1_SQRT_2,
+        ],
+        connections.iter().map(|connection| connection.gain),
+    );
+}
+
+/// Records the length of every block it processes, and reports a boundary
+/// at `boundary` frames after it started.
+#[derive(Debug)]
+struct BlockSizes {
+    sizes: Arc<RwLock<Vec<usize>>>,
+    boundary: Option<usize>,
+    frames: usize,
+}
+
+impl GraphProcessor for BlockSizes {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        ctx.out_audio_buffers.channel_mut(0).fill(1.0);
+        self.sizes.write().unwrap().push(ctx.num_frames);
+        self.frames += ctx.num_frames;
+    }
+
+    fn next_boundary(
+        &self,
+        _transport: &GraphTransportInfo,
+        _sample_rate: u32,
+        _num_frames: usize,
+    ) -> Option<usize> {
+        self.boundary
+            .and_then(|boundary| boundary.checked_sub(self.frames))
+    }
+}
+
+/// A worker whose output node is a `BlockSizes`, and the sizes it records.
+fn block_sizes_worker(boundary: Option<usize>) -> (GraphWorker, Arc<RwLock<Vec<usize>>>) {
+    let mut app = test_app();
+    let w = app.world_mut();
+
+    let sizes = Arc::new(RwLock::new(Vec::with_capacity(64)));
+    let node = w
+        .spawn((GraphNodeDesc::default().audio(0, 1), GraphOutputNode))
+        .id();
+    graph_set_processor(
+        w,
+        node,
+        Box::new(BlockSizes {
+            sizes: sizes.clone(),
+            boundary,
+            frames: 0,
+        }),
+    );
+
+    app.update();
+    app.update();
+
+    let worker = app.world_mut().remove_non_send().unwrap();
+    (worker, sizes)
+}
+
+#[test]
+fn long_blocks_are_processed_in_pieces() {
+    let (mut worker, sizes) = block_sizes_worker(None);
+
+    worker.configure(1, 1, 64);
+    let mut data = vec![0.0; 160];
+    worker.tick(&mut data, Duration::default());
+    assert_eq!(vec![64, 64, 32], *sizes.read().unwrap());
+
+    // The node's buffers are reallocated for the new maximum, otherwise
+    // processing would overrun them
+    sizes.write().unwrap().clear();
+    worker.configure(1, 1, 2048);
+    let mut data = vec![0.0; 2048];
+    worker.tick(&mut data, Duration::default());
+    assert_eq!(vec![2048], *sizes.read().unwrap());
+    assert!(data.iter().all(|sample| *sample == 1.0));
+}
+
+#[test]
+fn blocks_can_be_split_at_boundaries() {
+    let (mut worker, sizes) = block_sizes_worker(Some(30));
+    worker.configure(1, 1, 64);
+
+    let mut data = vec![0.0; 100];
+    worker.tick(&mut data, Duration::default());
+    assert_eq!(vec![64, 36], *sizes.read().unwrap());
+
+    let (mut worker, sizes) = block_sizes_worker(Some(30));
+    worker.configure(1, 1, 64);
+    worker.set_split_at_boundaries(true);
+
+    worker.tick(&mut data, Duration::default());
+    assert_eq!(vec![30, 34, 36], *sizes.read().unwrap());
+    assert!(data.iter().all(|sample| *sample == 1.0));
+}
+
+/// Takes a lock that the realtime guard knows about.
+#[derive(Debug)]
+struct Locking(Arc<crate::sync::Mutex<f32>>);
+
+impl GraphProcessor for Locking {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        let value = *self.0.lock().unwrap();
+        for out in ctx.out_audio_buffers.raw_data_mut() {
+            *out = value;
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "on the audio thread")]
+fn locking_on_the_audio_thread_fails() {
+    let mut app = test_app();
+
+    let node = app
+        .world_mut()
+        .spawn((
+            node::GraphNodeDesc::default().audio(0, 2),
+            node::GraphOutputNode,
+        ))
+        .id();
+    let lock = Arc::new(crate::sync::Mutex::new(1.0));
+    node::graph_set_processor(app.world_mut(), node, Box::new(Locking(lock)));
+
+    app.update();
+
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.configure(2, 1, DEFAULT_MAX_BLOCK_FRAMES);
+    audio_graph_worker.tick(&mut [0.0, 0.0], Duration::default());
+}
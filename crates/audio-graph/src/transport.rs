@@ -0,0 +1,160 @@
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+
+use crate::GraphController;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct GraphTimeSignature {
+    pub numerator: u16,
+    pub denominator: u16,
+}
+
+impl Default for GraphTimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+/// A region of the timeline, in beats.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct GraphLoopRegion {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// The transport as seen from the ECS. Changes are sent to the worker by
+/// `transport_system`; the song position itself is owned by the worker and can
+/// be read back with `GraphController::transport_position`, or moved with a
+/// `GraphSeekEvent`.
+#[derive(Resource, Clone, Debug, PartialEq, Reflect)]
+pub struct GraphTransport {
+    pub playing: bool,
+    pub recording: bool,
+    /// Beats per minute.
+    pub tempo: f64,
+    pub time_signature: GraphTimeSignature,
+    /// While set, playback jumps back to the start of the region when it
+    /// reaches the end.
+    pub loop_region: Option<GraphLoopRegion>,
+}
+
+impl Default for GraphTransport {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            recording: false,
+            tempo: 120.0,
+            time_signature: GraphTimeSignature::default(),
+            loop_region: None,
+        }
+    }
+}
+
+/// Moves the song position to the given beat.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GraphSeekEvent(pub f64);
+
+/// The transport for the block currently being processed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphTransportInfo {
+    pub playing: bool,
+    pub recording: bool,
+    pub tempo: f64,
+    pub time_signature: GraphTimeSignature,
+    pub loop_region: Option<GraphLoopRegion>,
+    /// Song position, in beats, of the first frame of the block.
+    pub position: f64,
+}
+
+impl Default for GraphTransportInfo {
+    fn default() -> Self {
+        Self::new(&GraphTransport::default(), 0.0)
+    }
+}
+
+impl GraphTransportInfo {
+    pub(crate) fn new(transport: &GraphTransport, position: f64) -> Self {
+        Self {
+            playing: transport.playing,
+            recording: transport.recording,
+            tempo: transport.tempo,
+            time_signature: transport.time_signature,
+            loop_region: transport.loop_region,
+            position,
+        }
+    }
+
+    pub fn seconds_per_beat(&self) -> f64 {
+        60.0 / self.tempo
+    }
+
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        beats * self.seconds_per_beat()
+    }
+
+    pub fn beats_per_frame(&self, sample_rate: u32) -> f64 {
+        self.tempo / (60.0 * sample_rate as f64)
+    }
+
+    /// Beats are always quarter notes, so a bar of 6/8 is 3 beats long.
+    pub fn beats_per_bar(&self) -> f64 {
+        let GraphTimeSignature {
+            numerator,
+            denominator,
+        } = self.time_signature;
+        numerator as f64 * 4.0 / denominator as f64
+    }
+
+    /// Zero-based index of the bar containing `position`.
+    pub fn bar_number(&self) -> i32 {
+        (self.position / self.beats_per_bar()).floor() as i32
+    }
+
+    pub fn bar_start(&self) -> f64 {
+        self.bar_number() as f64 * self.beats_per_bar()
+    }
+
+    /// The song position `frame` frames into the block, wrapping around the
+    /// loop region if playback crosses its end.
+    pub fn position_at(&self, frame: usize, sample_rate: u32) -> f64 {
+        if !self.playing {
+            return self.position;
+        }
+
+        let position = self.position + frame as f64 * self.beats_per_frame(sample_rate);
+
+        match self.loop_region {
+            // Only wrap if we were inside the loop to start with, so that
+            // seeking past the end of the loop doesn't jump back.
+            Some(GraphLoopRegion { start, end })
+                if end > start && self.position < end && position >= end =>
+            {
+                start + (position - end) % (end - start)
+            }
+            _ => position,
+        }
+    }
+
+    pub(crate) fn advance(&mut self, num_frames: usize, sample_rate: u32) {
+        self.position = self.position_at(num_frames, sample_rate);
+    }
+}
+
+pub(crate) fn transport_system(
+    transport: Res<GraphTransport>,
+    mut audio_graph: NonSendMut<GraphController>,
+) {
+    if transport.is_changed() {
+        audio_graph.set_transport(&transport);
+    }
+}
+
+pub(crate) fn on_seek_event(
+    seek: On<GraphSeekEvent>,
+    mut audio_graph: NonSendMut<GraphController>,
+) {
+    audio_graph.seek(seek.0);
+}
@@ -1,29 +1,40 @@
-use std::{
-    cell::RefCell,
-    cmp::Reverse,
-    collections::{BinaryHeap, HashMap, HashSet, hash_map::Entry},
-    fmt::Debug,
-    time::Duration,
-};
-
-use audio_blocks::AudioBlockSequential;
+use std::{collections::HashMap, fmt::Debug, hint, mem, time::Duration};
+
+use audio_blocks::{AudioBlock, AudioBlockSequential};
 use bevy_ecs::entity::Entity;
 
-use crate::{GraphEvent, node};
+use crate::{GraphEvent, GraphTransportInfo, node};
 
 mod buffers;
-pub use buffers::{GraphAudioBuffers, GraphEventBuffers};
+pub use buffers::{DEFAULT_MAX_BLOCK_FRAMES, GraphAudioBuffers, GraphEventBuffers};
+
+mod cell;
+pub use cell::GraphRef;
+pub(crate) use cell::{GraphCell, GraphRefMut};
+
+mod compensation;
+pub(crate) use compensation::CompensationDelay;
+
+mod feedback;
+use feedback::FeedbackBuffer;
+
+mod parallel;
+pub(crate) use parallel::ParallelSchedule;
 
 mod state;
 use state::GraphStateBuffer;
 pub use state::{GraphStateReader, GraphStateValue, GraphStateWriter, graph_state_tracker};
 
+mod thread_pool;
+pub use thread_pool::GraphThreadPool;
+
 pub struct GraphProcessContext<'a> {
     pub graph: &'a GraphState,
     pub node: &'a GraphNode,
     pub num_frames: usize,
     pub sample_rate: u32,
     pub timestamp: &'a Duration,
+    pub transport: &'a GraphTransportInfo,
     pub out_audio_buffers: &'a mut AudioBlockSequential<f32>,
     pub out_event_buffers: &'a mut [Vec<GraphEvent>],
     pub state: &'a mut GraphStateBuffer,
@@ -31,20 +42,107 @@ pub struct GraphProcessContext<'a> {
 
 pub trait GraphProcessor: Send + Debug {
     fn process(&mut self, ctx: GraphProcessContext);
+
+    /// Called between calls to `process` when the sample rate of the graph,
+    /// or the most frames it processes at once, changes.
+    fn configure(&mut self, _sample_rate: u32, _max_block_frames: usize) {}
+
+    /// The first frame, after the start of a block of `num_frames` frames,
+    /// at which the processor will send an event or cross an automation
+    /// point, if there is one. Only asked when the worker splits blocks at
+    /// boundaries, for processors that only look at events and automation
+    /// once per block.
+    fn next_boundary(
+        &self,
+        _transport: &GraphTransportInfo,
+        _sample_rate: u32,
+        _num_frames: usize,
+    ) -> Option<usize> {
+        None
+    }
+
+    /// How many frames later than its inputs the processor's output is. The
+    /// graph delays parallel paths to match, and checks this before every
+    /// block so it can change at any time. The new delays take effect once
+    /// the main thread has planned them.
+    fn latency(&self) -> usize {
+        0
+    }
 }
 
+pub(crate) type ProcessorMap = HashMap<Entity, ProcessorCell>;
+pub(crate) type NodeMap = HashMap<Entity, GraphNode>;
+
+/// A processor that the graph's threads take turns to run. Processors only
+/// need to be `Send`, so it's only ever borrowed mutably.
+pub(crate) struct ProcessorCell(GraphCell<Box<dyn GraphProcessor>>);
+
+// SAFETY: Only one thread at a time can borrow the processor, as with a
+// `Mutex`, so it's never shared.
+unsafe impl Sync for ProcessorCell {}
+
 #[derive(Default)]
 pub(crate) struct Processors {
-    processors: HashMap<Entity, Box<dyn GraphProcessor>>,
+    processors: ProcessorMap,
 }
 
 impl Processors {
-    fn get_mut(&mut self, entity: Entity) -> &mut dyn GraphProcessor {
-        self.processors.get_mut(&entity).unwrap().as_mut()
+    fn borrow_mut(&self, entity: Entity) -> GraphRefMut<'_, Box<dyn GraphProcessor>> {
+        self.processors.get(&entity).unwrap().0.borrow_mut()
+    }
+
+    /// Returns the processor that `processor` replaces, if there was one.
+    pub(crate) fn set(
+        &mut self,
+        entity: Entity,
+        processor: Box<dyn GraphProcessor>,
+    ) -> Option<Box<dyn GraphProcessor>> {
+        self.processors
+            .insert(entity, ProcessorCell(GraphCell::new(processor)))
+            .map(|old| old.0.into_inner())
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<Box<dyn GraphProcessor>> {
+        self.processors
+            .remove(&entity)
+            .map(|processor| processor.0.into_inner())
+    }
+
+    /// Moves the processors into `processors`, which has more room, and
+    /// returns the old map.
+    pub(crate) fn reserve(&mut self, mut processors: ProcessorMap) -> ProcessorMap {
+        processors.extend(self.processors.drain());
+        mem::replace(&mut self.processors, processors)
+    }
+
+    pub(crate) fn configure(&mut self, sample_rate: u32, max_block_frames: usize) {
+        for processor in self.processors.values_mut() {
+            processor
+                .0
+                .get_mut()
+                .configure(sample_rate, max_block_frames);
+        }
     }
 
-    pub(crate) fn set(&mut self, entity: Entity, processor: Box<dyn GraphProcessor>) {
-        let _ = self.processors.insert(entity, processor);
+    fn latency(&mut self, entity: Entity) -> usize {
+        self.processors
+            .get_mut(&entity)
+            .map_or(0, |processor| processor.0.get_mut().latency())
+    }
+
+    fn next_boundary(
+        &mut self,
+        entity: Entity,
+        transport: &GraphTransportInfo,
+        sample_rate: u32,
+        num_frames: usize,
+    ) -> Option<usize> {
+        self.processors.get_mut(&entity).and_then(|processor| {
+            processor
+                .0
+                .get_mut()
+                .next_boundary(transport, sample_rate, num_frames)
+        })
     }
 }
 
@@ -53,166 +151,409 @@ pub struct GraphNode {
     pub desc: node::GraphNodeDesc,
     pub output_audio_buffers: GraphAudioBuffers,
     pub output_event_buffers: GraphEventBuffers,
+    compensation: Vec<CompensationDelay>,
+    feedback: Vec<FeedbackBuffer>,
 }
 
 impl GraphNode {
-    fn new(entity: Entity, desc: node::GraphNodeDesc) -> Self {
-        const HARDCODED_NUM_FRAMES: usize = 1024;
+    /// Called on the main thread, so that the buffers aren't allocated on
+    /// the audio thread. They're big enough for blocks of up to
+    /// `max_block_frames` frames.
+    pub(crate) fn new(entity: Entity, desc: node::GraphNodeDesc, max_block_frames: usize) -> Self {
         let output_audio_buffers =
-            GraphAudioBuffers::new(desc.audio_channels.num_outputs, HARDCODED_NUM_FRAMES);
+            GraphAudioBuffers::new(desc.audio_channels.num_outputs, max_block_frames);
         let output_event_buffers = GraphEventBuffers::new(desc.event_channels.num_outputs as usize);
 
+        // Enough channels for every channel of the source that's read
+        let feedback = desc
+            .feedback_inputs
+            .iter()
+            .map(|src| {
+                let num_channels = desc
+                    .audio_channels
+                    .connections
+                    .iter()
+                    .filter(|connection| connection.src == *src)
+                    .map(|connection| connection.src_channel + 1)
+                    .max()
+                    .unwrap_or(0);
+                FeedbackBuffer::new(*src, num_channels, max_block_frames)
+            })
+            .collect();
+
         Self {
             entity,
             desc,
             output_audio_buffers,
             output_event_buffers,
+            compensation: Vec::new(),
+            feedback,
+        }
+    }
+
+    /// Reallocates every buffer the node processes into for blocks of up to
+    /// `max_block_frames` frames.
+    fn resize(&mut self, max_block_frames: usize) {
+        self.output_audio_buffers.resize(max_block_frames);
+        for delay in self.compensation.iter_mut() {
+            delay.output.resize(max_block_frames);
+        }
+        for feedback in self.feedback.iter_mut() {
+            feedback.output.resize(max_block_frames);
+        }
+    }
+
+    /// Takes the audio from `old`'s feedback buffers that are still needed.
+    fn keep_feedback(&mut self, old: &mut GraphNode) {
+        for buffer in self.feedback.iter_mut() {
+            let num_channels = buffer.output.get().num_channels();
+            if let Some(old) = old.feedback.iter_mut().find(|old| {
+                old.src == buffer.src && old.output.get().num_channels() == num_channels
+            }) {
+                mem::swap(old, buffer);
+            }
+        }
+    }
+
+    /// Replaces the node's compensation delays, keeping the ones that are
+    /// unchanged so that the audio already in them isn't lost. Returns the
+    /// delays that are no longer needed.
+    fn set_compensation(&mut self, mut delays: Vec<CompensationDelay>) -> Vec<CompensationDelay> {
+        for delay in delays.iter_mut() {
+            if let Some(old) = self
+                .compensation
+                .iter_mut()
+                .find(|old| old.src == delay.src && old.delay == delay.delay)
+            {
+                mem::swap(old, delay);
+            }
+        }
+
+        mem::replace(&mut self.compensation, delays)
+    }
+}
+
+/// A change to the graph, with everything the worker needs allocated up front
+/// by the main thread.
+pub(crate) struct GraphUpdate {
+    /// Nodes that are new or whose description has changed.
+    pub(crate) nodes: Vec<GraphNode>,
+    pub(crate) removed: Vec<Entity>,
+    pub(crate) output: Option<Entity>,
+    pub(crate) schedule: Vec<Entity>,
+    /// The dependencies between the nodes in `schedule`.
+    pub(crate) parallel: ParallelSchedule,
+    /// The latency of each node in `schedule` that the compensation delays
+    /// were planned for.
+    pub(crate) latencies: Vec<usize>,
+    /// New compensation delays for the nodes whose delays have changed.
+    pub(crate) compensation: Vec<(Entity, Vec<CompensationDelay>)>,
+    /// Room for the nodes, processors and delays that the update replaces,
+    /// so that they can be sent back to the main thread to be freed.
+    retired_nodes: Vec<GraphNode>,
+    retired_processors: Vec<Box<dyn GraphProcessor>>,
+    retired_compensation: Vec<Vec<CompensationDelay>>,
+}
+
+impl GraphUpdate {
+    pub(crate) fn new(
+        nodes: Vec<GraphNode>,
+        removed: Vec<Entity>,
+        output: Option<Entity>,
+        schedule: Vec<Entity>,
+        parallel: ParallelSchedule,
+        latencies: Vec<usize>,
+        compensation: Vec<(Entity, Vec<CompensationDelay>)>,
+    ) -> Self {
+        Self {
+            retired_nodes: Vec::with_capacity(nodes.len() + removed.len()),
+            retired_processors: Vec::with_capacity(removed.len()),
+            retired_compensation: Vec::with_capacity(compensation.len()),
+            nodes,
+            removed,
+            output,
+            schedule,
+            parallel,
+            latencies,
+            compensation,
         }
     }
 }
 
 #[derive(Default)]
 pub struct GraphState {
-    pub(crate) nodes: HashMap<Entity, GraphNode>,
-    pub(crate) processors: RefCell<Processors>,
+    pub(crate) nodes: NodeMap,
+    pub(crate) processors: Processors,
+    output: Option<Entity>,
+    /// The order to process the nodes that `output` and the nodes routed
+    /// straight to the device depend on, worked out on the main thread
+    /// whenever the topology changes.
+    schedule: Vec<Entity>,
+    /// Which nodes in `schedule` can be processed at the same time.
+    parallel: ParallelSchedule,
+    /// The latency of each node in `schedule` when the main thread last heard
+    /// about it.
+    latencies: Vec<usize>,
 }
 
 impl GraphState {
-    pub(crate) fn update(
-        &mut self,
-        changed: Vec<(Entity, node::GraphNodeDesc)>,
-        removed: Vec<Entity>,
-    ) {
-        if !removed.is_empty() {
-            for node in removed {
-                self.nodes.remove(&node);
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: HashMap::with_capacity(capacity),
+            processors: Processors {
+                processors: HashMap::with_capacity(capacity),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Applies `update`, leaving whatever it replaces in it. Removed nodes
+    /// take their processors with them.
+    pub(crate) fn update(&mut self, update: &mut GraphUpdate) {
+        for entity in &update.removed {
+            if let Some(node) = self.nodes.remove(entity) {
+                update.retired_nodes.push(node);
+            }
+            if let Some(processor) = self.processors.remove(*entity) {
+                update.retired_processors.push(processor);
             }
         }
 
-        for (entity, node) in changed {
-            match self.nodes.entry(entity) {
-                Entry::Occupied(mut entry) => {
-                    entry.get_mut().desc = node;
-                }
-                Entry::Vacant(entry) => {
-                    entry.insert(GraphNode::new(entity, node));
-                }
+        for mut node in update.nodes.drain(..) {
+            if let Some(mut old) = self.nodes.remove(&node.entity) {
+                // Keep the audio that's already in the delays
+                mem::swap(&mut node.compensation, &mut old.compensation);
+                node.keep_feedback(&mut old);
+                update.retired_nodes.push(old);
+            }
+            self.nodes.insert(node.entity, node);
+        }
+
+        for (entity, delays) in update.compensation.drain(..) {
+            let old = match self.nodes.get_mut(&entity) {
+                Some(node) => node.set_compensation(delays),
+                None => delays,
             };
+            update.retired_compensation.push(old);
+        }
+
+        self.output = update.output;
+        mem::swap(&mut self.schedule, &mut update.schedule);
+        mem::swap(&mut self.parallel, &mut update.parallel);
+        mem::swap(&mut self.latencies, &mut update.latencies);
+    }
+
+    /// Moves the nodes into `nodes`, which has more room, and returns the old
+    /// map.
+    pub(crate) fn reserve_nodes(&mut self, mut nodes: NodeMap) -> NodeMap {
+        nodes.extend(self.nodes.drain());
+        mem::replace(&mut self.nodes, nodes)
+    }
+
+    /// Reallocates every node's buffers for blocks of up to
+    /// `max_block_frames` frames. This allocates, so it's only called while
+    /// the graph isn't being processed.
+    pub(crate) fn resize(&mut self, max_block_frames: usize) {
+        for node in self.nodes.values_mut() {
+            node.resize(max_block_frames);
         }
     }
 
     pub fn get_node(&self, node_entity: Entity) -> Option<&GraphNode> {
-        let v = self.nodes.get(&node_entity);
-        if v.is_none() {
-            println!("** WARNING: asked for non-existent node {node_entity:?}");
+        self.nodes.get(&node_entity)
+    }
+
+    /// The audio `node` receives from `src`, delayed if needed to line up
+    /// with its other inputs. Processors should read their audio inputs
+    /// through this rather than from `src`'s output buffers.
+    pub fn input_audio_buffers<'a>(
+        &'a self,
+        node: &'a GraphNode,
+        src: Entity,
+    ) -> Option<GraphRef<'a, AudioBlockSequential<f32>>> {
+        if let Some(feedback) = node.feedback.iter().find(|feedback| feedback.src == src) {
+            return Some(feedback.output.get());
         }
-        v
+
+        if let Some(delay) = node.compensation.iter().find(|delay| delay.src == src) {
+            return Some(delay.output.get());
+        }
+
+        self.get_node(src)
+            .map(|node| node.output_audio_buffers.get())
     }
 
+    /// Processes every node that the output node and routed nodes set by the
+    /// last `update` depend on, then keeps what feedback connections need for the next
+    /// block. `num_frames` must be no more than the maximum block size the
+    /// nodes were allocated for.
     pub fn process(
         &mut self,
-        node_entity: Entity,
         num_frames: usize,
         sample_rate: u32,
         timestamp: &Duration,
+        transport: &GraphTransportInfo,
         state: &mut GraphStateBuffer,
     ) {
-        let ordered = self.build_breadth_first_traversal(node_entity);
-        for node_entity in ordered {
-            let Some(node) = self.get_node(node_entity) else {
-                continue;
-            };
-
-            node.output_audio_buffers.prepare_for_processing(num_frames);
-
-            let mut out_audio_buffers = node.output_audio_buffers.buffers.borrow_mut();
-
-            node.output_event_buffers.prepare_for_processing();
-
-            let mut out_event_buffers = node.output_event_buffers.ports.borrow_mut();
-            let out_event_buffers = out_event_buffers.as_mut_slice();
-
-            let mut processors = self.processors.borrow_mut();
-            let processor = processors.get_mut(node_entity);
-
-            processor.process(GraphProcessContext {
-                graph: self,
-                node,
+        for &node_entity in &self.schedule {
+            self.process_node(
+                node_entity,
                 num_frames,
                 sample_rate,
                 timestamp,
-                out_audio_buffers: &mut out_audio_buffers,
-                out_event_buffers,
+                transport,
                 state,
-            });
+            );
         }
-    }
 
-    fn build_breadth_first_traversal(&self, start_node: Entity) -> Vec<Entity> {
-        let reachable = self.get_reachable_nodes(start_node);
+        self.capture_feedback();
+    }
 
-        let mut incoming: HashMap<Entity, usize> = HashMap::with_capacity(self.nodes.len());
+    /// Like `process`, but shares the nodes out between this thread and the
+    /// threads in `pool`. Each node is processed as soon as all of its inputs
+    /// have been, and with exactly the same inputs as `process` would give it,
+    /// so the output is the same.
+    pub(crate) fn process_parallel(
+        &self,
+        pool: &GraphThreadPool,
+        num_frames: usize,
+        sample_rate: u32,
+        timestamp: &Duration,
+        transport: &GraphTransportInfo,
+        state: &mut GraphStateBuffer,
+    ) {
+        self.parallel.reset();
+
+        pool.run(
+            &|state: &mut GraphStateBuffer| loop {
+                if let Some(index) = self.parallel.pop() {
+                    self.process_node(
+                        self.schedule[index],
+                        num_frames,
+                        sample_rate,
+                        timestamp,
+                        transport,
+                        state,
+                    );
+                    self.parallel.finish(index);
+                } else if self.parallel.is_finished() {
+                    break;
+                } else {
+                    GraphThreadPool::help();
+                    hint::spin_loop();
+                }
+            },
+            state,
+        );
 
-        let mut outputs: HashMap<Entity, Vec<Entity>> = HashMap::with_capacity(self.nodes.len());
+        self.capture_feedback();
+    }
 
-        let mut heap: BinaryHeap<Reverse<Entity>> = BinaryHeap::with_capacity(self.nodes.len());
-        for node_entity in reachable.iter() {
+    fn capture_feedback(&self) {
+        for node_entity in &self.schedule {
             let Some(node) = self.get_node(*node_entity) else {
                 continue;
             };
-            for input_entity in node.desc.inputs.iter() {
-                if !outputs.contains_key(input_entity) {
-                    outputs.insert(*input_entity, Vec::default());
-                }
-                outputs.get_mut(input_entity).unwrap().push(*node_entity);
-            }
-            incoming.insert(*node_entity, node.desc.inputs.len());
-
-            if *incoming.get(node_entity).unwrap_or(&0) == 0 {
-                heap.push(Reverse(*node_entity));
+            for feedback in &node.feedback {
+                let src = self.get_node(feedback.src);
+                let input = src.map(|src| src.output_audio_buffers.get());
+                feedback.capture(input.as_deref());
             }
         }
+    }
 
-        let mut ordered = Vec::with_capacity(self.nodes.len());
+    pub(crate) fn schedule_len(&self) -> usize {
+        self.schedule.len()
+    }
 
-        while let Some(Reverse(node_entity)) = heap.pop() {
-            assert_eq!(incoming[&node_entity], 0);
-            ordered.push(node_entity);
+    /// The earliest frame within the next `num_frames` frames, other than
+    /// the first, that any scheduled processor reports as a boundary.
+    pub(crate) fn next_boundary(
+        &mut self,
+        transport: &GraphTransportInfo,
+        sample_rate: u32,
+        num_frames: usize,
+    ) -> Option<usize> {
+        let processors = &mut self.processors;
+        self.schedule
+            .iter()
+            .filter_map(|entity| {
+                processors.next_boundary(*entity, transport, sample_rate, num_frames)
+            })
+            .filter(|frame| (1..num_frames).contains(frame))
+            .min()
+    }
 
-            if let Some(outputs) = outputs.get(&node_entity) {
-                for input in outputs {
-                    *incoming.get_mut(input).unwrap() -= 1;
-                    if incoming[input] == 0 {
-                        heap.push(Reverse(*input));
-                    }
-                }
+    fn process_node(
+        &self,
+        node_entity: Entity,
+        num_frames: usize,
+        sample_rate: u32,
+        timestamp: &Duration,
+        transport: &GraphTransportInfo,
+        state: &mut GraphStateBuffer,
+    ) {
+        let Some(node) = self.get_node(node_entity) else {
+            return;
+        };
+
+        for delay in &node.compensation {
+            if let Some(src) = self.nodes.get(&delay.src) {
+                delay.process(&src.output_audio_buffers.get(), num_frames);
             }
         }
 
-        ordered
-    }
+        for feedback in &node.feedback {
+            feedback.output.prepare_for_processing(num_frames);
+        }
 
-    pub(crate) fn get_reachable_nodes(&self, start_node: Entity) -> HashSet<Entity> {
-        let mut reachable = HashSet::with_capacity(self.nodes.len());
-        let mut stack = Vec::with_capacity(self.nodes.len());
+        node.output_audio_buffers.prepare_for_processing(num_frames);
 
-        for (entity, node) in self.nodes.iter() {
-            if node.desc.always_run {
-                stack.push(*entity);
-            }
-        }
+        let mut out_audio_buffers = node.output_audio_buffers.buffers.borrow_mut();
+
+        node.output_event_buffers.prepare_for_processing();
+
+        let mut out_event_buffers = node.output_event_buffers.ports.borrow_mut();
+        let out_event_buffers = out_event_buffers.as_mut_slice();
+
+        let mut processor = self.processors.borrow_mut(node_entity);
 
-        stack.push(start_node);
-        while let Some(node) = stack.pop() {
-            if !reachable.contains(&node) {
-                reachable.insert(node);
-                let Some(node) = self.get_node(node) else {
-                    continue;
-                };
-                stack.extend_from_slice(node.desc.inputs.as_slice());
+        processor.process(GraphProcessContext {
+            graph: self,
+            node,
+            num_frames,
+            sample_rate,
+            timestamp,
+            transport,
+            out_audio_buffers: &mut out_audio_buffers,
+            out_event_buffers,
+            state,
+        });
+    }
+
+    /// Calls `report` for each scheduled node whose processor's latency has
+    /// changed since the compensation delays were planned, so that the main
+    /// thread can plan new ones. `report` returns false if it couldn't pass
+    /// the change on, in which case it's reported again next time.
+    pub(crate) fn report_latencies(&mut self, mut report: impl FnMut(Entity, usize) -> bool) {
+        for (entity, reported) in self.schedule.iter().zip(self.latencies.iter_mut()) {
+            let latency = self.processors.latency(*entity);
+            if latency != *reported && report(*entity, latency) {
+                *reported = latency;
             }
         }
+    }
 
-        reachable
+    #[cfg(test)]
+    pub(crate) fn get_reachable_nodes(
+        &self,
+        start_node: Entity,
+    ) -> std::collections::HashSet<Entity> {
+        let descs = self
+            .nodes
+            .iter()
+            .map(|(entity, node)| (*entity, &node.desc))
+            .collect();
+        crate::schedule::reachable_nodes(&descs, &[start_node])
     }
 }
@@ -1,48 +1,64 @@
-use std::cell::{Ref, RefCell};
-
 use audio_blocks::{AudioBlock, AudioBlockMut, AudioBlockSequential};
 
 use crate::GraphEvent;
 
+use super::{GraphCell, GraphRef};
+
+/// The most frames a node processes at once until the worker is configured
+/// with a maximum block size of its own.
+pub const DEFAULT_MAX_BLOCK_FRAMES: usize = 1024;
+
+/// How many events each event output can hold before it has to grow, which
+/// would allocate on the audio thread.
+const EVENT_CAPACITY: usize = 512;
+
 pub struct GraphAudioBuffers {
-    pub(crate) buffers: RefCell<AudioBlockSequential<f32>>,
+    pub(crate) buffers: GraphCell<AudioBlockSequential<f32>>,
 }
 
 impl GraphAudioBuffers {
     pub(crate) fn new(num_channels: u16, num_frames: usize) -> Self {
         GraphAudioBuffers {
-            buffers: RefCell::new(AudioBlockSequential::new(num_channels, num_frames)),
+            buffers: GraphCell::new(AudioBlockSequential::new(num_channels, num_frames)),
         }
     }
 
-    pub fn get(&self) -> Ref<'_, AudioBlockSequential<f32>> {
+    pub fn get(&self) -> GraphRef<'_, AudioBlockSequential<f32>> {
         self.buffers.borrow()
     }
 
+    /// Reallocates the buffers to hold `num_frames` frames, losing the audio
+    /// that's in them.
+    pub(crate) fn resize(&mut self, num_frames: usize) {
+        let num_channels = self.buffers.get_mut().num_channels();
+        *self = Self::new(num_channels, num_frames);
+    }
+
     pub(crate) fn prepare_for_processing(&self, num_frames: usize) {
         let mut buffers = self.buffers.borrow_mut();
 
-        if buffers.num_frames_allocated() < num_frames {
-            println!("Allocating new audio buffers for {num_frames} frames");
-            *buffers = AudioBlockSequential::new(buffers.num_channels(), num_frames);
-        } else {
-            buffers.set_num_frames_visible(num_frames);
-        }
+        debug_assert!(num_frames <= buffers.num_frames_allocated());
+        let num_frames = num_frames.min(buffers.num_frames_allocated());
+        buffers.set_num_frames_visible(num_frames);
     }
 }
 
 pub struct GraphEventBuffers {
-    pub(crate) ports: RefCell<Vec<Vec<GraphEvent>>>,
+    pub(crate) ports: GraphCell<Vec<Vec<GraphEvent>>>,
 }
 
 impl GraphEventBuffers {
     pub(crate) fn new(num_ports: usize) -> Self {
         GraphEventBuffers {
-            ports: RefCell::new((0..num_ports).map(|_| Vec::new()).collect()),
+            ports: GraphCell::new(
+                (0..num_ports)
+                    .map(|_| Vec::with_capacity(EVENT_CAPACITY))
+                    .collect(),
+            ),
         }
     }
 
-    pub fn get(&self) -> Ref<'_, Vec<Vec<GraphEvent>>> {
+    pub fn get(&self) -> GraphRef<'_, Vec<Vec<GraphEvent>>> {
         self.ports.borrow()
     }
 
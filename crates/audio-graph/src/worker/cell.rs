@@ -0,0 +1,106 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+/// Like a `RefCell`, but it can be shared between the threads that process
+/// the graph. Borrowing never blocks: as with a `RefCell`, a borrow that
+/// conflicts with another one panics. The schedule makes sure that a node's
+/// buffers are only written while nothing is reading them.
+pub(crate) struct GraphCell<T> {
+    /// The number of shared borrows, or -1 while mutably borrowed.
+    borrows: AtomicIsize,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Borrows are tracked atomically, so shared references are only ever
+// handed out while there's no mutable one, as with `RwLock`.
+unsafe impl<T: Send> Send for GraphCell<T> {}
+unsafe impl<T: Send + Sync> Sync for GraphCell<T> {}
+
+impl<T> GraphCell<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            borrows: AtomicIsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn borrow(&self) -> GraphRef<'_, T> {
+        let mut borrows = self.borrows.load(Ordering::Relaxed);
+        loop {
+            assert!(borrows >= 0, "already mutably borrowed");
+            match self.borrows.compare_exchange_weak(
+                borrows,
+                borrows + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return GraphRef { cell: self },
+                Err(current) => borrows = current,
+            }
+        }
+    }
+
+    pub(crate) fn borrow_mut(&self) -> GraphRefMut<'_, T> {
+        let borrowed = self
+            .borrows
+            .compare_exchange(0, -1, Ordering::Acquire, Ordering::Relaxed);
+        assert!(borrowed.is_ok(), "already borrowed");
+        GraphRefMut { cell: self }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct GraphRef<'a, T> {
+    cell: &'a GraphCell<T>,
+}
+
+impl<T> Deref for GraphRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: There's no mutable borrow while this one exists
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for GraphRef<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrows.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub(crate) struct GraphRefMut<'a, T> {
+    cell: &'a GraphCell<T>,
+}
+
+impl<T> Deref for GraphRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: This is the only borrow
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> DerefMut for GraphRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: This is the only borrow
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for GraphRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrows.store(0, Ordering::Release);
+    }
+}
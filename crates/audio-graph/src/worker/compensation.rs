@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+use audio_blocks::{AudioBlock, AudioBlockMut, AudioBlockSequential};
+use bevy_ecs::entity::Entity;
+
+use super::{GraphAudioBuffers, GraphCell};
+
+/// Delays the audio a node receives from one of its inputs, so that it lines
+/// up with inputs that arrive through plugins with more latency.
+pub(crate) struct CompensationDelay {
+    pub(crate) src: Entity,
+    pub(crate) delay: usize,
+    lines: GraphCell<Vec<VecDeque<f32>>>,
+    pub(crate) output: GraphAudioBuffers,
+}
+
+impl CompensationDelay {
+    /// Called on the main thread, so that the delay lines aren't allocated on
+    /// the audio thread.
+    pub(crate) fn new(
+        src: Entity,
+        delay: usize,
+        num_channels: u16,
+        max_block_frames: usize,
+    ) -> Self {
+        let new_line = || {
+            let mut line = VecDeque::with_capacity(delay + 1);
+            line.resize(delay, 0.0);
+            line
+        };
+
+        Self {
+            src,
+            delay,
+            lines: GraphCell::new((0..num_channels).map(|_| new_line()).collect()),
+            output: GraphAudioBuffers::new(num_channels, max_block_frames),
+        }
+    }
+
+    pub(crate) fn process(&self, input: &AudioBlockSequential<f32>, num_frames: usize) {
+        self.output.prepare_for_processing(num_frames);
+
+        let mut output = self.output.buffers.borrow_mut();
+        let mut lines = self.lines.borrow_mut();
+        let num_channels = input.num_channels().min(output.num_channels());
+
+        for (channel, line) in lines.iter_mut().enumerate().take(num_channels as usize) {
+            let channel = channel as u16;
+            for (input, output) in input
+                .channel(channel)
+                .iter()
+                .zip(output.channel_mut(channel).iter_mut())
+            {
+                line.push_back(*input);
+                *output = line.pop_front().unwrap_or_default();
+            }
+        }
+    }
+}
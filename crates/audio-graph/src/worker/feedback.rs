@@ -0,0 +1,43 @@
+use audio_blocks::{AudioBlock, AudioBlockMut, AudioBlockSequential};
+use bevy_ecs::entity::Entity;
+
+use super::GraphAudioBuffers;
+
+/// Keeps what a node received through a feedback connection in one block, so
+/// that it can read it in the next.
+pub(crate) struct FeedbackBuffer {
+    pub(crate) src: Entity,
+    pub(crate) output: GraphAudioBuffers,
+}
+
+impl FeedbackBuffer {
+    /// Called on the main thread, so that the buffer isn't allocated on the
+    /// audio thread.
+    pub(crate) fn new(src: Entity, num_channels: u16, max_block_frames: usize) -> Self {
+        Self {
+            src,
+            output: GraphAudioBuffers::new(num_channels, max_block_frames),
+        }
+    }
+
+    /// Copies `input` into the buffer, padding it with silence in case the
+    /// next block is longer.
+    pub(crate) fn capture(&self, input: Option<&AudioBlockSequential<f32>>) {
+        let mut output = self.output.buffers.borrow_mut();
+        let num_frames = output.num_frames_allocated();
+        output.set_num_frames_visible(num_frames);
+
+        for channel in 0..output.num_channels() {
+            let output = output.channel_mut(channel);
+            output.fill(0.0);
+
+            if let Some(input) = input
+                && channel < input.num_channels()
+            {
+                for (input, output) in input.channel(channel).iter().zip(output.iter_mut()) {
+                    *output = *input;
+                }
+            }
+        }
+    }
+}
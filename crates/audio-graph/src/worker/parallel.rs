@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The dependencies between the nodes in a schedule, worked out on the main
+/// thread, and the counters the graph's threads use to hand nodes to each
+/// other as their inputs become ready.
+#[derive(Default)]
+pub(crate) struct ParallelSchedule {
+    /// For each node in the schedule, the indices of the nodes that read from
+    /// it.
+    dependents: Vec<Vec<usize>>,
+    /// For each node in the schedule, how many of the other nodes it reads
+    /// from.
+    num_inputs: Vec<usize>,
+    /// How many of each node's inputs are still to be processed this block.
+    pending: Vec<AtomicUsize>,
+    /// Indices of nodes that are ready to process, plus one so that zero
+    /// means the slot hasn't been filled yet. Every node is pushed exactly
+    /// once per block, so this never fills up.
+    ready: Vec<AtomicUsize>,
+    pushed: AtomicUsize,
+    popped: AtomicUsize,
+    /// How many nodes haven't been processed yet this block.
+    remaining: AtomicUsize,
+}
+
+impl ParallelSchedule {
+    pub(crate) fn new(dependents: Vec<Vec<usize>>, num_inputs: Vec<usize>) -> Self {
+        let len = num_inputs.len();
+        Self {
+            dependents,
+            num_inputs,
+            pending: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+            ready: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+            pushed: AtomicUsize::new(0),
+            popped: AtomicUsize::new(0),
+            remaining: AtomicUsize::new(0),
+        }
+    }
+
+    /// Gets ready for a new block. Must only be called while no other thread
+    /// is using the schedule.
+    pub(crate) fn reset(&self) {
+        for (pending, num_inputs) in self.pending.iter().zip(&self.num_inputs) {
+            pending.store(*num_inputs, Ordering::Relaxed);
+        }
+        for slot in &self.ready {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.pushed.store(0, Ordering::Relaxed);
+        self.popped.store(0, Ordering::Relaxed);
+        self.remaining
+            .store(self.num_inputs.len(), Ordering::Release);
+
+        for (index, num_inputs) in self.num_inputs.iter().enumerate() {
+            if *num_inputs == 0 {
+                self.push(index);
+            }
+        }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.remaining.load(Ordering::Acquire) == 0
+    }
+
+    /// Takes a node whose inputs have all been processed, if there is one.
+    pub(crate) fn pop(&self) -> Option<usize> {
+        loop {
+            let popped = self.popped.load(Ordering::Acquire);
+            if popped >= self.pushed.load(Ordering::Acquire) {
+                return None;
+            }
+
+            // The slot has been claimed, but might not have been filled yet
+            let index = self.ready[popped].load(Ordering::Acquire);
+            if index == 0 {
+                return None;
+            }
+
+            if self
+                .popped
+                .compare_exchange_weak(popped, popped + 1, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(index - 1);
+            }
+        }
+    }
+
+    /// Marks a node as processed, making ready any nodes that were only
+    /// waiting for it.
+    pub(crate) fn finish(&self, index: usize) {
+        for dependent in &self.dependents[index] {
+            if self.pending[*dependent].fetch_sub(1, Ordering::AcqRel) == 1 {
+                self.push(*dependent);
+            }
+        }
+        self.remaining.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    fn push(&self, index: usize) {
+        let slot = self.pushed.fetch_add(1, Ordering::AcqRel);
+        self.ready[slot].store(index + 1, Ordering::Release);
+    }
+}
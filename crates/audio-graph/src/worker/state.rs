@@ -1,61 +1,70 @@
 use std::{
-    mem::swap,
+    cell::UnsafeCell,
     ops::{Deref, DerefMut},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use bevy_ecs::entity::{Entity, EntityHashMap};
 
+/// How many values each buffer can hold. Values for new keys are dropped once
+/// a buffer is full, rather than allocating on the audio thread.
+const STATE_CAPACITY: usize = 1024;
+
+/// Set in `Shared::back` when the writer has swapped in a buffer that the
+/// reader hasn't seen yet.
+const NEW_DATA: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
 pub fn graph_state_tracker() -> (GraphStateReader, GraphStateWriter) {
-    let inner: Arc<Mutex<Inner>> = Arc::default();
+    let shared = Arc::new(Shared {
+        buffers: std::array::from_fn(|_| UnsafeCell::new(GraphStateBuffer::default())),
+        back: AtomicUsize::new(2),
+    });
 
     let reader = GraphStateReader {
-        inner: inner.clone(),
-        buffer: GraphStateBuffer::default(),
+        shared: shared.clone(),
+        index: 0,
     };
 
-    let writer = GraphStateWriter {
-        inner,
-        buffer: GraphStateBuffer::default(),
-    };
+    let writer = GraphStateWriter { shared, index: 1 };
 
     (reader, writer)
 }
 
-pub struct GraphStateReader {
-    inner: Arc<Mutex<Inner>>,
-    buffer: GraphStateBuffer,
+/// A triple buffer: the reader and writer each own one of the buffers, and
+/// swap theirs with the spare one in `back` without blocking each other.
+struct Shared {
+    buffers: [UnsafeCell<GraphStateBuffer>; 3],
+    back: AtomicUsize,
 }
 
-pub struct GraphStateWriter {
-    inner: Arc<Mutex<Inner>>,
-    buffer: GraphStateBuffer,
-}
+// SAFETY: Each buffer is only ever accessed through whichever of the reader
+// and writer holds its index, and indices are handed over through `back`.
+unsafe impl Sync for Shared {}
 
-struct Inner {
-    ready_to_read_buffer: Option<GraphStateBuffer>,
-    ready_to_write_buffer: Option<GraphStateBuffer>,
+pub struct GraphStateReader {
+    shared: Arc<Shared>,
+    index: usize,
 }
 
-impl Default for Inner {
-    fn default() -> Self {
-        // We start with one extra buffer reader for writing to.
-        Self {
-            ready_to_read_buffer: Default::default(),
-            ready_to_write_buffer: Some(GraphStateBuffer::default()),
-        }
-    }
+pub struct GraphStateWriter {
+    shared: Arc<Shared>,
+    index: usize,
 }
 
 impl GraphStateReader {
     pub fn swap_buffers(&mut self) {
-        let mut inner = self.inner.lock().unwrap();
-
-        if let Some(mut buffer) = inner.ready_to_read_buffer.take() {
-            assert!(inner.ready_to_write_buffer.is_none());
-            swap(&mut self.buffer, &mut buffer);
-            inner.ready_to_write_buffer = Some(buffer);
+        // Only swap if the writer has put a newer buffer in the back, so that
+        // we don't swap back to an older one
+        if self.shared.back.load(Ordering::Relaxed) & NEW_DATA == 0 {
+            return;
         }
+
+        let back = self.shared.back.swap(self.index, Ordering::AcqRel);
+        self.index = back & INDEX_MASK;
     }
 }
 
@@ -63,31 +72,21 @@ impl Deref for GraphStateReader {
     type Target = GraphStateBuffer;
 
     fn deref(&self) -> &Self::Target {
-        &self.buffer
+        // SAFETY: Only the reader has this index
+        unsafe { &*self.shared.buffers[self.index].get() }
     }
 }
 
 impl GraphStateWriter {
+    /// Publishes the buffer that has been written to, and carries on writing
+    /// to the spare one. Never blocks, so it's safe to call on the audio
+    /// thread.
     pub fn swap_buffers(&mut self) {
-        let mut inner = self.inner.lock().unwrap();
-
-        // ready_to_read_buffer should always contain the most recently written
-        // one. If there's one there already, then we must have put it there
-        // previously, so we swap our one with that one.
-        if let Some(mut buffer) = inner.ready_to_read_buffer.take() {
-            swap(&mut self.buffer, &mut buffer);
-            inner.ready_to_read_buffer = Some(buffer);
-        }
-        // if there's no buffer in ready_to_read then that means that something
-        // is reading from it. But there might be one for us in ready_to_write,
-        // so we can use that
-        else if let Some(mut buffer) = inner.ready_to_write_buffer.take() {
-            assert!(inner.ready_to_read_buffer.is_none());
-            swap(&mut self.buffer, &mut buffer);
-            inner.ready_to_read_buffer = Some(buffer);
-        }
-
-        // There's nothing for us to swap with, so we'll have to reuse this buffer.
+        let back = self
+            .shared
+            .back
+            .swap(self.index | NEW_DATA, Ordering::AcqRel);
+        self.index = back & INDEX_MASK;
     }
 }
 
@@ -95,13 +94,15 @@ impl Deref for GraphStateWriter {
     type Target = GraphStateBuffer;
 
     fn deref(&self) -> &Self::Target {
-        &self.buffer
+        // SAFETY: Only the writer has this index
+        unsafe { &*self.shared.buffers[self.index].get() }
     }
 }
 
 impl DerefMut for GraphStateWriter {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.buffer
+        // SAFETY: Only the writer has this index
+        unsafe { &mut *self.shared.buffers[self.index].get() }
     }
 }
 
@@ -112,14 +113,30 @@ pub enum GraphStateValue {
     Stereo(f32, f32),
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct GraphStateBuffer {
     data: EntityHashMap<GraphStateValue>,
 }
 
+impl Default for GraphStateBuffer {
+    fn default() -> Self {
+        let mut data = EntityHashMap::default();
+        data.reserve(STATE_CAPACITY);
+        Self { data }
+    }
+}
+
 impl GraphStateBuffer {
+    /// Does nothing if `key` is new and the buffer is full.
     pub fn insert(&mut self, key: Entity, value: GraphStateValue) -> Option<GraphStateValue> {
-        self.data.insert(key, value)
+        if let Some(existing) = self.data.get_mut(&key) {
+            return Some(std::mem::replace(existing, value));
+        }
+
+        if self.data.len() < self.data.capacity() {
+            self.data.insert(key, value);
+        }
+        None
     }
 
     pub fn get(&self, key: &Entity) -> Option<&GraphStateValue> {
@@ -129,6 +146,14 @@ impl GraphStateBuffer {
     pub fn clear(&mut self) {
         self.data.clear();
     }
+
+    /// Moves every value in `other` into this buffer, leaving `other` empty
+    /// but with its room intact.
+    pub(crate) fn merge(&mut self, other: &mut GraphStateBuffer) {
+        for (key, value) in other.data.drain() {
+            self.insert(key, value);
+        }
+    }
 }
 
 #[cfg(test)]
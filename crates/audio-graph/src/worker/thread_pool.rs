@@ -0,0 +1,301 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    hint, mem, ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use audio_thread_priority::{RtPriorityHandle, promote_current_thread_to_real_time};
+
+use super::{GraphCell, GraphStateBuffer};
+
+/// How many times an idle thread checks for a new job before it goes to
+/// sleep.
+const SPIN_LIMIT: usize = 1 << 14;
+
+type Job<'a> = dyn Fn(&mut GraphStateBuffer) + Sync + 'a;
+type Task<'a> = dyn Fn(u32) + Sync + 'a;
+
+thread_local! {
+    /// The pool whose job this thread is running, so that processors can
+    /// hand it tasks.
+    static CURRENT: Cell<*const PoolShared> = const { Cell::new(ptr::null()) };
+}
+
+/// Threads that help the audio thread process the graph. Each block, every
+/// thread takes nodes whose inputs are ready until the whole graph has been
+/// processed. Once they've started, the threads never allocate, free or lock,
+/// and while they're idle they help with any tasks that processors hand out
+/// through `execute`.
+///
+/// The audio thread waits on the pool's threads, so they ask for the same
+/// real-time priority as an audio callback. If the OS won't give it to them
+/// they print a warning and run at normal priority, where under load they
+/// can be preempted in the middle of a block and make the audio thread miss
+/// its deadline.
+pub struct GraphThreadPool {
+    shared: Arc<PoolShared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+struct PoolShared {
+    /// Bumped for every job, so that the threads know there's a new one.
+    generation: AtomicUsize,
+    job: UnsafeCell<Option<&'static Job<'static>>>,
+    /// Set while threads may join in with `job`.
+    job_open: AtomicBool,
+    /// How many threads are looking at `job`.
+    active: AtomicUsize,
+    shutdown: AtomicBool,
+    /// Each thread's own state buffer, merged into the audio thread's once
+    /// the job is done.
+    states: Vec<GraphCell<GraphStateBuffer>>,
+    tasks: Tasks,
+    /// Allocations made by the threads during jobs, for the audio thread to
+    /// report.
+    #[cfg(any(test, feature = "rt-guard"))]
+    violations: AtomicUsize,
+}
+
+// SAFETY: `job` is only written while no thread can be reading it: before
+// `job_open` is set, or after it has been cleared and `active` has dropped to
+// zero.
+unsafe impl Sync for PoolShared {}
+
+/// A batch of tasks handed to the pool by `execute`. Only one batch can run
+/// at a time.
+#[derive(Default)]
+struct Tasks {
+    busy: AtomicBool,
+    task: UnsafeCell<Option<&'static Task<'static>>>,
+    open: AtomicBool,
+    active: AtomicUsize,
+    next: AtomicU32,
+    count: AtomicU32,
+}
+
+impl Tasks {
+    /// Runs tasks from the open batch, if there is one, until they've all been
+    /// claimed.
+    fn help(&self) {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        if self.open.load(Ordering::SeqCst) {
+            // SAFETY: `task` isn't written while the batch is open
+            if let Some(task) = unsafe { *self.task.get() } {
+                self.run(task);
+            }
+        }
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn run(&self, task: &Task<'_>) {
+        let count = self.count.load(Ordering::Relaxed);
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            if index >= count {
+                break;
+            }
+            task(index);
+        }
+    }
+}
+
+impl GraphThreadPool {
+    /// One thread fewer than the machine has cores, as the audio thread takes
+    /// part too.
+    pub fn default_num_threads() -> usize {
+        thread::available_parallelism().map_or(0, |cores| cores.get() - 1)
+    }
+
+    /// The threads' real-time priority is sized for blocks of
+    /// `max_block_frames` at `sample_rate`.
+    pub(crate) fn new(num_threads: usize, sample_rate: u32, max_block_frames: usize) -> Self {
+        let shared = Arc::new(PoolShared {
+            generation: AtomicUsize::new(0),
+            job: UnsafeCell::new(None),
+            job_open: AtomicBool::new(false),
+            active: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            states: (0..num_threads)
+                .map(|_| GraphCell::new(GraphStateBuffer::default()))
+                .collect(),
+            tasks: Tasks::default(),
+            #[cfg(any(test, feature = "rt-guard"))]
+            violations: AtomicUsize::new(0),
+        });
+
+        let threads = (0..num_threads)
+            .map(|index| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("graph-worker-{index}"))
+                    .spawn(move || {
+                        // Held for as long as the thread runs
+                        let _priority = promote_to_real_time(sample_rate, max_block_frames);
+                        worker_loop(&shared, index);
+                    })
+                    .expect("graph worker thread should start")
+            })
+            .collect();
+
+        Self { shared, threads }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs `job` on this thread and all of the pool's threads, returning
+    /// once they've all finished with it. Whatever the threads write to their
+    /// state buffers is moved into `state`.
+    pub(crate) fn run(&self, job: &Job<'_>, state: &mut GraphStateBuffer) {
+        let shared = &*self.shared;
+
+        // SAFETY: No thread reads the job until it's opened, and it's closed
+        // again and every thread has finished with it before this returns
+        unsafe {
+            *shared.job.get() = Some(mem::transmute::<&Job<'_>, &'static Job<'static>>(job));
+        }
+        shared.job_open.store(true, Ordering::SeqCst);
+        shared.generation.fetch_add(1, Ordering::Release);
+        for thread in &self.threads {
+            thread.thread().unpark();
+        }
+
+        let previous = CURRENT.replace(ptr::from_ref(shared));
+        job(state);
+        CURRENT.set(previous);
+
+        shared.job_open.store(false, Ordering::SeqCst);
+        while shared.active.load(Ordering::SeqCst) > 0 {
+            hint::spin_loop();
+        }
+        // SAFETY: The job is closed and no thread is looking at it
+        unsafe {
+            *shared.job.get() = None;
+        }
+
+        for thread_state in &shared.states {
+            state.merge(&mut thread_state.borrow_mut());
+        }
+
+        #[cfg(any(test, feature = "rt-guard"))]
+        crate::rt_guard::add_violations(shared.violations.swap(0, Ordering::Relaxed));
+    }
+
+    /// Runs `task` once for each index below `num_tasks`, spread across the
+    /// threads of the pool that's running the current thread's job. Returns
+    /// false without running anything if this thread isn't running a job, or
+    /// if another batch of tasks is already running, in which case the caller
+    /// should run the tasks itself.
+    pub fn execute(num_tasks: u32, task: &(dyn Fn(u32) + Sync)) -> bool {
+        let shared = CURRENT.get();
+        if shared.is_null() {
+            return false;
+        }
+
+        // SAFETY: `CURRENT` is only set while the pool is alive
+        let tasks = unsafe { &(*shared).tasks };
+        if tasks.busy.swap(true, Ordering::Acquire) {
+            return false;
+        }
+
+        // SAFETY: Helpers only read the task once the batch is opened, and
+        // it's closed again and every helper has finished before this returns
+        unsafe {
+            *tasks.task.get() = Some(mem::transmute::<&Task<'_>, &'static Task<'static>>(task));
+        }
+        tasks.count.store(num_tasks, Ordering::Relaxed);
+        tasks.next.store(0, Ordering::Relaxed);
+        tasks.open.store(true, Ordering::SeqCst);
+
+        tasks.run(task);
+
+        tasks.open.store(false, Ordering::SeqCst);
+        while tasks.active.load(Ordering::SeqCst) > 0 {
+            hint::spin_loop();
+        }
+        // SAFETY: The batch is closed and no helper is looking at it
+        unsafe {
+            *tasks.task.get() = None;
+        }
+        tasks.busy.store(false, Ordering::Release);
+
+        true
+    }
+
+    /// Helps with any tasks handed to the current thread's pool. Called by
+    /// threads that are waiting for a node to become ready.
+    pub(crate) fn help() {
+        let shared = CURRENT.get();
+        if !shared.is_null() {
+            // SAFETY: `CURRENT` is only set while the pool is alive
+            unsafe { &(*shared).tasks }.help();
+        }
+    }
+}
+
+impl Drop for GraphThreadPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        for thread in self.threads.drain(..) {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn promote_to_real_time(sample_rate: u32, max_block_frames: usize) -> Option<RtPriorityHandle> {
+    match promote_current_thread_to_real_time(max_block_frames as u32, sample_rate) {
+        Ok(handle) => Some(handle),
+        Err(err) => {
+            eprintln!("** Graph worker thread is running at normal priority: {err}");
+            None
+        }
+    }
+}
+
+fn worker_loop(shared: &Arc<PoolShared>, index: usize) {
+    CURRENT.set(Arc::as_ptr(shared));
+
+    let mut seen = 0;
+    let mut idle = 0;
+    while !shared.shutdown.load(Ordering::Acquire) {
+        let generation = shared.generation.load(Ordering::Acquire);
+        if generation == seen {
+            if idle < SPIN_LIMIT {
+                idle += 1;
+                hint::spin_loop();
+            } else {
+                // Woken by `run` or `drop`
+                thread::park();
+            }
+            continue;
+        }
+        seen = generation;
+        idle = 0;
+
+        shared.active.fetch_add(1, Ordering::SeqCst);
+        if shared.job_open.load(Ordering::SeqCst) {
+            // SAFETY: `job` isn't written while the job is open and this
+            // thread is active
+            if let Some(job) = unsafe { *shared.job.get() } {
+                #[cfg(any(test, feature = "rt-guard"))]
+                let guard = crate::rt_guard::RealtimeGuard::enter();
+
+                job(&mut shared.states[index].borrow_mut());
+
+                #[cfg(any(test, feature = "rt-guard"))]
+                shared
+                    .violations
+                    .fetch_add(guard.finish(), Ordering::Relaxed);
+            }
+        }
+        shared.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    CURRENT.set(ptr::null());
+}
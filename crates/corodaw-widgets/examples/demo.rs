@@ -1,6 +1,7 @@
 use corodaw_widgets::{
     arranger::{ArrangerDataProvider, ArrangerWidget},
     meter::Meter,
+    piano_roll::{PianoRollDataProvider, PianoRollNote, PianoRollWidget},
 };
 use eframe::egui::{
     self, Align2, CollapsingHeader, Color32, FontId, Rect, Sense, Stroke, Ui, Vec2, pos2, vec2,
@@ -21,6 +22,8 @@ fn main() {
 
 struct App {
     channels: Vec<usize>,
+    notes: Vec<PianoRollNote>,
+    edits: usize,
     perlin: Perlin1D,
     perlin_x: f32,
 }
@@ -29,8 +32,20 @@ impl App {
     fn new(_: &eframe::CreationContext<'_>) -> Self {
         let channels = vec![0, 1, 2, 3, 4, 5, 6, 7];
         let perlin = Perlin1D::new(1337);
+        let notes = [60, 64, 67, 72, 67, 64, 60, 55]
+            .into_iter()
+            .enumerate()
+            .map(|(i, key)| PianoRollNote {
+                key,
+                velocity: 64 + i as u8 * 8,
+                start: i as f64 * 0.5,
+                length: 0.5,
+            })
+            .collect();
         Self {
             channels,
+            notes,
+            edits: 0,
             perlin,
             perlin_x: 0.0,
         }
@@ -171,6 +186,48 @@ impl App {
         ArrangerWidget::new("arranger").show(TestArranger(self), ui);
     }
 
+    fn test_piano_roll(&mut self, ui: &mut Ui) {
+        struct TestPianoRoll<'a>(&'a mut App);
+        impl<'a> PianoRollDataProvider for TestPianoRoll<'a> {
+            fn num_notes(&self) -> usize {
+                self.0.notes.len()
+            }
+
+            fn note(&self, index: usize) -> PianoRollNote {
+                self.0.notes[index]
+            }
+
+            fn length(&self) -> f64 {
+                16.0
+            }
+
+            fn add_note(&mut self, note: PianoRollNote) {
+                self.0.notes.push(note);
+            }
+
+            fn set_note(&mut self, index: usize, note: PianoRollNote) {
+                self.0.notes[index] = note;
+            }
+
+            fn delete_note(&mut self, index: usize) {
+                self.0.notes.remove(index);
+            }
+
+            fn end_edit(&mut self) {
+                self.0.edits += 1;
+            }
+        }
+
+        ui.label(format!(
+            "{} notes, {} edits. Double click to add a note, drag to select.",
+            self.notes.len(),
+            self.edits
+        ));
+        ui.allocate_ui(vec2(ui.available_width(), 400.0), |ui| {
+            PianoRollWidget::new("piano_roll").show(TestPianoRoll(self), ui);
+        });
+    }
+
     fn test_meters(&mut self, ui: &mut Ui) {
         ui.horizontal_wrapped(|ui| {
             let count = 32;
@@ -205,6 +262,11 @@ impl eframe::App for App {
             .show(ui, |ui| {
                 self.test_arranger(ui);
             });
+        CollapsingHeader::new("Piano roll")
+            .default_open(false)
+            .show(ui, |ui| {
+                self.test_piano_roll(ui);
+            });
         CollapsingHeader::new("Meters")
             .default_open(false)
             .show(ui, |ui| {
@@ -0,0 +1,77 @@
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+
+use egui::{Response, Sense, Ui, Widget, remap_clamp, vec2};
+
+/// A rotary control. Drag up or down to change the value and double-click to
+/// reset it.
+pub struct Knob<'a> {
+    value: &'a mut f32,
+    range: RangeInclusive<f32>,
+    default_value: f32,
+    diameter: f32,
+}
+
+impl<'a> Knob<'a> {
+    pub fn new(value: &'a mut f32, range: RangeInclusive<f32>) -> Self {
+        Self {
+            value,
+            default_value: *range.start(),
+            range,
+            diameter: 20.0,
+        }
+    }
+
+    pub fn default_value(self, default_value: f32) -> Self {
+        Self {
+            default_value,
+            ..self
+        }
+    }
+
+    pub fn diameter(self, diameter: f32) -> Self {
+        Self { diameter, ..self }
+    }
+}
+
+impl Widget for Knob<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, mut response) =
+            ui.allocate_exact_size(vec2(self.diameter, self.diameter), Sense::click_and_drag());
+
+        let (min, max) = (*self.range.start(), *self.range.end());
+        let old_value = *self.value;
+        if response.double_clicked() {
+            *self.value = self.default_value;
+        } else if response.dragged() {
+            // A 100 pixel drag covers the whole range
+            let delta = -response.drag_delta().y / 100.0 * (max - min);
+            *self.value = (*self.value + delta).clamp(min, max);
+        }
+        if *self.value != old_value {
+            response.mark_changed();
+        }
+
+        let visuals = ui.style().interact(&response);
+        let p = ui.painter_at(rect);
+        let center = rect.center();
+        let radius = self.diameter * 0.5 - 1.0;
+
+        p.circle(center, radius, visuals.bg_fill, visuals.fg_stroke);
+
+        // The pointer sweeps three quarters of a turn, from bottom left
+        // through the top to bottom right
+        let t = remap_clamp(*self.value, self.range.clone(), 0.0..=1.0);
+        let angle = (0.75 + t * 1.5) * PI;
+        let direction = vec2(angle.cos(), angle.sin());
+        p.line_segment(
+            [
+                center + direction * radius * 0.3,
+                center + direction * radius,
+            ],
+            visuals.fg_stroke,
+        );
+
+        response
+    }
+}
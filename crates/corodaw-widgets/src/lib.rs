@@ -1,2 +1,4 @@
 pub mod arranger;
+pub mod knob;
 pub mod meter;
+pub mod piano_roll;
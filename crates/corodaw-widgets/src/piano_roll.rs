@@ -0,0 +1,651 @@
+use egui::{
+    Align2, Color32, Context, CursorIcon, FontId, Id, Key, Pos2, Rect, Sense, Stroke, StrokeKind,
+    Ui, Vec2, pos2, remap_clamp, vec2,
+};
+
+const NUM_KEYS: u8 = 128;
+const DEFAULT_VELOCITY: u8 = 100;
+const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+const VELOCITY_HIT_RADIUS: f32 = 4.0;
+/// The length of new notes, and the shortest a note can be resized to, when
+/// there's no snap grid.
+const UNSNAPPED_NOTE_LENGTH: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PianoRollNote {
+    pub key: u8,
+    pub velocity: u8,
+    pub start: f64,
+    pub length: f64,
+}
+
+impl PianoRollNote {
+    pub fn end(&self) -> f64 {
+        self.start + self.length
+    }
+}
+
+pub trait PianoRollDataProvider {
+    fn num_notes(&self) -> usize;
+    fn note(&self, index: usize) -> PianoRollNote;
+    /// Length of the editable region, in beats.
+    fn length(&self) -> f64;
+    /// New notes are expected to be added to the end.
+    fn add_note(&mut self, note: PianoRollNote);
+    fn set_note(&mut self, index: usize, note: PianoRollNote);
+    fn delete_note(&mut self, index: usize);
+
+    /// Called before a gesture starts changing notes. Every change up until the
+    /// matching `end_edit` can be treated as a single undoable edit.
+    fn begin_edit(&mut self) {}
+    fn end_edit(&mut self) {}
+}
+
+#[derive(Clone, Copy, Debug)]
+enum DragMode {
+    Move,
+    Resize,
+    Select { origin: Pos2 },
+}
+
+#[derive(Clone, Debug)]
+struct Drag {
+    mode: DragMode,
+    /// The notes being dragged, as they were when the drag started.
+    notes: Vec<(usize, PianoRollNote)>,
+}
+
+#[derive(Clone, Debug)]
+struct State {
+    pixels_per_beat: f32,
+    offset: Vec2,
+    selection: Vec<usize>,
+    drag: Option<Drag>,
+}
+
+impl State {
+    const DEFAULT_PIXELS_PER_BEAT: f32 = 80.0;
+    const MIN_PIXELS_PER_BEAT: f32 = 10.0;
+    const MAX_PIXELS_PER_BEAT: f32 = 800.0;
+
+    fn load(ctx: &Context, id: Id) -> Option<Self> {
+        ctx.data(|d| d.get_temp(id))
+    }
+
+    fn store(self, ctx: &Context, id: Id) {
+        ctx.data_mut(|d| d.insert_temp(id, self));
+    }
+}
+
+/// Converts between beats/keys and screen positions in the note grid.
+#[derive(Clone, Copy, Debug)]
+struct View {
+    rect: Rect,
+    pixels_per_beat: f32,
+    key_height: f32,
+    offset: Vec2,
+}
+
+impl View {
+    fn x(&self, beat: f64) -> f32 {
+        self.rect.min.x + beat as f32 * self.pixels_per_beat - self.offset.x
+    }
+
+    fn beat(&self, x: f32) -> f64 {
+        ((x - self.rect.min.x + self.offset.x) / self.pixels_per_beat) as f64
+    }
+
+    /// The top of the row for `key`; the highest key is at the top.
+    fn y(&self, key: u8) -> f32 {
+        self.rect.min.y + (NUM_KEYS - 1 - key) as f32 * self.key_height - self.offset.y
+    }
+
+    fn key(&self, y: f32) -> u8 {
+        let row = ((y - self.rect.min.y + self.offset.y) / self.key_height).floor();
+        (NUM_KEYS as f32 - 1.0 - row).clamp(0.0, NUM_KEYS as f32 - 1.0) as u8
+    }
+
+    fn note_rect(&self, note: &PianoRollNote) -> Rect {
+        Rect::from_min_max(
+            pos2(self.x(note.start), self.y(note.key)),
+            pos2(self.x(note.end()), self.y(note.key) + self.key_height),
+        )
+    }
+}
+
+pub struct PianoRollWidget {
+    id: Id,
+    snap: Option<f64>,
+    beats_per_bar: f64,
+    key_height: f32,
+    keyboard_width: f32,
+    velocity_height: f32,
+}
+
+impl PianoRollWidget {
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            id: id.into(),
+            snap: Some(0.25),
+            beats_per_bar: 4.0,
+            key_height: 12.0,
+            keyboard_width: 50.0,
+            velocity_height: 60.0,
+        }
+    }
+
+    /// The grid, in beats, that notes are placed, moved and resized on. With
+    /// `None`, or a grid that isn't positive, they go wherever they're put.
+    pub fn snap(self, snap: Option<f64>) -> Self {
+        Self {
+            snap: snap.filter(|snap| *snap > 0.0),
+            ..self
+        }
+    }
+
+    /// `beats` moved onto the snap grid with `round`, if there is one.
+    fn snapped(&self, beats: f64, round: fn(f64) -> f64) -> f64 {
+        match self.snap {
+            Some(snap) => round(beats / snap) * snap,
+            None => beats,
+        }
+    }
+
+    fn min_note_length(&self) -> f64 {
+        self.snap.unwrap_or(UNSNAPPED_NOTE_LENGTH)
+    }
+
+    pub fn beats_per_bar(self, beats_per_bar: f64) -> Self {
+        Self {
+            beats_per_bar,
+            ..self
+        }
+    }
+
+    pub fn key_height(self, key_height: f32) -> Self {
+        Self { key_height, ..self }
+    }
+
+    pub fn show(self, mut data: impl PianoRollDataProvider, ui: &mut Ui) {
+        let id = self.id;
+        let gap = 5.0;
+
+        let rect = ui.available_rect_before_wrap();
+        let _ = ui.allocate_rect(rect, Sense::empty());
+
+        let keyboard_rect = Rect::from_min_max(
+            rect.min,
+            pos2(
+                rect.min.x + self.keyboard_width,
+                rect.max.y - self.velocity_height - gap,
+            ),
+        );
+        let grid_rect = Rect::from_min_max(
+            pos2(keyboard_rect.max.x + gap, rect.min.y),
+            pos2(rect.max.x, keyboard_rect.max.y),
+        );
+        let velocity_rect =
+            Rect::from_min_max(pos2(grid_rect.min.x, grid_rect.max.y + gap), rect.max);
+
+        let mut state = State::load(ui.ctx(), id).unwrap_or_else(|| State {
+            pixels_per_beat: State::DEFAULT_PIXELS_PER_BEAT,
+            // Start with middle C roughly in the middle
+            offset: vec2(0.0, (NUM_KEYS - 72) as f32 * self.key_height),
+            selection: Vec::new(),
+            drag: None,
+        });
+        state.selection.retain(|index| *index < data.num_notes());
+
+        self.zoom_and_scroll(&mut state, keyboard_rect.union(grid_rect), grid_rect, ui);
+
+        let view = View {
+            rect: grid_rect,
+            pixels_per_beat: state.pixels_per_beat,
+            key_height: self.key_height,
+            offset: state.offset,
+        };
+
+        self.show_keyboard(&view, keyboard_rect, ui);
+        self.show_grid(&view, data.length(), ui);
+        self.show_notes(&view, &mut state, &mut data, ui);
+        self.show_velocities(&view, velocity_rect, &mut state, &mut data, ui);
+
+        if !state.selection.is_empty()
+            && ui.rect_contains_pointer(rect)
+            && ui.input(|i| i.key_pressed(Key::Delete) || i.key_pressed(Key::Backspace))
+        {
+            delete_selection(&mut state, &mut data);
+        }
+
+        state.store(ui.ctx(), id);
+    }
+
+    fn zoom_and_scroll(&self, state: &mut State, scroll_rect: Rect, grid_rect: Rect, ui: &Ui) {
+        let (hover_pos, zoom, scroll) = ui.input(|i| {
+            (
+                i.pointer.hover_pos().filter(|p| scroll_rect.contains(*p)),
+                i.zoom_delta(),
+                i.smooth_scroll_delta,
+            )
+        });
+        let Some(hover_pos) = hover_pos else {
+            return;
+        };
+
+        if zoom != 1.0 {
+            // Keep the beat under the mouse where it is
+            let cursor_x = (hover_pos.x - grid_rect.min.x).max(0.0);
+            let beat = (state.offset.x + cursor_x) / state.pixels_per_beat;
+            state.pixels_per_beat = (state.pixels_per_beat * zoom)
+                .clamp(State::MIN_PIXELS_PER_BEAT, State::MAX_PIXELS_PER_BEAT);
+            state.offset.x = beat * state.pixels_per_beat - cursor_x;
+        } else {
+            state.offset -= scroll;
+        }
+
+        let max_y = (NUM_KEYS as f32 * self.key_height - grid_rect.height()).max(0.0);
+        state.offset.x = state.offset.x.max(0.0);
+        state.offset.y = state.offset.y.clamp(0.0, max_y);
+    }
+
+    fn show_keyboard(&self, view: &View, rect: Rect, ui: &Ui) {
+        let p = ui.painter_at(rect);
+        p.rect_filled(rect, 0.0, ui.style().visuals.widgets.noninteractive.bg_fill);
+
+        for key in 0..NUM_KEYS {
+            let y = view.y(key);
+            let key_rect =
+                Rect::from_min_max(pos2(rect.min.x, y), pos2(rect.max.x, y + self.key_height));
+            if !key_rect.intersects(rect) {
+                continue;
+            }
+
+            let (fill, text) = if is_black_key(key) {
+                (Color32::from_gray(30), Color32::WHITE)
+            } else {
+                (Color32::from_gray(220), Color32::BLACK)
+            };
+            p.rect_filled(key_rect.shrink2(vec2(0.0, 0.5)), 1.0, fill);
+
+            if key % 12 == 0 {
+                p.text(
+                    pos2(key_rect.max.x - 3.0, key_rect.center().y),
+                    Align2::RIGHT_CENTER,
+                    format!("C{}", key as i32 / 12 - 1),
+                    FontId::proportional(self.key_height * 0.8),
+                    text,
+                );
+            }
+        }
+    }
+
+    fn show_grid(&self, view: &View, length: f64, ui: &Ui) {
+        let rect = view.rect;
+        let p = ui.painter_at(rect);
+        p.rect_filled(rect, 0.0, Color32::from_rgb(30, 40, 60));
+
+        for key in 0..NUM_KEYS {
+            let y = view.y(key);
+            if y > rect.max.y || y + self.key_height < rect.min.y {
+                continue;
+            }
+            if is_black_key(key) {
+                let row =
+                    Rect::from_min_max(pos2(rect.min.x, y), pos2(rect.max.x, y + self.key_height));
+                p.rect_filled(row, 0.0, Color32::from_rgb(24, 32, 48));
+            }
+            p.hline(
+                rect.x_range(),
+                y,
+                Stroke::new(0.5, Color32::from_rgb(45, 55, 75)),
+            );
+        }
+
+        // Only draw the snap lines when they're far enough apart to be useful
+        let step = match self.snap {
+            Some(snap) if snap as f32 * view.pixels_per_beat >= 6.0 => snap,
+            _ => 1.0,
+        };
+        let first = (view.beat(rect.min.x) / step).floor() as i64;
+        let last = (view.beat(rect.max.x) / step).ceil() as i64;
+        for i in first.max(0)..=last {
+            let beat = i as f64 * step;
+            let color = if is_multiple(beat, self.beats_per_bar) {
+                Color32::from_rgb(100, 110, 130)
+            } else if is_multiple(beat, 1.0) {
+                Color32::from_rgb(70, 80, 100)
+            } else {
+                Color32::from_rgb(45, 55, 75)
+            };
+            p.vline(view.x(beat), rect.y_range(), Stroke::new(1.0, color));
+        }
+
+        let end_x = view.x(length);
+        if end_x < rect.max.x {
+            let outside = Rect::from_min_max(pos2(end_x.max(rect.min.x), rect.min.y), rect.max);
+            p.rect_filled(outside, 0.0, Color32::from_black_alpha(100));
+        }
+    }
+
+    fn show_notes(
+        &self,
+        view: &View,
+        state: &mut State,
+        data: &mut impl PianoRollDataProvider,
+        ui: &mut Ui,
+    ) {
+        let id = self.id;
+        let grid_rect = view.rect;
+        let shift = ui.input(|i| i.modifiers.shift);
+
+        // Interact with the background first so the notes are on top of it
+        let background = ui.interact(grid_rect, id.with("__grid"), Sense::click_and_drag());
+
+        if background.double_clicked()
+            && let Some(pos) = background.interact_pointer_pos()
+        {
+            let note = PianoRollNote {
+                key: view.key(pos.y),
+                velocity: DEFAULT_VELOCITY,
+                start: self.snapped(view.beat(pos.x), f64::floor).max(0.0),
+                length: self.min_note_length(),
+            };
+            data.begin_edit();
+            data.add_note(note);
+            data.end_edit();
+            state.selection = vec![data.num_notes() - 1];
+        } else if background.clicked() && !shift {
+            state.selection.clear();
+        }
+
+        if background.drag_started()
+            && let Some(origin) = background.interact_pointer_pos()
+        {
+            let notes = if shift {
+                state
+                    .selection
+                    .iter()
+                    .map(|i| (*i, data.note(*i)))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            state.drag = Some(Drag {
+                mode: DragMode::Select { origin },
+                notes,
+            });
+        }
+
+        let mut clicked_note = None;
+        let mut delete = false;
+
+        for index in 0..data.num_notes() {
+            let note = data.note(index);
+            let note_rect = view.note_rect(&note);
+            if !note_rect.intersects(grid_rect) {
+                continue;
+            }
+
+            let body = ui.interact(
+                note_rect.intersect(grid_rect),
+                id.with(("__note", index)),
+                Sense::click_and_drag(),
+            );
+            let handle_rect = note_rect
+                .with_min_x((note_rect.max.x - RESIZE_HANDLE_WIDTH).max(note_rect.center().x))
+                .intersect(grid_rect);
+            let handle = ui.interact(
+                handle_rect,
+                id.with(("__note_resize", index)),
+                Sense::drag(),
+            );
+
+            if handle.hovered() || handle.dragged() {
+                ui.ctx().set_cursor_icon(CursorIcon::ResizeHorizontal);
+            }
+
+            if body.clicked() {
+                clicked_note = Some(index);
+            }
+
+            for (response, mode) in [(&body, DragMode::Move), (&handle, DragMode::Resize)] {
+                if response.drag_started() {
+                    if !state.selection.contains(&index) {
+                        state.selection = vec![index];
+                    }
+                    state.drag = Some(Drag {
+                        mode,
+                        notes: state
+                            .selection
+                            .iter()
+                            .map(|i| (*i, data.note(*i)))
+                            .collect(),
+                    });
+                    data.begin_edit();
+                }
+            }
+
+            body.context_menu(|ui| {
+                if ui.button("Delete").clicked() {
+                    if !state.selection.contains(&index) {
+                        state.selection = vec![index];
+                    }
+                    delete = true;
+                }
+            });
+        }
+
+        if let Some(index) = clicked_note {
+            if shift {
+                if let Some(position) = state.selection.iter().position(|i| *i == index) {
+                    state.selection.remove(position);
+                } else {
+                    state.selection.push(index);
+                }
+            } else {
+                state.selection = vec![index];
+            }
+        }
+
+        self.update_drag(view, state, data, ui);
+
+        let p = ui.painter_at(grid_rect);
+        for index in 0..data.num_notes() {
+            let note = data.note(index);
+            let note_rect = view.note_rect(&note);
+            if !note_rect.intersects(grid_rect) {
+                continue;
+            }
+            let selected = state.selection.contains(&index);
+            p.rect_filled(note_rect.shrink(0.5), 2.0, note_color(note.velocity));
+            p.rect_stroke(
+                note_rect.shrink(0.5),
+                2.0,
+                if selected {
+                    Stroke::new(2.0, Color32::WHITE)
+                } else {
+                    Stroke::new(1.0, Color32::from_rgb(20, 30, 50))
+                },
+                StrokeKind::Inside,
+            );
+        }
+
+        if let Some(Drag {
+            mode: DragMode::Select { origin },
+            ..
+        }) = state.drag
+            && let Some(pos) = ui.ctx().pointer_latest_pos()
+        {
+            let select_rect = Rect::from_two_pos(origin, pos);
+            p.rect(
+                select_rect,
+                0.0,
+                Color32::from_white_alpha(20),
+                Stroke::new(1.0, Color32::WHITE),
+                StrokeKind::Inside,
+            );
+        }
+
+        if delete {
+            delete_selection(state, data);
+        }
+    }
+
+    fn update_drag(
+        &self,
+        view: &View,
+        state: &mut State,
+        data: &mut impl PianoRollDataProvider,
+        ui: &Ui,
+    ) {
+        let Some(drag) = &state.drag else {
+            return;
+        };
+
+        let (pointer, origin, down) = ui.input(|i| {
+            (
+                i.pointer.latest_pos(),
+                i.pointer.press_origin(),
+                i.pointer.primary_down(),
+            )
+        });
+
+        if let (Some(pointer), Some(origin)) = (pointer, origin) {
+            let delta = pointer - origin;
+            let beats = (delta.x / view.pixels_per_beat) as f64;
+            let beats = self.snapped(beats, f64::round);
+            let keys = -(delta.y / view.key_height).round() as i32;
+
+            match drag.mode {
+                DragMode::Move => {
+                    for (index, note) in &drag.notes {
+                        let moved = PianoRollNote {
+                            key: (note.key as i32 + keys).clamp(0, NUM_KEYS as i32 - 1) as u8,
+                            start: (note.start + beats).max(0.0),
+                            ..*note
+                        };
+                        if data.note(*index) != moved {
+                            data.set_note(*index, moved);
+                        }
+                    }
+                }
+                DragMode::Resize => {
+                    for (index, note) in &drag.notes {
+                        let resized = PianoRollNote {
+                            length: (note.length + beats).max(self.min_note_length()),
+                            ..*note
+                        };
+                        if data.note(*index) != resized {
+                            data.set_note(*index, resized);
+                        }
+                    }
+                }
+                DragMode::Select { origin } => {
+                    let select_rect = Rect::from_two_pos(origin, pointer);
+                    let mut selection: Vec<usize> =
+                        drag.notes.iter().map(|(index, _)| *index).collect();
+                    for index in 0..data.num_notes() {
+                        if !selection.contains(&index)
+                            && view.note_rect(&data.note(index)).intersects(select_rect)
+                        {
+                            selection.push(index);
+                        }
+                    }
+                    state.selection = selection;
+                }
+            }
+        }
+
+        if !down {
+            if let Some(Drag {
+                mode: DragMode::Move | DragMode::Resize,
+                ..
+            }) = state.drag
+            {
+                data.end_edit();
+            }
+            state.drag = None;
+        }
+    }
+
+    fn show_velocities(
+        &self,
+        view: &View,
+        rect: Rect,
+        state: &mut State,
+        data: &mut impl PianoRollDataProvider,
+        ui: &mut Ui,
+    ) {
+        let p = ui.painter_at(rect);
+        p.rect_filled(rect, 0.0, Color32::from_rgb(30, 40, 60));
+
+        let velocity_y =
+            |velocity: u8| remap_clamp(velocity as f32, 0.0..=127.0, rect.max.y..=rect.min.y + 4.0);
+
+        let lane = ui.interact(rect, self.id.with("__velocity"), Sense::click_and_drag());
+
+        if lane.drag_started() || lane.clicked() {
+            data.begin_edit();
+        }
+        if (lane.dragged() || lane.clicked())
+            && let Some(pos) = lane.interact_pointer_pos()
+        {
+            let velocity =
+                remap_clamp(pos.y, rect.max.y..=rect.min.y + 4.0, 1.0..=127.0).round() as u8;
+            for index in 0..data.num_notes() {
+                let note = data.note(index);
+                let in_selection = state.selection.is_empty() || state.selection.contains(&index);
+                if in_selection
+                    && (view.x(note.start) - pos.x).abs() <= VELOCITY_HIT_RADIUS
+                    && note.velocity != velocity
+                {
+                    data.set_note(index, PianoRollNote { velocity, ..note });
+                }
+            }
+        }
+        if lane.drag_stopped() || lane.clicked() {
+            data.end_edit();
+        }
+
+        for index in 0..data.num_notes() {
+            let note = data.note(index);
+            let x = view.x(note.start);
+            if x < rect.min.x || x > rect.max.x {
+                continue;
+            }
+            let color = if state.selection.contains(&index) {
+                Color32::WHITE
+            } else {
+                note_color(note.velocity)
+            };
+            let top = pos2(x, velocity_y(note.velocity));
+            p.vline(x, top.y..=rect.max.y, Stroke::new(2.0, color));
+            p.circle_filled(top, 3.0, color);
+        }
+    }
+}
+
+fn delete_selection(state: &mut State, data: &mut impl PianoRollDataProvider) {
+    let mut selection = std::mem::take(&mut state.selection);
+    selection.sort_unstable();
+
+    data.begin_edit();
+    for index in selection.into_iter().rev() {
+        data.delete_note(index);
+    }
+    data.end_edit();
+}
+
+fn is_black_key(key: u8) -> bool {
+    matches!(key % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+fn is_multiple(value: f64, of: f64) -> bool {
+    (value / of - (value / of).round()).abs() < 1e-6
+}
+
+fn note_color(velocity: u8) -> Color32 {
+    let t = velocity as f32 / 127.0;
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Color32::from_rgb(lerp(60, 150), lerp(90, 200), lerp(140, 250))
+}
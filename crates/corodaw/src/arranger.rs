@@ -1,20 +1,29 @@
-use audio_graph::{GraphStateReader, GraphStateValue};
+use audio_graph::{GraphController, GraphSeekEvent, GraphStateReader, GraphStateValue};
 use bevy_ecs::prelude::*;
 use bevy_ecs::system::SystemParam;
 
 use corodaw_widgets::arranger::{ArrangerDataProvider, ArrangerWidget};
+use corodaw_widgets::knob::Knob;
 use corodaw_widgets::meter::Meter;
 use egui::text::{CCursor, CCursorRange};
 use egui::{
-    Align, Align2, Button, Color32, FontId, Frame, Id, Key, Label, Layout, Margin, Popup, Rect,
-    RichText, Sense, Slider, Stroke, TextEdit, Ui, pos2, vec2,
+    Align, Align2, Button, Color32, CursorIcon, FontId, Frame, Id, Key, Label, Layout, Margin,
+    Popup, Rect, RichText, Sense, Slider, Stroke, StrokeKind, TextEdit, Ui, pos2, vec2,
 };
 use egui_extras::{Size, StripBuilder};
+use engine::builtin::SummerOwner;
 use engine::plugins::{ClapManager, PluginManager};
 use project::{
-    AddChannelEdit, AvailablePlugin, ChannelButton, ChannelButtonEdit, ChannelGain,
-    ChannelMixerState, ChannelOrder, ChannelPluginBinding, ChannelPluginInstance, ChannelSnapshot,
-    DeleteChannelEdit, EditHistory, MoveChannelEdit, RenameChannelEdit, SetGainEdit, SetPluginEdit,
+    AddAutomationLaneEdit, AddChannelEdit, AddClipEdit, AddInsertEdit, AddSendEdit,
+    AutomationCurve, AutomationLane, AutomationPoint, AutomationTarget, AvailablePlugin,
+    ChannelAutomation, ChannelButton, ChannelButtonEdit, ChannelClips, ChannelGain,
+    ChannelInsertInstances, ChannelInsertSlot, ChannelInserts, ChannelKind, ChannelMixerState,
+    ChannelOrder, ChannelPanLaw, ChannelPluginBinding, ChannelPluginInstance, ChannelPluginParams,
+    ChannelRoutes, ChannelRouting, ChannelSend, ChannelSnapshot, DeleteAutomationLaneEdit,
+    DeleteChannelEdit, DeleteClipEdit, EditHistory, MidiClip, MoveChannelEdit, MoveClipEdit,
+    MoveInsertEdit, RemoveInsertEdit, RemoveSendEdit, RenameChannelEdit, ResizeClipEdit,
+    SetAutomationPointsEdit, SetChannelDeviceOutputEdit, SetChannelOutputEdit, SetGainEdit,
+    SetInsertBypassEdit, SetPanEdit, SetPanLawEdit, SetPluginEdit, SetSendEdit,
 };
 
 #[derive(SystemParam)]
@@ -34,11 +43,27 @@ pub struct ArrangerData<'w, 's> {
             Option<&'static ChannelPluginBinding>,
         ),
     >,
+    clips: Query<'w, 's, &'static mut ChannelClips>,
+    automation: Query<'w, 's, &'static mut ChannelAutomation>,
+    plugin_params: Query<'w, 's, &'static ChannelPluginParams>,
+    inserts: Query<'w, 's, &'static mut ChannelInserts>,
+    insert_instances: Query<'w, 's, &'static mut ChannelInsertInstances>,
+    routing: Query<
+        'w,
+        's,
+        (
+            &'static project::StableId,
+            &'static ChannelKind,
+            &'static mut ChannelRouting,
+        ),
+    >,
     available_plugins: Query<'w, 's, &'static AvailablePlugin>,
     channel_order: Single<'w, 's, &'static mut ChannelOrder>,
     state_reader: NonSend<'w, GraphStateReader>,
     clap_plugin_manager: NonSend<'w, ClapManager>,
     command_manager: NonSendMut<'w, EditHistory>,
+    audio_graph: NonSend<'w, GraphController>,
+    summer: NonSend<'w, SummerOwner>,
 }
 
 impl ArrangerData<'_, '_> {
@@ -49,15 +74,246 @@ impl ArrangerData<'_, '_> {
     pub fn can_redo(&self) -> bool {
         self.command_manager.can_redo()
     }
+
+    fn add_channel(&mut self, index: usize, kind: ChannelKind) {
+        let snapshot = ChannelSnapshot {
+            kind,
+            ..Default::default()
+        };
+        let id = snapshot.id;
+        let entity = self
+            .commands
+            .spawn((snapshot.state, snapshot.name, snapshot.kind, snapshot.id))
+            .id();
+        self.channel_order
+            .as_mut()
+            .channel_order
+            .insert(index, entity);
+        self.command_manager
+            .add_undo(Box::new(DeleteChannelEdit::new(id, index)));
+    }
+
+    /// The bus and group channels, with their names.
+    fn buses(&self) -> Vec<(project::StableId, String)> {
+        self.routing
+            .iter()
+            .filter(|(_, kind, _)| kind.is_bus())
+            .filter_map(|(id, _, _)| Some((*id, self.channel_name(*id)?)))
+            .collect()
+    }
+
+    fn channel_name(&self, channel: project::StableId) -> Option<String> {
+        self.channels
+            .iter()
+            .find(|(_, id, ..)| **id == channel)
+            .map(|(_, _, name, ..)| name.as_str().to_owned())
+    }
+
+    fn show_routing_menus(&mut self, entity: Entity, channel: project::StableId, ui: &mut Ui) {
+        let buses = self.buses();
+        let num_device_channels = self.audio_graph.num_channels();
+        let width = self.summer.layout.num_channels().max(1);
+        let routes = ChannelRoutes::new(self.routing.iter().map(|(id, _, routing)| (id, routing)));
+        let Ok((_, _, mut routing)) = self.routing.get_mut(entity) else {
+            return;
+        };
+
+        ui.menu_button("Output", |ui| {
+            let mut output = routing.output;
+            ui.radio_value(&mut output, None, "Master");
+            for (bus, name) in &buses {
+                if !routes.would_cycle(channel, *bus) {
+                    ui.radio_value(&mut output, Some(*bus), name);
+                }
+            }
+            if output != routing.output {
+                self.command_manager
+                    .add_undo(Box::new(SetChannelOutputEdit::new(channel, routing.output)));
+                routing.output = output;
+            }
+        });
+        // Devices with more channels than the master bus can take channels on
+        // outputs of their own
+        if num_device_channels > width {
+            ui.menu_button("Device output", |ui| {
+                let mut device_output = routing.device_output;
+                ui.radio_value(&mut device_output, None, "None");
+                for first in (0..=num_device_channels - width).step_by(width as usize) {
+                    let label = device_channels_label(first, width);
+                    ui.radio_value(&mut device_output, Some(first), label);
+                }
+                if device_output != routing.device_output {
+                    self.command_manager
+                        .add_undo(Box::new(SetChannelDeviceOutputEdit::new(
+                            channel,
+                            routing.device_output,
+                        )));
+                    routing.device_output = device_output;
+                }
+            });
+        }
+        ui.menu_button("Sends", |ui| {
+            // Edit a copy so that the channel is only rewired when something
+            // actually changes
+            let mut new_routing = routing.clone();
+            show_sends_menu(
+                channel,
+                &mut new_routing,
+                &buses,
+                &routes,
+                &mut self.command_manager,
+                ui,
+            );
+            if new_routing != *routing {
+                *routing = new_routing;
+            }
+        });
+    }
+
+    fn show_playhead(&self, ui: &Ui, rect: Rect, pixels_per_beat: f32) {
+        let x = rect.min.x + self.audio_graph.transport_position() as f32 * pixels_per_beat;
+        ui.painter()
+            .vline(x, rect.y_range(), Stroke::new(1.5, Color32::LIGHT_GREEN));
+    }
+
+    fn show_clips(&mut self, index: usize, ui: &mut Ui, rect: Rect, pixels_per_beat: f32) {
+        let Some(&entity) = self.channel_order.as_ref().channel_order.get(index) else {
+            return;
+        };
+        let Ok((_, &channel_id, ..)) = self.channels.get(entity) else {
+            return;
+        };
+
+        // Double clicking on an empty part of the strip creates a one bar clip
+        let background = ui.interact(rect, Id::new(("channel_strip", channel_id)), Sense::click());
+        if background.double_clicked()
+            && let Some(pos) = background.interact_pointer_pos()
+            && let Ok(mut clips) = self.clips.get_mut(entity)
+        {
+            let beat = ((pos.x - rect.min.x) / pixels_per_beat).max(0.0) as f64;
+            let start = (beat / BEATS_PER_MEASURE as f64).floor() * BEATS_PER_MEASURE as f64;
+            let clip = MidiClip::new(start, BEATS_PER_MEASURE as f64);
+            self.command_manager
+                .add_undo(Box::new(DeleteClipEdit::new(clip.id)));
+            clips.0.push(clip);
+        }
+
+        let Ok(clips) = self.clips.get(entity) else {
+            return;
+        };
+
+        let mut action = None;
+        for clip in &clips.0 {
+            if let Some(clip_action) = show_clip(
+                clip,
+                channel_id,
+                rect,
+                pixels_per_beat,
+                &mut self.command_manager,
+                ui,
+            ) {
+                action = Some((clip.id, clip_action));
+            }
+        }
+
+        let Some((clip_id, action)) = action else {
+            return;
+        };
+        let Ok(mut clips) = self.clips.get_mut(entity) else {
+            return;
+        };
+        match action {
+            ClipAction::Move(start) => {
+                if let Some(clip) = clips.clip_mut(clip_id) {
+                    clip.start = start;
+                }
+            }
+            ClipAction::Resize(length) => {
+                if let Some(clip) = clips.clip_mut(clip_id) {
+                    clip.length = length;
+                }
+            }
+            ClipAction::Delete => clips.0.retain(|clip| clip.id != clip_id),
+        }
+    }
+
+    /// Shows the channel's automation lanes stacked below `rect`, if they're
+    /// expanded.
+    fn show_automation(&mut self, index: usize, ui: &mut Ui, rect: Rect, pixels_per_beat: f32) {
+        let Some(&entity) = self.channel_order.as_ref().channel_order.get(index) else {
+            return;
+        };
+        let Ok((_, &channel_id, ..)) = self.channels.get(entity) else {
+            return;
+        };
+        let Ok(automation) = self.automation.get(entity) else {
+            return;
+        };
+        if !automation.expanded {
+            return;
+        }
+
+        let params = self.plugin_params.get(entity).ok();
+
+        let mut action = None;
+        for (lane_index, lane) in automation.lanes.iter().enumerate() {
+            let lane_rect = Rect::from_min_size(
+                pos2(
+                    rect.min.x,
+                    rect.max.y + lane_index as f32 * AUTOMATION_LANE_HEIGHT,
+                ),
+                vec2(rect.width(), AUTOMATION_LANE_HEIGHT),
+            );
+            if let Some(lane_action) = show_automation_lane(
+                lane,
+                &automation_target_name(lane.target, params),
+                channel_id,
+                lane_rect,
+                pixels_per_beat,
+                &mut self.command_manager,
+                ui,
+            ) {
+                action = Some((lane.id, lane_action));
+            }
+        }
+
+        let Some((lane_id, action)) = action else {
+            return;
+        };
+        let Ok(mut automation) = self.automation.get_mut(entity) else {
+            return;
+        };
+        match action {
+            LaneAction::SetPoints(points) => {
+                if let Some(lane) = automation.lane_mut(lane_id) {
+                    lane.points = points;
+                }
+            }
+            LaneAction::Delete => automation.lanes.retain(|lane| lane.id != lane_id),
+        }
+    }
 }
 
+const BEATS_PER_MEASURE: usize = 4;
+const CLIP_AREA_HEIGHT: f32 = 100.0;
+const AUTOMATION_LANE_HEIGHT: f32 = 60.0;
+
 impl ArrangerDataProvider for ArrangerData<'_, '_> {
     fn num_channels(&self) -> usize {
         self.channel_order.as_ref().channel_order.len()
     }
 
-    fn channel_height(&self, _: usize) -> f32 {
-        100.0
+    fn channel_height(&self, index: usize) -> f32 {
+        let num_lanes = self
+            .channel_order
+            .as_ref()
+            .channel_order
+            .get(index)
+            .and_then(|entity| self.automation.get(*entity).ok())
+            .filter(|automation| automation.expanded)
+            .map_or(0, |automation| automation.lanes.len());
+
+        CLIP_AREA_HEIGHT + num_lanes as f32 * AUTOMATION_LANE_HEIGHT
     }
 
     fn show_channel(&mut self, index: usize, ui: &mut Ui) {
@@ -68,6 +324,18 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
             .get(index)
             .expect("ChannelOrder index out of bounds");
 
+        let (kind, output, device_output) = self.routing.get(entity).map_or(
+            (ChannelKind::Instrument, None, None),
+            |(_, kind, routing)| (*kind, routing.bus_output(), routing.device_output),
+        );
+        let output_name = match device_output {
+            Some(first) => Some(format!(
+                "Out {}",
+                device_channels_label(first, self.summer.layout.num_channels())
+            )),
+            None => output.and_then(|output| self.channel_name(output)),
+        };
+
         let Ok((entity, channel, mut name, mut state, gain_control, audio_view, channel_data)) =
             self.channels.get_mut(entity)
         else {
@@ -108,31 +376,55 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
                                     });
                                     strip.cell(|ui| {
                                         ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
-                                            let input_button_response;
+                                            if kind.is_bus() {
+                                                ui.label(match kind {
+                                                    ChannelKind::Group => "📁",
+                                                    _ => "🔀",
+                                                });
+                                            } else {
+                                                let input_button_response;
 
-                                            if let Some(mut audio_view) = audio_view {
-                                                input_button_response = ui.button("🎵");
+                                                if let Some(mut audio_view) = audio_view {
+                                                    input_button_response = ui.button("🎵");
 
-                                                show_gui_button(
-                                                    &self.clap_plugin_manager,
-                                                    &mut audio_view,
-                                                    ui,
-                                                );
-                                            } else {
-                                                input_button_response = ui.button("?");
+                                                    show_gui_button(
+                                                        &self.clap_plugin_manager,
+                                                        &mut audio_view,
+                                                        ui,
+                                                    );
+                                                } else {
+                                                    input_button_response = ui.button("?");
+                                                }
+
+                                                Popup::menu(&input_button_response).show(|ui| {
+                                                    show_available_plugins_menu(
+                                                        &mut self.commands,
+                                                        entity,
+                                                        *channel,
+                                                        channel_data,
+                                                        &mut self.command_manager,
+                                                        self.available_plugins,
+                                                        ui,
+                                                    );
+                                                });
                                             }
 
-                                            Popup::menu(&input_button_response).show(|ui| {
-                                                show_available_plugins_menu(
-                                                    &mut self.commands,
-                                                    entity,
-                                                    *channel,
-                                                    channel_data,
-                                                    &mut self.command_manager,
-                                                    self.available_plugins,
-                                                    ui,
-                                                );
-                                            });
+                                            if let Some(output_name) = &output_name {
+                                                ui.label(format!("→ {output_name}"));
+                                            }
+
+                                            if let Ok(mut automation) =
+                                                self.automation.get_mut(entity)
+                                            {
+                                                show_automation_button(&mut automation, ui);
+                                            }
+
+                                            show_pan_knob(
+                                                channel,
+                                                &mut state,
+                                                &mut self.command_manager,
+                                                ui,
+                                            );
 
                                             show_gain_slider(
                                                 channel,
@@ -149,14 +441,14 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
             });
     }
 
-    fn show_strip(&mut self, _: usize, ui: &mut Ui, pixels_per_beat: f32) {
+    fn show_strip(&mut self, index: usize, ui: &mut Ui, pixels_per_beat: f32) {
         let strip_rect = ui.available_rect_before_wrap();
 
         const MEASURES: usize = 32;
-        const BEATS_PER_MEASURE: usize = 4;
         let total_width = MEASURES as f32 * BEATS_PER_MEASURE as f32 * pixels_per_beat;
 
         let r = Rect::from_min_size(strip_rect.min, vec2(total_width, strip_rect.height()));
+        let clip_area = Rect::from_min_size(r.min, vec2(r.width(), CLIP_AREA_HEIGHT));
         let _ = ui.allocate_rect(r, Sense::empty());
 
         let p = ui.painter();
@@ -180,13 +472,16 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
                 );
             }
         }
+
+        self.show_clips(index, ui, clip_area, pixels_per_beat);
+        self.show_automation(index, ui, clip_area, pixels_per_beat);
+        self.show_playhead(ui, r, pixels_per_beat);
     }
 
     fn show_timestrip(&mut self, ui: &mut Ui, pixels_per_beat: f32) {
         let rect = ui.available_rect_before_wrap();
 
         const MEASURES: usize = 32;
-        const BEATS_PER_MEASURE: usize = 4;
 
         let p = ui.painter();
 
@@ -213,21 +508,20 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
                 );
             }
         }
+
+        let response = ui.interact(rect, Id::new("arranger_timestrip"), Sense::click());
+        if response.clicked()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            let beat = ((pos.x - rect.min.x) / pixels_per_beat).max(0.0);
+            self.commands.trigger(GraphSeekEvent(beat as f64));
+        }
+
+        self.show_playhead(ui, rect, pixels_per_beat);
     }
 
     fn on_add_channel(&mut self, index: usize) {
-        let snapshot = ChannelSnapshot::default();
-        let id = snapshot.id;
-        let entity = self
-            .commands
-            .spawn((snapshot.state, snapshot.name, snapshot.id))
-            .id();
-        self.channel_order
-            .as_mut()
-            .channel_order
-            .insert(index, entity);
-        self.command_manager
-            .add_undo(Box::new(DeleteChannelEdit::new(id, index)));
+        self.add_channel(index, ChannelKind::Instrument);
     }
 
     fn move_channel(&mut self, index: usize, destination: usize) {
@@ -245,15 +539,39 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
 
         let (_, channel_id, name, state, _gain_control, _audio_view, channel_data) =
             self.channels.get(entity).unwrap();
+        let channel_id = *channel_id;
+        let current_pan_law = state.pan_law;
+        let (kind, routing) = self
+            .routing
+            .get(entity)
+            .map(|(_, kind, routing)| (*kind, routing.clone()))
+            .unwrap_or_default();
 
         ui.label(name.as_str());
         ui.separator();
         if ui.button("Delete").clicked() {
             let snapshot = ChannelSnapshot {
                 name: name.clone(),
+                kind,
                 state: state.clone(),
                 data: channel_data.cloned(),
-                id: *channel_id,
+                id: channel_id,
+                clips: self
+                    .clips
+                    .get(entity)
+                    .map(|clips| clips.0.clone())
+                    .unwrap_or_default(),
+                automation: self
+                    .automation
+                    .get(entity)
+                    .map(|automation| automation.clone())
+                    .unwrap_or_default(),
+                inserts: self
+                    .inserts
+                    .get(entity)
+                    .map(|inserts| inserts.clone())
+                    .unwrap_or_default(),
+                routing,
             };
             self.channel_order
                 .as_mut()
@@ -261,9 +579,78 @@ impl ArrangerDataProvider for ArrangerData<'_, '_> {
             self.command_manager
                 .add_undo(Box::new(AddChannelEdit::new(index, snapshot)));
         }
+        ui.menu_button("Add Automation", |ui| {
+            let params = self.plugin_params.get(entity).ok();
+            let param_targets = params
+                .iter()
+                .flat_map(|params| &params.params)
+                .filter(|param| param.automatable && !param.hidden)
+                .map(|param| AutomationTarget::ClapParam(param.id));
+            let targets = [
+                AutomationTarget::Gain,
+                AutomationTarget::Mute,
+                AutomationTarget::Pan,
+            ]
+            .into_iter()
+            .chain(param_targets);
+
+            for target in targets {
+                if ui.button(automation_target_name(target, params)).clicked()
+                    && let Ok(mut automation) = self.automation.get_mut(entity)
+                {
+                    let lane = AutomationLane::new(target);
+                    self.command_manager
+                        .add_undo(Box::new(DeleteAutomationLaneEdit::new(lane.id)));
+                    automation.lanes.push(lane);
+                    automation.expanded = true;
+                }
+            }
+        });
+        ui.menu_button("Inserts", |ui| {
+            if let Ok(mut inserts) = self.inserts.get_mut(entity) {
+                let mut instances = self.insert_instances.get_mut(entity).ok();
+                // Edit a copy so that the chain is only rebuilt when something
+                // actually changes
+                let mut new_inserts = inserts.clone();
+                show_inserts_menu(
+                    channel_id,
+                    &mut new_inserts,
+                    instances.as_deref_mut(),
+                    self.available_plugins,
+                    &self.clap_plugin_manager,
+                    &mut self.command_manager,
+                    ui,
+                );
+                if new_inserts != *inserts {
+                    *inserts = new_inserts;
+                }
+            }
+        });
+        self.show_routing_menus(entity, channel_id, ui);
+        ui.menu_button("Pan Law", |ui| {
+            for pan_law in ChannelPanLaw::ALL {
+                if ui
+                    .radio(current_pan_law == pan_law, pan_law.label())
+                    .clicked()
+                    && let Ok((_, _, _, mut state, ..)) = self.channels.get_mut(entity)
+                    && state.pan_law != pan_law
+                {
+                    let undo = SetPanLawEdit::new(channel_id, state.pan_law);
+                    state.pan_law = pan_law;
+                    self.command_manager.add_undo(Box::new(undo));
+                }
+            }
+        });
         ui.separator();
+        let new_index = (index + 1).min(self.num_channels());
         if ui.button("Add Channel").clicked() {
-            self.on_add_channel((index + 1).min(self.num_channels()));
+            self.on_add_channel(new_index);
+        }
+        if ui.button("Add Bus").clicked() {
+            self.add_channel(new_index, ChannelKind::Bus);
+        }
+        if ui.button("Add Group").clicked() {
+            self.add_channel(new_index, ChannelKind::Group);
         }
     }
 
@@ -295,6 +682,173 @@ fn show_available_plugins_menu(
     }
 }
 
+enum InsertAction {
+    Add(String),
+    SetBypass(usize, bool),
+    Move(usize, usize),
+    Remove(usize),
+    ShowGui(project::StableId),
+}
+
+fn show_inserts_menu(
+    channel_id: project::StableId,
+    inserts: &mut ChannelInserts,
+    instances: Option<&mut ChannelInsertInstances>,
+    available_plugins: Query<'_, '_, &'static AvailablePlugin, ()>,
+    clap_plugin_manager: &ClapManager,
+    command_manager: &mut EditHistory,
+    ui: &mut Ui,
+) {
+    let plugin_name = |plugin_id: &str| {
+        available_plugins
+            .iter()
+            .find(|p| p.0.id == plugin_id)
+            .map_or_else(|| plugin_id.to_owned(), |p| p.0.name.clone())
+    };
+
+    let mut action = None;
+    let num_slots = inserts.0.len();
+    for (index, slot) in inserts.0.iter().enumerate() {
+        ui.menu_button(plugin_name(&slot.plugin_id), |ui| {
+            let mut bypassed = slot.bypassed;
+            if ui.checkbox(&mut bypassed, "Bypass").changed() {
+                action = Some(InsertAction::SetBypass(index, bypassed));
+            }
+            if ui.add_enabled(index > 0, Button::new("Move Up")).clicked() {
+                action = Some(InsertAction::Move(index, index - 1));
+            }
+            if ui
+                .add_enabled(index + 1 < num_slots, Button::new("Move Down"))
+                .clicked()
+            {
+                action = Some(InsertAction::Move(index, index + 1));
+            }
+            if ui.button("Show GUI").clicked() {
+                action = Some(InsertAction::ShowGui(slot.id));
+            }
+            ui.separator();
+            if ui.button("Remove").clicked() {
+                action = Some(InsertAction::Remove(index));
+            }
+        });
+    }
+
+    if num_slots > 0 {
+        ui.separator();
+    }
+    ui.menu_button("Add Insert", |ui| {
+        for AvailablePlugin(found_plugin) in available_plugins.iter() {
+            if ui.button(found_plugin.name.as_str()).clicked() {
+                action = Some(InsertAction::Add(found_plugin.id.clone()));
+            }
+        }
+    });
+
+    match action {
+        Some(InsertAction::Add(plugin_id)) => {
+            let slot = ChannelInsertSlot::new(plugin_id);
+            command_manager.add_undo(Box::new(RemoveInsertEdit::new(slot.id)));
+            inserts.0.push(slot);
+        }
+        Some(InsertAction::SetBypass(index, bypassed)) => {
+            let slot = &mut inserts.0[index];
+            command_manager.add_undo(Box::new(SetInsertBypassEdit::new(slot.id, slot.bypassed)));
+            slot.bypassed = bypassed;
+        }
+        Some(InsertAction::Move(from, to)) => {
+            let slot = inserts.0.remove(from);
+            command_manager.add_undo(Box::new(MoveInsertEdit::new(slot.id, from)));
+            inserts.0.insert(to, slot);
+        }
+        Some(InsertAction::Remove(index)) => {
+            let slot = inserts.0.remove(index);
+            command_manager.add_undo(Box::new(AddInsertEdit::new(channel_id, index, slot)));
+        }
+        Some(InsertAction::ShowGui(slot)) => {
+            if let Some(instance) = instances.and_then(|instances| instances.get_mut(slot)) {
+                // TODO: use the executor resource so we don't need a block_on here
+                let gui_handle = futures::executor::block_on(async {
+                    clap_plugin_manager
+                        .show_gui(instance.plugin_id::<ClapManager>(), "<untitled>".to_owned())
+                        .await
+                        .unwrap()
+                });
+                instance.set_gui_handle(gui_handle);
+            }
+        }
+        None => {}
+    }
+}
+
+fn show_sends_menu(
+    channel: project::StableId,
+    routing: &mut ChannelRouting,
+    buses: &[(project::StableId, String)],
+    routes: &ChannelRoutes,
+    command_manager: &mut EditHistory,
+    ui: &mut Ui,
+) {
+    let bus_name = |bus: project::StableId| {
+        buses
+            .iter()
+            .find(|(id, _)| *id == bus)
+            .map_or("<missing>", |(_, name)| name.as_str())
+    };
+
+    let mut removed = None;
+    for (index, send) in routing.sends.iter_mut().enumerate() {
+        ui.menu_button(bus_name(send.target), |ui| {
+            let mut level = send.level;
+            let drag_start_id = Id::new(("send_drag_start", send.id));
+            let response = ui.add(Slider::new(&mut level, 0.0..=1.0).text("Level"));
+            if response.drag_started() {
+                ui.ctx()
+                    .data_mut(|d| d.insert_temp(drag_start_id, send.clone()));
+            }
+            if response.changed() {
+                send.level = level;
+            }
+            if response.drag_stopped() {
+                let start: Option<ChannelSend> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+                if let Some(start) = start
+                    && start != *send
+                {
+                    command_manager.add_undo(Box::new(SetSendEdit::new(start)));
+                }
+            }
+
+            let mut pre_fader = send.pre_fader;
+            if ui.checkbox(&mut pre_fader, "Pre-fader").changed() {
+                command_manager.add_undo(Box::new(SetSendEdit::new(send.clone())));
+                send.pre_fader = pre_fader;
+            }
+
+            ui.separator();
+            if ui.button("Remove").clicked() {
+                removed = Some(index);
+            }
+        });
+    }
+
+    if let Some(index) = removed {
+        let send = routing.sends.remove(index);
+        command_manager.add_undo(Box::new(AddSendEdit::new(channel, index, send)));
+    }
+
+    if !routing.sends.is_empty() {
+        ui.separator();
+    }
+    ui.menu_button("Add Send", |ui| {
+        for (bus, name) in buses {
+            if !routes.would_cycle(channel, *bus) && ui.button(name).clicked() {
+                let send = ChannelSend::new(*bus);
+                command_manager.add_undo(Box::new(RemoveSendEdit::new(send.id)));
+                routing.sends.push(send);
+            }
+        }
+    });
+}
+
 fn show_channel_name_editor(
     channel: &project::StableId,
     name: &mut Name,
@@ -390,6 +944,400 @@ fn show_gain_slider(
     });
 }
 
+fn show_pan_knob(
+    channel: &project::StableId,
+    state: &mut ChannelMixerState,
+    command_manager: &mut EditHistory,
+    ui: &mut Ui,
+) {
+    let mut pan = state.pan;
+    let drag_start_id = Id::new(("pan_drag_start", channel));
+    let response = ui
+        .add(Knob::new(&mut pan, -1.0..=1.0).default_value(0.0))
+        .on_hover_text(pan_label(pan));
+    if response.drag_started() {
+        ui.ctx()
+            .data_mut(|d| d.insert_temp(drag_start_id, state.pan));
+    }
+    if response.changed() {
+        if response.double_clicked() {
+            command_manager.add_undo(Box::new(SetPanEdit::new(*channel, state.pan)));
+        }
+        state.pan = pan;
+    }
+    if response.drag_stopped() {
+        let start_value: Option<f32> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+        if let Some(start_value) = start_value
+            && start_value != state.pan
+        {
+            let undo = SetPanEdit::new(*channel, start_value);
+            command_manager.add_undo(Box::new(undo));
+        }
+    }
+}
+
+fn pan_label(pan: f32) -> String {
+    let percent = (pan * 100.0).round();
+    if percent < 0.0 {
+        format!("L{}", -percent)
+    } else if percent > 0.0 {
+        format!("R{percent}")
+    } else {
+        "C".to_owned()
+    }
+}
+
+enum ClipAction {
+    Move(f64),
+    Resize(f64),
+    Delete,
+}
+
+fn show_clip(
+    clip: &MidiClip,
+    channel: project::StableId,
+    strip_rect: Rect,
+    pixels_per_beat: f32,
+    command_manager: &mut EditHistory,
+    ui: &mut Ui,
+) -> Option<ClipAction> {
+    const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+
+    let rect = Rect::from_min_max(
+        pos2(
+            strip_rect.min.x + clip.start as f32 * pixels_per_beat,
+            strip_rect.min.y + 2.0,
+        ),
+        pos2(
+            strip_rect.min.x + clip.end() as f32 * pixels_per_beat,
+            strip_rect.max.y - 2.0,
+        ),
+    );
+    let handle_rect =
+        Rect::from_min_max(pos2(rect.max.x - RESIZE_HANDLE_WIDTH, rect.min.y), rect.max);
+
+    let body = ui.interact(rect, Id::new(("clip", clip.id)), Sense::click_and_drag());
+    let handle = ui.interact(
+        handle_rect,
+        Id::new(("clip_resize", clip.id)),
+        Sense::drag(),
+    );
+    if handle.hovered() || handle.dragged() {
+        ui.ctx().set_cursor_icon(CursorIcon::ResizeHorizontal);
+    }
+
+    let p = ui.painter();
+    p.rect_filled(rect, 3.0, Color32::from_rgb(70, 110, 160));
+    p.rect_stroke(
+        rect,
+        3.0,
+        Stroke::new(1.0, Color32::from_rgb(140, 180, 220)),
+        StrokeKind::Inside,
+    );
+    show_clip_notes(clip, rect, pixels_per_beat, ui);
+    if !clip.name.is_empty() {
+        p.text(
+            rect.min + vec2(3.0, 2.0),
+            Align2::LEFT_TOP,
+            clip.name.as_str(),
+            FontId::default(),
+            Color32::WHITE,
+        );
+    }
+
+    let mut action = None;
+
+    let drag_start_id = Id::new(("clip_drag_start", clip.id));
+    let drag_delta = || {
+        let delta = ui.input(|i| Some(i.pointer.interact_pos()? - i.pointer.press_origin()?));
+        delta.map(|delta| (delta.x / pixels_per_beat) as f64)
+    };
+
+    if body.drag_started() {
+        ui.ctx()
+            .data_mut(|d| d.insert_temp(drag_start_id, clip.start));
+    }
+    if body.dragged() {
+        let start_value: Option<f64> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+        if let Some(start_value) = start_value
+            && let Some(delta) = drag_delta()
+        {
+            let start = (start_value + delta).round().max(0.0);
+            if start != clip.start {
+                action = Some(ClipAction::Move(start));
+            }
+        }
+    }
+    if body.drag_stopped() {
+        let start_value: Option<f64> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+        if let Some(start_value) = start_value
+            && start_value != clip.start
+        {
+            let undo = MoveClipEdit::new(clip.id, channel, start_value);
+            command_manager.add_undo(Box::new(undo));
+        }
+    }
+
+    if handle.drag_started() {
+        ui.ctx()
+            .data_mut(|d| d.insert_temp(drag_start_id, clip.length));
+    }
+    if handle.dragged() {
+        let start_value: Option<f64> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+        if let Some(start_value) = start_value
+            && let Some(delta) = drag_delta()
+        {
+            let length = (start_value + delta).round().max(1.0);
+            if length != clip.length {
+                action = Some(ClipAction::Resize(length));
+            }
+        }
+    }
+    if handle.drag_stopped() {
+        let start_value: Option<f64> = ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+        if let Some(start_value) = start_value
+            && start_value != clip.length
+        {
+            let undo = ResizeClipEdit::new(clip.id, start_value);
+            command_manager.add_undo(Box::new(undo));
+        }
+    }
+
+    body.context_menu(|ui| {
+        if ui.button("Delete").clicked() {
+            command_manager.add_undo(Box::new(AddClipEdit::new(channel, clip.clone())));
+            action = Some(ClipAction::Delete);
+        }
+    });
+
+    action
+}
+
+/// Draws a miniature of the clip's notes, scaled to fit the range of keys used.
+fn show_clip_notes(clip: &MidiClip, rect: Rect, pixels_per_beat: f32, ui: &Ui) {
+    let Some((min_key, max_key)) =
+        clip.notes
+            .iter()
+            .map(|note| note.key)
+            .fold(None, |range, key| match range {
+                None => Some((key, key)),
+                Some((min, max)) => Some((key.min(min), key.max(max))),
+            })
+    else {
+        return;
+    };
+
+    let rows = (max_key - min_key) as f32 + 1.0;
+    let row_height = ((rect.height() - 4.0) / rows).min(4.0);
+
+    let p = ui.painter_at(rect);
+    for note in clip.timeline_notes() {
+        let x = rect.min.x + (note.start - clip.start) as f32 * pixels_per_beat;
+        let y = rect.max.y - 2.0 - (note.key - min_key + 1) as f32 * row_height;
+        p.rect_filled(
+            Rect::from_min_size(
+                pos2(x, y),
+                vec2(note.length as f32 * pixels_per_beat, row_height),
+            ),
+            0.0,
+            Color32::from_rgb(200, 220, 240),
+        );
+    }
+}
+
+fn show_automation_button(automation: &mut ChannelAutomation, ui: &mut Ui) {
+    let response = ui
+        .add(Button::new("A").selected(automation.expanded))
+        .on_hover_text("Show automation lanes");
+    if response.clicked() {
+        automation.expanded = !automation.expanded;
+    }
+}
+
+enum LaneAction {
+    SetPoints(Vec<AutomationPoint>),
+    Delete,
+}
+
+/// Plugin parameters are named by the plugin.
+fn automation_target_name(
+    target: AutomationTarget,
+    params: Option<&ChannelPluginParams>,
+) -> String {
+    match target {
+        AutomationTarget::ClapParam(id) => params
+            .and_then(|params| params.param(id))
+            .map_or_else(|| target.name(), |param| param.name.clone()),
+        _ => target.name(),
+    }
+}
+
+fn show_automation_lane(
+    lane: &AutomationLane,
+    name: &str,
+    channel: project::StableId,
+    rect: Rect,
+    pixels_per_beat: f32,
+    command_manager: &mut EditHistory,
+    ui: &mut Ui,
+) -> Option<LaneAction> {
+    const POINT_RADIUS: f32 = 4.0;
+    const LINE_COLOR: Color32 = Color32::from_rgb(230, 180, 80);
+
+    let range = lane.target.range();
+    let (min, max) = (*range.start(), *range.end());
+    let inner = rect.shrink2(vec2(0.0, POINT_RADIUS + 1.0));
+    let value_to_y = |value: f32| inner.max.y - (value - min) / (max - min) * inner.height();
+    let y_to_value =
+        |y: f32| (min + (inner.max.y - y) / inner.height() * (max - min)).clamp(min, max);
+    let beat_to_x = |beat: f64| rect.min.x + beat as f32 * pixels_per_beat;
+    let x_to_beat = |x: f32| ((x - rect.min.x) / pixels_per_beat).max(0.0) as f64;
+
+    let background = ui.interact(rect, Id::new(("automation_lane", lane.id)), Sense::click());
+
+    let p = ui.painter();
+    p.rect_filled(rect, 0.0, Color32::from_rgb(25, 30, 45));
+    p.hline(
+        rect.x_range(),
+        rect.min.y,
+        Stroke::new(1.0, Color32::from_rgb(80, 90, 110)),
+    );
+
+    // Draw the envelope across the visible part of the lane
+    let envelope = lane.envelope();
+    let visible = rect.intersect(ui.clip_rect());
+    if !envelope.is_empty() && visible.width() > 0.0 {
+        let line: Vec<_> = (0..=(visible.width() / 2.0) as usize)
+            .filter_map(|step| {
+                let x = visible.min.x + step as f32 * 2.0;
+                let value = envelope.value_at(x_to_beat(x))?;
+                Some(pos2(x, value_to_y(value)))
+            })
+            .collect();
+        p.line(line, Stroke::new(1.5, LINE_COLOR));
+    }
+
+    p.text(
+        pos2(visible.min.x + 3.0, rect.min.y + 2.0),
+        Align2::LEFT_TOP,
+        name,
+        FontId::proportional(11.0),
+        Color32::GRAY,
+    );
+
+    let mut action = None;
+
+    let drag_start_id = Id::new(("automation_drag_start", lane.id));
+    for (index, point) in lane.points.iter().enumerate() {
+        let center = pos2(beat_to_x(point.position), value_to_y(point.value));
+        let response = ui.interact(
+            Rect::from_center_size(center, vec2(POINT_RADIUS * 3.0, POINT_RADIUS * 3.0)),
+            Id::new(("automation_point", lane.id, index)),
+            Sense::click_and_drag(),
+        );
+
+        let highlighted = response.hovered() || response.dragged();
+        ui.painter().circle(
+            center,
+            if highlighted {
+                POINT_RADIUS + 1.0
+            } else {
+                POINT_RADIUS
+            },
+            LINE_COLOR,
+            Stroke::new(1.0, Color32::WHITE),
+        );
+
+        if response.drag_started() {
+            ui.ctx()
+                .data_mut(|d| d.insert_temp(drag_start_id, lane.points.clone()));
+        }
+        if response.dragged()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            // Points stay between their neighbours so that they keep their order
+            let earliest = index
+                .checked_sub(1)
+                .map_or(0.0, |previous| lane.points[previous].position);
+            let latest = lane
+                .points
+                .get(index + 1)
+                .map_or(f64::MAX, |next| next.position);
+
+            let mut points = lane.points.clone();
+            points[index].position = x_to_beat(pos.x).clamp(earliest, latest);
+            points[index].value = y_to_value(pos.y);
+            if points[index] != *point {
+                action = Some(LaneAction::SetPoints(points));
+            }
+        }
+        if response.drag_stopped() {
+            let start_points: Option<Vec<AutomationPoint>> =
+                ui.ctx().data_mut(|d| d.get_temp(drag_start_id));
+            if let Some(start_points) = start_points
+                && start_points != lane.points
+            {
+                let undo = SetAutomationPointsEdit::new(lane.id, start_points);
+                command_manager.add_undo(Box::new(undo));
+            }
+        }
+
+        response.context_menu(|ui| {
+            for curve in [
+                AutomationCurve::Linear,
+                AutomationCurve::Step,
+                AutomationCurve::Smooth,
+            ] {
+                if ui
+                    .radio(point.curve == curve, format!("{curve:?}"))
+                    .clicked()
+                    && point.curve != curve
+                {
+                    let mut points = lane.points.clone();
+                    points[index].curve = curve;
+                    let undo = SetAutomationPointsEdit::new(lane.id, lane.points.clone());
+                    command_manager.add_undo(Box::new(undo));
+                    action = Some(LaneAction::SetPoints(points));
+                }
+            }
+            ui.separator();
+            if ui.button("Delete Point").clicked() {
+                let mut points = lane.points.clone();
+                points.remove(index);
+                let undo = SetAutomationPointsEdit::new(lane.id, lane.points.clone());
+                command_manager.add_undo(Box::new(undo));
+                action = Some(LaneAction::SetPoints(points));
+            }
+        });
+    }
+
+    // Double clicking on the lane adds a point
+    if background.double_clicked()
+        && let Some(pos) = background.interact_pointer_pos()
+    {
+        let mut points = lane.points.clone();
+        points.push(AutomationPoint {
+            position: x_to_beat(pos.x),
+            value: y_to_value(pos.y),
+            curve: AutomationCurve::default(),
+        });
+        points.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+        let undo = SetAutomationPointsEdit::new(lane.id, lane.points.clone());
+        command_manager.add_undo(Box::new(undo));
+        action = Some(LaneAction::SetPoints(points));
+    }
+
+    background.context_menu(|ui| {
+        if ui.button("Delete Lane").clicked() {
+            command_manager.add_undo(Box::new(AddAutomationLaneEdit::new(channel, lane.clone())));
+            action = Some(LaneAction::Delete);
+        }
+    });
+
+    action
+}
+
 fn show_meters(peaks: Option<&GraphStateValue>, ui: &mut Ui) {
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing = vec2(1.0, 0.0);
@@ -462,3 +1410,13 @@ fn mute_solo_arm_buttons(
 pub fn arranger_ui(data: ArrangerData, ui: &mut Ui) {
     ArrangerWidget::new("arranger").show(data, ui);
 }
+
+/// The device channels a channel outputs to when it starts at `first`, as
+/// shown to the user.
+fn device_channels_label(first: u16, width: u16) -> String {
+    if width <= 1 {
+        format!("{}", first + 1)
+    } else {
+        format!("{}-{}", first + 1, first + width)
+    }
+}
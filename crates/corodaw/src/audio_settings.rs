@@ -0,0 +1,189 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use egui::Ui;
+use engine::audio::{AudioDeviceSettings, AudioHostInfo, AudioOutput, list_audio_hosts};
+
+const SAMPLE_RATES: [u32; 5] = [44_100, 48_000, 88_200, 96_000, 192_000];
+const BUFFER_SIZES: [u32; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
+
+#[derive(Resource, Default)]
+pub struct AudioSettingsWindow {
+    open: bool,
+    settings: AudioDeviceSettings,
+    // Enumerating devices can be slow, so this is only done when the window
+    // is opened.
+    hosts: Option<Vec<AudioHostInfo>>,
+    error: Option<String>,
+}
+
+impl AudioSettingsWindow {
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+}
+
+pub fn audio_settings_system(
+    mut contexts: EguiContexts,
+    mut window: ResMut<AudioSettingsWindow>,
+    mut audio_output: NonSendMut<AudioOutput>,
+) -> Result {
+    if !window.open {
+        return Ok(());
+    }
+
+    let window = &mut *window;
+
+    if window.hosts.is_none() {
+        window.settings = audio_output.settings().clone();
+        window.hosts = Some(list_audio_hosts());
+        window.error = None;
+    }
+
+    let ctx = contexts.ctx_mut()?;
+
+    let mut open = true;
+    let mut apply = false;
+    egui::Window::new("Audio Settings")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let hosts = window.hosts.as_deref().unwrap_or_default();
+            settings_ui(ui, &mut window.settings, hosts);
+
+            if let Some(error) = &window.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            ui.separator();
+            apply = ui
+                .add_enabled(
+                    window.settings != *audio_output.settings(),
+                    egui::Button::new("Apply"),
+                )
+                .clicked();
+        });
+
+    if apply {
+        window.error = match audio_output.reconfigure(window.settings.clone()) {
+            Ok(()) => audio_output
+                .settings()
+                .save()
+                .err()
+                .map(|e| format!("Failed to save audio settings: {e}")),
+            Err(e) => Some(e.to_string()),
+        };
+    }
+
+    if !open {
+        window.open = false;
+        window.hosts = None;
+    }
+
+    Ok(())
+}
+
+fn settings_ui(ui: &mut Ui, settings: &mut AudioDeviceSettings, hosts: &[AudioHostInfo]) {
+    let host = hosts
+        .iter()
+        .find(|host| settings.host.as_ref() == Some(&host.name));
+    let device = host.and_then(|host| {
+        host.devices
+            .iter()
+            .find(|device| settings.device.as_ref() == Some(&device.name))
+    });
+
+    egui::Grid::new("audio_settings")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Host");
+            egui::ComboBox::from_id_salt("audio_host")
+                .selected_text(settings.host.as_deref().unwrap_or("Default"))
+                .show_ui(ui, |ui| {
+                    let mut changed = ui
+                        .selectable_value(&mut settings.host, None, "Default")
+                        .changed();
+                    for host in hosts {
+                        changed |= ui
+                            .selectable_value(
+                                &mut settings.host,
+                                Some(host.name.clone()),
+                                &host.name,
+                            )
+                            .changed();
+                    }
+                    if changed {
+                        settings.device = None;
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Device");
+            egui::ComboBox::from_id_salt("audio_device")
+                .selected_text(settings.device.as_deref().unwrap_or("Default"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.device, None, "Default");
+                    for device in host.iter().flat_map(|host| &host.devices) {
+                        ui.selectable_value(
+                            &mut settings.device,
+                            Some(device.name.clone()),
+                            &device.name,
+                        );
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Channels");
+            ui.add(egui::DragValue::new(&mut settings.channels).range(1..=32));
+            ui.end_row();
+
+            ui.label("Sample rate");
+            egui::ComboBox::from_id_salt("audio_sample_rate")
+                .selected_text(format!("{} Hz", settings.sample_rate))
+                .show_ui(ui, |ui| {
+                    for sample_rate in SAMPLE_RATES {
+                        ui.selectable_value(
+                            &mut settings.sample_rate,
+                            sample_rate,
+                            format!("{sample_rate} Hz"),
+                        );
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Buffer size");
+            egui::ComboBox::from_id_salt("audio_buffer_size")
+                .selected_text(buffer_size_text(settings.buffer_size))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.buffer_size, None, buffer_size_text(None));
+                    for buffer_size in BUFFER_SIZES {
+                        ui.selectable_value(
+                            &mut settings.buffer_size,
+                            Some(buffer_size),
+                            buffer_size_text(Some(buffer_size)),
+                        );
+                    }
+                });
+            ui.end_row();
+        });
+
+    // Only a specific device can be checked up front; anything using a
+    // default is checked when it's applied.
+    if let Some(device) = device
+        && !device
+            .configs
+            .iter()
+            .any(|config| config.supports(settings))
+    {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            "This device doesn't support these settings",
+        );
+    }
+}
+
+fn buffer_size_text(buffer_size: Option<u32>) -> String {
+    match buffer_size {
+        Some(size) => format!("{size} frames"),
+        None => "Default".to_owned(),
+    }
+}
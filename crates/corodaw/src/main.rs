@@ -1,27 +1,37 @@
+use anyhow::{Error, anyhow};
 use audio_graph::{
-    GraphConnection, GraphNodeDesc, GraphOutputNode, GraphPlugin, GraphPorts, GraphStateReader,
+    GraphChannelLayout, GraphConnection, GraphNodeDesc, GraphOutputNode, GraphPlugin, GraphPorts,
+    GraphStateReader, GraphThreadPool, GraphWorker,
 };
 use bevy::prelude::*;
 use bevy_app::AppExit;
-use bevy_ecs::{message::MessageWriter, world::CommandQueue};
+use bevy_ecs::{message::MessageWriter, system::SystemParam, world::CommandQueue};
 use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
 use bevy_inspector_egui::bevy_inspector;
 use egui::{Button, KeyboardShortcut, MenuBar, Modifiers, Ui};
 use engine::{
-    audio::AudioOutput,
+    audio::{AudioBackend, AudioDeviceSettings, AudioOutput},
     builtin::{MidiInputOwner, SummerOwner},
     plugins::ClapManager,
 };
 use project::{
-    AvailablePlugin, ChannelGain, ChannelMixerState, ChannelOrder, ChannelPlugin,
-    ChannelPluginBinding, EditHistoryPlugin, LoadEvent, ProjectInfo, ProjectPlugin, SaveEvent,
-    StableId, UndoRedoEvent, add_available_plugins,
+    AvailablePlugin, ChannelAutomation, ChannelBusInput, ChannelClips, ChannelGain, ChannelInserts,
+    ChannelKind, ChannelMixerState, ChannelOrder, ChannelPanLaw, ChannelPlugin,
+    ChannelPluginBinding, ChannelPluginParams, ChannelRouting, ChannelSendNodes, ChannelSequencer,
+    EditHistoryPlugin, LoadEvent, ProjectInfo, ProjectPlugin, SaveEvent, StableId, UndoRedoEvent,
+    add_available_plugins,
 };
 use smol::{LocalExecutor, Task, future};
 
 use crate::arranger::arranger_ui;
+use crate::audio_settings::{AudioSettingsWindow, audio_settings_system};
+use crate::render::{RenderArgs, render_project};
+use crate::transport::{TransportData, transport_ui};
 
 mod arranger;
+mod audio_settings;
+mod render;
+mod transport;
 
 #[derive(Default)]
 struct AsyncTaskRunner {
@@ -64,13 +74,20 @@ fn swap_buffers_system(mut state_reader: NonSendMut<GraphStateReader>) {
 #[derive(Resource, Default)]
 struct InspectorEnabled(bool);
 
+#[derive(SystemParam)]
+struct MenuBarData<'w, 's> {
+    commands: Commands<'w, 's>,
+    app_exit: MessageWriter<'w, AppExit>,
+    inspector_enabled: ResMut<'w, InspectorEnabled>,
+    audio_settings_window: ResMut<'w, AudioSettingsWindow>,
+}
+
 fn ui_system(
     mut contexts: EguiContexts,
     async_task_runner: NonSend<AsyncTaskRunner>,
-    mut commands: Commands,
-    mut app_exit: MessageWriter<AppExit>,
-    mut inspector_enabled: ResMut<InspectorEnabled>,
+    mut menu_bar: MenuBarData,
     data: arranger::ArrangerData,
+    transport: TransportData,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
     ctx.request_repaint();
@@ -85,10 +102,10 @@ fn ui_system(
 
     if !async_task_runner.is_active() {
         if can_undo && ctx.input_mut(|i| i.consume_shortcut(&undo_shortcut)) {
-            commands.trigger(UndoRedoEvent::Undo);
+            menu_bar.commands.trigger(UndoRedoEvent::Undo);
         }
         if can_redo && ctx.input_mut(|i| i.consume_shortcut(&redo_shortcut)) {
-            commands.trigger(UndoRedoEvent::Redo);
+            menu_bar.commands.trigger(UndoRedoEvent::Redo);
         }
     }
 
@@ -105,14 +122,14 @@ fn ui_system(
         if async_task_runner.is_active() {
             ui.disable();
         }
-        menu_bar_ui(
-            ui,
-            &mut commands,
-            can_undo,
-            can_redo,
-            &mut app_exit,
-            &mut inspector_enabled,
-        );
+        menu_bar_ui(ui, &mut menu_bar, can_undo, can_redo);
+    });
+
+    egui::Panel::top("transport").show_inside(&mut root, |ui| {
+        if async_task_runner.is_active() {
+            ui.disable();
+        }
+        transport_ui(transport, ui);
     });
 
     egui::CentralPanel::default().show_inside(&mut root, |ui| {
@@ -125,14 +142,13 @@ fn ui_system(
     Ok(())
 }
 
-fn menu_bar_ui(
-    ui: &mut Ui,
-    commands: &mut Commands,
-    can_undo: bool,
-    can_redo: bool,
-    app_exit: &mut MessageWriter<AppExit>,
-    inspector_enabled: &mut InspectorEnabled,
-) {
+fn menu_bar_ui(ui: &mut Ui, data: &mut MenuBarData, can_undo: bool, can_redo: bool) {
+    let MenuBarData {
+        commands,
+        app_exit,
+        inspector_enabled,
+        audio_settings_window,
+    } = data;
     MenuBar::new().ui(ui, |ui| {
         ui.menu_button("File", |ui| {
             if ui.button("Open...").clicked() {
@@ -142,6 +158,10 @@ fn menu_bar_ui(
                 commands.trigger(FileAction::Save);
             }
             ui.separator();
+            if ui.button("Audio Settings...").clicked() {
+                audio_settings_window.open();
+            }
+            ui.separator();
             if ui.button("Quit").clicked() {
                 app_exit.write(AppExit::Success);
             }
@@ -257,16 +277,13 @@ fn setup_camera(mut commands: Commands) {
     commands.spawn(Camera2d);
 }
 
-fn main() {
-    let mut app = App::new();
-
+/// Adds everything needed to load and play a project, without any UI. The
+/// master bus, and every channel with it, uses `layout`.
+fn add_engine_plugins(app: &mut App, layout: GraphChannelLayout) {
     app.add_plugins((GraphPlugin, ProjectPlugin::new()));
 
-    let audio_graph_worker = app.world_mut().remove_non_send().unwrap();
-    let audio = AudioOutput::new(audio_graph_worker).unwrap();
-
     let midi_input = MidiInputOwner::new(app.world_mut());
-    let summer = SummerOwner::new(app.world_mut(), 2);
+    let summer = SummerOwner::new(app.world_mut(), layout);
     app.world_mut()
         .entity_mut(summer.entity)
         .insert(GraphOutputNode);
@@ -274,23 +291,100 @@ fn main() {
     app.insert_non_send(ClapManager::default())
         .insert_non_send(midi_input)
         .insert_non_send(summer)
-        .insert_non_send(audio)
         .add_plugins((ChannelPlugin::new(), EditHistoryPlugin));
 
+    add_available_plugins(app.world_mut());
+}
+
+/// `--audio-backend <cpal|null>` picks how the graph is driven; cpal is used
+/// if it isn't specified.
+fn parse_audio_backend(args: &[String]) -> Result<AudioBackend, Error> {
+    let Some(index) = args.iter().position(|a| a == "--audio-backend") else {
+        return Ok(AudioBackend::default());
+    };
+
+    args.get(index + 1)
+        .ok_or_else(|| anyhow!("usage: --audio-backend <cpal|null>"))?
+        .parse()
+}
+
+/// Reports a command line argument that couldn't be parsed, and exits.
+fn exit_with_error(error: Error) -> ! {
+    eprintln!("{error}");
+    std::process::exit(1);
+}
+
+/// `--channel-layout <mono|stereo|quad|5.1|7.1>` picks the layout of the
+/// channels and the master bus; stereo is used if it isn't specified.
+fn parse_channel_layout(args: &[String]) -> Result<GraphChannelLayout, Error> {
+    let Some(index) = args.iter().position(|a| a == "--channel-layout") else {
+        return Ok(GraphChannelLayout::Stereo);
+    };
+    let layout = args
+        .get(index + 1)
+        .ok_or_else(|| anyhow!("usage: --channel-layout <mono|stereo|quad|5.1|7.1>"))?;
+
+    match layout.as_str() {
+        "mono" => Ok(GraphChannelLayout::Mono),
+        "stereo" => Ok(GraphChannelLayout::Stereo),
+        "quad" => Ok(GraphChannelLayout::Quad),
+        "5.1" => Ok(GraphChannelLayout::Surround51),
+        "7.1" => Ok(GraphChannelLayout::Surround71),
+        _ => Err(anyhow!(
+            "unknown channel layout '{layout}' (expected 'mono', 'stereo', 'quad', '5.1' or '7.1')"
+        )),
+    }
+}
+
+fn main() {
+    let mut app = App::new();
+
+    let args: Vec<String> = std::env::args().collect();
+    let layout = parse_channel_layout(&args).unwrap_or_else(|error| exit_with_error(error));
+    add_engine_plugins(&mut app, layout);
+
+    if let Some(render_args) = RenderArgs::parse(&args) {
+        if let Err(err) = render_args.and_then(|args| render_project(app, args)) {
+            eprintln!("Render failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.set_num_threads(GraphThreadPool::default_num_threads());
+    // For plugins that only read events and automation once per block
+    audio_graph_worker.set_split_at_boundaries(args.iter().any(|a| a == "--split-blocks"));
+    let audio = AudioOutput::with_settings(
+        audio_graph_worker,
+        parse_audio_backend(&args).unwrap_or_else(|error| exit_with_error(error)),
+        AudioDeviceSettings::load(),
+    );
+    app.insert_non_send(audio);
+
     // Register types for bevy-inspector-egui
     app.register_type::<StableId>()
         .register_type::<ProjectInfo>()
         .register_type::<ChannelOrder>()
         .register_type::<ChannelPluginBinding>()
         .register_type::<ChannelMixerState>()
+        .register_type::<ChannelPanLaw>()
         .register_type::<ChannelGain>()
+        .register_type::<ChannelSequencer>()
+        .register_type::<ChannelClips>()
+        .register_type::<ChannelAutomation>()
+        .register_type::<ChannelPluginParams>()
+        .register_type::<ChannelInserts>()
+        .register_type::<ChannelKind>()
+        .register_type::<ChannelRouting>()
+        .register_type::<ChannelBusInput>()
+        .register_type::<ChannelSendNodes>()
         .register_type::<AvailablePlugin>()
         .register_type::<GraphOutputNode>()
         .register_type::<GraphNodeDesc>()
         .register_type::<GraphConnection>()
-        .register_type::<GraphPorts>();
-
-    add_available_plugins(app.world_mut());
+        .register_type::<GraphPorts>()
+        .register_type::<GraphChannelLayout>();
 
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
@@ -302,7 +396,8 @@ fn main() {
     }));
     app.add_plugins(EguiPlugin::default());
     app.add_plugins(bevy_inspector_egui::DefaultInspectorConfigPlugin);
-    app.init_resource::<InspectorEnabled>();
+    app.init_resource::<InspectorEnabled>()
+        .init_resource::<AudioSettingsWindow>();
 
     app.add_systems(Startup, setup_camera);
     app.add_systems(First, update_executor_system);
@@ -310,7 +405,10 @@ fn main() {
         EguiPrimaryContextPass,
         (swap_buffers_system, ui_system).chain(),
     );
-    app.add_systems(EguiPrimaryContextPass, world_inspector_system);
+    app.add_systems(
+        EguiPrimaryContextPass,
+        (world_inspector_system, audio_settings_system),
+    );
     app.add_systems(PostUpdate, set_titlebar_system);
     app.insert_non_send(AsyncTaskRunner::default());
     app.add_observer(on_file_action);
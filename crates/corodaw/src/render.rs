@@ -0,0 +1,97 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Error, anyhow};
+use audio_graph::{GraphController, GraphSeekEvent, GraphThreadPool, GraphTransport, GraphWorker};
+use bevy_app::App;
+use engine::render::{OfflineRender, OfflineRenderSettings, WavBitDepth};
+use project::LoadEvent;
+
+/// Arguments for rendering a project to a WAV file without opening a window:
+///
+/// `corodaw --render <project.corodaw> <output.wav> [--length <seconds>]
+/// [--sample-rate <hz>] [--bit-depth <16|24|32>]`
+pub struct RenderArgs {
+    project: PathBuf,
+    output: PathBuf,
+    settings: OfflineRenderSettings,
+}
+
+impl RenderArgs {
+    /// Returns `None` if the command line doesn't ask for a render.
+    pub fn parse(args: &[String]) -> Option<Result<Self, Error>> {
+        let position = args.iter().position(|a| a == "--render")?;
+        Some(Self::parse_render_args(&args[position + 1..]))
+    }
+
+    fn parse_render_args(args: &[String]) -> Result<Self, Error> {
+        let mut args = args.iter();
+        let usage = || anyhow!("usage: --render <project.corodaw> <output.wav>");
+
+        let project = PathBuf::from(args.next().ok_or_else(usage)?);
+        let output = PathBuf::from(args.next().ok_or_else(usage)?);
+
+        let mut settings = OfflineRenderSettings::default();
+
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("missing value for {arg}"))?;
+
+            match arg.as_str() {
+                "--length" => settings.length = Duration::from_secs_f64(value.parse()?),
+                "--sample-rate" => settings.sample_rate = value.parse()?,
+                "--bit-depth" => {
+                    settings.bit_depth = WavBitDepth::from_bits(value.parse()?)
+                        .ok_or_else(|| anyhow!("unsupported bit depth {value}"))?;
+                }
+                _ => return Err(anyhow!("unknown render argument {arg}")),
+            }
+        }
+
+        Ok(Self {
+            project,
+            output,
+            settings,
+        })
+    }
+}
+
+pub fn render_project(mut app: App, args: RenderArgs) -> Result<(), Error> {
+    let mut audio_graph_worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    audio_graph_worker.set_num_threads(GraphThreadPool::default_num_threads());
+
+    // Configure the worker before loading so that plugins are created at the
+    // render's sample rate.
+    let mut render = OfflineRender::new(audio_graph_worker, args.settings);
+
+    app.world_mut().trigger(LoadEvent::new(args.project));
+
+    // Loading the project, creating the plugins and then sending the resulting
+    // graph to the worker takes a few frames, as the worker's replies, such as
+    // plugin latencies, lead to more changes. It's done once a frame changes
+    // nothing.
+    loop {
+        app.update();
+        if !app
+            .world()
+            .non_send::<GraphController>()
+            .has_pending_updates()
+        {
+            break;
+        }
+        render.update();
+    }
+
+    // Play from the start, with the project's tempo and time signature
+    app.world_mut().resource_mut::<GraphTransport>().playing = true;
+    app.world_mut().trigger(GraphSeekEvent(0.0));
+    app.update();
+
+    println!(
+        "Rendering {:?} of audio to {}",
+        render.settings().length,
+        args.output.display()
+    );
+
+    render.render_to_wav(&args.output)
+}
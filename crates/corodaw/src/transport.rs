@@ -0,0 +1,121 @@
+use audio_graph::{GraphController, GraphLoopRegion, GraphSeekEvent, GraphTransport};
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::SystemParam;
+use egui::{Button, Color32, ComboBox, DragValue, RichText, Ui};
+use project::RecordMode;
+
+#[derive(SystemParam)]
+pub struct TransportData<'w, 's> {
+    commands: Commands<'w, 's>,
+    transport: ResMut<'w, GraphTransport>,
+    record_mode: ResMut<'w, RecordMode>,
+    audio_graph: NonSend<'w, GraphController>,
+}
+
+pub fn transport_ui(mut data: TransportData, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        if ui.button("⏮").clicked() {
+            data.commands.trigger(GraphSeekEvent(0.0));
+        }
+
+        let playing = data.transport.playing;
+        if ui
+            .add(Button::new(if playing { "⏹" } else { "▶" }).selected(playing))
+            .clicked()
+        {
+            data.transport.playing = !playing;
+        }
+
+        let recording = data.transport.recording;
+        if ui
+            .add(Button::new(RichText::new("⏺").color(Color32::RED)).selected(recording))
+            .clicked()
+        {
+            data.transport.recording = !recording;
+        }
+
+        let mut record_mode = *data.record_mode;
+        ComboBox::from_id_salt("record_mode")
+            .width(80.0)
+            .selected_text(record_mode_name(record_mode))
+            .show_ui(ui, |ui| {
+                for mode in [RecordMode::Overdub, RecordMode::Replace] {
+                    ui.selectable_value(&mut record_mode, mode, record_mode_name(mode));
+                }
+            });
+        if record_mode != *data.record_mode {
+            *data.record_mode = record_mode;
+        }
+
+        let mut looping = data.transport.loop_region.is_some();
+        if ui.checkbox(&mut looping, "Loop").changed() {
+            data.transport.loop_region = looping.then(|| default_loop_region(&data.transport));
+        }
+
+        ui.separator();
+
+        let position = data.audio_graph.transport_position();
+        let beats_per_bar = beats_per_bar(&data.transport);
+        ui.monospace(format!(
+            "{:>3}.{}",
+            (position / beats_per_bar).floor() as i64 + 1,
+            (position % beats_per_bar).floor() as i64 + 1
+        ));
+
+        ui.separator();
+
+        let mut tempo = data.transport.tempo;
+        if ui
+            .add(
+                DragValue::new(&mut tempo)
+                    .range(20.0..=300.0)
+                    .speed(0.1)
+                    .suffix(" bpm"),
+            )
+            .changed()
+        {
+            data.transport.tempo = tempo;
+        }
+
+        let mut numerator = data.transport.time_signature.numerator;
+        if ui
+            .add(DragValue::new(&mut numerator).range(1..=32))
+            .changed()
+        {
+            data.transport.time_signature.numerator = numerator;
+        }
+        ui.label("/");
+        let mut denominator = data.transport.time_signature.denominator;
+        ComboBox::from_id_salt("time_signature_denominator")
+            .width(40.0)
+            .selected_text(denominator.to_string())
+            .show_ui(ui, |ui| {
+                for value in [2, 4, 8, 16] {
+                    ui.selectable_value(&mut denominator, value, value.to_string());
+                }
+            });
+        if denominator != data.transport.time_signature.denominator {
+            data.transport.time_signature.denominator = denominator;
+        }
+    });
+}
+
+fn record_mode_name(mode: RecordMode) -> &'static str {
+    match mode {
+        RecordMode::Overdub => "Overdub",
+        RecordMode::Replace => "Replace",
+    }
+}
+
+fn beats_per_bar(transport: &GraphTransport) -> f64 {
+    let time_signature = transport.time_signature;
+    time_signature.numerator as f64 * 4.0 / time_signature.denominator as f64
+}
+
+/// Loops the first four bars.
+fn default_loop_region(transport: &GraphTransport) -> GraphLoopRegion {
+    GraphLoopRegion {
+        start: 0.0,
+        end: 4.0 * beats_per_bar(transport),
+    }
+}
@@ -1,52 +1,287 @@
-use anyhow::Error;
+use std::{
+    str::FromStr,
+    sync::mpsc::{Receiver, Sender, channel},
+    time::Duration,
+};
+
+use anyhow::{Error, anyhow};
 use cpal::{
-    BufferSize, OutputCallbackInfo, Stream, StreamConfig, StreamInstant,
-    traits::{DeviceTrait, HostTrait, StreamTrait},
+    OutputCallbackInfo, Stream, StreamInstant,
+    traits::{DeviceTrait, StreamTrait},
+};
+
+use audio_graph::{DEFAULT_MAX_BLOCK_FRAMES, GraphWorker};
+
+mod null;
+mod settings;
+
+pub use null::{NullAudioConfig, NullAudioOutput};
+pub use settings::{
+    AudioConfigRange, AudioDeviceInfo, AudioDeviceSettings, AudioHostInfo, list_audio_hosts,
 };
 
-use audio_graph::GraphWorker;
+/// Which implementation `AudioOutput` uses to drive the graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioBackend {
+    /// Output to a real device via cpal, falling back to `Null` if there isn't
+    /// one.
+    #[default]
+    Cpal,
+    /// Drive the graph from a timer thread and discard the output.
+    Null,
+}
+
+impl FromStr for AudioBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpal" => Ok(AudioBackend::Cpal),
+            "null" => Ok(AudioBackend::Null),
+            _ => Err(anyhow!(
+                "unknown audio backend '{s}' (expected 'cpal' or 'null')"
+            )),
+        }
+    }
+}
 
 pub struct AudioOutput {
-    _stream: Stream,
+    backend: AudioBackend,
+    settings: AudioDeviceSettings,
+    stream: Option<AudioOutputStream>,
+}
+
+enum AudioOutputStream {
+    Cpal {
+        stream: Stream,
+        // The audio thread sends the worker back here when the stream is
+        // dropped.
+        worker: Receiver<GraphWorker>,
+    },
+    Null {
+        output: NullAudioOutput,
+    },
 }
 
 impl AudioOutput {
-    pub fn new(mut audio_graph_worker: GraphWorker) -> Result<AudioOutput, Error> {
-        let cpal = cpal::host_from_id(cpal::HostId::Asio)?;
-        let device = cpal.default_output_device().unwrap();
-
-        let config = StreamConfig {
-            channels: 2,
-            sample_rate: 48_000,
-            buffer_size: BufferSize::Fixed(4096),
+    pub fn new(audio_graph_worker: GraphWorker) -> AudioOutput {
+        Self::with_backend(audio_graph_worker, AudioBackend::Cpal)
+    }
+
+    pub fn with_backend(audio_graph_worker: GraphWorker, backend: AudioBackend) -> AudioOutput {
+        Self::with_settings(audio_graph_worker, backend, AudioDeviceSettings::default())
+    }
+
+    /// If `settings` can't be used (eg the device has been unplugged) then the
+    /// defaults are tried instead. If they can't be used either the graph is
+    /// driven by null output, until `reconfigure` finds settings that work.
+    pub fn with_settings(
+        audio_graph_worker: GraphWorker,
+        backend: AudioBackend,
+        mut settings: AudioDeviceSettings,
+    ) -> AudioOutput {
+        let stream = match start(audio_graph_worker, backend, &settings) {
+            Ok(stream) => Ok(stream),
+            Err((error, audio_graph_worker)) if settings != AudioDeviceSettings::default() => {
+                println!("Failed to apply audio settings ({error}), using the defaults");
+                settings = AudioDeviceSettings::default();
+                start(audio_graph_worker, backend, &settings)
+            }
+            Err(error) => Err(error),
         };
-        println!("cpal: {:?}", cpal.id());
-        println!("Audio device: {:?}", device.description());
 
-        audio_graph_worker.configure(config.channels, config.sample_rate);
+        let stream = stream.unwrap_or_else(|(error, audio_graph_worker)| {
+            println!("Failed to start audio output ({error}), falling back to null output");
+            start_null(audio_graph_worker, settings.null_config())
+        });
+
+        AudioOutput {
+            backend,
+            settings,
+            stream: Some(stream),
+        }
+    }
 
-        let mut audio_thread = AudioOutputThread {
-            audio_graph_worker,
-            first_playback: None,
+    pub fn null(audio_graph_worker: GraphWorker, config: NullAudioConfig) -> AudioOutput {
+        let settings = AudioDeviceSettings {
+            channels: config.channels,
+            sample_rate: config.sample_rate,
+            buffer_size: Some(config.block_size as u32),
+            ..Default::default()
         };
 
-        let stream = device.build_output_stream(
+        AudioOutput {
+            backend: AudioBackend::Null,
+            settings,
+            stream: Some(start_null(audio_graph_worker, config)),
+        }
+    }
+
+    pub fn settings(&self) -> &AudioDeviceSettings {
+        &self.settings
+    }
+
+    /// Rebuilds the stream with new settings, reconfiguring the graph (and so
+    /// every processor in it) for the new sample rate. If the new settings
+    /// can't be used then the previous ones are restored and the error is
+    /// returned.
+    pub fn reconfigure(&mut self, settings: AudioDeviceSettings) -> Result<(), Error> {
+        let audio_graph_worker = self.stream.take().unwrap().into_worker();
+
+        match start(audio_graph_worker, self.backend, &settings) {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.settings = settings;
+                Ok(())
+            }
+            Err((error, audio_graph_worker)) => {
+                let stream = start(audio_graph_worker, self.backend, &self.settings)
+                    .unwrap_or_else(|(_, audio_graph_worker)| {
+                        println!("Failed to restore audio output, falling back to null output");
+                        start_null(audio_graph_worker, self.settings.null_config())
+                    });
+                self.stream = Some(stream);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl AudioOutputStream {
+    fn into_worker(self) -> GraphWorker {
+        match self {
+            AudioOutputStream::Cpal { stream, worker } => {
+                drop(stream);
+                worker
+                    .recv()
+                    .expect("audio thread should return the graph worker")
+            }
+            AudioOutputStream::Null { output } => output.into_worker(),
+        }
+    }
+}
+
+/// On failure the worker is handed back along with the error.
+fn start(
+    audio_graph_worker: GraphWorker,
+    backend: AudioBackend,
+    settings: &AudioDeviceSettings,
+) -> Result<AudioOutputStream, (Error, GraphWorker)> {
+    match backend {
+        AudioBackend::Cpal => start_cpal(audio_graph_worker, settings),
+        AudioBackend::Null => Ok(start_null(audio_graph_worker, settings.null_config())),
+    }
+}
+
+fn start_null(audio_graph_worker: GraphWorker, config: NullAudioConfig) -> AudioOutputStream {
+    println!(
+        "Audio: null output ({} channels, {} Hz, {} frames per block)",
+        config.channels, config.sample_rate, config.block_size
+    );
+
+    AudioOutputStream::Null {
+        output: NullAudioOutput::new(audio_graph_worker, config),
+    }
+}
+
+fn start_cpal(
+    mut audio_graph_worker: GraphWorker,
+    settings: &AudioDeviceSettings,
+) -> Result<AudioOutputStream, (Error, GraphWorker)> {
+    let host = match settings.find_host() {
+        Ok(Some(host)) => host,
+        Ok(None) => {
+            println!("No audio host available, falling back to null output");
+            return Ok(start_null(audio_graph_worker, settings.null_config()));
+        }
+        Err(error) => return Err((error, audio_graph_worker)),
+    };
+
+    let device = match settings.find_device(&host) {
+        Ok(Some(device)) => device,
+        Ok(None) => {
+            println!("No audio output device found, falling back to null output");
+            return Ok(start_null(audio_graph_worker, settings.null_config()));
+        }
+        Err(error) => return Err((error, audio_graph_worker)),
+    };
+
+    let config = match settings.stream_config(&device) {
+        Ok(config) => config,
+        Err(error) => return Err((error, audio_graph_worker)),
+    };
+
+    println!("cpal: {:?}", host.id());
+    println!("Audio device: {:?}", device.description());
+    println!("Audio config: {:?}", config);
+
+    // Without a fixed buffer size the callbacks can be any length, and
+    // longer ones are processed in pieces
+    let max_block_frames = settings
+        .buffer_size
+        .map_or(DEFAULT_MAX_BLOCK_FRAMES, |size| size as usize);
+    audio_graph_worker.configure(config.channels, config.sample_rate, max_block_frames);
+
+    let (worker_sender, worker_receiver) = channel();
+
+    let mut audio_thread = AudioOutputThread {
+        audio_graph_worker: Some(audio_graph_worker),
+        worker_sender,
+        first_playback: None,
+    };
+
+    let stream = device
+        .build_output_stream(
             &config,
             move |data: &mut [f32], info| {
                 audio_thread.data_callback(data, info);
             },
             |a| println!("error_callback: {:?}", a),
             None,
-        )?;
+        )
+        .map_err(Error::from)
+        .and_then(|stream| {
+            stream.play()?;
+            Ok(stream)
+        });
 
-        stream.play()?;
+    match stream {
+        Ok(stream) => Ok(AudioOutputStream::Cpal {
+            stream,
+            worker: worker_receiver,
+        }),
+        // The callback, and so the worker, has been dropped by now.
+        Err(error) => Err((
+            error,
+            worker_receiver
+                .recv()
+                .expect("audio thread should return the graph worker"),
+        )),
+    }
+}
+
+fn default_host() -> Option<cpal::Host> {
+    #[cfg(windows)]
+    if let Ok(host) = cpal::host_from_id(cpal::HostId::Asio) {
+        return Some(host);
+    }
 
-        Ok(AudioOutput { _stream: stream })
+    if cpal::available_hosts().is_empty() {
+        None
+    } else {
+        Some(cpal::default_host())
     }
 }
 
+pub(crate) fn frames_to_duration(frames: u64, sample_rate: u32) -> Duration {
+    const NS_PER_SECOND: u128 = 1_000_000_000u128;
+    let nanoseconds = (frames as u128 * NS_PER_SECOND) / sample_rate as u128;
+    Duration::from_nanos(nanoseconds as u64)
+}
+
 struct AudioOutputThread {
-    audio_graph_worker: GraphWorker,
+    audio_graph_worker: Option<GraphWorker>,
+    worker_sender: Sender<GraphWorker>,
     first_playback: Option<StreamInstant>,
 }
 
@@ -60,7 +295,16 @@ impl AudioOutputThread {
             *first_playback = playback_time;
         }
 
-        self.audio_graph_worker
-            .tick(data, playback_time.duration_since(first_playback).unwrap());
+        if let Some(audio_graph_worker) = &mut self.audio_graph_worker {
+            audio_graph_worker.tick(data, playback_time.duration_since(first_playback).unwrap());
+        }
+    }
+}
+
+impl Drop for AudioOutputThread {
+    fn drop(&mut self) {
+        if let Some(audio_graph_worker) = self.audio_graph_worker.take() {
+            let _ = self.worker_sender.send(audio_graph_worker);
+        }
     }
 }
@@ -0,0 +1,121 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+#[cfg(test)]
+use crossbeam::channel::Sender;
+
+use audio_graph::GraphWorker;
+
+use super::frames_to_duration;
+
+#[derive(Debug, Clone)]
+pub struct NullAudioConfig {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub block_size: usize,
+    /// If set, every block of interleaved samples is sent here rather than
+    /// being thrown away. Only for tests, as it allocates a copy of every
+    /// block on the audio thread.
+    #[cfg(test)]
+    pub(crate) tap: Option<Sender<Vec<f32>>>,
+}
+
+impl Default for NullAudioConfig {
+    fn default() -> Self {
+        Self {
+            channels: 2,
+            sample_rate: 48_000,
+            block_size: 1024,
+            #[cfg(test)]
+            tap: None,
+        }
+    }
+}
+
+/// An audio "device" for machines without a sound card. A timer thread calls
+/// `GraphWorker::tick` at the rate a real device with the same configuration
+/// would.
+pub struct NullAudioOutput {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<GraphWorker>>,
+}
+
+impl NullAudioOutput {
+    pub fn new(mut audio_graph_worker: GraphWorker, config: NullAudioConfig) -> Self {
+        assert!(config.block_size > 0);
+        assert!(config.channels > 0);
+
+        audio_graph_worker.configure(config.channels, config.sample_rate, config.block_size);
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || run(audio_graph_worker, config, &stop))
+        };
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the timer thread and hands back the worker it was driving.
+    pub fn into_worker(mut self) -> GraphWorker {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .take()
+            .unwrap()
+            .join()
+            .expect("null audio thread panicked")
+    }
+}
+
+impl Drop for NullAudioOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    mut audio_graph_worker: GraphWorker,
+    config: NullAudioConfig,
+    stop: &AtomicBool,
+) -> GraphWorker {
+    let mut data = vec![0.0; config.block_size * config.channels as usize];
+
+    let start = Instant::now();
+    let mut frames: u64 = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        let timestamp = frames_to_duration(frames, config.sample_rate);
+
+        audio_graph_worker.tick(&mut data, timestamp);
+
+        #[cfg(test)]
+        if let Some(tap) = &config.tap {
+            let _ = tap.send(data.clone());
+        }
+
+        frames += config.block_size as u64;
+
+        let next_block = start + frames_to_duration(frames, config.sample_rate);
+        if let Some(wait) = next_block.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    audio_graph_worker
+}
+
+#[cfg(test)]
+mod tests;
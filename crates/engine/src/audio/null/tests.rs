@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use crossbeam::channel;
+
+use super::*;
+use crate::test_util::constant_worker;
+
+#[test]
+fn tap_receives_the_graph_output() {
+    let (tap, blocks) = channel::unbounded();
+    let config = NullAudioConfig {
+        block_size: 64,
+        tap: Some(tap),
+        ..Default::default()
+    };
+    let output = NullAudioOutput::new(constant_worker(0.5), config);
+
+    for _ in 0..2 {
+        let block = blocks.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(block, vec![0.5; 128]);
+    }
+
+    // The worker is handed back once the timer thread stops
+    let mut worker = output.into_worker();
+    let mut data = [0.0; 2];
+    worker.tick(&mut data, Duration::default());
+    assert_eq!(data, [0.5, 0.5]);
+}
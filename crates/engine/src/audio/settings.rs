@@ -0,0 +1,241 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, anyhow};
+use cpal::{
+    BufferSize, Device, Host, SampleFormat, StreamConfig, SupportedBufferSize,
+    traits::{DeviceTrait, HostTrait},
+};
+use serde::{Deserialize, Serialize};
+
+use super::NullAudioConfig;
+
+/// Where the settings are saved, in the user's config directory.
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("corodaw").join("audio-settings.json"))
+}
+
+/// The user's choice of audio output. Anything left as `None` uses whatever
+/// cpal reports as the default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioDeviceSettings {
+    /// The name of the cpal host, eg "ALSA", "JACK" or "ASIO".
+    pub host: Option<String>,
+    /// The name of the output device, as reported by the host.
+    pub device: Option<String>,
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Frames per callback.
+    pub buffer_size: Option<u32>,
+}
+
+impl Default for AudioDeviceSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            device: None,
+            channels: 2,
+            sample_rate: 48_000,
+            buffer_size: None,
+        }
+    }
+}
+
+impl AudioDeviceSettings {
+    /// Loads the settings saved by `save`, or the defaults if there aren't
+    /// any.
+    pub fn load() -> Self {
+        settings_path().map_or_else(Self::default, |path| Self::load_from(&path))
+    }
+
+    fn load_from(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(settings) => {
+                    println!("Loaded audio settings from {}", path.display());
+                    settings
+                }
+                Err(err) => {
+                    println!("Failed to parse {} ({err})", path.display());
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Writes the settings to "corodaw/audio-settings.json" in the user's
+    /// config directory.
+    pub fn save(&self) -> Result<(), Error> {
+        let path =
+            settings_path().ok_or_else(|| anyhow!("there's no config directory to save to"))?;
+        self.save_to(&path)
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let json = serde_json::to_string_pretty(self)?;
+        let mut f = std::fs::File::create(path)?;
+        f.write_all(json.as_bytes())?;
+        f.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub(crate) fn find_host(&self) -> Result<Option<Host>, Error> {
+        let Some(name) = &self.host else {
+            return Ok(super::default_host());
+        };
+
+        let id = cpal::available_hosts()
+            .into_iter()
+            .find(|id| id.name() == name)
+            .ok_or_else(|| anyhow!("audio host '{name}' is not available"))?;
+
+        Ok(Some(cpal::host_from_id(id)?))
+    }
+
+    pub(crate) fn find_device(&self, host: &Host) -> Result<Option<Device>, Error> {
+        let Some(name) = &self.device else {
+            return Ok(host.default_output_device());
+        };
+
+        let device = host
+            .output_devices()?
+            .find(|device| device_name(device).as_deref() == Some(name.as_str()))
+            .ok_or_else(|| anyhow!("audio device '{name}' was not found"))?;
+
+        Ok(Some(device))
+    }
+
+    /// Checks the settings against the configurations the device supports.
+    pub(crate) fn stream_config(&self, device: &Device) -> Result<StreamConfig, Error> {
+        let configs = AudioConfigRange::from_device(device)?;
+
+        if !configs.iter().any(|config| config.supports(self)) {
+            return Err(anyhow!(
+                "device doesn't support {} channels at {} Hz{}",
+                self.channels,
+                self.sample_rate,
+                self.buffer_size
+                    .map(|size| format!(" with {size} frame buffers"))
+                    .unwrap_or_default()
+            ));
+        }
+
+        Ok(StreamConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            buffer_size: match self.buffer_size {
+                Some(size) => BufferSize::Fixed(size),
+                None => BufferSize::Default,
+            },
+        })
+    }
+
+    pub(crate) fn null_config(&self) -> NullAudioConfig {
+        NullAudioConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            block_size: self.buffer_size.unwrap_or(1024) as usize,
+            ..Default::default()
+        }
+    }
+}
+
+/// An audio host and the output devices it provides.
+#[derive(Debug, Clone)]
+pub struct AudioHostInfo {
+    pub name: String,
+    pub devices: Vec<AudioDeviceInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub configs: Vec<AudioConfigRange>,
+}
+
+/// A range of output configurations supported by a device. Only f32
+/// configurations are reported since that's all the graph produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// `None` if the device can't say which buffer sizes it supports.
+    pub buffer_size: Option<(u32, u32)>,
+}
+
+impl AudioConfigRange {
+    fn from_device(device: &Device) -> Result<Vec<Self>, Error> {
+        Ok(device
+            .supported_output_configs()?
+            .filter(|config| config.sample_format() == SampleFormat::F32)
+            .map(|config| AudioConfigRange {
+                channels: config.channels(),
+                min_sample_rate: config.min_sample_rate(),
+                max_sample_rate: config.max_sample_rate(),
+                buffer_size: match config.buffer_size() {
+                    SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+                    SupportedBufferSize::Unknown => None,
+                },
+            })
+            .collect())
+    }
+
+    pub fn supports(&self, settings: &AudioDeviceSettings) -> bool {
+        let buffer_size_ok = match (settings.buffer_size, self.buffer_size) {
+            (Some(size), Some((min, max))) => (min..=max).contains(&size),
+            _ => true,
+        };
+
+        self.channels == settings.channels
+            && (self.min_sample_rate..=self.max_sample_rate).contains(&settings.sample_rate)
+            && buffer_size_ok
+    }
+}
+
+/// Lists every available cpal host along with its output devices. Hosts or
+/// devices that fail to enumerate are skipped.
+pub fn list_audio_hosts() -> Vec<AudioHostInfo> {
+    cpal::available_hosts()
+        .into_iter()
+        .filter_map(|id| {
+            let host = cpal::host_from_id(id).ok()?;
+            let devices = host
+                .output_devices()
+                .map(|devices| {
+                    devices
+                        .filter_map(|device| {
+                            Some(AudioDeviceInfo {
+                                name: device_name(&device)?,
+                                configs: AudioConfigRange::from_device(&device).ok()?,
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            Some(AudioHostInfo {
+                name: id.name().to_owned(),
+                devices,
+            })
+        })
+        .collect()
+}
+
+fn device_name(device: &Device) -> Option<String> {
+    device
+        .description()
+        .ok()
+        .map(|description| description.name().to_owned())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use super::*;
+
+fn stereo_range(buffer_size: Option<(u32, u32)>) -> AudioConfigRange {
+    AudioConfigRange {
+        channels: 2,
+        min_sample_rate: 44_100,
+        max_sample_rate: 96_000,
+        buffer_size,
+    }
+}
+
+#[test]
+fn config_range_supports_settings_within_it() {
+    let range = stereo_range(Some((64, 1024)));
+
+    assert!(range.supports(&AudioDeviceSettings::default()));
+    assert!(range.supports(&AudioDeviceSettings {
+        sample_rate: 96_000,
+        buffer_size: Some(64),
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn config_range_rejects_settings_outside_it() {
+    let range = stereo_range(Some((64, 1024)));
+
+    assert!(!range.supports(&AudioDeviceSettings {
+        channels: 1,
+        ..Default::default()
+    }));
+    assert!(!range.supports(&AudioDeviceSettings {
+        sample_rate: 192_000,
+        ..Default::default()
+    }));
+    assert!(!range.supports(&AudioDeviceSettings {
+        buffer_size: Some(2048),
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn config_range_without_buffer_sizes_supports_any() {
+    let range = stereo_range(None);
+
+    assert!(range.supports(&AudioDeviceSettings {
+        buffer_size: Some(4096),
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn settings_round_trip_through_json() {
+    let dir = std::env::temp_dir().join(format!("audio-settings-{}", std::process::id()));
+    let path = dir.join("audio-settings.json");
+    let settings = AudioDeviceSettings {
+        host: Some("ALSA".to_owned()),
+        device: Some("Speakers".to_owned()),
+        channels: 4,
+        sample_rate: 44_100,
+        buffer_size: Some(256),
+    };
+
+    settings.save_to(&path).unwrap();
+    assert_eq!(AudioDeviceSettings::load_from(&path), settings);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn missing_or_invalid_settings_load_as_defaults() {
+    let dir = std::env::temp_dir().join(format!("audio-settings-invalid-{}", std::process::id()));
+    let path = dir.join("audio-settings.json");
+    assert_eq!(
+        AudioDeviceSettings::load_from(&path),
+        AudioDeviceSettings::default()
+    );
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&path, "not json").unwrap();
+    assert_eq!(
+        AudioDeviceSettings::load_from(&path),
+        AudioDeviceSettings::default()
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
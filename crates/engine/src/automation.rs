@@ -0,0 +1,75 @@
+/// How the value moves from one point to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EnvelopeCurve {
+    #[default]
+    Linear,
+    /// Holds the value until the next point.
+    Step,
+    /// Eases in and out of the next point.
+    Smooth,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvelopePoint {
+    /// In beats.
+    pub position: f64,
+    pub value: f32,
+    /// The shape of the segment from this point to the next.
+    pub curve: EnvelopeCurve,
+}
+
+/// A value that changes over the timeline, evaluated on the audio thread.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AutomationEnvelope {
+    /// Sorted by position.
+    points: Vec<EnvelopePoint>,
+}
+
+impl AutomationEnvelope {
+    pub fn new(mut points: Vec<EnvelopePoint>) -> Self {
+        points.sort_by(|a, b| a.position.total_cmp(&b.position));
+        Self { points }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The value at `position`. Before the first point and after the last the
+    /// value of that point is held.
+    pub fn value_at(&self, position: f64) -> Option<f32> {
+        self.evaluate(position).map(|(_, value)| value)
+    }
+
+    /// The position of the first point after `position`, if there is one.
+    pub fn next_point(&self, position: f64) -> Option<f64> {
+        let next = self.points.partition_point(|p| p.position <= position);
+        self.points.get(next).map(|point| point.position)
+    }
+
+    /// Like `value_at`, but also returns the index of the segment `position`
+    /// falls in, so callers can tell when a point has been crossed.
+    pub(crate) fn evaluate(&self, position: f64) -> Option<(usize, f32)> {
+        let next = self.points.partition_point(|p| p.position <= position);
+
+        let value = match (
+            next.checked_sub(1).map(|i| &self.points[i]),
+            self.points.get(next),
+        ) {
+            (None, None) => return None,
+            (None, Some(first)) => first.value,
+            (Some(last), None) => last.value,
+            (Some(from), Some(to)) => {
+                let t = ((position - from.position) / (to.position - from.position)) as f32;
+                let t = match from.curve {
+                    EnvelopeCurve::Linear => t,
+                    EnvelopeCurve::Step => 0.0,
+                    EnvelopeCurve::Smooth => t * t * (3.0 - 2.0 * t),
+                };
+                from.value + (to.value - from.value) * t
+            }
+        };
+
+        Some((next, value))
+    }
+}
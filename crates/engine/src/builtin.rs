@@ -1,8 +1,17 @@
 mod gain;
 mod midi_input;
+mod midi_recorder;
 mod peak;
+mod sequencer;
+mod smoothed;
 mod summer;
 
-pub use gain::GainNodeOwner;
+pub use gain::{GainAutomation, GainNodeOwner, PanLaw};
 pub use midi_input::MidiInputOwner;
+pub use midi_recorder::{MidiRecorderEvent, MidiRecorderOwner};
+pub use sequencer::{SequencerNote, SequencerOwner};
+pub use smoothed::SmoothedValue;
 pub use summer::SummerOwner;
+
+#[cfg(test)]
+mod tests;
@@ -1,59 +1,185 @@
 use audio_blocks::AudioBlock;
 use bevy_ecs::prelude::*;
-use crossbeam::channel::{self, Receiver, Sender};
+use crossbeam::channel::{Receiver, Sender};
 
 use audio_graph::{
-    GraphConnection, GraphNodeDesc, GraphProcessContext, GraphProcessor, GraphStateValue,
+    GraphChannelLayout, GraphConnection, GraphController, GraphNodeDesc, GraphProcessContext,
+    GraphProcessor, GraphStateValue,
 };
 
-use crate::builtin::peak::PeakMeter;
+use crate::{
+    automation::AutomationEnvelope,
+    builtin::{peak::PeakMeter, smoothed::SmoothedValue},
+    messages::message_channel,
+    retired::{Retire, Retired, retired_channel},
+};
+
+/// Envelopes that override a gain node's settings while they have points.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GainAutomation {
+    pub gain: AutomationEnvelope,
+    /// Values of 0.5 and above mute.
+    pub mute: AutomationEnvelope,
+    /// -1 is hard left, 1 is hard right.
+    pub pan: AutomationEnvelope,
+}
+
+/// How panning splits a channel between the left and right outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanLaw {
+    /// The opposite side is turned down while the side being panned towards
+    /// stays at unity.
+    #[default]
+    Balance,
+    /// Constant power, -3 dB at the centre.
+    ConstantPower,
+    /// Halfway between constant power and linear, -4.5 dB at the centre.
+    Compromise,
+    /// Constant amplitude, -6 dB at the centre.
+    Linear,
+}
+
+impl PanLaw {
+    /// The left and right gains for `pan`, where -1 is hard left and 1 is
+    /// hard right.
+    pub fn gains(self, pan: f32) -> [f32; 2] {
+        let pan = pan.clamp(-1.0, 1.0);
+        let x = (pan + 1.0) * 0.5;
+        let angle = x * std::f32::consts::FRAC_PI_2;
+
+        match self {
+            PanLaw::Balance => [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)],
+            PanLaw::ConstantPower => [angle.cos(), angle.sin()],
+            PanLaw::Compromise => [((1.0 - x) * angle.cos()).sqrt(), (x * angle.sin()).sqrt()],
+            PanLaw::Linear => [1.0 - x, x],
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GainMessage {
+    Gain(f32),
+    Muted(bool),
+    Pan(f32, PanLaw),
+    Automation(GainAutomation),
+}
 
 #[derive(Debug)]
 pub struct GainNodeOwner {
     pub entity: Entity,
-    sender: Sender<f32>,
+    sender: Sender<GainMessage>,
+    retired: Retired<GainAutomation>,
+    /// The settings the processor has been sent, so that they're only sent
+    /// again when they change.
+    sent_gain: f32,
+    sent_muted: bool,
+    sent_pan: (f32, PanLaw),
 }
 
 impl GainNodeOwner {
-    pub fn new(commands: &mut Commands, initial_gain: f32) -> Self {
-        let (sender, receiver) = channel::unbounded();
+    /// Spawns a gain node with inputs and outputs in `layout`. Panning moves
+    /// audio between the first two channels, which are the front left and
+    /// right of the speaker layouts.
+    pub fn new(commands: &mut Commands, initial_gain: f32, layout: GraphChannelLayout) -> Self {
+        let (sender, receiver) = message_channel();
+        let (retire, retired) = retired_channel();
 
-        let entity = commands.spawn(GraphNodeDesc::default().audio(2, 2)).id();
+        let entity = commands
+            .spawn(GraphNodeDesc::default().audio_layout(layout, layout))
+            .id();
 
         commands.queue(move |world: &mut World| {
+            let max_block_frames = world.non_send::<GraphController>().max_block_frames();
             audio_graph::graph_set_processor(
                 world,
                 entity,
                 Box::new(GainProcessor {
                     receiver,
+                    retire,
                     gain: initial_gain,
-                    vu_meters: Default::default(),
+                    muted: false,
+                    pan: 0.0,
+                    pan_law: PanLaw::default(),
+                    automation: GainAutomation::default(),
+                    smoothed_gain: SmoothedValue::new(initial_gain),
+                    smoothed_mute: SmoothedValue::new(1.0),
+                    smoothed_pan: SmoothedValue::new(0.0),
+                    frame_gains: Vec::with_capacity(max_block_frames),
+                    frame_pans: Vec::with_capacity(max_block_frames),
+                    vu_meters: (0..layout.num_channels())
+                        .map(|_| PeakMeter::default())
+                        .collect(),
                 }),
             );
         });
 
-        GainNodeOwner { entity, sender }
+        GainNodeOwner {
+            entity,
+            sender,
+            retired,
+            sent_gain: initial_gain,
+            sent_muted: false,
+            sent_pan: (0.0, PanLaw::default()),
+        }
+    }
+
+    /// Does nothing if the gain hasn't changed. A change that doesn't fit in
+    /// the processor's queue is sent the next time this is called.
+    pub fn set_gain(&mut self, gain: f32) {
+        if gain != self.sent_gain && self.sender.try_send(GainMessage::Gain(gain)).is_ok() {
+            self.sent_gain = gain;
+        }
     }
 
-    pub fn set_gain(&self, gain: f32) {
-        self.sender.send(gain).unwrap();
+    /// Like `set_gain`, only sent when it changes.
+    pub fn set_muted(&mut self, muted: bool) {
+        if muted != self.sent_muted && self.sender.try_send(GainMessage::Muted(muted)).is_ok() {
+            self.sent_muted = muted;
+        }
+    }
+
+    /// Sets the static pan position, which pan automation overrides. Like
+    /// `set_gain`, only sent when it changes.
+    pub fn set_pan(&mut self, pan: f32, law: PanLaw) {
+        if (pan, law) != self.sent_pan && self.sender.try_send(GainMessage::Pan(pan, law)).is_ok() {
+            self.sent_pan = (pan, law);
+        }
+    }
+
+    pub fn set_automation(&self, automation: GainAutomation) {
+        self.retired.free();
+        let _ = self.sender.try_send(GainMessage::Automation(automation));
     }
 }
 
 #[derive(Debug)]
 struct GainProcessor {
-    receiver: Receiver<f32>,
+    receiver: Receiver<GainMessage>,
+    /// Automation that has been replaced, to be freed by the owner.
+    retire: Retire<GainAutomation>,
     gain: f32,
+    muted: bool,
+    pan: f32,
+    pan_law: PanLaw,
+    automation: GainAutomation,
+    /// The settings above, ramped to avoid clicks, or their automation.
+    smoothed_gain: SmoothedValue,
+    /// 1 while audible and 0 while muted, so that muting fades out.
+    smoothed_mute: SmoothedValue,
+    smoothed_pan: SmoothedValue,
+    /// The gain of each frame in the block, before panning. Has room for the
+    /// longest block.
+    frame_gains: Vec<f32>,
+    /// The left and right pan gains of each frame in the block.
+    frame_pans: Vec<[f32; 2]>,
+    /// One for each output channel.
     vu_meters: Vec<PeakMeter>,
 }
 
 impl GraphProcessor for GainProcessor {
     fn process(&mut self, ctx: GraphProcessContext) {
         self.process_messages();
-
-        let num_channels = ctx.out_audio_buffers.num_channels();
-        self.vu_meters
-            .resize_with(num_channels as usize, Default::default);
+        self.update_frame_gains(&ctx);
 
         for ((output_channel, output_buffer), vu_meter) in ctx
             .out_audio_buffers
@@ -67,17 +193,23 @@ impl GraphProcessor for GainProcessor {
                 channel,
                 src,
                 src_channel,
+                gain,
+                ..
             } in &ctx.node.desc.audio_channels.connections
             {
                 if *channel == output_channel as u16 {
-                    let Some(input_node) = ctx.graph.get_node(*src) else {
+                    let Some(input_buffers) = ctx.graph.input_audio_buffers(ctx.node, *src) else {
                         continue;
                     };
-                    let input_buffers = input_node.output_audio_buffers.get();
                     let input_buffer = input_buffers.channel(*src_channel);
 
-                    for (input, output) in input_buffer.iter().zip(output_buffer.iter_mut()) {
-                        *output += *input * self.gain;
+                    for (frame, (input, output)) in input_buffer
+                        .iter()
+                        .zip(output_buffer.iter_mut())
+                        .enumerate()
+                    {
+                        let pan = self.frame_pans[frame].get(output_channel).unwrap_or(&1.0);
+                        *output += *input * gain * self.frame_gains[frame] * pan;
                     }
                 }
             }
@@ -92,12 +224,77 @@ impl GraphProcessor for GainProcessor {
         };
         ctx.state.insert(ctx.node.entity, value);
     }
+
+    fn configure(&mut self, _sample_rate: u32, max_block_frames: usize) {
+        self.frame_gains.clear();
+        self.frame_gains.reserve(max_block_frames);
+        self.frame_pans.clear();
+        self.frame_pans.reserve(max_block_frames);
+    }
 }
 
 impl GainProcessor {
     fn process_messages(&mut self) {
-        while let Ok(gain) = self.receiver.try_recv() {
-            self.gain = gain;
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                GainMessage::Gain(gain) => self.gain = gain,
+                GainMessage::Muted(muted) => self.muted = muted,
+                GainMessage::Pan(pan, law) => {
+                    self.pan = pan;
+                    self.pan_law = law;
+                }
+                GainMessage::Automation(automation) => {
+                    let old = std::mem::replace(&mut self.automation, automation);
+                    self.retire.retire(old);
+                }
+            }
+        }
+    }
+
+    fn update_frame_gains(&mut self, ctx: &GraphProcessContext) {
+        let GainAutomation { gain, mute, pan } = &self.automation;
+        let automated = !(gain.is_empty() && mute.is_empty() && pan.is_empty());
+        let sample_rate = ctx.sample_rate;
+
+        self.frame_gains.clear();
+        self.frame_pans.clear();
+
+        let mut last_pan = None;
+        for frame in 0..ctx.num_frames {
+            let position = automated.then(|| ctx.transport.position_at(frame, sample_rate));
+            let value_at =
+                |envelope: &AutomationEnvelope| position.and_then(|p| envelope.value_at(p));
+
+            // Automation is followed exactly, so that steps in it stay steps.
+            // Only the manual settings are ramped.
+            match (self.muted, value_at(mute)) {
+                (true, _) => self.smoothed_mute.set_target(0.0, sample_rate),
+                (false, Some(muted)) => {
+                    self.smoothed_mute
+                        .set_immediate(if muted >= 0.5 { 0.0 } else { 1.0 })
+                }
+                (false, None) => self.smoothed_mute.set_target(1.0, sample_rate),
+            }
+            match value_at(gain) {
+                Some(gain) => self.smoothed_gain.set_immediate(gain),
+                None => self.smoothed_gain.set_target(self.gain, sample_rate),
+            }
+            match value_at(pan) {
+                Some(pan) => self.smoothed_pan.set_immediate(pan),
+                None => self.smoothed_pan.set_target(self.pan, sample_rate),
+            }
+
+            self.frame_gains
+                .push(self.smoothed_gain.next_frame() * self.smoothed_mute.next_frame());
+
+            // Only recalculate the pan law when the pan moves
+            let pan = self.smoothed_pan.next_frame();
+            let pan_gains = match last_pan {
+                Some((last, gains)) if last == pan => gains,
+                _ => self.pan_law.gains(pan),
+            };
+            last_pan = Some((pan, pan_gains));
+            self.frame_pans.push(pan_gains);
         }
     }
 }
@@ -0,0 +1,149 @@
+use std::sync::Mutex;
+
+use bevy_ecs::prelude::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+use wmidi::MidiMessage;
+
+use audio_graph::{
+    GraphConnection, GraphLoopRegion, GraphNodeDesc, GraphProcessContext, GraphProcessor,
+    GraphTransportInfo,
+};
+
+/// Room for a lot of playing between two frames on the main thread, and for
+/// every key being released at once when recording stops.
+const EVENT_CAPACITY: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiRecorderEvent {
+    /// A note played while recording. Positions are in beats on the timeline.
+    Note {
+        key: u8,
+        velocity: u8,
+        start: f64,
+        end: f64,
+    },
+    /// Recording has stopped; every note from the take has been sent.
+    Stopped,
+}
+
+/// A node that records the notes arriving on its event input while the
+/// transport is recording.
+#[derive(Debug)]
+pub struct MidiRecorderOwner {
+    pub entity: Entity,
+    /// In a mutex only so that the owner can be a component. It's never
+    /// locked, as receiving needs `&mut self`.
+    receiver: Mutex<Consumer<MidiRecorderEvent>>,
+}
+
+impl MidiRecorderOwner {
+    pub fn new(commands: &mut Commands) -> Self {
+        let (sender, receiver) = RingBuffer::new(EVENT_CAPACITY);
+
+        // Nothing depends on the recorder's output, so it has to be told to run
+        let entity = commands
+            .spawn(GraphNodeDesc::default().event(1, 0).always_run())
+            .id();
+
+        commands.queue(move |world: &mut World| {
+            audio_graph::graph_set_processor(
+                world,
+                entity,
+                Box::new(MidiRecorderProcessor {
+                    sender,
+                    held_notes: [None; 128],
+                    was_recording: false,
+                }),
+            );
+        });
+
+        MidiRecorderOwner {
+            entity,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    pub fn receive(&mut self) -> impl Iterator<Item = MidiRecorderEvent> + '_ {
+        let receiver = self.receiver.get_mut().unwrap();
+        std::iter::from_fn(|| receiver.pop().ok())
+    }
+}
+
+#[derive(Debug)]
+struct MidiRecorderProcessor {
+    sender: Producer<MidiRecorderEvent>,
+    /// Start position and velocity of each key that's currently down.
+    held_notes: [Option<(f64, u8)>; 128],
+    was_recording: bool,
+}
+
+impl GraphProcessor for MidiRecorderProcessor {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        let transport = ctx.transport;
+        let recording = transport.playing && transport.recording;
+
+        if !recording {
+            if self.was_recording {
+                self.stop(transport);
+            }
+            return;
+        }
+        self.was_recording = true;
+
+        for GraphConnection {
+            src, src_channel, ..
+        } in &ctx.node.desc.event_channels.connections
+        {
+            let Some(node) = ctx.graph.get_node(*src) else {
+                continue;
+            };
+
+            for event in &node.output_event_buffers.get()[*src_channel as usize] {
+                let seconds = event.timestamp.saturating_sub(*ctx.timestamp).as_secs_f64();
+                let frame = (seconds * ctx.sample_rate as f64) as usize;
+                let position = transport.position_at(frame, ctx.sample_rate);
+
+                match event.midi {
+                    MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                        self.release(transport, note as u8, position);
+                        self.held_notes[note as usize] = Some((position, u8::from(velocity)));
+                    }
+                    MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                        self.release(transport, note as u8, position);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl MidiRecorderProcessor {
+    fn release(&mut self, transport: &GraphTransportInfo, key: u8, position: f64) {
+        let Some((start, velocity)) = self.held_notes[key as usize].take() else {
+            return;
+        };
+
+        // A note held while playback jumped back to the start of the loop
+        // finishes at the end of the loop.
+        let end = match transport.loop_region {
+            Some(GraphLoopRegion { end, .. }) if position < start => end,
+            _ => position,
+        };
+
+        let _ = self.sender.push(MidiRecorderEvent::Note {
+            key,
+            velocity,
+            start,
+            end,
+        });
+    }
+
+    fn stop(&mut self, transport: &GraphTransportInfo) {
+        for key in 0..128 {
+            self.release(transport, key, transport.position);
+        }
+        let _ = self.sender.push(MidiRecorderEvent::Stopped);
+        self.was_recording = false;
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use bevy_ecs::prelude::*;
+use crossbeam::channel::{Receiver, Sender};
+use wmidi::{Channel, MidiMessage, Note, U7};
+
+use audio_graph::{
+    GraphEvent, GraphLoopRegion, GraphNodeDesc, GraphProcessContext, GraphProcessor,
+    GraphTransportInfo,
+};
+
+use crate::{
+    messages::message_channel,
+    retired::{Retire, Retired, retired_channel},
+};
+
+/// One for every MIDI key. More notes than this can only be playing at once
+/// if they overlap on the same key, and the extra ones aren't started.
+const MAX_PLAYING_NOTES: usize = 128;
+
+/// A note played by a `SequencerOwner`. Positions are in beats on the
+/// timeline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SequencerNote {
+    pub key: u8,
+    pub velocity: u8,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A node that plays a list of notes in time with the transport, sending them
+/// out of its single event output.
+#[derive(Debug)]
+pub struct SequencerOwner {
+    pub entity: Entity,
+    sender: Sender<Vec<SequencerNote>>,
+    retired: Retired<Vec<SequencerNote>>,
+}
+
+impl SequencerOwner {
+    pub fn new(commands: &mut Commands) -> Self {
+        let (sender, receiver) = message_channel();
+        let (retire, retired) = retired_channel();
+
+        let entity = commands.spawn(GraphNodeDesc::default().event(0, 1)).id();
+
+        commands.queue(move |world: &mut World| {
+            audio_graph::graph_set_processor(
+                world,
+                entity,
+                Box::new(SequencerProcessor {
+                    receiver,
+                    retire,
+                    notes: Vec::new(),
+                    playing_notes: Vec::with_capacity(MAX_PLAYING_NOTES),
+                    next_position: None,
+                }),
+            );
+        });
+
+        SequencerOwner {
+            entity,
+            sender,
+            retired,
+        }
+    }
+
+    /// Replaces all the notes the sequencer plays.
+    pub fn set_notes(&self, mut notes: Vec<SequencerNote>) {
+        self.retired.free();
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        let _ = self.sender.try_send(notes);
+    }
+}
+
+#[derive(Debug)]
+struct SequencerProcessor {
+    receiver: Receiver<Vec<SequencerNote>>,
+    /// Notes that have been replaced, to be freed by the owner.
+    retire: Retire<Vec<SequencerNote>>,
+    /// Sorted by start.
+    notes: Vec<SequencerNote>,
+    /// Notes that have been started but not stopped, with the beat they end on.
+    playing_notes: Vec<(u8, f64)>,
+    /// Where the next block should start if the transport keeps playing
+    /// without being moved.
+    next_position: Option<f64>,
+}
+
+impl GraphProcessor for SequencerProcessor {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        self.process_messages();
+
+        let transport = ctx.transport;
+        let events = &mut ctx.out_event_buffers[0];
+        let timing = BlockTiming {
+            timestamp: *ctx.timestamp,
+            sample_rate: ctx.sample_rate,
+            beats_per_frame: transport.beats_per_frame(ctx.sample_rate),
+        };
+
+        // Anything playing was started from somewhere else on the timeline if
+        // playback has stopped or the position was moved.
+        if !transport.playing || self.next_position != Some(transport.position) {
+            self.stop_all(timing.timestamp, events);
+        }
+
+        if !transport.playing {
+            self.next_position = None;
+            return;
+        }
+
+        let start = transport.position;
+        let end = start + ctx.num_frames as f64 * timing.beats_per_frame;
+
+        match transport.loop_region {
+            Some(GraphLoopRegion {
+                start: loop_start,
+                end: loop_end,
+            }) if loop_end > loop_start && start < loop_end && end >= loop_end => {
+                let wrap_frame = (loop_end - start) / timing.beats_per_frame;
+                self.play_range(start, loop_end, 0.0, &timing, events);
+                self.stop_all(timing.timestamp(wrap_frame), events);
+                self.play_range(
+                    loop_start,
+                    loop_start + (end - loop_end),
+                    wrap_frame,
+                    &timing,
+                    events,
+                );
+            }
+            _ => self.play_range(start, end, 0.0, &timing, events),
+        }
+
+        sort_by_timestamp(events);
+
+        self.next_position = Some(transport.position_at(ctx.num_frames, ctx.sample_rate));
+    }
+
+    /// The frame before the next note starts or stops, or the loop wraps. A
+    /// block split there begins with the event, give or take a frame.
+    fn next_boundary(
+        &self,
+        transport: &GraphTransportInfo,
+        sample_rate: u32,
+        _num_frames: usize,
+    ) -> Option<usize> {
+        if !transport.playing {
+            return None;
+        }
+
+        let start = transport.position;
+        let beats_per_frame = transport.beats_per_frame(sample_rate);
+        let first = self.notes.partition_point(|note| note.start <= start);
+        let next_start = self.notes.get(first).map(|note| note.start);
+        let ends = self.playing_notes.iter().map(|(_, end)| *end);
+        let loop_end = transport.loop_region.map(|region| region.end);
+
+        next_start
+            .into_iter()
+            .chain(ends)
+            .chain(loop_end)
+            .filter(|beat| *beat > start)
+            .map(|beat| ((beat - start) / beats_per_frame).floor() as usize)
+            .min()
+    }
+}
+
+impl SequencerProcessor {
+    fn process_messages(&mut self) {
+        while let Ok(notes) = self.receiver.try_recv() {
+            let old = std::mem::replace(&mut self.notes, notes);
+            self.retire.retire(old);
+        }
+    }
+
+    /// Sends the events for the beats `from..to`, which start `first_frame`
+    /// frames into the block.
+    fn play_range(
+        &mut self,
+        from: f64,
+        to: f64,
+        first_frame: f64,
+        timing: &BlockTiming,
+        events: &mut Vec<GraphEvent>,
+    ) {
+        let timestamp = |beat: f64| {
+            timing.timestamp(first_frame + (beat.max(from) - from) / timing.beats_per_frame)
+        };
+
+        // Stop notes first so that a note ending where another with the same
+        // key starts is retriggered.
+        self.playing_notes.retain(|&(key, end)| {
+            if end < to {
+                push_event(events, note_off(key, timestamp(end)));
+                false
+            } else {
+                true
+            }
+        });
+
+        let first = self.notes.partition_point(|note| note.start < from);
+        for note in self.notes[first..]
+            .iter()
+            .take_while(|note| note.start < to)
+        {
+            let ends_in_range = note.end < to;
+            if !ends_in_range && self.playing_notes.len() == self.playing_notes.capacity() {
+                continue;
+            }
+
+            push_event(
+                events,
+                note_on(note.key, note.velocity, timestamp(note.start)),
+            );
+            if ends_in_range {
+                push_event(events, note_off(note.key, timestamp(note.end)));
+            } else {
+                self.playing_notes.push((note.key, note.end));
+            }
+        }
+    }
+
+    fn stop_all(&mut self, timestamp: Duration, events: &mut Vec<GraphEvent>) {
+        for (key, _) in self.playing_notes.drain(..) {
+            push_event(events, note_off(key, timestamp));
+        }
+    }
+}
+
+/// Drops the event if `events` is full, rather than growing it.
+fn push_event(events: &mut Vec<GraphEvent>, event: GraphEvent) {
+    if events.len() < events.capacity() {
+        events.push(event);
+    }
+}
+
+/// A stable insertion sort, as the standard library's stable sort can
+/// allocate. The events are mostly in order already.
+fn sort_by_timestamp(events: &mut [GraphEvent]) {
+    for index in 1..events.len() {
+        let mut index = index;
+        while index > 0 && events[index - 1].timestamp > events[index].timestamp {
+            events.swap(index - 1, index);
+            index -= 1;
+        }
+    }
+}
+
+struct BlockTiming {
+    timestamp: Duration,
+    sample_rate: u32,
+    beats_per_frame: f64,
+}
+
+impl BlockTiming {
+    /// Rounds up so that converting back to frames lands on the same frame.
+    fn timestamp(&self, frame: f64) -> Duration {
+        let nanoseconds = (frame * 1_000_000_000.0 / self.sample_rate as f64).ceil();
+        self.timestamp + Duration::from_nanos(nanoseconds as u64)
+    }
+}
+
+fn note_on(key: u8, velocity: u8, timestamp: Duration) -> GraphEvent {
+    GraphEvent {
+        timestamp,
+        midi: MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::from_u8_lossy(key),
+            U7::from_u8_lossy(velocity),
+        ),
+    }
+}
+
+fn note_off(key: u8, timestamp: Duration) -> GraphEvent {
+    GraphEvent {
+        timestamp,
+        midi: MidiMessage::NoteOff(Channel::Ch1, Note::from_u8_lossy(key), U7::from_u8_lossy(0)),
+    }
+}
@@ -0,0 +1,81 @@
+/// A parameter that ramps linearly to a new target over a fixed time instead
+/// of jumping to it, so that changes don't click or zipper.
+#[derive(Clone, Debug)]
+pub struct SmoothedValue {
+    current: f32,
+    target: f32,
+    step: f32,
+    remaining: u32,
+    ramp_time: f32,
+}
+
+impl SmoothedValue {
+    /// 10 ms is long enough to hide a step and short enough to feel instant.
+    pub const DEFAULT_RAMP_TIME: f32 = 0.01;
+
+    pub fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+            ramp_time: Self::DEFAULT_RAMP_TIME,
+        }
+    }
+
+    /// Sets how long a ramp to a new target takes, in seconds.
+    pub fn with_ramp_time(self, ramp_time: f32) -> Self {
+        Self { ramp_time, ..self }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Starts a ramp from the current value to `target`. Setting the target
+    /// that is already being ramped to leaves the ramp alone, so this can be
+    /// called every frame.
+    pub fn set_target(&mut self, target: f32, sample_rate: u32) {
+        if target == self.target {
+            return;
+        }
+
+        let ramp_frames = (self.ramp_time * sample_rate as f32).round();
+        if ramp_frames < 1.0 {
+            self.set_immediate(target);
+            return;
+        }
+
+        self.target = target;
+        self.remaining = ramp_frames as u32;
+        self.step = (target - self.current) / ramp_frames;
+    }
+
+    /// Jumps straight to `value`, abandoning any ramp in progress.
+    pub fn set_immediate(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    /// Advances the ramp by one frame and returns the new value.
+    pub fn next_frame(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.current = if self.remaining == 0 {
+                self.target
+            } else {
+                self.current + self.step
+            };
+        }
+        self.current
+    }
+}
@@ -1,20 +1,37 @@
 use bevy_ecs::prelude::*;
 
-use audio_graph::{GraphNodeDesc, GraphProcessContext, GraphProcessor};
+use audio_graph::{GraphChannelLayout, GraphNodeDesc, GraphProcessContext, GraphProcessor};
 
+#[derive(Debug)]
 pub struct SummerOwner {
     pub entity: Entity,
+    /// The layout of both the inputs and the outputs.
+    pub layout: GraphChannelLayout,
 }
 
 impl SummerOwner {
-    pub fn new(world: &mut World, num_channels: u16) -> Self {
+    pub fn new(world: &mut World, layout: GraphChannelLayout) -> Self {
         let entity = world
-            .spawn(GraphNodeDesc::default().audio(num_channels, num_channels))
+            .spawn(GraphNodeDesc::default().audio_layout(layout, layout))
             .id();
 
         audio_graph::graph_set_processor(world, entity, Box::new(SummerProcessor));
 
-        Self { entity }
+        Self { entity, layout }
+    }
+
+    /// Like `new`, but for use from systems that can only queue changes to
+    /// the world.
+    pub fn spawn(commands: &mut Commands, layout: GraphChannelLayout) -> Self {
+        let entity = commands
+            .spawn(GraphNodeDesc::default().audio_layout(layout, layout))
+            .id();
+
+        commands.queue(move |world: &mut World| {
+            audio_graph::graph_set_processor(world, entity, Box::new(SummerProcessor));
+        });
+
+        Self { entity, layout }
     }
 }
 
@@ -34,14 +51,13 @@ impl GraphProcessor for SummerProcessor {
                 .filter(|c| c.channel == output_channel as u16);
 
             for input in inputs {
-                let Some(input_node) = ctx.graph.get_node(input.src) else {
+                let Some(input_buffers) = ctx.graph.input_audio_buffers(ctx.node, input.src) else {
                     continue;
                 };
-                let input_buffers = input_node.output_audio_buffers.get();
                 let input_buffer = &input_buffers.channel(input.src_channel);
 
-                for (input, output) in input_buffer.iter().zip(output_buffer.iter_mut()) {
-                    *output += *input;
+                for (sample, output) in input_buffer.iter().zip(output_buffer.iter_mut()) {
+                    *output += *sample * input.gain;
                 }
             }
         }
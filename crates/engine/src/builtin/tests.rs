@@ -0,0 +1,482 @@
+use std::time::Duration;
+
+use audio_graph::{
+    GraphChannelLayout, GraphConnection, GraphEvent, GraphLoopRegion, GraphNodeDesc,
+    GraphOutputNode, GraphPlugin, GraphProcessContext, GraphProcessor, GraphSeekEvent,
+    GraphTransport, GraphWorker,
+};
+use bevy_app::App;
+use bevy_ecs::prelude::*;
+use wmidi::{Channel, MidiMessage, Note, U7};
+
+use super::*;
+use crate::{
+    automation::{AutomationEnvelope, EnvelopeCurve, EnvelopePoint},
+    test_util::Constant,
+};
+
+// Fails any test that allocates on the audio thread
+#[global_allocator]
+static ALLOCATOR: audio_graph::rt_guard::GuardedAllocator = audio_graph::rt_guard::GuardedAllocator;
+
+const SAMPLE_RATE: u32 = 48000;
+const BLOCK_SIZE: usize = 256;
+
+/// A constant signal running through a gain node into the graph output.
+fn gain_test_graph() -> (GainNodeOwner, GraphWorker) {
+    let mut app = App::new();
+    app.add_plugins(GraphPlugin);
+
+    let world = app.world_mut();
+    let source = world.spawn(GraphNodeDesc::default().audio(0, 2)).id();
+    audio_graph::graph_set_processor(world, source, Box::new(Constant(1.0)));
+
+    let gain = GainNodeOwner::new(&mut world.commands(), 1.0, GraphChannelLayout::Stereo);
+    world.flush();
+    world.entity_mut(gain.entity).insert(GraphOutputNode);
+    for port in 0..2 {
+        audio_graph::graph_connect_audio(
+            world,
+            gain.entity,
+            GraphConnection::new(port, source, port),
+        )
+        .unwrap();
+    }
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(2, SAMPLE_RATE, BLOCK_SIZE);
+
+    (gain, worker)
+}
+
+/// Renders `num_blocks` blocks and returns the left channel.
+fn render(worker: &mut GraphWorker, num_blocks: usize) -> Vec<f32> {
+    let mut left = Vec::new();
+    for _ in 0..num_blocks {
+        let mut data = [0.0; BLOCK_SIZE * 2];
+        worker.tick(&mut data, Duration::default());
+        left.extend(data.iter().step_by(2));
+    }
+    left
+}
+
+fn largest_step(samples: &[f32]) -> f32 {
+    samples
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn smoothed_value_ramps_to_target() {
+    let mut value = SmoothedValue::new(0.0).with_ramp_time(0.004);
+    value.set_target(1.0, 1000);
+
+    let frames: Vec<f32> = (0..6).map(|_| value.next_frame()).collect();
+
+    assert_eq!(frames, [0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+    assert!(!value.is_smoothing());
+}
+
+#[test]
+fn smoothed_value_keeps_ramp_for_same_target() {
+    let mut value = SmoothedValue::new(0.0).with_ramp_time(0.004);
+    value.set_target(1.0, 1000);
+    value.next_frame();
+    value.set_target(1.0, 1000);
+
+    assert_eq!(value.next_frame(), 0.5);
+}
+
+#[test]
+fn smoothed_value_retargets_from_current_value() {
+    let mut value = SmoothedValue::new(0.0).with_ramp_time(0.002);
+    value.set_target(1.0, 1000);
+    value.next_frame();
+    value.set_target(0.0, 1000);
+
+    assert_eq!(value.next_frame(), 0.25);
+    assert_eq!(value.next_frame(), 0.0);
+}
+
+#[test]
+fn gain_change_has_no_discontinuity() {
+    let (mut gain, mut worker) = gain_test_graph();
+
+    let mut output = render(&mut worker, 2);
+    gain.set_gain(0.0);
+    output.extend(render(&mut worker, 4));
+
+    assert_eq!(output[BLOCK_SIZE * 2 - 1], 1.0);
+    assert_eq!(*output.last().unwrap(), 0.0);
+    assert!(largest_step(&output) < 0.01);
+}
+
+#[test]
+fn mute_fades_out_and_in() {
+    let (mut gain, mut worker) = gain_test_graph();
+
+    let mut output = render(&mut worker, 1);
+    gain.set_muted(true);
+    output.extend(render(&mut worker, 3));
+    assert_eq!(*output.last().unwrap(), 0.0);
+
+    gain.set_muted(false);
+    output.extend(render(&mut worker, 3));
+    assert_eq!(*output.last().unwrap(), 1.0);
+
+    assert!(largest_step(&output) < 0.01);
+}
+
+#[test]
+fn gain_automation_is_not_smoothed() {
+    let (gain, mut worker) = gain_test_graph();
+
+    render(&mut worker, 1);
+    gain.set_automation(GainAutomation {
+        gain: AutomationEnvelope::new(vec![EnvelopePoint {
+            position: 0.0,
+            value: 0.5,
+            curve: EnvelopeCurve::Step,
+        }]),
+        ..Default::default()
+    });
+    let output = render(&mut worker, 1);
+
+    assert!(output.iter().all(|&sample| sample == 0.5));
+}
+
+#[test]
+fn many_gain_changes_are_received_without_freeing() {
+    let (mut gain, mut worker) = gain_test_graph();
+
+    // Enough messages to cross the blocks an unbounded channel would free as
+    // the audio thread reads them
+    for step in 0..100 {
+        gain.set_gain(step as f32 / 100.0);
+        gain.set_muted(step % 2 == 0);
+        render(&mut worker, 1);
+    }
+
+    gain.set_gain(0.5);
+    gain.set_muted(false);
+    let output = render(&mut worker, 4);
+    assert_eq!(*output.last().unwrap(), 0.5);
+}
+
+#[test]
+fn unchanged_gain_settings_are_not_sent_again() {
+    let (mut gain, mut worker) = gain_test_graph();
+
+    // Setting them every UI frame would fill the processor's queue if they
+    // were all sent
+    for _ in 0..1000 {
+        gain.set_gain(1.0);
+        gain.set_muted(false);
+        gain.set_pan(0.0, PanLaw::Balance);
+    }
+    gain.set_gain(0.5);
+
+    let output = render(&mut worker, 4);
+    assert_eq!(*output.last().unwrap(), 0.5);
+}
+
+/// Passes on the events from its inputs without allocating.
+#[derive(Debug)]
+struct EventSink(rtrb::Producer<GraphEvent>);
+
+impl GraphProcessor for EventSink {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        for GraphConnection {
+            src, src_channel, ..
+        } in &ctx.node.desc.event_channels.connections
+        {
+            let Some(node) = ctx.graph.get_node(*src) else {
+                continue;
+            };
+            for event in &node.output_event_buffers.get()[*src_channel as usize] {
+                let _ = self.0.push(event.clone());
+            }
+        }
+    }
+}
+
+/// A sequencer playing `notes`, with its events collected by a sink. At 60bpm
+/// and a sample rate of 1 each frame is one beat, and one second.
+fn sequencer_test_graph(
+    transport: GraphTransport,
+    notes: Vec<SequencerNote>,
+) -> (App, GraphWorker, rtrb::Consumer<GraphEvent>) {
+    let mut app = App::new();
+    app.add_plugins(GraphPlugin);
+    app.insert_resource(GraphTransport {
+        tempo: 60.0,
+        ..transport
+    });
+
+    let world = app.world_mut();
+    let sequencer = SequencerOwner::new(&mut world.commands());
+    world.flush();
+    sequencer.set_notes(notes);
+
+    let (producer, consumer) = rtrb::RingBuffer::new(64);
+    let sink = world
+        .spawn((
+            GraphNodeDesc::default().event(1, 0).audio(0, 2),
+            GraphOutputNode,
+        ))
+        .id();
+    audio_graph::graph_set_processor(world, sink, Box::new(EventSink(producer)));
+    audio_graph::graph_connect_event(world, sink, GraphConnection::new(0, sequencer.entity, 0))
+        .unwrap();
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(2, 1, BLOCK_SIZE);
+
+    (app, worker, consumer)
+}
+
+/// Processes `num_frames` frames starting `start` seconds in.
+fn tick_at(worker: &mut GraphWorker, start: u64, num_frames: usize) {
+    let mut data = vec![0.0; num_frames * 2];
+    worker.tick(&mut data, Duration::from_secs(start));
+}
+
+/// Processes `num_frames` frames starting `start` seconds in, and returns the
+/// events the sequencer sent.
+fn tick_events(
+    worker: &mut GraphWorker,
+    events: &mut rtrb::Consumer<GraphEvent>,
+    start: u64,
+    num_frames: usize,
+) -> Vec<GraphEvent> {
+    tick_at(worker, start, num_frames);
+    std::iter::from_fn(|| events.pop().ok()).collect()
+}
+
+fn note(key: u8, start: f64, end: f64) -> SequencerNote {
+    SequencerNote {
+        key,
+        velocity: 100,
+        start,
+        end,
+    }
+}
+
+fn note_on(key: u8, seconds: u64) -> GraphEvent {
+    GraphEvent {
+        timestamp: Duration::from_secs(seconds),
+        midi: MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::from_u8_lossy(key),
+            U7::from_u8_lossy(100),
+        ),
+    }
+}
+
+fn note_off(key: u8, seconds: u64) -> GraphEvent {
+    GraphEvent {
+        timestamp: Duration::from_secs(seconds),
+        midi: MidiMessage::NoteOff(Channel::Ch1, Note::from_u8_lossy(key), U7::from_u8_lossy(0)),
+    }
+}
+
+#[test]
+fn sequencer_plays_notes_in_time() {
+    let transport = GraphTransport {
+        playing: true,
+        ..Default::default()
+    };
+    let (_app, mut worker, mut events) =
+        sequencer_test_graph(transport, vec![note(62, 3.0, 5.0), note(60, 1.0, 2.0)]);
+
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 0, 4),
+        [note_on(60, 1), note_off(60, 2), note_on(62, 3)]
+    );
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 4, 4),
+        [note_off(62, 5)]
+    );
+}
+
+#[test]
+fn sequencer_restarts_notes_when_the_loop_wraps() {
+    let transport = GraphTransport {
+        playing: true,
+        loop_region: Some(GraphLoopRegion {
+            start: 0.0,
+            end: 4.0,
+        }),
+        ..Default::default()
+    };
+    let (_app, mut worker, mut events) = sequencer_test_graph(transport, vec![note(60, 1.0, 6.0)]);
+
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 0, 6),
+        [note_on(60, 1), note_off(60, 4), note_on(60, 5)]
+    );
+}
+
+#[test]
+fn sequencer_plays_from_the_new_position_after_a_seek() {
+    let transport = GraphTransport {
+        playing: true,
+        ..Default::default()
+    };
+    let (mut app, mut worker, mut events) =
+        sequencer_test_graph(transport, vec![note(60, 1.0, 10.0), note(62, 21.0, 22.0)]);
+
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 0, 4),
+        [note_on(60, 1)]
+    );
+
+    app.world_mut().trigger(GraphSeekEvent(20.0));
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 4, 4),
+        [note_off(60, 4), note_on(62, 5), note_off(62, 6)]
+    );
+}
+
+#[test]
+fn sequencer_stops_notes_when_the_transport_stops() {
+    let transport = GraphTransport {
+        playing: true,
+        ..Default::default()
+    };
+    let (mut app, mut worker, mut events) =
+        sequencer_test_graph(transport, vec![note(60, 1.0, 10.0)]);
+
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 0, 4),
+        [note_on(60, 1)]
+    );
+
+    app.world_mut().resource_mut::<GraphTransport>().playing = false;
+    app.update();
+    assert_eq!(
+        tick_events(&mut worker, &mut events, 4, 4),
+        [note_off(60, 4)]
+    );
+    assert!(tick_events(&mut worker, &mut events, 8, 4).is_empty());
+}
+
+/// Sends out whatever events the test has queued up for it.
+#[derive(Debug)]
+struct EventSource(rtrb::Consumer<GraphEvent>);
+
+impl GraphProcessor for EventSource {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        while let Ok(event) = self.0.pop() {
+            ctx.out_event_buffers[0].push(event);
+        }
+    }
+}
+
+/// A recorder recording the events queued on the returned producer. At 60bpm
+/// and a sample rate of 1 each frame is one beat, and one second.
+fn recorder_test_graph(
+    transport: GraphTransport,
+) -> (
+    App,
+    GraphWorker,
+    rtrb::Producer<GraphEvent>,
+    MidiRecorderOwner,
+) {
+    let mut app = App::new();
+    app.add_plugins(GraphPlugin);
+    app.insert_resource(GraphTransport {
+        tempo: 60.0,
+        playing: true,
+        recording: true,
+        ..transport
+    });
+
+    let world = app.world_mut();
+    let (producer, consumer) = rtrb::RingBuffer::new(64);
+    let source = world.spawn(GraphNodeDesc::default().event(0, 1)).id();
+    audio_graph::graph_set_processor(world, source, Box::new(EventSource(consumer)));
+
+    let recorder = MidiRecorderOwner::new(&mut world.commands());
+    world.flush();
+    audio_graph::graph_connect_event(world, recorder.entity, GraphConnection::new(0, source, 0))
+        .unwrap();
+
+    app.update();
+
+    let mut worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    worker.configure(2, 1, BLOCK_SIZE);
+
+    (app, worker, producer, recorder)
+}
+
+fn recorded_note(key: u8, start: f64, end: f64) -> MidiRecorderEvent {
+    MidiRecorderEvent::Note {
+        key,
+        velocity: 100,
+        start,
+        end,
+    }
+}
+
+#[test]
+fn recorder_turns_events_into_notes() {
+    let (_app, mut worker, mut events, mut recorder) =
+        recorder_test_graph(GraphTransport::default());
+
+    events.push(note_on(60, 1)).unwrap();
+    events.push(note_on(62, 2)).unwrap();
+    events.push(note_off(60, 3)).unwrap();
+    tick_at(&mut worker, 0, 4);
+
+    assert_eq!(
+        recorder.receive().collect::<Vec<_>>(),
+        [recorded_note(60, 1.0, 3.0)]
+    );
+}
+
+#[test]
+fn recorder_ends_notes_held_across_a_loop_wrap_at_the_loop_end() {
+    let transport = GraphTransport {
+        loop_region: Some(GraphLoopRegion {
+            start: 0.0,
+            end: 4.0,
+        }),
+        ..Default::default()
+    };
+    let (_app, mut worker, mut events, mut recorder) = recorder_test_graph(transport);
+
+    events.push(note_on(60, 2)).unwrap();
+    tick_at(&mut worker, 0, 4);
+    events.push(note_off(60, 5)).unwrap();
+    tick_at(&mut worker, 4, 4);
+
+    assert_eq!(
+        recorder.receive().collect::<Vec<_>>(),
+        [recorded_note(60, 2.0, 4.0)]
+    );
+}
+
+#[test]
+fn recorder_ends_held_notes_when_the_transport_stops() {
+    let (mut app, mut worker, mut events, mut recorder) =
+        recorder_test_graph(GraphTransport::default());
+
+    events.push(note_on(60, 1)).unwrap();
+    tick_at(&mut worker, 0, 4);
+    assert_eq!(recorder.receive().count(), 0);
+
+    app.world_mut().resource_mut::<GraphTransport>().playing = false;
+    app.update();
+    tick_at(&mut worker, 4, 4);
+
+    assert_eq!(
+        recorder.receive().collect::<Vec<_>>(),
+        [recorded_note(60, 1.0, 4.0), MidiRecorderEvent::Stopped]
+    );
+}
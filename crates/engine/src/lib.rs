@@ -1,4 +1,10 @@
 pub mod audio;
+pub mod automation;
 pub mod builtin;
+mod messages;
 pub mod midi;
 pub mod plugins;
+pub mod render;
+mod retired;
+#[cfg(test)]
+mod test_util;
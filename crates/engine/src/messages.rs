@@ -0,0 +1,15 @@
+//! Carries updates from the main thread to a processor. The channel is
+//! bounded, as an unbounded one frees a block of its storage on the audio
+//! thread every few dozen messages.
+
+use crossbeam::channel::{self, Receiver, Sender};
+
+/// How many updates can be waiting for a processor. It takes them all at the
+/// start of every block, so this is only reached if it isn't running.
+const MESSAGE_CAPACITY: usize = 256;
+
+/// Owners `try_send` on the sender, so that a processor that isn't running
+/// can't block the main thread.
+pub(crate) fn message_channel<T>() -> (Sender<T>, Receiver<T>) {
+    channel::bounded(MESSAGE_CAPACITY)
+}
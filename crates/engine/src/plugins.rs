@@ -6,7 +6,8 @@ use std::{
     pin::Pin,
     rc::Rc,
     sync::{
-        Arc, RwLock,
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
         mpsc::{Receiver, Sender, TryRecvError, channel},
     },
     thread::JoinHandle,
@@ -16,31 +17,51 @@ use bevy_ecs::prelude::*;
 use clack_extensions::{
     audio_ports::{AudioPortInfoBuffer, PluginAudioPorts},
     gui::{GuiSize, HostGui, HostGuiImpl, PluginGui},
+    latency::{HostLatency, HostLatencyImpl, PluginLatency},
     log::{HostLog, HostLogImpl},
-    params::{HostParams, HostParamsImplMainThread, HostParamsImplShared},
+    note_ports::PluginNotePorts,
+    params::{
+        HostParams, HostParamsImplMainThread, HostParamsImplShared, ParamClearFlags,
+        ParamRescanFlags, PluginParams,
+    },
     state::{HostState, HostStateImpl, PluginState},
+    thread_pool::{HostThreadPool, HostThreadPoolImpl, PluginThreadPool},
     timer::{HostTimer, PluginTimer},
 };
 use clack_host::{
+    events::event_types::ParamValueEvent,
     host::{self, HostHandlers, HostInfo},
-    plugin::{InitializedPluginHandle, InitializingPluginHandle, PluginInstance},
-    process::{PluginAudioConfiguration, PluginAudioProcessor},
+    plugin::{
+        InitializedPluginHandle, InitializingPluginHandle, PluginInstance, PluginSharedHandle,
+    },
+    prelude::{EventBuffer, HostError, InputEvents, OutputEvents},
+    process::{PluginAudioConfiguration, PluginAudioProcessor, StoppedPluginAudioProcessor},
 };
 use derivative::Derivative;
 use futures_channel::oneshot;
 use smol::LocalExecutor;
 
-use clap_adapter::ClapProcessor;
+use clap_adapter::{ClapProcessor, ClapProcessorMessage, ParamAutomation, param_value_channel};
 
-use audio_graph::{GraphNodeDesc, GraphProcessor};
+use crate::{
+    automation::AutomationEnvelope,
+    messages::message_channel,
+    retired::{Retire, Retired, retired_channel},
+};
+use audio_graph::{
+    GraphChannelLayout, GraphNodeDesc, GraphProcessor, GraphThreadPool, sync::RwLock,
+};
 use discovery::PluginDescriptor;
+use params::read_params;
 use timers::Timers;
 use ui_host::PluginUiHost;
 
+pub use crate::plugins::params::{ClapParam, ClapParamEvent};
 pub use crate::plugins::ui_host::PluginGuiHandle;
 
 mod clap_adapter;
 pub mod discovery;
+mod params;
 mod timers;
 mod ui_host;
 
@@ -87,6 +108,17 @@ pub trait PluginManager {
 
     fn plugin_name(plugin: &Self::Plugin) -> &str;
 
+    /// Replaces the envelopes driving the plugin's parameters, keyed by
+    /// parameter id.
+    fn set_param_automation(plugin: &Self::Plugin, automation: Vec<(u32, AutomationEnvelope)>);
+
+    /// Sets a parameter to a value in its own range. The change reaches the
+    /// plugin at the start of the next block it processes.
+    fn set_param_value(plugin: &Self::Plugin, param_id: u32, value: f64);
+
+    /// Parameter changes made by the plugin since this was last called.
+    fn receive_param_events(plugin: &Self::Plugin) -> Vec<ClapParamEvent>;
+
     fn load_plugin_state(
         &self,
         clap_plugin_id: ClapId,
@@ -100,10 +132,25 @@ pub trait PluginManager {
 
     fn save_plugin_state(&self, clap_plugin_id: ClapId) -> oneshot::Receiver<Option<Vec<u8>>>;
 
+    /// Creates a processor for `plugin` that's ready to run at `sample_rate`
+    /// in blocks of up to `max_block_frames` frames.
     fn create_audio_graph_node(
         &self,
         plugin: &Self::Plugin,
+        sample_rate: u32,
+        max_block_frames: usize,
     ) -> (GraphNodeDesc, Box<dyn GraphProcessor>);
+
+    /// If `plugin` has asked to be restarted, creates a processor to replace
+    /// the one in its node. The new processor outputs silence until the old
+    /// one has been retired by the graph, which deactivates the plugin so
+    /// that it can be activated again.
+    fn restart_audio_graph_node(
+        &self,
+        plugin: &Self::Plugin,
+        sample_rate: u32,
+        max_block_frames: usize,
+    ) -> Option<Box<dyn GraphProcessor>>;
 }
 
 impl ClapManager {
@@ -132,6 +179,36 @@ impl PluginManager for ClapManager {
         &plugin.plugin_name
     }
 
+    fn set_param_automation(plugin: &ClapProxy, automation: Vec<(u32, AutomationEnvelope)>) {
+        plugin.retired_automation.free();
+
+        let automation = automation
+            .into_iter()
+            .map(|(param_id, envelope)| ParamAutomation::new(param_id, envelope))
+            .collect();
+        let _ = plugin
+            .processor_channel
+            .try_send(ClapProcessorMessage::ParamAutomation(automation));
+    }
+
+    fn set_param_value(plugin: &ClapProxy, param_id: u32, value: f64) {
+        let _ = plugin
+            .processor_channel
+            .try_send(ClapProcessorMessage::SetParamValue(param_id, value));
+    }
+
+    fn receive_param_events(plugin: &ClapProxy) -> Vec<ClapParamEvent> {
+        let values = plugin
+            .param_value_receiver
+            .try_iter()
+            .map(|(param_id, value)| ClapParamEvent::Value(param_id, value));
+        plugin
+            .param_event_receiver
+            .try_iter()
+            .chain(values)
+            .collect()
+    }
+
     fn show_gui(
         &self,
         clap_plugin_id: ClapId,
@@ -175,8 +252,34 @@ impl PluginManager for ClapManager {
     fn create_audio_graph_node(
         &self,
         plugin: &ClapProxy,
+        sample_rate: u32,
+        max_block_frames: usize,
     ) -> (GraphNodeDesc, Box<dyn GraphProcessor>) {
-        plugin.create_audio_graph_node_sync()
+        plugin.create_audio_graph_node_sync(sample_rate, max_block_frames)
+    }
+
+    fn restart_audio_graph_node(
+        &self,
+        plugin: &ClapProxy,
+        sample_rate: u32,
+        max_block_frames: usize,
+    ) -> Option<Box<dyn GraphProcessor>> {
+        if !plugin.restart_requested.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Message::RestartProcessor(
+                plugin.plugin_id,
+                sample_rate,
+                max_block_frames,
+                sender,
+            ))
+            .unwrap();
+        Some(futures::executor::block_on(async {
+            receiver.await.unwrap()
+        }))
     }
 }
 
@@ -228,6 +331,8 @@ impl PluginHostThread {
                                     this.plugins.borrow_mut().insert(id, clap_plugin.clone());
                                 assert!(old_plugin.is_none());
 
+                                clap_plugin.rescan_params();
+
                                 sender.send(clap_plugin_shared).unwrap();
                             })
                             .detach();
@@ -255,20 +360,96 @@ impl PluginHostThread {
                     Message::SetTitle(clap_plugin_id, title) => {
                         self.plugin_ui_host.set_title(clap_plugin_id, &title);
                     }
+                    Message::RescanParams(clap_plugin_id) => {
+                        self.get_plugin(clap_plugin_id).rescan_params();
+                    }
+                    Message::ClearParam(clap_plugin_id, param_id) => {
+                        self.get_plugin(clap_plugin_id)
+                            .send_param_event(ClapParamEvent::Cleared(param_id));
+                    }
+                    Message::FlushParams(clap_plugin_id) => {
+                        self.get_plugin(clap_plugin_id).flush_params();
+                    }
+                    Message::LatencyChanged(clap_plugin_id) => {
+                        self.get_plugin(clap_plugin_id).update_latency();
+                    }
                     Message::ResizeHintsChanged(clap_plugin_id) => {
                         self.plugin_ui_host.resize_hints_changed(clap_plugin_id);
                     }
                     Message::RequestResize(clap_plugin_id, gui_size) => {
                         self.plugin_ui_host.request_resize(clap_plugin_id, gui_size);
                     }
-                    Message::CreateProcessor(clap_plugin_id, sender) => {
+                    Message::CreateProcessor(
+                        clap_plugin_id,
+                        sample_rate,
+                        max_block_frames,
+                        sender,
+                    ) => {
                         let clap_plugin = self.get_plugin(clap_plugin_id);
-                        let processor = Box::new(ClapProcessor::new(&clap_plugin));
-
-                        let num_inputs = 0; // TODO: support inputs!
-                        let num_outputs = processor.get_total_output_channels() as u16;
-
-                        sender.send((num_inputs, num_outputs, processor)).unwrap();
+                        let processor = Box::new(ClapProcessor::new(
+                            &clap_plugin,
+                            sample_rate,
+                            max_block_frames,
+                        ));
+
+                        let input_layout = processor.get_input_layout();
+                        let output_layout = processor.get_output_layout();
+                        let num_event_outputs = clap_plugin.get_note_ports(false);
+
+                        sender
+                            .send((input_layout, output_layout, num_event_outputs, processor))
+                            .unwrap();
+                    }
+                    Message::RestartProcessor(
+                        clap_plugin_id,
+                        sample_rate,
+                        max_block_frames,
+                        sender,
+                    ) => {
+                        // The plugin is activated again once the processor
+                        // it's replacing has been deactivated
+                        let clap_plugin = self.get_plugin(clap_plugin_id);
+                        clap_plugin
+                            .pending_activation
+                            .set(Some((sample_rate, max_block_frames)));
+
+                        sender
+                            .send(Box::new(ClapProcessor::inactive(
+                                &clap_plugin,
+                                sample_rate,
+                                max_block_frames,
+                            )))
+                            .unwrap();
+                    }
+                    Message::ReactivateProcessor(
+                        clap_plugin_id,
+                        processor,
+                        sample_rate,
+                        max_block_frames,
+                        sender,
+                    ) => {
+                        let clap_plugin = self.get_plugin(clap_plugin_id);
+                        clap_plugin.plugin.borrow_mut().deactivate(processor);
+
+                        sender
+                            .send(
+                                clap_plugin
+                                    .get_audio_processor(sample_rate as f64, max_block_frames),
+                            )
+                            .unwrap();
+                    }
+                    Message::DeactivateProcessor(clap_plugin_id, processor) => {
+                        let clap_plugin = self.get_plugin(clap_plugin_id);
+                        clap_plugin.plugin.borrow_mut().deactivate(processor);
+
+                        if let Some((sample_rate, max_block_frames)) =
+                            clap_plugin.pending_activation.take()
+                        {
+                            let processor = clap_plugin
+                                .get_audio_processor(sample_rate as f64, max_block_frames);
+                            clap_plugin
+                                .send_to_processor(ClapProcessorMessage::Activated(processor));
+                        }
                     }
                     Message::SaveState(clap_plugin_id, sender) => {
                         let clap_plugin = self.get_plugin(clap_plugin_id);
@@ -334,6 +515,10 @@ pub struct ClapInstance {
     clap_plugin_id: ClapId,
     pub plugin: RefCell<PluginInstance<Self>>,
     plugin_audio_ports: RefCell<Option<PluginAudioPorts>>,
+    plugin_note_ports: Option<PluginNotePorts>,
+    /// The configuration to activate the plugin with once it has been
+    /// deactivated, while it's being restarted.
+    pending_activation: Cell<Option<(u32, usize)>>,
 }
 
 impl ClapInstance {
@@ -353,8 +538,23 @@ impl ClapInstance {
         let host =
             HostInfo::new("corodaw", "damyanp", "https://github.com/damyanp", "0.0.1").unwrap();
 
+        let (processor_channel, processor_receiver) = message_channel();
+        let (param_event_sender, param_event_receiver) = crossbeam::channel::unbounded();
+        let (param_value_sender, param_value_receiver) = param_value_channel();
+        let (retire_automation, retired_automation) = retired_channel();
+
         let shared = ClapProxy {
             channel: sender,
+            processor_channel,
+            processor_receiver,
+            retire_automation,
+            retired_automation,
+            param_event_sender,
+            param_event_receiver,
+            param_value_sender,
+            param_value_receiver,
+            latency: Arc::default(),
+            restart_requested: Arc::default(),
             plugin_id: clap_plugin_id,
             plugin_name: plugin.name.clone(),
             extensions: Arc::default(),
@@ -365,7 +565,7 @@ impl ClapInstance {
         let shared_clone = shared.clone();
         let plugin = clack_host::plugin::PluginInstance::new(
             move |_| shared_clone,
-            move |_| ClapMainThread::new(initialized_sender),
+            move |shared| ClapMainThread::new(shared, initialized_sender),
             &bundle,
             plugin_id.as_c_str(),
             &host,
@@ -374,19 +574,24 @@ impl ClapInstance {
 
         initialized_receiver.await.unwrap();
 
-        let audio_ports =
-            plugin.access_shared_handler(|h: &ClapProxy| h.extensions.read().unwrap().audio_ports);
+        let (audio_ports, note_ports) = plugin.access_shared_handler(|h: &ClapProxy| {
+            let extensions = h.extensions.read().unwrap();
+            (extensions.audio_ports, extensions.note_ports)
+        });
 
         let clap_plugin = Rc::new(Self {
             clap_plugin_id,
             plugin: RefCell::new(plugin),
             plugin_audio_ports: RefCell::new(audio_ports),
+            plugin_note_ports: note_ports,
+            pending_activation: Cell::new(None),
         });
 
         (clap_plugin, shared)
     }
 
-    pub fn get_audio_ports(&self, is_input: bool) -> Vec<u32> {
+    /// The layout of each of the plugin's input or output ports.
+    pub fn get_audio_ports(&self, is_input: bool) -> Vec<GraphChannelLayout> {
         let audio_ports = self.plugin_audio_ports.borrow_mut();
         let mut plugin = self.plugin.borrow_mut();
         let mut handle = plugin.plugin_handle();
@@ -397,35 +602,160 @@ impl ClapInstance {
                 (0..count)
                     .map(|index| {
                         let mut buffer = AudioPortInfoBuffer::new();
-                        audio_ports
+                        let info = audio_ports
                             .get(&mut handle, index, is_input, &mut buffer)
-                            .unwrap()
-                            .channel_count
+                            .unwrap();
+                        port_layout(
+                            info.port_type.map(|port_type| port_type.0.to_bytes()),
+                            info.channel_count,
+                        )
                     })
                     .collect()
             })
             .unwrap_or_default()
     }
 
-    pub fn get_audio_processor(&self, sample_rate: f64) -> PluginAudioProcessor<ClapInstance> {
+    /// How many input or output note ports the plugin has. Each one is an
+    /// event channel of the plugin's node.
+    pub fn get_note_ports(&self, is_input: bool) -> u16 {
+        let Some(note_ports) = self.plugin_note_ports else {
+            return 0;
+        };
+        let mut plugin = self.plugin.borrow_mut();
+        note_ports.count(&mut plugin.plugin_handle(), is_input) as u16
+    }
+
+    /// Activates the plugin for blocks of up to `max_block_frames` frames,
+    /// which should match the graph's maximum block size.
+    pub fn get_audio_processor(
+        &self,
+        sample_rate: f64,
+        max_block_frames: usize,
+    ) -> PluginAudioProcessor<ClapInstance> {
         let configuration = PluginAudioConfiguration {
             sample_rate,
             min_frames_count: 1,
-            max_frames_count: 100_000,
+            max_frames_count: max_block_frames as u32,
         };
-        PluginAudioProcessor::Started(
-            self.plugin
-                .borrow_mut()
-                .activate(|_, _| (), configuration)
-                .unwrap()
-                .start_processing()
-                .unwrap(),
-        )
+        let processor = self
+            .plugin
+            .borrow_mut()
+            .activate(
+                |shared, main_thread| ClapAudioThread {
+                    plugin: main_thread.plugin.as_ref().map(|plugin| plugin.shared()),
+                    plugin_thread_pool: shared.extensions.read().unwrap().plugin_thread_pool,
+                },
+                configuration,
+            )
+            .unwrap()
+            .start_processing()
+            .unwrap();
+        self.update_latency();
+        PluginAudioProcessor::Started(processor)
+    }
+
+    /// Reads the plugin's latency, which its `ClapProcessor` reports to the
+    /// audio graph. Plugins can only be asked for this while active.
+    fn update_latency(&self) {
+        let plugin_latency = self
+            .plugin
+            .borrow()
+            .access_shared_handler(|h: &ClapProxy| h.extensions.read().unwrap().plugin_latency);
+        let Some(plugin_latency) = plugin_latency else {
+            return;
+        };
+
+        let mut plugin = self.plugin.borrow_mut();
+        if !plugin.is_active() {
+            return;
+        }
+
+        let latency = plugin_latency.get(&mut plugin.plugin_handle());
+        plugin.access_shared_handler(|h: &ClapProxy| h.latency.store(latency, Ordering::Relaxed));
     }
 
     pub fn get_id(&self) -> ClapId {
         self.clap_plugin_id
     }
+
+    fn plugin_params(&self) -> Option<PluginParams> {
+        self.plugin
+            .borrow()
+            .access_shared_handler(|h: &ClapProxy| h.extensions.read().unwrap().plugin_params)
+    }
+
+    /// Waits for room rather than dropping the message, as it's only used to
+    /// hand over the activated plugin, and the processor that takes it is
+    /// already running.
+    fn send_to_processor(&self, message: ClapProcessorMessage) {
+        self.plugin
+            .borrow()
+            .access_shared_handler(|h: &ClapProxy| h.processor_channel.send(message))
+            .unwrap();
+    }
+
+    fn send_param_event(&self, event: ClapParamEvent) {
+        self.plugin
+            .borrow()
+            .access_shared_handler(|h: &ClapProxy| h.param_event_sender.send(event))
+            .unwrap();
+    }
+
+    fn rescan_params(&self) {
+        let Some(plugin_params) = self.plugin_params() else {
+            return;
+        };
+
+        let params = read_params(
+            &plugin_params,
+            &mut self.plugin.borrow_mut().plugin_handle(),
+        );
+        self.send_param_event(ClapParamEvent::Rescanned(params));
+    }
+
+    /// Lets the plugin send parameter changes made from its GUI. While the
+    /// plugin is active its `ClapProcessor` collects them instead.
+    fn flush_params(&self) {
+        let Some(plugin_params) = self.plugin_params() else {
+            return;
+        };
+        if self.plugin.borrow().is_active() {
+            return;
+        }
+
+        let mut output_events = EventBuffer::new();
+        plugin_params.flush(
+            &mut self.plugin.borrow_mut().plugin_handle(),
+            &InputEvents::empty(),
+            &mut OutputEvents::from_buffer(&mut output_events),
+        );
+
+        for event in output_events.iter() {
+            if let Some(event) = event.as_event::<ParamValueEvent>()
+                && let Some(param_id) = event.param_id()
+            {
+                self.send_param_event(ClapParamEvent::Value(param_id.get(), event.value()));
+            }
+        }
+    }
+}
+
+/// The layout of a port from its CLAP port type. Ports without a type are
+/// taken to be mono or stereo if they have one or two channels.
+fn port_layout(port_type: Option<&[u8]>, channel_count: u32) -> GraphChannelLayout {
+    let num_channels = channel_count as u16;
+    match (port_type, num_channels) {
+        (Some(b"mono") | None, 1) => GraphChannelLayout::Mono,
+        (Some(b"stereo") | None, 2) => GraphChannelLayout::Stereo,
+        (Some(b"surround"), 4) => GraphChannelLayout::Quad,
+        (Some(b"surround"), 6) => GraphChannelLayout::Surround51,
+        (Some(b"surround"), 8) => GraphChannelLayout::Surround71,
+        (Some(b"ambisonic"), _) => (0..=u8::MAX)
+            .map(GraphChannelLayout::Ambisonic)
+            .find(|layout| layout.num_channels() == num_channels)
+            .unwrap_or(GraphChannelLayout::Discrete(num_channels)),
+        _ => GraphChannelLayout::Discrete(num_channels),
+    }
 }
 
 enum Message {
@@ -433,9 +763,32 @@ enum Message {
     ShowGui(ClapId, String, oneshot::Sender<PluginGuiHandle>),
     SetTitle(ClapId, String),
     RunOnMainThread(ClapId),
+    LatencyChanged(ClapId),
     ResizeHintsChanged(ClapId),
     RequestResize(ClapId, GuiSize),
-    CreateProcessor(ClapId, oneshot::Sender<(u16, u16, Box<dyn GraphProcessor>)>),
+    RescanParams(ClapId),
+    ClearParam(ClapId, u32),
+    FlushParams(ClapId),
+    CreateProcessor(
+        ClapId,
+        u32,
+        usize,
+        oneshot::Sender<(
+            GraphChannelLayout,
+            GraphChannelLayout,
+            u16,
+            Box<dyn GraphProcessor>,
+        )>,
+    ),
+    RestartProcessor(ClapId, u32, usize, oneshot::Sender<Box<dyn GraphProcessor>>),
+    ReactivateProcessor(
+        ClapId,
+        StoppedPluginAudioProcessor<ClapInstance>,
+        u32,
+        usize,
+        oneshot::Sender<PluginAudioProcessor<ClapInstance>>,
+    ),
+    DeactivateProcessor(ClapId, StoppedPluginAudioProcessor<ClapInstance>),
     SaveState(ClapId, oneshot::Sender<Option<Vec<u8>>>),
     LoadState(ClapId, Vec<u8>, oneshot::Sender<Result<(), String>>),
 }
@@ -443,7 +796,7 @@ enum Message {
 impl HostHandlers for ClapInstance {
     type Shared<'a> = ClapProxy;
     type MainThread<'a> = ClapMainThread<'a>;
-    type AudioProcessor<'a> = ();
+    type AudioProcessor<'a> = ClapAudioThread<'a>;
 
     fn declare_extensions(
         builder: &mut clack_host::prelude::HostExtensions<Self>,
@@ -454,7 +807,9 @@ impl HostHandlers for ClapInstance {
             .register::<HostGui>()
             .register::<HostTimer>()
             .register::<HostParams>()
-            .register::<HostState>();
+            .register::<HostState>()
+            .register::<HostLatency>()
+            .register::<HostThreadPool>();
     }
 }
 
@@ -462,6 +817,24 @@ impl HostHandlers for ClapInstance {
 #[derivative(Debug)]
 pub struct ClapProxy {
     channel: Sender<Message>,
+    /// Messages for whichever `ClapProcessor` is currently running the plugin.
+    processor_channel: crossbeam::channel::Sender<ClapProcessorMessage>,
+    processor_receiver: crossbeam::channel::Receiver<ClapProcessorMessage>,
+    /// Automation the processor has replaced, freed here on the main thread.
+    retire_automation: Retire<Vec<ParamAutomation>>,
+    retired_automation: Retired<Vec<ParamAutomation>>,
+    /// Parameter events from the plugin host thread.
+    param_event_sender: crossbeam::channel::Sender<ClapParamEvent>,
+    param_event_receiver: crossbeam::channel::Receiver<ClapParamEvent>,
+    /// Values the plugin set while its `ClapProcessor` was processing.
+    param_value_sender: crossbeam::channel::Sender<(u32, f64)>,
+    param_value_receiver: crossbeam::channel::Receiver<(u32, f64)>,
+    /// The plugin's latency in frames, as of when it was last activated or
+    /// said that it changed.
+    latency: Arc<AtomicU32>,
+    /// Set when the plugin asks to be restarted. Its `ClapProcessor` outputs
+    /// silence until `restart_audio_graph_node` has replaced it.
+    restart_requested: Arc<AtomicBool>,
     pub plugin_id: ClapId,
     pub plugin_name: String,
     #[derivative(Debug = "ignore")]
@@ -472,26 +845,49 @@ pub struct ClapProxy {
 pub struct ClapExtensions {
     pub plugin_gui: Option<PluginGui>,
     pub audio_ports: Option<PluginAudioPorts>,
+    pub note_ports: Option<PluginNotePorts>,
     pub plugin_state: Option<PluginState>,
+    pub plugin_params: Option<PluginParams>,
+    pub plugin_latency: Option<PluginLatency>,
+    pub plugin_thread_pool: Option<PluginThreadPool>,
 }
 
 impl ClapProxy {
-    pub async fn create_audio_graph_node(&self) -> (GraphNodeDesc, Box<dyn GraphProcessor>) {
+    pub async fn create_audio_graph_node(
+        &self,
+        sample_rate: u32,
+        max_block_frames: usize,
+    ) -> (GraphNodeDesc, Box<dyn GraphProcessor>) {
         let (sender, receiver) = oneshot::channel();
         self.channel
-            .send(Message::CreateProcessor(self.plugin_id, sender))
+            .send(Message::CreateProcessor(
+                self.plugin_id,
+                sample_rate,
+                max_block_frames,
+                sender,
+            ))
             .unwrap();
-        let (num_inputs, num_outputs, processor) = receiver.await.unwrap();
+        let (input_layout, output_layout, num_event_outputs, processor) = receiver.await.unwrap();
 
+        // Events the plugin sends on its output note ports come out of the
+        // node's event outputs, so that e.g. a MIDI effect can drive another
+        // plugin
         let node = GraphNodeDesc::default()
-            .audio(num_inputs, num_outputs)
-            .event(1, 0);
+            .audio_layout(input_layout, output_layout)
+            .event(1, num_event_outputs);
 
         (node, processor)
     }
 
-    pub fn create_audio_graph_node_sync(&self) -> (GraphNodeDesc, Box<dyn GraphProcessor>) {
-        futures::executor::block_on(async { self.create_audio_graph_node().await })
+    pub fn create_audio_graph_node_sync(
+        &self,
+        sample_rate: u32,
+        max_block_frames: usize,
+    ) -> (GraphNodeDesc, Box<dyn GraphProcessor>) {
+        futures::executor::block_on(async {
+            self.create_audio_graph_node(sample_rate, max_block_frames)
+                .await
+        })
     }
 }
 
@@ -527,23 +923,40 @@ impl HostGuiImpl for ClapProxy {
     }
 }
 
+// The plugin is borrowed while it calls these, so the work is queued for the
+// plugin host thread to pick up afterwards.
 impl<'a> HostParamsImplMainThread for ClapMainThread<'a> {
-    fn rescan(&mut self, _flags: clack_extensions::params::ParamRescanFlags) {
-        todo!()
+    fn rescan(&mut self, _flags: ParamRescanFlags) {
+        self.shared
+            .channel
+            .send(Message::RescanParams(self.shared.plugin_id))
+            .unwrap();
     }
 
-    fn clear(
-        &mut self,
-        _param_id: clack_host::prelude::ClapId,
-        _flags: clack_extensions::params::ParamClearFlags,
-    ) {
-        todo!()
+    fn clear(&mut self, param_id: clack_host::prelude::ClapId, flags: ParamClearFlags) {
+        if flags.intersects(ParamClearFlags::ALL | ParamClearFlags::AUTOMATIONS) {
+            self.shared
+                .channel
+                .send(Message::ClearParam(self.shared.plugin_id, param_id.get()))
+                .unwrap();
+        }
     }
 }
 
 impl HostParamsImplShared for ClapProxy {
     fn request_flush(&self) {
-        todo!()
+        self.channel
+            .send(Message::FlushParams(self.plugin_id))
+            .unwrap();
+    }
+}
+
+impl<'a> HostLatencyImpl for ClapMainThread<'a> {
+    fn changed(&mut self) {
+        self.shared
+            .channel
+            .send(Message::LatencyChanged(self.shared.plugin_id))
+            .unwrap();
     }
 }
 
@@ -560,12 +973,16 @@ impl<'a> host::SharedHandler<'a> for ClapProxy {
     fn initializing(&self, instance: InitializingPluginHandle<'a>) {
         let mut extensions = self.extensions.write().unwrap();
         extensions.audio_ports = instance.get_extension();
+        extensions.note_ports = instance.get_extension();
         extensions.plugin_gui = instance.get_extension();
         extensions.plugin_state = instance.get_extension();
+        extensions.plugin_params = instance.get_extension();
+        extensions.plugin_latency = instance.get_extension();
+        extensions.plugin_thread_pool = instance.get_extension();
     }
 
     fn request_restart(&self) {
-        todo!()
+        self.restart_requested.store(true, Ordering::Relaxed);
     }
 
     fn request_process(&self) {
@@ -580,6 +997,7 @@ impl<'a> host::SharedHandler<'a> for ClapProxy {
 }
 
 pub struct ClapMainThread<'a> {
+    shared: &'a ClapProxy,
     plugin: Option<InitializedPluginHandle<'a>>,
     initialized: Cell<Option<oneshot::Sender<()>>>,
     timer_support: Option<PluginTimer>,
@@ -587,8 +1005,9 @@ pub struct ClapMainThread<'a> {
 }
 
 impl<'a> ClapMainThread<'a> {
-    fn new(initialized: oneshot::Sender<()>) -> Self {
+    fn new(shared: &'a ClapProxy, initialized: oneshot::Sender<()>) -> Self {
         Self {
+            shared,
             plugin: None,
             initialized: Cell::new(Some(initialized)),
             timer_support: None,
@@ -608,3 +1027,30 @@ impl<'a> host::MainThreadHandler<'a> for ClapMainThread<'a> {
         self.plugin = Some(instance);
     }
 }
+
+/// The host's side of an active plugin, used on whichever graph thread
+/// processes it.
+pub struct ClapAudioThread<'a> {
+    plugin: Option<PluginSharedHandle<'a>>,
+    plugin_thread_pool: Option<PluginThreadPool>,
+}
+
+impl<'a> host::AudioProcessorHandler<'a> for ClapAudioThread<'a> {}
+
+// Plugins ask for this from inside `process`, so the tasks are shared out
+// between the graph's threads that are waiting for nodes to become ready.
+impl<'a> HostThreadPoolImpl for ClapAudioThread<'a> {
+    fn request_exec(&mut self, task_count: u32) -> Result<(), HostError> {
+        let (Some(plugin), Some(thread_pool)) = (&self.plugin, &self.plugin_thread_pool) else {
+            return Err(HostError::Message("Plugin has no thread pool"));
+        };
+
+        if GraphThreadPool::execute(task_count, &|task_index| {
+            thread_pool.exec(plugin, task_index)
+        }) {
+            Ok(())
+        } else {
+            Err(HostError::Message("No graph threads are available"))
+        }
+    }
+}
@@ -1,23 +1,129 @@
-use std::{fmt::Debug, time::Duration};
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::Sender,
+    },
+    time::Duration,
+};
 
 use clack_host::{
-    events::event_types::MidiEvent,
+    events::{
+        EventFlags, EventHeader, Match, Pckn,
+        event_types::{
+            MidiEvent, NoteOffEvent, NoteOnEvent, ParamValueEvent, TransportEvent, TransportFlags,
+        },
+    },
     prelude::{
-        AudioPortBuffer, AudioPortBufferType, AudioPorts, EventBuffer, InputAudioBuffers,
-        OutputEvents,
+        AudioPortBuffer, AudioPortBufferType, AudioPorts, EventBuffer, InputChannel, OutputEvents,
     },
     process::PluginAudioProcessor,
+    utils::{BeatTime, ClapId as ClapParamId, Cookie, SecondsTime},
 };
+use crossbeam::channel::{Receiver, Sender as CrossbeamSender};
+use futures_channel::oneshot;
+
+use crate::{
+    automation::AutomationEnvelope,
+    plugins::{ClapId, ClapInstance, ClapProxy, Message},
+    retired::Retire,
+};
+use audio_graph::{
+    GraphChannelLayout, GraphConnection, GraphEvent, GraphNode, GraphProcessContext,
+    GraphProcessor, GraphState, GraphTransportInfo,
+};
+use wmidi::{Channel, MidiMessage, Note, U7};
+
+/// Parameter automation is sent to the plugin at least this often while the
+/// value is changing, and exactly when an automation point is crossed.
+const PARAM_AUTOMATION_INTERVAL: usize = 32;
+
+/// How many events the plugin can be sent, or can send, in one block. Any
+/// more are dropped rather than allocating on the audio thread.
+const EVENT_CAPACITY: usize = 1024;
+
+/// How many parameters can have values waiting to be sent to the plugin.
+const PENDING_PARAM_CAPACITY: usize = 256;
+
+/// How many values the plugin sets while processing can wait for the main
+/// thread. Any more are dropped.
+const PARAM_VALUE_CAPACITY: usize = 1024;
+
+/// Carries the values the plugin sets while processing to the main thread.
+/// Bounded, so that sending never allocates.
+pub(crate) fn param_value_channel() -> (CrossbeamSender<(u32, f64)>, Receiver<(u32, f64)>) {
+    crossbeam::channel::bounded(PARAM_VALUE_CAPACITY)
+}
+
+pub enum ClapProcessorMessage {
+    ParamAutomation(Vec<ParamAutomation>),
+    SetParamValue(u32, f64),
+    /// The plugin, activated again after a restart.
+    Activated(PluginAudioProcessor<ClapInstance>),
+}
 
-use crate::plugins::ClapInstance;
-use audio_graph::{GraphConnection, GraphNode, GraphProcessContext, GraphProcessor, GraphState};
+#[derive(Debug)]
+pub struct ParamAutomation {
+    param_id: u32,
+    envelope: AutomationEnvelope,
+    /// The segment and value last sent to the plugin.
+    sent: Option<(usize, f32)>,
+    frames_since_sent: usize,
+}
+
+impl ParamAutomation {
+    /// Built on the main thread, so that the processor doesn't allocate.
+    pub(crate) fn new(param_id: u32, envelope: AutomationEnvelope) -> Self {
+        Self {
+            param_id,
+            envelope,
+            sent: None,
+            frames_since_sent: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum InputEvent {
+    Midi([u8; 3]),
+    ParamValue(u32, f64),
+}
 
 pub struct ClapProcessor {
-    plugin_audio_processor: PluginAudioProcessor<ClapInstance>,
+    // Only `None` while the plugin is being re-activated.
+    plugin_audio_processor: Option<PluginAudioProcessor<ClapInstance>>,
+    /// Set once the plugin has asked to be restarted, after which this
+    /// processor only outputs silence until it's replaced.
+    restarting: bool,
+    clap_plugin_id: ClapId,
+    channel: Sender<Message>,
+    receiver: Receiver<ClapProcessorMessage>,
+    param_value_sender: CrossbeamSender<(u32, f64)>,
+    latency: Arc<AtomicU32>,
+    restart_requested: Arc<AtomicBool>,
     sample_rate: u32,
+    max_block_frames: usize,
+    input_ports: AudioPorts,
+    /// The layout of each input port.
+    input_port_layouts: Vec<GraphChannelLayout>,
+    /// The layout of each output port.
+    output_port_layouts: Vec<GraphChannelLayout>,
+    /// One buffer per input channel, across all the input ports, with room
+    /// for the largest block.
+    input_buffers: Vec<Vec<f32>>,
     audio_ports: AudioPorts,
+    /// The events for the next block, before they're sorted into
+    /// `input_events`.
+    events: Vec<(u32, InputEvent)>,
     input_events: EventBuffer,
-    num_outputs: usize,
+    output_events: EventBuffer,
+    param_automation: Vec<ParamAutomation>,
+    /// Automation that has been replaced, to be freed on the main thread.
+    retire_automation: Retire<Vec<ParamAutomation>>,
+    /// Values set by the host, sent at the start of the next block. Only the
+    /// latest value for each parameter is kept.
+    pending_param_values: Vec<(u32, f64)>,
 }
 
 impl Debug for ClapProcessor {
@@ -27,49 +133,154 @@ impl Debug for ClapProcessor {
 }
 
 impl ClapProcessor {
-    pub fn new(clap_plugin: &ClapInstance) -> Self {
-        let output_channels = clap_plugin.get_audio_ports(false);
-        let total_channel_count = output_channels
-            .iter()
-            .copied()
-            .reduce(|a, b| a + b)
-            .unwrap_or(0) as usize;
+    pub fn new(clap_plugin: &ClapInstance, sample_rate: u32, max_block_frames: usize) -> Self {
+        let plugin_audio_processor =
+            clap_plugin.get_audio_processor(sample_rate as f64, max_block_frames);
+        Self::with_audio_processor(
+            clap_plugin,
+            Some(plugin_audio_processor),
+            sample_rate,
+            max_block_frames,
+        )
+    }
+
+    /// A processor for a plugin that's being restarted, which outputs
+    /// silence until it's sent the plugin's new audio processor.
+    pub fn inactive(clap_plugin: &ClapInstance, sample_rate: u32, max_block_frames: usize) -> Self {
+        Self::with_audio_processor(clap_plugin, None, sample_rate, max_block_frames)
+    }
+
+    fn with_audio_processor(
+        clap_plugin: &ClapInstance,
+        plugin_audio_processor: Option<PluginAudioProcessor<ClapInstance>>,
+        sample_rate: u32,
+        max_block_frames: usize,
+    ) -> Self {
+        let output_port_layouts = clap_plugin.get_audio_ports(false);
+        let total_channel_count = total_channels(&output_port_layouts) as usize;
 
-        let audio_channels = AudioPorts::with_capacity(total_channel_count, output_channels.len());
+        let audio_channels =
+            AudioPorts::with_capacity(total_channel_count, output_port_layouts.len());
 
-        let sample_rate = 48_000;
+        let input_port_layouts = clap_plugin.get_audio_ports(true);
+        let total_input_count = total_channels(&input_port_layouts) as usize;
+        let input_ports = AudioPorts::with_capacity(total_input_count, input_port_layouts.len());
+
+        let (channel, receiver, retire_automation, param_value_sender, latency, restart_requested) =
+            clap_plugin
+                .plugin
+                .borrow()
+                .access_shared_handler(|h: &ClapProxy| {
+                    (
+                        h.channel.clone(),
+                        h.processor_receiver.clone(),
+                        h.retire_automation.clone(),
+                        h.param_value_sender.clone(),
+                        h.latency.clone(),
+                        h.restart_requested.clone(),
+                    )
+                });
 
         Self {
-            plugin_audio_processor: clap_plugin.get_audio_processor(sample_rate as f64),
+            plugin_audio_processor,
+            restarting: false,
+            clap_plugin_id: clap_plugin.get_id(),
+            channel,
+            receiver,
+            param_value_sender,
+            latency,
+            restart_requested,
             sample_rate,
+            max_block_frames,
+            input_ports,
+            input_port_layouts,
+            output_port_layouts,
+            input_buffers: (0..total_input_count)
+                .map(|_| Vec::with_capacity(max_block_frames))
+                .collect(),
             audio_ports: audio_channels,
-            input_events: EventBuffer::new(),
-            num_outputs: total_channel_count,
+            events: Vec::with_capacity(EVENT_CAPACITY),
+            input_events: EventBuffer::with_capacity(EVENT_CAPACITY),
+            output_events: EventBuffer::with_capacity(EVENT_CAPACITY),
+            param_automation: Vec::new(),
+            retire_automation,
+            pending_param_values: Vec::with_capacity(PENDING_PARAM_CAPACITY),
         }
     }
 
-    pub fn get_total_output_channels(&self) -> usize {
-        self.num_outputs
+    /// The layout of the plugin's only input port, or all of its input
+    /// channels side by side if it has more than one.
+    pub fn get_input_layout(&self) -> GraphChannelLayout {
+        node_layout(&self.input_port_layouts)
+    }
+
+    /// Like `get_input_layout`, for the output ports.
+    pub fn get_output_layout(&self) -> GraphChannelLayout {
+        node_layout(&self.output_port_layouts)
+    }
+}
+
+fn total_channels(port_layouts: &[GraphChannelLayout]) -> u16 {
+    port_layouts
+        .iter()
+        .map(|layout| layout.num_channels())
+        .sum()
+}
+
+fn node_layout(port_layouts: &[GraphChannelLayout]) -> GraphChannelLayout {
+    match port_layouts {
+        [layout] => *layout,
+        _ => GraphChannelLayout::Discrete(total_channels(port_layouts)),
     }
 }
 
 impl GraphProcessor for ClapProcessor {
     fn process(&mut self, ctx: GraphProcessContext) {
-        self.update_input_events(ctx.graph, ctx.node, ctx.timestamp);
+        // Messages are left for the processor that replaces this one
+        self.restarting |= self.restart_requested.load(Ordering::Relaxed);
+        if !self.restarting {
+            self.process_messages();
+        }
 
-        let processor = if self.plugin_audio_processor.is_started() {
-            self.plugin_audio_processor.as_started_mut()
+        if self.restarting || self.plugin_audio_processor.is_none() {
+            for channel in ctx.out_audio_buffers.channels_mut() {
+                channel.fill(0.0);
+            }
+            return;
+        }
+
+        self.update_input_events(&ctx);
+        self.update_input_buffers(&ctx);
+
+        let plugin_audio_processor = self.plugin_audio_processor.as_mut().unwrap();
+        let processor = if plugin_audio_processor.is_started() {
+            plugin_audio_processor.as_started_mut()
         } else {
-            println!("Starting processor!");
-            self.plugin_audio_processor.start_processing()
+            plugin_audio_processor.start_processing()
         }
         .unwrap();
 
-        let audio_inputs = InputAudioBuffers::empty();
+        let num_frames = ctx.num_frames;
+        let mut input_buffers = self.input_buffers.as_mut_slice();
+        let audio_inputs = self
+            .input_ports
+            .with_input_buffers(self.input_port_layouts.iter().map(|layout| {
+                let (port, rest) =
+                    std::mem::take(&mut input_buffers).split_at_mut(layout.num_channels() as usize);
+                input_buffers = rest;
+                AudioPortBuffer {
+                    latency: 0,
+                    channels: AudioPortBufferType::f32_input_only(
+                        port.iter_mut()
+                            .map(|buffer| InputChannel::variable(&mut buffer[..num_frames])),
+                    ),
+                }
+            }));
         let input_events = self.input_events.as_input();
-        let mut output_events = OutputEvents::void();
+        self.output_events.clear();
+        let mut output_events = OutputEvents::from_buffer(&mut self.output_events);
         let steady_time = None;
-        let transport = None;
+        let transport = transport_event(ctx.transport);
 
         let mut audio_outputs =
             self.audio_ports
@@ -87,54 +298,393 @@ impl GraphProcessor for ClapProcessor {
                 &input_events,
                 &mut output_events,
                 steady_time,
-                transport,
+                Some(&transport),
             )
             .unwrap();
+
+        send_param_values(&self.output_events, &self.param_value_sender);
+        self.send_output_events(ctx.timestamp, ctx.out_event_buffers);
+    }
+
+    /// CLAP plugins can only change sample rate or block size by being
+    /// deactivated and activated again, which has to happen on the plugin
+    /// host thread.
+    fn configure(&mut self, sample_rate: u32, max_block_frames: usize) {
+        if sample_rate != self.sample_rate || max_block_frames != self.max_block_frames {
+            self.reactivate(sample_rate, max_block_frames);
+        }
+
+        for buffer in self.input_buffers.iter_mut() {
+            buffer.reserve(max_block_frames);
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.latency.load(Ordering::Relaxed) as usize
+    }
+
+    /// The frame where parameter automation next crosses a point, since
+    /// that's where a parameter's value jumps or turns.
+    fn next_boundary(
+        &self,
+        transport: &GraphTransportInfo,
+        sample_rate: u32,
+        _num_frames: usize,
+    ) -> Option<usize> {
+        if !transport.playing {
+            return None;
+        }
+
+        let beats_per_frame = transport.beats_per_frame(sample_rate);
+        self.param_automation
+            .iter()
+            .filter_map(|automation| automation.envelope.next_point(transport.position))
+            .map(|point| ((point - transport.position) / beats_per_frame).ceil() as usize)
+            .min()
+    }
+}
+
+/// The graph hands processors back to the main thread once it has finished
+/// with them, so this is where the plugin is deactivated.
+impl Drop for ClapProcessor {
+    fn drop(&mut self) {
+        if let Some(plugin_audio_processor) = self.plugin_audio_processor.take() {
+            let _ = self.channel.send(Message::DeactivateProcessor(
+                self.clap_plugin_id,
+                plugin_audio_processor.into_stopped(),
+            ));
+        }
     }
 }
 
 impl ClapProcessor {
-    fn update_input_events(&mut self, graph: &GraphState, node: &GraphNode, timestamp: &Duration) {
+    /// Deactivates the plugin and activates it again, waiting for the plugin
+    /// host thread to do it. Only `configure` calls this, as it's never
+    /// called while the graph is processing.
+    fn reactivate(&mut self, sample_rate: u32, max_block_frames: usize) {
+        let Some(plugin_audio_processor) = self.plugin_audio_processor.take() else {
+            return;
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.channel
+            .send(Message::ReactivateProcessor(
+                self.clap_plugin_id,
+                plugin_audio_processor.into_stopped(),
+                sample_rate,
+                max_block_frames,
+                sender,
+            ))
+            .unwrap();
+
+        self.plugin_audio_processor = Some(futures::executor::block_on(receiver).unwrap());
+        self.sample_rate = sample_rate;
+        self.max_block_frames = max_block_frames;
+    }
+
+    fn process_messages(&mut self) {
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                ClapProcessorMessage::ParamAutomation(automation) => {
+                    let old = std::mem::replace(&mut self.param_automation, automation);
+                    self.retire_automation.retire(old);
+                }
+                ClapProcessorMessage::SetParamValue(param_id, value) => {
+                    let pending = &mut self.pending_param_values;
+                    if let Some(pending) = pending.iter_mut().find(|(id, _)| *id == param_id) {
+                        pending.1 = value;
+                    } else if pending.len() < pending.capacity() {
+                        pending.push((param_id, value));
+                    }
+                }
+                ClapProcessorMessage::Activated(processor) => {
+                    self.plugin_audio_processor = Some(processor);
+                }
+            }
+        }
+    }
+
+    /// Passes on the notes and MIDI the plugin sent to its output note ports,
+    /// each port to the node's event output with the same index.
+    fn send_output_events(&self, timestamp: &Duration, out_event_buffers: &mut [Vec<GraphEvent>]) {
+        for event in self.output_events.iter() {
+            let (port_index, midi) = if let Some(event) = event.as_event::<NoteOnEvent>() {
+                let Some(midi) = note_message(event.channel(), event.key(), |channel, key| {
+                    let velocity = (event.velocity().clamp(0.0, 1.0) * 127.0).round() as u8;
+                    MidiMessage::NoteOn(channel, key, U7::from_u8_lossy(velocity))
+                }) else {
+                    continue;
+                };
+                (event.port_index(), midi)
+            } else if let Some(event) = event.as_event::<NoteOffEvent>() {
+                let Some(midi) = note_message(event.channel(), event.key(), |channel, key| {
+                    MidiMessage::NoteOff(channel, key, U7::from_u8_lossy(0))
+                }) else {
+                    continue;
+                };
+                (event.port_index(), midi)
+            } else if let Some(event) = event.as_event::<MidiEvent>() {
+                // Sysex would have to be copied, which can't happen here
+                let Some(midi) = MidiMessage::try_from(&event.data()[..])
+                    .ok()
+                    .and_then(|midi| midi.drop_unowned_sysex())
+                else {
+                    continue;
+                };
+                (Match::Specific(event.port_index()), midi)
+            } else {
+                continue;
+            };
+
+            let Match::Specific(port_index) = port_index else {
+                continue;
+            };
+            // Growing the buffer would allocate, so events past its capacity
+            // are dropped
+            let Some(events) = out_event_buffers
+                .get_mut(port_index as usize)
+                .filter(|events| events.len() < events.capacity())
+            else {
+                continue;
+            };
+
+            // Rounded up so that converting back to frames lands on the same
+            // frame
+            let frame = event.header().time() as u64;
+            let nanoseconds = (frame * 1_000_000_000).div_ceil(self.sample_rate.max(1) as u64);
+            events.push(GraphEvent {
+                timestamp: *timestamp + Duration::from_nanos(nanoseconds),
+                midi,
+            });
+        }
+    }
+
+    /// Sums everything connected to each input channel into its buffer.
+    fn update_input_buffers(&mut self, ctx: &GraphProcessContext) {
+        for (input_channel, buffer) in self.input_buffers.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.resize(ctx.num_frames, 0.0);
+
+            for GraphConnection {
+                channel,
+                src,
+                src_channel,
+                gain,
+                ..
+            } in &ctx.node.desc.audio_channels.connections
+            {
+                if *channel as usize != input_channel {
+                    continue;
+                }
+                let Some(input_buffers) = ctx.graph.input_audio_buffers(ctx.node, *src) else {
+                    continue;
+                };
+                for (input, output) in input_buffers
+                    .channel(*src_channel)
+                    .iter()
+                    .zip(buffer.iter_mut())
+                {
+                    *output += *input * gain;
+                }
+            }
+        }
+    }
+
+    fn update_input_events(&mut self, ctx: &GraphProcessContext) {
         self.input_events.clear();
 
-        if node.desc.event_channels.connections.is_empty() {
-            return;
+        let mut events = std::mem::take(&mut self.events);
+        events.clear();
+        for (param_id, value) in self.pending_param_values.drain(..) {
+            push_event(&mut events, 0, InputEvent::ParamValue(param_id, value));
         }
+        self.add_midi_events(ctx.graph, ctx.node, ctx.timestamp, &mut events);
+        self.add_param_automation_events(ctx, &mut events);
 
-        // TODO: handle multiple inputs
-        assert_eq!(node.desc.event_channels.connections.len(), 1);
+        // CLAP requires input events to be in time order. The sort is stable so
+        // events from the same input stay in the order they were sent.
+        sort_by_time(&mut events);
 
-        let GraphConnection {
+        for (samples, event) in events.drain(..) {
+            match event {
+                InputEvent::Midi(data) => {
+                    self.input_events.push(&MidiEvent::new(samples, 0, data));
+                }
+                InputEvent::ParamValue(param_id, value) => {
+                    self.input_events.push(&ParamValueEvent::new(
+                        samples,
+                        ClapParamId::new(param_id),
+                        Pckn::match_all(),
+                        value,
+                        Cookie::empty(),
+                    ));
+                }
+            }
+        }
+        self.events = events;
+    }
+
+    fn add_midi_events(
+        &self,
+        graph: &GraphState,
+        node: &GraphNode,
+        timestamp: &Duration,
+        events: &mut Vec<(u32, InputEvent)>,
+    ) {
+        for GraphConnection {
             src, src_channel, ..
-        } = node.desc.event_channels.connections[0];
+        } in &node.desc.event_channels.connections
+        {
+            let Some(node) = graph.get_node(*src) else {
+                continue;
+            };
 
-        let Some(node) = graph.get_node(src) else {
-            return;
-        };
+            for event in &node.output_event_buffers.get()[*src_channel as usize] {
+                let mut data: [u8; 3] = Default::default();
+                event.midi.copy_to_slice(&mut data).unwrap();
 
-        let events = &node.output_event_buffers.get()[src_channel as usize];
+                assert!(event.timestamp >= *timestamp);
 
-        for event in events {
-            let mut data: [u8; 3] = Default::default();
-            event.midi.copy_to_slice(&mut data).unwrap();
+                const NS_PER_SECOND: u128 = 1_000_000_000u128;
+                // sample_rate = samples / seconds
+                // samples = sample_rate * seconds
+                // samples = sample_rate * (nanoseconds / NS_PER_SECOND)
+                let timediff = event.timestamp - *timestamp;
+                let nanoseconds = timediff.as_nanos();
+                let samples = (self.sample_rate as u128)
+                    .saturating_mul(nanoseconds)
+                    .saturating_div(NS_PER_SECOND);
 
-            assert!(event.timestamp >= *timestamp);
+                debug_assert!(samples <= (u32::MAX as u128));
 
-            const NS_PER_SECOND: u128 = 1_000_000_000u128;
-            // sample_rate = samples / seconds
-            // samples = sample_rate * seconds
-            // samples = sample_rate * (nanoseconds / NS_PER_SECOND)
-            let timediff = event.timestamp - *timestamp;
-            let nanoseconds = timediff.as_nanos();
-            let samples = (self.sample_rate as u128)
-                .saturating_mul(nanoseconds)
-                .saturating_div(NS_PER_SECOND);
+                push_event(events, samples as u32, InputEvent::Midi(data));
+            }
+        }
+    }
 
-            debug_assert!(samples <= (u32::MAX as u128));
+    fn add_param_automation_events(
+        &mut self,
+        ctx: &GraphProcessContext,
+        events: &mut Vec<(u32, InputEvent)>,
+    ) {
+        for automation in &mut self.param_automation {
+            for frame in 0..ctx.num_frames {
+                let position = ctx.transport.position_at(frame, ctx.sample_rate);
+                let Some((segment, value)) = automation.envelope.evaluate(position) else {
+                    continue;
+                };
 
-            let me = MidiEvent::new(samples as u32, 0, data);
+                automation.frames_since_sent += 1;
+
+                let (crossed_point, changed) = match automation.sent {
+                    Some((sent_segment, sent_value)) => {
+                        (sent_segment != segment, sent_value != value)
+                    }
+                    None => (true, true),
+                };
+
+                if changed
+                    && (crossed_point || automation.frames_since_sent >= PARAM_AUTOMATION_INTERVAL)
+                {
+                    push_event(
+                        events,
+                        frame as u32,
+                        InputEvent::ParamValue(automation.param_id, value as f64),
+                    );
+                    automation.sent = Some((segment, value));
+                    automation.frames_since_sent = 0;
+                }
+            }
+        }
+    }
+}
 
-            self.input_events.push(&me);
+/// Passes on parameter changes the plugin made while processing, e.g. from
+/// its GUI. They're dropped if the main thread has fallen too far behind.
+fn send_param_values(output_events: &EventBuffer, sender: &CrossbeamSender<(u32, f64)>) {
+    for event in output_events.iter() {
+        if let Some(event) = event.as_event::<ParamValueEvent>()
+            && let Some(param_id) = event.param_id()
+        {
+            let _ = sender.try_send((param_id.get(), event.value()));
         }
     }
 }
+
+/// Drops the event if `events` is full, rather than growing it.
+fn push_event(events: &mut Vec<(u32, InputEvent)>, samples: u32, event: InputEvent) {
+    if events.len() < events.capacity() {
+        events.push((samples, event));
+    }
+}
+
+/// A stable insertion sort, as the standard library's stable sort can
+/// allocate. The events are mostly in order already.
+fn sort_by_time(events: &mut [(u32, InputEvent)]) {
+    for index in 1..events.len() {
+        let mut index = index;
+        while index > 0 && events[index - 1].0 > events[index].0 {
+            events.swap(index - 1, index);
+            index -= 1;
+        }
+    }
+}
+
+/// The MIDI message for a note event, unless it's for every key. Notes for
+/// every channel go to the first one.
+fn note_message(
+    channel: Match<u16>,
+    key: Match<u16>,
+    message: impl FnOnce(Channel, Note) -> MidiMessage<'static>,
+) -> Option<MidiMessage<'static>> {
+    let Match::Specific(key) = key else {
+        return None;
+    };
+    let channel = match channel {
+        Match::Specific(channel) => Channel::from_index(channel as u8).ok()?,
+        Match::All => Channel::Ch1,
+    };
+    Some(message(channel, Note::from_u8_lossy(key as u8)))
+}
+
+fn transport_event(transport: &GraphTransportInfo) -> TransportEvent {
+    let mut flags = TransportFlags::HAS_TEMPO
+        | TransportFlags::HAS_BEATS_TIMELINE
+        | TransportFlags::HAS_SECONDS_TIMELINE
+        | TransportFlags::HAS_TIME_SIGNATURE;
+
+    if transport.playing {
+        flags |= TransportFlags::IS_PLAYING;
+    }
+    if transport.recording {
+        flags |= TransportFlags::IS_RECORDING;
+    }
+
+    let (loop_start, loop_end) = match transport.loop_region {
+        Some(region) => {
+            flags |= TransportFlags::IS_LOOP_ACTIVE;
+            (region.start, region.end)
+        }
+        None => (0.0, 0.0),
+    };
+
+    TransportEvent {
+        header: EventHeader::new_core(0, EventFlags::empty()),
+        flags,
+        song_pos_beats: BeatTime::from_float(transport.position),
+        song_pos_seconds: SecondsTime::from_float(transport.beats_to_seconds(transport.position)),
+        tempo: transport.tempo,
+        tempo_inc: 0.0,
+        loop_start_beats: BeatTime::from_float(loop_start),
+        loop_end_beats: BeatTime::from_float(loop_end),
+        loop_start_seconds: SecondsTime::from_float(transport.beats_to_seconds(loop_start)),
+        loop_end_seconds: SecondsTime::from_float(transport.beats_to_seconds(loop_end)),
+        bar_start: BeatTime::from_float(transport.bar_start()),
+        bar_number: transport.bar_number(),
+        time_signature_numerator: transport.time_signature.numerator,
+        time_signature_denominator: transport.time_signature.denominator,
+    }
+}
+
+#[cfg(test)]
+mod tests;
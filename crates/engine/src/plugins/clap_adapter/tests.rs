@@ -0,0 +1,44 @@
+use audio_graph::rt_guard::RealtimeGuard;
+
+use super::*;
+
+fn param_values(count: u32) -> EventBuffer {
+    let mut events = EventBuffer::new();
+    for param_id in 0..count {
+        events.push(&ParamValueEvent::new(
+            0,
+            ClapParamId::new(param_id),
+            Pckn::match_all(),
+            param_id as f64 / 100.0,
+            Cookie::empty(),
+        ));
+    }
+    events
+}
+
+#[test]
+fn param_values_are_sent_without_allocating() {
+    let (sender, receiver) = param_value_channel();
+    // More than fit in one block of an unbounded channel
+    let events = param_values(100);
+
+    let guard = RealtimeGuard::enter();
+    send_param_values(&events, &sender);
+    drop(guard);
+
+    let values: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(values.len(), 100);
+    assert_eq!(values[42], (42, 0.42));
+}
+
+#[test]
+fn param_values_are_dropped_once_the_channel_is_full() {
+    let (sender, receiver) = param_value_channel();
+    let events = param_values(PARAM_VALUE_CAPACITY as u32 + 10);
+
+    let guard = RealtimeGuard::enter();
+    send_param_values(&events, &sender);
+    drop(guard);
+
+    assert_eq!(receiver.try_iter().count(), PARAM_VALUE_CAPACITY);
+}
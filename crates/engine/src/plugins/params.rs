@@ -0,0 +1,79 @@
+use clack_extensions::params::{ParamInfoBuffer, ParamInfoFlags, PluginParams};
+use clack_host::plugin::PluginMainThreadHandle;
+
+/// A parameter exposed by a plugin's `params` extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClapParam {
+    pub id: u32,
+    pub name: String,
+    /// Slash separated path used to group parameters, e.g. "Oscillators/Osc 1".
+    pub module: String,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    pub value: f64,
+    pub automatable: bool,
+    pub stepped: bool,
+    pub hidden: bool,
+    pub read_only: bool,
+}
+
+impl ClapParam {
+    /// Maps `value` from the parameter's range to 0..=1.
+    pub fn normalize(&self, value: f64) -> f64 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Maps `normalized` from 0..=1 to the parameter's range.
+    pub fn denormalize(&self, normalized: f64) -> f64 {
+        let value = self.min + normalized.clamp(0.0, 1.0) * (self.max - self.min);
+        if self.stepped { value.round() } else { value }
+    }
+}
+
+/// Changes to a plugin's parameters that the host needs to pick up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClapParamEvent {
+    /// The plugin's parameter list, sent when the plugin is created and
+    /// whenever the plugin asks for a rescan.
+    Rescanned(Vec<ClapParam>),
+    /// The plugin changed a parameter's value, e.g. from its GUI.
+    Value(u32, f64),
+    /// The plugin asked the host to drop its automation of a parameter. The
+    /// parameter itself is still there.
+    Cleared(u32),
+}
+
+pub(crate) fn read_params(
+    params: &PluginParams,
+    handle: &mut PluginMainThreadHandle,
+) -> Vec<ClapParam> {
+    let mut buffer = ParamInfoBuffer::new();
+    (0..params.count(handle))
+        .filter_map(|index| {
+            let info = params.get_info(handle, index, &mut buffer)?;
+            let id = info.id;
+            let param = ClapParam {
+                id: id.get(),
+                name: String::from_utf8_lossy(info.name).into_owned(),
+                module: String::from_utf8_lossy(info.module).into_owned(),
+                min: info.min_value,
+                max: info.max_value,
+                default: info.default_value,
+                value: info.default_value,
+                automatable: info.flags.contains(ParamInfoFlags::IS_AUTOMATABLE),
+                stepped: info.flags.contains(ParamInfoFlags::IS_STEPPED),
+                hidden: info.flags.contains(ParamInfoFlags::IS_HIDDEN),
+                read_only: info.flags.contains(ParamInfoFlags::IS_READONLY),
+            };
+            Some(ClapParam {
+                value: params.get_value(handle, id).unwrap_or(param.default),
+                ..param
+            })
+        })
+        .collect()
+}
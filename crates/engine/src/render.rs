@@ -0,0 +1,172 @@
+use std::{fs::File, io::BufWriter, path::Path, time::Duration};
+
+use anyhow::Error;
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use audio_graph::GraphWorker;
+
+use crate::audio::frames_to_duration;
+
+/// The sample format written to the WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavBitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavBitDepth {
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        match bits {
+            16 => Some(WavBitDepth::Int16),
+            24 => Some(WavBitDepth::Int24),
+            32 => Some(WavBitDepth::Float32),
+            _ => None,
+        }
+    }
+
+    fn spec(self) -> (u16, SampleFormat) {
+        match self {
+            WavBitDepth::Int16 => (16, SampleFormat::Int),
+            WavBitDepth::Int24 => (24, SampleFormat::Int),
+            WavBitDepth::Float32 => (32, SampleFormat::Float),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OfflineRenderSettings {
+    pub sample_rate: u32,
+    pub num_channels: u16,
+    pub bit_depth: WavBitDepth,
+    pub length: Duration,
+    pub block_size: usize,
+}
+
+impl Default for OfflineRenderSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            num_channels: 2,
+            bit_depth: WavBitDepth::Int24,
+            length: Duration::from_secs(10),
+            block_size: 1024,
+        }
+    }
+}
+
+/// Drives a `GraphWorker` as fast as the CPU allows, rather than from an audio
+/// device callback. Since nothing here depends on wall-clock time the output is
+/// deterministic for a given graph.
+pub struct OfflineRender {
+    audio_graph_worker: GraphWorker,
+    settings: OfflineRenderSettings,
+}
+
+impl OfflineRender {
+    pub fn new(mut audio_graph_worker: GraphWorker, settings: OfflineRenderSettings) -> Self {
+        assert!(settings.block_size > 0);
+        assert!(settings.num_channels > 0);
+
+        audio_graph_worker.configure(
+            settings.num_channels,
+            settings.sample_rate,
+            settings.block_size,
+        );
+
+        Self {
+            audio_graph_worker,
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> &OfflineRenderSettings {
+        &self.settings
+    }
+
+    pub fn num_frames(&self) -> usize {
+        const NS_PER_SECOND: u128 = 1_000_000_000u128;
+        let frames = self.settings.length.as_nanos() * self.settings.sample_rate as u128;
+        (frames / NS_PER_SECOND) as usize
+    }
+
+    /// Renders `settings.length` worth of audio, calling `on_block` with each
+    /// block of interleaved samples as it is produced.
+    pub fn render(&mut self, mut on_block: impl FnMut(&[f32])) {
+        let num_channels = self.settings.num_channels as usize;
+        let total_frames = self.num_frames();
+
+        let mut data = vec![0.0; self.settings.block_size * num_channels];
+        let mut frame = 0;
+
+        while frame < total_frames {
+            let num_frames = (total_frames - frame).min(self.settings.block_size);
+            let block = &mut data[..num_frames * num_channels];
+
+            let timestamp = frames_to_duration(frame as u64, self.settings.sample_rate);
+            self.audio_graph_worker.tick(block, timestamp);
+
+            on_block(block);
+            frame += num_frames;
+        }
+    }
+
+    /// Picks up changes to the graph without rendering anything, so that it
+    /// can be built up before rendering starts.
+    pub fn update(&mut self) {
+        self.audio_graph_worker.tick(&mut [], Duration::ZERO);
+    }
+
+    pub fn render_to_wav(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let (bits_per_sample, sample_format) = self.settings.bit_depth.spec();
+        let spec = WavSpec {
+            channels: self.settings.num_channels,
+            sample_rate: self.settings.sample_rate,
+            bits_per_sample,
+            sample_format,
+        };
+
+        let mut writer = WavWriter::create(path, spec)?;
+        let bit_depth = self.settings.bit_depth;
+
+        let mut result = Ok(());
+        self.render(|block| {
+            if result.is_ok() {
+                result = write_block(&mut writer, bit_depth, block);
+            }
+        });
+        result?;
+
+        writer.finalize()?;
+        Ok(())
+    }
+
+    pub fn into_worker(self) -> GraphWorker {
+        self.audio_graph_worker
+    }
+}
+
+fn write_block(
+    writer: &mut WavWriter<BufWriter<File>>,
+    bit_depth: WavBitDepth,
+    block: &[f32],
+) -> Result<(), hound::Error> {
+    for sample in block {
+        match bit_depth {
+            WavBitDepth::Int16 => {
+                let value = sample.clamp(-1.0, 1.0) * i16::MAX as f32;
+                writer.write_sample(value as i16)?;
+            }
+            WavBitDepth::Int24 => {
+                const MAX_24: f32 = ((1 << 23) - 1) as f32;
+                let value = sample.clamp(-1.0, 1.0) * MAX_24;
+                writer.write_sample(value as i32)?;
+            }
+            WavBitDepth::Float32 => writer.write_sample(*sample)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use hound::WavReader;
+
+use super::*;
+use crate::test_util::constant_worker;
+
+/// A render of a graph that outputs `value` on both channels.
+fn constant_render(value: f32, settings: OfflineRenderSettings) -> OfflineRender {
+    OfflineRender::new(constant_worker(value), settings)
+}
+
+fn temp_wav(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("{name}-{}.wav", std::process::id()))
+}
+
+#[test]
+fn renders_the_requested_length() {
+    let settings = OfflineRenderSettings {
+        sample_rate: 1000,
+        length: Duration::from_millis(100),
+        // Not a whole number of blocks
+        block_size: 32,
+        ..Default::default()
+    };
+    let mut render = constant_render(0.5, settings);
+
+    let mut num_samples = 0;
+    render.render(|block| {
+        assert!(block.iter().all(|sample| *sample == 0.5));
+        num_samples += block.len();
+    });
+
+    assert_eq!(render.num_frames(), 100);
+    assert_eq!(num_samples, 200);
+}
+
+#[test]
+fn wav_file_can_be_read_back() {
+    let path = temp_wav("render-float");
+    let settings = OfflineRenderSettings {
+        sample_rate: 1000,
+        length: Duration::from_millis(100),
+        bit_depth: WavBitDepth::Float32,
+        block_size: 32,
+        ..Default::default()
+    };
+    constant_render(0.5, settings).render_to_wav(&path).unwrap();
+
+    let mut reader = WavReader::open(&path).unwrap();
+    let spec = reader.spec();
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.sample_rate, 1000);
+    assert_eq!(reader.duration(), 100);
+
+    let samples: Vec<f32> = reader.samples().map(Result::unwrap).collect();
+    assert_eq!(samples, vec![0.5; 200]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn integer_wav_samples_are_scaled_and_clipped() {
+    let path = temp_wav("render-int16");
+    let settings = OfflineRenderSettings {
+        sample_rate: 1000,
+        length: Duration::from_millis(10),
+        bit_depth: WavBitDepth::Int16,
+        ..Default::default()
+    };
+    constant_render(2.0, settings).render_to_wav(&path).unwrap();
+
+    let mut reader = WavReader::open(&path).unwrap();
+    assert_eq!(reader.spec().bits_per_sample, 16);
+    let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+    assert_eq!(samples, vec![i16::MAX; 20]);
+
+    std::fs::remove_file(path).unwrap();
+}
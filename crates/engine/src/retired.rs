@@ -0,0 +1,49 @@
+//! Hands values that processors replace back to the main thread, so that
+//! they're freed there rather than on the audio thread.
+
+use crossbeam::channel::{self, Receiver, Sender};
+
+/// How many replaced values can be waiting to be freed. If the main thread
+/// falls this far behind, the processor frees them itself as a last resort.
+const RETIRED_CAPACITY: usize = 64;
+
+/// A bounded channel, so that sending never allocates.
+pub(crate) fn retired_channel<T>() -> (Retire<T>, Retired<T>) {
+    let (sender, receiver) = channel::bounded(RETIRED_CAPACITY);
+    (Retire(sender), Retired(receiver))
+}
+
+/// The processor's end.
+#[derive(Debug)]
+pub(crate) struct Retire<T>(Sender<T>);
+
+impl<T> Clone for Retire<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Retire<T> {
+    pub(crate) fn retire(&self, value: T) {
+        let _ = self.0.try_send(value);
+    }
+}
+
+/// The main thread's end.
+#[derive(Debug)]
+pub(crate) struct Retired<T>(Receiver<T>);
+
+impl<T> Clone for Retired<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Retired<T> {
+    /// Frees everything that has been retired so far.
+    pub(crate) fn free(&self) {
+        for value in self.0.try_iter() {
+            drop(value);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the engine's tests.
+
+use audio_blocks::AudioBlockMut;
+use audio_graph::{
+    GraphNodeDesc, GraphOutputNode, GraphPlugin, GraphProcessContext, GraphProcessor, GraphWorker,
+};
+use bevy_app::App;
+
+/// Outputs the same value on every channel.
+#[derive(Debug)]
+pub(crate) struct Constant(pub(crate) f32);
+
+impl GraphProcessor for Constant {
+    fn process(&mut self, ctx: GraphProcessContext) {
+        for out in ctx.out_audio_buffers.raw_data_mut() {
+            *out = self.0;
+        }
+    }
+}
+
+/// The worker for a graph that outputs `value` on both channels.
+pub(crate) fn constant_worker(value: f32) -> GraphWorker {
+    let mut app = App::new();
+    app.add_plugins(GraphPlugin);
+
+    let world = app.world_mut();
+    let node = world
+        .spawn((GraphNodeDesc::default().audio(0, 2), GraphOutputNode))
+        .id();
+    audio_graph::graph_set_processor(world, node, Box::new(Constant(value)));
+
+    app.update();
+
+    app.world_mut().remove_non_send().unwrap()
+}
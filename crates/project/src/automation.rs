@@ -0,0 +1,25 @@
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+
+mod components;
+mod edits;
+
+pub use components::*;
+pub use edits::*;
+
+/// Finds the channel that owns an automation lane, and the lane's index within
+/// its `ChannelAutomation`.
+pub(crate) fn find_lane(world: &mut World, lane: StableId) -> Option<(Entity, usize)> {
+    let mut query = world.query::<(Entity, &ChannelAutomation)>();
+    query.iter(world).find_map(|(entity, automation)| {
+        automation
+            .lanes
+            .iter()
+            .position(|l| l.id == lane)
+            .map(|index| (entity, index))
+    })
+}
+
+#[cfg(test)]
+mod tests;
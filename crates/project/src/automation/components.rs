@@ -0,0 +1,170 @@
+use std::ops::RangeInclusive;
+
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+use serde::{Deserialize, Serialize};
+
+use engine::automation::{AutomationEnvelope, EnvelopeCurve, EnvelopePoint};
+use engine::builtin::GainAutomation;
+use engine::plugins::ClapParam;
+
+use crate::StableId;
+
+/// How the value moves from one point to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum AutomationCurve {
+    #[default]
+    Linear,
+    Step,
+    Smooth,
+}
+
+impl From<AutomationCurve> for EnvelopeCurve {
+    fn from(curve: AutomationCurve) -> Self {
+        match curve {
+            AutomationCurve::Linear => EnvelopeCurve::Linear,
+            AutomationCurve::Step => EnvelopeCurve::Step,
+            AutomationCurve::Smooth => EnvelopeCurve::Smooth,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct AutomationPoint {
+    /// In beats.
+    pub position: f64,
+    pub value: f32,
+    /// The shape of the segment from this point to the next.
+    #[serde(default)]
+    pub curve: AutomationCurve,
+}
+
+/// What an automation lane controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum AutomationTarget {
+    Gain,
+    Mute,
+    Pan,
+    /// A parameter of the channel's plugin, by CLAP parameter id. Values are
+    /// normalized to 0..=1 across the parameter's range.
+    ClapParam(u32),
+}
+
+impl AutomationTarget {
+    pub fn range(self) -> RangeInclusive<f32> {
+        match self {
+            AutomationTarget::Gain | AutomationTarget::Mute => 0.0..=1.0,
+            AutomationTarget::Pan => -1.0..=1.0,
+            AutomationTarget::ClapParam(_) => 0.0..=1.0,
+        }
+    }
+
+    /// The value used for the first point of a new lane.
+    pub fn default_value(self) -> f32 {
+        match self {
+            AutomationTarget::Gain => 1.0,
+            AutomationTarget::Mute | AutomationTarget::Pan => 0.0,
+            AutomationTarget::ClapParam(_) => 0.5,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            AutomationTarget::Gain => "Gain".to_owned(),
+            AutomationTarget::Mute => "Mute".to_owned(),
+            AutomationTarget::Pan => "Pan".to_owned(),
+            AutomationTarget::ClapParam(id) => format!("Param {id}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct AutomationLane {
+    pub id: StableId,
+    pub target: AutomationTarget,
+    /// Sorted by position.
+    #[serde(default)]
+    pub points: Vec<AutomationPoint>,
+}
+
+impl AutomationLane {
+    pub fn new(target: AutomationTarget) -> Self {
+        Self {
+            id: StableId::new(),
+            target,
+            points: Vec::new(),
+        }
+    }
+
+    pub fn envelope(&self) -> AutomationEnvelope {
+        self.mapped_envelope(|value| value)
+    }
+
+    fn mapped_envelope(&self, map: impl Fn(f32) -> f32) -> AutomationEnvelope {
+        AutomationEnvelope::new(
+            self.points
+                .iter()
+                .map(|point| EnvelopePoint {
+                    position: point.position,
+                    value: map(point.value),
+                    curve: point.curve.into(),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The automation lanes on a channel. Every channel has one.
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize, Reflect)]
+pub struct ChannelAutomation {
+    #[serde(default)]
+    pub lanes: Vec<AutomationLane>,
+    /// Whether the lanes are shown in the arranger.
+    #[serde(default)]
+    pub expanded: bool,
+}
+
+impl ChannelAutomation {
+    pub fn lane(&self, lane: StableId) -> Option<&AutomationLane> {
+        self.lanes.iter().find(|l| l.id == lane)
+    }
+
+    pub fn lane_mut(&mut self, lane: StableId) -> Option<&mut AutomationLane> {
+        self.lanes.iter_mut().find(|l| l.id == lane)
+    }
+
+    /// The envelopes for the channel's gain node. If a target has more than
+    /// one lane the first one wins.
+    pub fn gain_automation(&self) -> GainAutomation {
+        let envelope = |target| {
+            self.lanes
+                .iter()
+                .find(|lane| lane.target == target)
+                .map(AutomationLane::envelope)
+                .unwrap_or_default()
+        };
+
+        GainAutomation {
+            gain: envelope(AutomationTarget::Gain),
+            mute: envelope(AutomationTarget::Mute),
+            pan: envelope(AutomationTarget::Pan),
+        }
+    }
+
+    /// The envelopes for the channel's plugin, keyed by parameter id and
+    /// scaled to each parameter's range. Lanes for parameters the plugin
+    /// doesn't have are skipped.
+    pub fn param_automation(&self, params: &[ClapParam]) -> Vec<(u32, AutomationEnvelope)> {
+        self.lanes
+            .iter()
+            .filter_map(|lane| {
+                let AutomationTarget::ClapParam(id) = lane.target else {
+                    return None;
+                };
+                let param = params.iter().find(|p| p.id == id)?;
+                let envelope = lane.mapped_envelope(|value| param.denormalize(value as f64) as f32);
+                Some((id, envelope))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,79 @@
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+use crate::commands::EditCommand;
+
+use super::components::{AutomationLane, AutomationPoint, ChannelAutomation};
+use super::find_lane;
+
+#[derive(Debug)]
+pub struct AddAutomationLaneEdit {
+    channel: StableId,
+    lane: AutomationLane,
+}
+
+impl AddAutomationLaneEdit {
+    pub fn new(channel: StableId, lane: AutomationLane) -> Self {
+        Self { channel, lane }
+    }
+}
+
+impl EditCommand for AddAutomationLaneEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        world
+            .get_mut::<ChannelAutomation>(entity)?
+            .lanes
+            .push(self.lane.clone());
+        Some(Box::new(DeleteAutomationLaneEdit::new(self.lane.id)))
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteAutomationLaneEdit {
+    lane: StableId,
+}
+
+impl DeleteAutomationLaneEdit {
+    pub fn new(lane: StableId) -> Self {
+        Self { lane }
+    }
+}
+
+impl EditCommand for DeleteAutomationLaneEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_lane(world, self.lane)?;
+        let channel = *world.get::<StableId>(entity)?;
+        let lane = world
+            .get_mut::<ChannelAutomation>(entity)?
+            .lanes
+            .remove(index);
+        Some(Box::new(AddAutomationLaneEdit::new(channel, lane)))
+    }
+}
+
+/// Replaces all the points in an automation lane.
+#[derive(Debug)]
+pub struct SetAutomationPointsEdit {
+    lane: StableId,
+    points: Vec<AutomationPoint>,
+}
+
+impl SetAutomationPointsEdit {
+    pub fn new(lane: StableId, mut points: Vec<AutomationPoint>) -> Self {
+        points.sort_by(|a, b| a.position.total_cmp(&b.position));
+        Self { lane, points }
+    }
+}
+
+impl EditCommand for SetAutomationPointsEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_lane(world, self.lane)?;
+        let mut automation = world.get_mut::<ChannelAutomation>(entity)?;
+        let old_points =
+            std::mem::replace(&mut automation.lanes[index].points, self.points.clone());
+        Some(Box::new(SetAutomationPointsEdit::new(
+            self.lane, old_points,
+        )))
+    }
+}
@@ -0,0 +1,142 @@
+use engine::plugins::ClapParam;
+
+use super::*;
+use crate::{AddChannelEdit, ChannelOrder, ChannelSnapshot, DeleteChannelEdit, EditCommand};
+
+fn setup_world_with_channel() -> (World, StableId) {
+    let mut world = World::new();
+    world.spawn(ChannelOrder::default());
+
+    let snapshot = ChannelSnapshot::default();
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+    (world, id)
+}
+
+fn get_lanes(world: &mut World, channel: StableId) -> Vec<AutomationLane> {
+    let entity = channel.find_entity(world).unwrap();
+    world
+        .get::<ChannelAutomation>(entity)
+        .unwrap()
+        .lanes
+        .clone()
+}
+
+fn point(position: f64, value: f32, curve: AutomationCurve) -> AutomationPoint {
+    AutomationPoint {
+        position,
+        value,
+        curve,
+    }
+}
+
+#[test]
+fn envelope_curves() {
+    let mut lane = AutomationLane::new(AutomationTarget::Gain);
+    assert_eq!(lane.envelope().value_at(0.0), None);
+
+    lane.points = vec![
+        point(4.0, 0.0, AutomationCurve::Linear),
+        point(8.0, 1.0, AutomationCurve::Step),
+        point(12.0, 0.5, AutomationCurve::Smooth),
+        point(16.0, 1.0, AutomationCurve::Linear),
+    ];
+    let envelope = lane.envelope();
+
+    // Held before the first point and after the last
+    assert_eq!(envelope.value_at(0.0), Some(0.0));
+    assert_eq!(envelope.value_at(20.0), Some(1.0));
+
+    assert_eq!(envelope.value_at(6.0), Some(0.5));
+    assert_eq!(envelope.value_at(8.0), Some(1.0));
+    assert_eq!(envelope.value_at(11.9), Some(1.0));
+    assert_eq!(envelope.value_at(12.0), Some(0.5));
+    assert_eq!(envelope.value_at(14.0), Some(0.75));
+    assert!(envelope.value_at(13.0).unwrap() < 0.625);
+}
+
+#[test]
+fn gain_and_param_automation() {
+    let mut automation = ChannelAutomation::default();
+    let mut pan = AutomationLane::new(AutomationTarget::Pan);
+    pan.points = vec![point(0.0, -1.0, AutomationCurve::Linear)];
+    let mut param = AutomationLane::new(AutomationTarget::ClapParam(7));
+    param.points = vec![point(0.0, 0.25, AutomationCurve::Linear)];
+    automation.lanes = vec![pan, param];
+
+    let gain = automation.gain_automation();
+    assert!(gain.gain.is_empty());
+    assert!(gain.mute.is_empty());
+    assert_eq!(gain.pan.value_at(2.0), Some(-1.0));
+
+    // Parameter lanes are scaled to the parameter's range
+    let param_info = ClapParam {
+        id: 7,
+        name: "Cutoff".to_owned(),
+        module: String::new(),
+        min: 100.0,
+        max: 500.0,
+        default: 100.0,
+        value: 100.0,
+        automatable: true,
+        stepped: false,
+        hidden: false,
+        read_only: false,
+    };
+    let params = automation.param_automation(std::slice::from_ref(&param_info));
+    assert_eq!(params.len(), 1);
+    assert_eq!(params[0].0, 7);
+    assert_eq!(params[0].1.value_at(2.0), Some(200.0));
+
+    // Lanes for parameters the plugin doesn't have are skipped
+    assert!(automation.param_automation(&[]).is_empty());
+}
+
+#[test]
+fn add_and_delete_lane() {
+    let (mut world, channel) = setup_world_with_channel();
+    let lane = AutomationLane::new(AutomationTarget::Mute);
+
+    let undo = AddAutomationLaneEdit::new(channel, lane.clone())
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_lanes(&mut world, channel), vec![lane.clone()]);
+
+    let redo = undo.execute(&mut world).unwrap();
+    assert!(get_lanes(&mut world, channel).is_empty());
+
+    redo.execute(&mut world);
+    assert_eq!(get_lanes(&mut world, channel), vec![lane]);
+}
+
+#[test]
+fn set_points_sorts_and_undoes() {
+    let (mut world, channel) = setup_world_with_channel();
+    let lane = AutomationLane::new(AutomationTarget::Gain);
+    AddAutomationLaneEdit::new(channel, lane.clone()).execute(&mut world);
+
+    let a = point(8.0, 0.5, AutomationCurve::Linear);
+    let b = point(2.0, 1.0, AutomationCurve::Step);
+    let undo = SetAutomationPointsEdit::new(lane.id, vec![a, b])
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_lanes(&mut world, channel)[0].points, vec![b, a]);
+
+    undo.execute(&mut world);
+    assert!(get_lanes(&mut world, channel)[0].points.is_empty());
+}
+
+#[test]
+fn delete_channel_keeps_automation() {
+    let (mut world, channel) = setup_world_with_channel();
+    let mut lane = AutomationLane::new(AutomationTarget::Pan);
+    lane.points = vec![point(0.0, 0.5, AutomationCurve::Linear)];
+    AddAutomationLaneEdit::new(channel, lane.clone()).execute(&mut world);
+
+    let undo = DeleteChannelEdit::new(channel, 0)
+        .execute(&mut world)
+        .unwrap();
+    undo.execute(&mut world);
+
+    assert_eq!(get_lanes(&mut world, channel), vec![lane]);
+}
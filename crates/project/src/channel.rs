@@ -1,16 +1,28 @@
-use audio_graph::{GraphConnection, GraphNodeDesc};
+use std::collections::HashMap;
+
+use audio_graph::{
+    GraphChannelLayout, GraphConnection, GraphController, GraphNodeDesc, GraphOutputMapping,
+    GraphOutputRoute,
+};
 use bevy_app::prelude::*;
 use bevy_ecs::{name::Name, prelude::*};
 
-use engine::builtin::{MidiInputOwner, SummerOwner};
+use engine::builtin::{
+    MidiInputOwner, MidiRecorderEvent, MidiRecorderOwner, SequencerNote, SequencerOwner,
+    SummerOwner,
+};
 use engine::{
     builtin::GainNodeOwner,
-    plugins::{ClapManager, PluginManager, discovery::PluginDescriptor},
+    plugins::{ClapManager, ClapParamEvent, PluginManager, discovery::PluginDescriptor},
 };
 
 use base64::{Engine, engine::general_purpose};
 
-use crate::{AvailablePlugin, ChannelOrder, StableId};
+use crate::{
+    AutomationTarget, AvailablePlugin, ChannelAutomation, ChannelClips, ChannelKind, ChannelOrder,
+    ChannelRoutes, ChannelRouting, EditHistory, MidiClip, MidiClipNote, RecordMode,
+    SetChannelClipsEdit, StableId,
+};
 
 mod components;
 mod edits;
@@ -37,12 +49,21 @@ impl<T: PluginManager> Default for ChannelPlugin<T> {
 
 impl<T: PluginManager + 'static> Plugin for ChannelPlugin<T> {
     fn build(&self, app: &mut App) {
+        app.init_resource::<RecordMode>();
         app.add_systems(
             Update,
             (
                 remove_plugins_system::<T>,
                 set_plugins_system::<T>,
+                update_buses_system,
+                update_inserts_system::<T>,
+                update_routing_system,
                 update_channels_system,
+                update_sequencers_system,
+                update_plugin_params_system::<T>,
+                restart_plugins_system::<T>,
+                update_automation_system::<T>,
+                record_system,
                 sync_channel_order_system,
                 sync_plugin_window_titles_system::<T>,
             )
@@ -65,6 +86,10 @@ fn sync_plugin_window_titles_system<T: PluginManager>(
         (&Name, &ChannelPluginInstance<T::Plugin>),
         Or<(Changed<Name>, Changed<ChannelPluginInstance<T::Plugin>>)>,
     >,
+    inserts: Query<
+        (&Name, &ChannelInsertInstances<T::Plugin>),
+        Or<(Changed<Name>, Changed<ChannelInsertInstances<T::Plugin>>)>,
+    >,
     plugin_factory: NonSend<T>,
 ) {
     for (name, view) in &channels {
@@ -73,6 +98,13 @@ fn sync_plugin_window_titles_system<T: PluginManager>(
             plugin_factory.set_title(view.plugin_id::<T>(), title);
         }
     }
+
+    for (name, instances) in &inserts {
+        for instance in instances.0.iter().filter(|instance| instance.has_gui()) {
+            let title = instance.window_title::<T>(name.as_str());
+            plugin_factory.set_title(instance.plugin_id::<T>(), title);
+        }
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -80,20 +112,31 @@ fn remove_plugins_system<T: PluginManager>(
     mut commands: Commands,
     mut removed: RemovedComponents<ChannelPluginBinding>,
     channels: Query<
-        (Entity, Option<&ChannelPluginInstance<T::Plugin>>),
+        (
+            Entity,
+            Option<&ChannelPluginInstance<T::Plugin>>,
+            Option<&ChannelInsertInstances<T::Plugin>>,
+        ),
         (With<ChannelMixerState>, Without<ChannelPluginBinding>),
     >,
 ) {
     for entity in removed.read() {
-        if let Ok((entity, audio_view)) = channels.get(entity) {
+        if let Ok((entity, audio_view, inserts)) = channels.get(entity) {
             // Despawn the plugin's audio graph node entity. The audio graph's
             // pre_update_system will automatically disconnect it from any
             // remaining nodes (e.g. the summer) on the next frame.
             if let Some(audio_view) = audio_view {
                 commands.entity(audio_view.plugin_node).despawn();
             }
+            // Without a plugin there's nothing to feed the inserts, so they're
+            // recreated from ChannelInserts when a plugin is set again.
+            for instance in inserts.iter().flat_map(|inserts| &inserts.0) {
+                commands.entity(instance.plugin_node).despawn();
+            }
             commands.entity(entity).remove::<(
                 ChannelPluginInstance<T::Plugin>,
+                ChannelInsertInstances<T::Plugin>,
+                ChannelPluginParams,
                 ChannelGain,
                 ChannelSourceNode,
             )>();
@@ -112,13 +155,16 @@ fn set_plugins_system<T: PluginManager>(
             &ChannelMixerState,
             &ChannelPluginBinding,
             Option<&ChannelGain>,
+            Option<&ChannelSequencer>,
+            Option<&ChannelRecorder>,
             Option<&ChannelPluginInstance<T::Plugin>>,
         ),
         Changed<ChannelPluginBinding>,
     >,
+    audio_graph: NonSend<GraphController>,
     summer: NonSend<SummerOwner>,
 ) {
-    for (entity, state, data, gain_control, old_audio_view) in &channels {
+    for (entity, state, data, gain_control, sequencer, recorder, old_audio_view) in &channels {
         let found_plugin = available_plugins
             .iter()
             .find(|p| p.0.id == data.plugin_id)
@@ -138,43 +184,70 @@ fn set_plugins_system<T: PluginManager>(
             .as_deref()
             .and_then(|s| general_purpose::STANDARD.decode(s).ok());
 
+        let format = NodeFormat {
+            sample_rate: audio_graph.sample_rate(),
+            max_block_frames: audio_graph.max_block_frames(),
+            layout: summer.layout,
+        };
+        let existing = ExistingNodes {
+            gain_control,
+            sequencer,
+            has_recorder: recorder.is_some(),
+        };
         set_plugin(
             &*plugin_factory,
-            &summer,
+            format,
             state,
             channel_entity,
             found_plugin,
-            gain_control,
+            existing,
             plugin_state_bytes.as_deref(),
         );
     }
 }
 
+/// What the audio graph nodes of a channel's plugin are created for.
+struct NodeFormat {
+    sample_rate: u32,
+    max_block_frames: usize,
+    layout: GraphChannelLayout,
+}
+
+/// The nodes a channel already has when its plugin is set. Missing ones are
+/// created along with the plugin.
+struct ExistingNodes<'a> {
+    gain_control: Option<&'a ChannelGain>,
+    sequencer: Option<&'a ChannelSequencer>,
+    has_recorder: bool,
+}
+
 fn set_plugin<T: PluginManager>(
     plugin_factory: &T,
-    summer: &SummerOwner,
+    format: NodeFormat,
     state: &ChannelMixerState,
     mut channel_entity: EntityCommands<'_>,
     found_plugin: &PluginDescriptor,
-    gain_control: Option<&ChannelGain>,
+    existing: ExistingNodes,
     plugin_state_data: Option<&[u8]>,
 ) {
+    let ExistingNodes {
+        gain_control,
+        sequencer,
+        has_recorder,
+    } = existing;
     let plugin = plugin_factory.create_plugin_sync(found_plugin.clone());
 
     if let Some(state_data) = plugin_state_data {
-        let plugin_id = T::plugin_id(&plugin);
-        let result = futures::executor::block_on(async {
-            plugin_factory
-                .load_plugin_state(plugin_id, state_data.to_vec())
-                .await
-                .unwrap()
-        });
-        if let Err(e) = result {
-            eprintln!("Warning: failed to load plugin state: {e}");
-        }
+        load_plugin_state(plugin_factory, &plugin, state_data);
     }
 
-    let (plugin_node, plugin_processor) = plugin_factory.create_audio_graph_node(&plugin);
+    let (plugin_node, plugin_processor) = plugin_factory.create_audio_graph_node(
+        &plugin,
+        format.sample_rate,
+        format.max_block_frames,
+    );
+
+    let has_event_input = plugin_node.event_channels.num_inputs > 0;
 
     let commands = channel_entity.commands_mut();
     let plugin_node_id = commands.spawn(plugin_node).id();
@@ -185,35 +258,58 @@ fn set_plugin<T: PluginManager>(
     let mut gain_control = gain_control;
     let mut new_gain_control = None;
     if gain_control.is_none() {
-        new_gain_control = Some(ChannelGain(GainNodeOwner::new(commands, state.gain_value)));
+        new_gain_control = Some(ChannelGain(GainNodeOwner::new(
+            commands,
+            state.gain_value,
+            format.layout,
+        )));
         gain_control = new_gain_control.as_ref();
     }
     let gain_control = gain_control.unwrap();
 
+    // The gain's output is connected by update_routing_system
     let gain_control_entity = gain_control.0.entity;
-    let summer_entity = summer.entity;
     commands.queue(move |world: &mut World| {
-        for port in 0..2 {
-            audio_graph::graph_connect_audio(
-                world,
-                gain_control_entity,
-                GraphConnection::new(port, plugin_node_id, port),
-            )
+        audio_graph::graph_connect_audio_layout(world, gain_control_entity, plugin_node_id)
             .unwrap();
-            audio_graph::graph_connect_audio(
+    });
+
+    let mut sequencer = sequencer;
+    let mut new_sequencer = None;
+    if sequencer.is_none() {
+        new_sequencer = Some(ChannelSequencer(SequencerOwner::new(commands)));
+        sequencer = new_sequencer.as_ref();
+    }
+    let sequencer_entity = sequencer.unwrap().0.entity;
+
+    // Effects have nothing to play
+    if has_event_input {
+        commands.queue(move |world: &mut World| {
+            audio_graph::graph_connect_event(
                 world,
-                summer_entity,
-                GraphConnection::new(port, gain_control_entity, port),
+                plugin_node_id,
+                GraphConnection::new(0, sequencer_entity, 0),
             )
             .unwrap();
-        }
-    });
+        });
+    }
 
     if let Some(new_gain_control) = new_gain_control {
         channel_entity.add_child(new_gain_control.0.entity);
         channel_entity.insert(new_gain_control);
     }
 
+    if let Some(new_sequencer) = new_sequencer {
+        channel_entity.add_child(new_sequencer.0.entity);
+        channel_entity.insert(new_sequencer);
+    }
+
+    if !has_recorder {
+        let recorder = MidiRecorderOwner::new(channel_entity.commands_mut());
+        channel_entity.add_child(recorder.entity);
+        channel_entity.insert(ChannelRecorder::new(recorder));
+    }
+
     let channel_audio_view = ChannelPluginInstance {
         plugin,
         plugin_node: plugin_node_id,
@@ -221,7 +317,419 @@ fn set_plugin<T: PluginManager>(
     };
 
     channel_entity.add_child(plugin_node_id);
-    channel_entity.insert((channel_audio_view, ChannelSourceNode(plugin_node_id)));
+    channel_entity.insert((
+        channel_audio_view,
+        ChannelPluginParams::default(),
+        ChannelSourceNode(plugin_node_id),
+    ));
+}
+
+fn load_plugin_state<T: PluginManager>(plugin_factory: &T, plugin: &T::Plugin, state_data: &[u8]) {
+    let plugin_id = T::plugin_id(plugin);
+    let result = futures::executor::block_on(async {
+        plugin_factory
+            .load_plugin_state(plugin_id, state_data.to_vec())
+            .await
+            .unwrap()
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: failed to load plugin state: {e}");
+    }
+}
+
+/// Creates the plugins for each channel's insert slots and wires them in
+/// series between the channel's plugin and its gain.
+#[allow(clippy::type_complexity)]
+fn update_inserts_system<T: PluginManager>(
+    mut commands: Commands,
+    available_plugins: Query<&AvailablePlugin>,
+    plugin_factory: NonSend<T>,
+    audio_graph: NonSend<GraphController>,
+    mut channels: Query<
+        (
+            Entity,
+            &ChannelInserts,
+            &ChannelSourceNode,
+            &ChannelGain,
+            Option<&mut ChannelInsertInstances<T::Plugin>>,
+        ),
+        Or<(
+            Changed<ChannelInserts>,
+            Changed<ChannelSourceNode>,
+            Added<ChannelGain>,
+        )>,
+    >,
+) {
+    for (entity, inserts, source, gain, mut instances) in &mut channels {
+        let mut old_instances = Vec::new();
+        if let Some(instances) = &mut instances {
+            old_instances = std::mem::take(&mut instances.0);
+        }
+
+        // Keep the plugins of slots that are still there, so they don't lose
+        // any state that hasn't been saved.
+        let mut new_instances = Vec::with_capacity(inserts.0.len());
+        for slot in &inserts.0 {
+            let existing = old_instances
+                .iter()
+                .position(|i| i.slot == slot.id && i.plugin_id == slot.plugin_id);
+            let instance = match existing {
+                Some(index) => old_instances.swap_remove(index),
+                None => {
+                    let Some(found_plugin) =
+                        available_plugins.iter().find(|p| p.0.id == slot.plugin_id)
+                    else {
+                        eprintln!("Warning: insert plugin {} not found", slot.plugin_id);
+                        continue;
+                    };
+                    create_insert(
+                        &*plugin_factory,
+                        audio_graph.sample_rate(),
+                        audio_graph.max_block_frames(),
+                        &mut commands,
+                        entity,
+                        slot,
+                        &found_plugin.0,
+                    )
+                }
+            };
+            new_instances.push(instance);
+        }
+
+        let insert_nodes: Vec<_> = new_instances.iter().map(|i| i.plugin_node).collect();
+        let removed_nodes: Vec<_> = old_instances.iter().map(|i| i.plugin_node).collect();
+        let active_nodes: Vec<_> = new_instances
+            .iter()
+            .filter(|i| inserts.slot(i.slot).is_some_and(|slot| !slot.bypassed))
+            .map(|i| i.plugin_node)
+            .collect();
+        let source = source.0;
+        let gain = gain.0.entity;
+        commands.queue(move |world: &mut World| {
+            let chain = InsertChain {
+                source,
+                inserts: &insert_nodes,
+                removed: &removed_nodes,
+                active: &active_nodes,
+                gain,
+            };
+            chain.connect(world);
+        });
+
+        for instance in old_instances {
+            commands.entity(instance.plugin_node).despawn();
+        }
+
+        match instances {
+            Some(mut instances) => instances.0 = new_instances,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(ChannelInsertInstances(new_instances));
+            }
+        }
+    }
+}
+
+fn create_insert<T: PluginManager>(
+    plugin_factory: &T,
+    sample_rate: u32,
+    max_block_frames: usize,
+    commands: &mut Commands,
+    channel: Entity,
+    slot: &ChannelInsertSlot,
+    found_plugin: &PluginDescriptor,
+) -> ChannelInsertInstance<T::Plugin> {
+    let plugin = plugin_factory.create_plugin_sync(found_plugin.clone());
+
+    let plugin_state_bytes = slot
+        .plugin_state
+        .as_deref()
+        .and_then(|s| general_purpose::STANDARD.decode(s).ok());
+    if let Some(state_data) = plugin_state_bytes {
+        load_plugin_state(plugin_factory, &plugin, &state_data);
+    }
+
+    let (plugin_node, plugin_processor) =
+        plugin_factory.create_audio_graph_node(&plugin, sample_rate, max_block_frames);
+    let plugin_node = commands.spawn(plugin_node).id();
+    commands.queue(move |world: &mut World| {
+        audio_graph::graph_set_processor(world, plugin_node, plugin_processor);
+    });
+    commands.entity(channel).add_child(plugin_node);
+
+    ChannelInsertInstance {
+        slot: slot.id,
+        plugin_id: slot.plugin_id.clone(),
+        plugin,
+        plugin_node,
+        gui_handle: None,
+    }
+}
+
+/// The audio graph nodes that make up a channel's insert chain.
+struct InsertChain<'a> {
+    source: Entity,
+    /// All the channel's insert nodes, including bypassed ones.
+    inserts: &'a [Entity],
+    /// Nodes of inserts that have just been removed from the channel.
+    removed: &'a [Entity],
+    /// The nodes to connect, in order.
+    active: &'a [Entity],
+    gain: Entity,
+}
+
+impl InsertChain<'_> {
+    /// Connects the source to the gain through each of the active insert
+    /// nodes in turn, replacing whatever connections the chain had before.
+    /// Each node is connected to the next by their channel layouts. Inserts
+    /// without audio inputs or outputs are skipped since they'd break the
+    /// chain.
+    fn connect(&self, world: &mut World) {
+        let Self {
+            source,
+            inserts,
+            removed,
+            active,
+            gain,
+        } = *self;
+
+        for &node in inserts.iter().chain([&gain]) {
+            for &input in std::iter::once(&source).chain(inserts).chain(removed) {
+                audio_graph::graph_disconnect_audio_input(world, node, input).unwrap();
+            }
+        }
+
+        let mut src = source;
+        for &dst in active.iter().chain([&gain]) {
+            let (num_inputs, num_outputs) = audio_ports(world, dst);
+            if dst != gain && (num_inputs == 0 || num_outputs == 0) {
+                continue;
+            }
+
+            audio_graph::graph_connect_audio_layout(world, dst, src).unwrap();
+            src = dst;
+        }
+    }
+}
+
+/// Creates the input and gain of each bus and group channel, in the master
+/// bus's layout. The input is connected to the gain by update_inserts_system.
+fn update_buses_system(
+    mut commands: Commands,
+    channels: Query<(Entity, &ChannelKind, &ChannelMixerState), Without<ChannelBusInput>>,
+    summer: NonSend<SummerOwner>,
+) {
+    for (entity, kind, state) in &channels {
+        if !kind.is_bus() {
+            continue;
+        }
+
+        let input = SummerOwner::spawn(&mut commands, summer.layout);
+        let gain = GainNodeOwner::new(&mut commands, state.gain_value, summer.layout);
+        let input_entity = input.entity;
+        commands
+            .entity(entity)
+            .add_children(&[input_entity, gain.entity])
+            .insert((
+                ChannelBusInput(input),
+                ChannelSourceNode(input_entity),
+                ChannelGain(gain),
+            ));
+    }
+}
+
+/// Connects each channel's gain to the bus or group it outputs to, or to the
+/// master output, and each of its sends to their bus. Channels with a device
+/// output are routed straight to the device instead, through the graph's
+/// output mapping, whose routes are owned by this system.
+#[allow(clippy::type_complexity)]
+fn update_routing_system(
+    mut commands: Commands,
+    changed: Query<
+        (),
+        Or<(
+            Changed<ChannelRouting>,
+            Changed<ChannelInserts>,
+            Changed<ChannelSourceNode>,
+            Added<ChannelGain>,
+            Added<ChannelBusInput>,
+        )>,
+    >,
+    mut removed: RemovedComponents<ChannelMixerState>,
+    mut channels: Query<(
+        Entity,
+        &StableId,
+        &ChannelRouting,
+        &ChannelGain,
+        Option<&mut ChannelSendNodes>,
+    )>,
+    buses: Query<(&StableId, &ChannelBusInput)>,
+    summer: NonSend<SummerOwner>,
+    mut output_mapping: ResMut<GraphOutputMapping>,
+) {
+    // Routing depends on other channels, so everything is rewired whenever
+    // any channel changes.
+    let any_removed = removed.read().count() > 0;
+    if changed.is_empty() && !any_removed {
+        return;
+    }
+
+    let bus_inputs: HashMap<StableId, Entity> = buses
+        .iter()
+        .map(|(id, input)| (*id, input.0.entity))
+        .collect();
+    let destinations: Vec<Entity> = std::iter::once(summer.entity)
+        .chain(bus_inputs.values().copied())
+        .collect();
+
+    // Documents edited by hand could route channels in a loop, so routes that
+    // would close a loop are dropped.
+    let mut routes = ChannelRoutes::default();
+    let mut route = |channel: StableId, target: StableId| {
+        let input = *bus_inputs.get(&target)?;
+        if routes.would_cycle(channel, target) {
+            return None;
+        }
+        routes.add(channel, target);
+        Some(input)
+    };
+
+    let mut device_routes = Vec::new();
+    for (entity, id, routing, gain, mut send_nodes) in &mut channels {
+        let output = if let Some(device_output) = routing.device_output {
+            for port in 0..summer.layout.num_channels() {
+                let device_channel = device_output.saturating_add(port);
+                device_routes.push(GraphOutputRoute::new(gain.0.entity, port, device_channel));
+            }
+            None
+        } else {
+            let output = routing.output.and_then(|output| route(*id, output));
+            Some(output.unwrap_or(summer.entity))
+        };
+
+        let mut old_nodes = Vec::new();
+        if let Some(send_nodes) = &mut send_nodes {
+            old_nodes = std::mem::take(&mut send_nodes.0);
+        }
+
+        let mut new_nodes = Vec::with_capacity(routing.sends.len());
+        let mut sends = Vec::with_capacity(routing.sends.len());
+        for send in &routing.sends {
+            let mut node = match old_nodes.iter().position(|(id, _)| *id == send.id) {
+                Some(index) => old_nodes.swap_remove(index).1,
+                None => {
+                    let node = GainNodeOwner::new(&mut commands, send.level, summer.layout);
+                    commands.entity(entity).add_child(node.entity);
+                    node
+                }
+            };
+            node.set_gain(send.level);
+            sends.push((node.entity, route(*id, send.target), send.pre_fader));
+            new_nodes.push((send.id, node));
+        }
+
+        let outputs = ChannelOutputs {
+            gain: gain.0.entity,
+            output,
+            sends,
+            removed: old_nodes.iter().map(|(_, node)| node.entity).collect(),
+            destinations: destinations.clone(),
+        };
+        commands.queue(move |world: &mut World| outputs.connect(world));
+
+        for (_, node) in old_nodes {
+            commands.entity(node.entity).despawn();
+        }
+
+        match send_nodes {
+            Some(mut send_nodes) => send_nodes.0 = new_nodes,
+            None => {
+                commands.entity(entity).insert(ChannelSendNodes(new_nodes));
+            }
+        }
+    }
+
+    if output_mapping.routes != device_routes {
+        output_mapping.routes = device_routes;
+    }
+}
+
+/// The audio graph nodes that take audio out of a channel.
+struct ChannelOutputs {
+    gain: Entity,
+    /// Where the gain's output goes, unless it goes straight to the device.
+    output: Option<Entity>,
+    /// Each send's gain node, the bus input it goes to and whether it's
+    /// pre-fader.
+    sends: Vec<(Entity, Option<Entity>, bool)>,
+    /// Nodes of sends that have just been removed from the channel.
+    removed: Vec<Entity>,
+    /// Every node that a channel can output to.
+    destinations: Vec<Entity>,
+}
+
+impl ChannelOutputs {
+    fn connect(&self, world: &mut World) {
+        let send_nodes = self.sends.iter().map(|(node, _, _)| node);
+        let outputs: Vec<_> = std::iter::once(&self.gain)
+            .chain(send_nodes)
+            .chain(&self.removed)
+            .copied()
+            .collect();
+        for &dst in &self.destinations {
+            for &output in &outputs {
+                audio_graph::graph_disconnect_audio_input(world, dst, output).unwrap();
+            }
+        }
+
+        if let Some(output) = self.output {
+            audio_graph::graph_connect_audio_layout(world, output, self.gain).unwrap();
+        }
+
+        for &(node, dst, pre_fader) in &self.sends {
+            let old_inputs = world.get::<GraphNodeDesc>(node).unwrap().inputs.clone();
+            for input in old_inputs {
+                audio_graph::graph_disconnect_audio_input(world, node, input).unwrap();
+            }
+
+            let Some(dst) = dst else {
+                continue;
+            };
+
+            if pre_fader {
+                // Pre-fader sends take whatever feeds the gain, skipping
+                // nodes that are about to be cleaned up.
+                let gain_inputs = world
+                    .get::<GraphNodeDesc>(self.gain)
+                    .unwrap()
+                    .audio_channels
+                    .connections
+                    .clone();
+                for connection in gain_inputs {
+                    if world.get_entity(connection.src).is_ok() {
+                        audio_graph::graph_connect_audio(world, node, connection).unwrap();
+                    }
+                }
+            } else {
+                audio_graph::graph_connect_audio_layout(world, node, self.gain).unwrap();
+            }
+
+            audio_graph::graph_connect_audio_layout(world, dst, node).unwrap();
+        }
+    }
+}
+
+fn audio_ports(world: &World, node: Entity) -> (u16, u16) {
+    world
+        .get::<GraphNodeDesc>(node)
+        .map(|desc| {
+            (
+                desc.audio_channels.num_inputs,
+                desc.audio_channels.num_outputs,
+            )
+        })
+        .unwrap_or_default()
 }
 
 fn sync_channel_order_system(
@@ -241,38 +749,244 @@ fn sync_channel_order_system(
 
 fn update_channels_system(
     mut commands: Commands,
-    channels: Query<(&ChannelMixerState, &ChannelSourceNode, &ChannelGain)>,
+    mut channels: Query<(
+        &StableId,
+        &ChannelMixerState,
+        &ChannelSourceNode,
+        &mut ChannelGain,
+        Option<&ChannelRecorder>,
+    )>,
+    routing: Query<(&StableId, &ChannelRouting)>,
     nodes: Query<&GraphNodeDesc>,
     midi_input: NonSend<MidiInputOwner>,
 ) {
-    let has_soloed = channels.iter().any(|(d, _, _)| d.soloed);
-    for (state, input_node, gain_control) in &channels {
-        let muted = state.muted || (has_soloed && !state.soloed);
-        let gain = if muted { 0.0 } else { state.gain_value };
-        gain_control.0.set_gain(gain);
-
-        let input_node_id = input_node.0;
-        let Ok(input_node) = nodes.get(input_node_id) else {
-            continue;
-        };
+    let soloed: Vec<StableId> = channels
+        .iter()
+        .filter(|(_, state, _, _, _)| state.soloed)
+        .map(|(id, _, _, _, _)| *id)
+        .collect();
+    let routes = ChannelRoutes::new(&routing);
+
+    // Soloing a channel keeps the buses it goes through audible, and soloing
+    // a bus keeps the channels that feed it audible.
+    let is_soloed = |id: StableId| {
+        soloed
+            .iter()
+            .any(|&s| s == id || routes.reaches(s, id) || routes.reaches(id, s))
+    };
+
+    for (id, state, input_node, mut gain_control, recorder) in &mut channels {
+        let muted = state.muted || (!soloed.is_empty() && !is_soloed(*id));
+        gain_control.0.set_gain(state.gain_value);
+        gain_control.0.set_muted(muted);
+        gain_control.0.set_pan(state.pan, state.pan_law.into());
+
         let midi_input = midi_input.entity;
-        if state.armed {
-            if !input_node.has_event_connected(midi_input) {
-                commands.queue(move |world: &mut World| {
-                    audio_graph::graph_connect_event(
-                        world,
-                        input_node_id,
-                        GraphConnection::new(0, midi_input, 0),
-                    )
-                    .unwrap();
-                });
+        connect_midi_input(&mut commands, &nodes, input_node.0, midi_input, state.armed);
+        if let Some(recorder) = recorder {
+            connect_midi_input(
+                &mut commands,
+                &nodes,
+                recorder.recorder.entity,
+                midi_input,
+                state.armed,
+            );
+        }
+    }
+}
+
+fn connect_midi_input(
+    commands: &mut Commands,
+    nodes: &Query<&GraphNodeDesc>,
+    node_id: Entity,
+    midi_input: Entity,
+    connect: bool,
+) {
+    let Ok(node) = nodes.get(node_id) else {
+        return;
+    };
+
+    // Effects and buses have nowhere to put MIDI
+    let connect = connect && node.event_channels.num_inputs > 0;
+
+    if connect {
+        if !node.has_event_connected(midi_input) {
+            commands.queue(move |world: &mut World| {
+                audio_graph::graph_connect_event(
+                    world,
+                    node_id,
+                    GraphConnection::new(0, midi_input, 0),
+                )
+                .unwrap();
+            });
+        }
+    } else if node.has_event_connected(midi_input) {
+        commands.queue(move |world: &mut World| {
+            audio_graph::graph_disconnect_event_input(world, node_id, midi_input).unwrap();
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_sequencers_system(
+    channels: Query<
+        (&ChannelClips, &ChannelSequencer),
+        Or<(Changed<ChannelClips>, Added<ChannelSequencer>)>,
+    >,
+) {
+    for (clips, sequencer) in &channels {
+        let notes = clips
+            .0
+            .iter()
+            .flat_map(MidiClip::timeline_notes)
+            .map(|note| SequencerNote {
+                key: note.key,
+                velocity: note.velocity,
+                start: note.start,
+                end: note.start + note.length,
+            })
+            .collect();
+        sequencer.0.set_notes(notes);
+    }
+}
+
+/// Sends each channel's automation to its gain node and plugin.
+#[allow(clippy::type_complexity)]
+fn update_automation_system<T: PluginManager>(
+    channels: Query<
+        (
+            &ChannelAutomation,
+            Option<&ChannelGain>,
+            Option<(&ChannelPluginInstance<T::Plugin>, &ChannelPluginParams)>,
+        ),
+        Or<(
+            Changed<ChannelAutomation>,
+            Added<ChannelGain>,
+            Changed<ChannelPluginParams>,
+        )>,
+    >,
+) {
+    for (automation, gain, plugin) in &channels {
+        if let Some(gain) = gain {
+            gain.0.set_automation(automation.gain_automation());
+        }
+        if let Some((plugin, params)) = plugin {
+            T::set_param_automation(&plugin.plugin, automation.param_automation(&params.params));
+        }
+    }
+}
+
+/// Keeps each channel's `ChannelPluginParams` in step with its plugin, and
+/// sends the plugin any values set by the host.
+fn update_plugin_params_system<T: PluginManager>(
+    mut channels: Query<(
+        &ChannelPluginInstance<T::Plugin>,
+        &mut ChannelPluginParams,
+        &mut ChannelAutomation,
+    )>,
+) {
+    for (plugin, mut params, mut automation) in &mut channels {
+        for event in T::receive_param_events(&plugin.plugin) {
+            match event {
+                ClapParamEvent::Rescanned(new_params) => params.params = new_params,
+                ClapParamEvent::Value(param_id, value) => {
+                    if let Some(param) = params.params.iter_mut().find(|p| p.id == param_id) {
+                        param.value = value;
+                    }
+                }
+                // The parameter is still there, only what refers to it goes.
+                // Rescans are what change the list of parameters.
+                ClapParamEvent::Cleared(param_id) => {
+                    automation
+                        .lanes
+                        .retain(|lane| lane.target != AutomationTarget::ClapParam(param_id));
+                }
             }
-        } else if input_node.has_event_connected(midi_input) {
+        }
+
+        let pending = std::mem::take(&mut params.bypass_change_detection().pending);
+        for (param_id, value) in pending {
+            T::set_param_value(&plugin.plugin, param_id, value);
+        }
+    }
+}
+
+/// Swaps a new processor into the node of every plugin that has asked to be
+/// restarted. The graph retires the old processor, which deactivates the
+/// plugin so that the new one can activate it again.
+#[allow(clippy::type_complexity)]
+fn restart_plugins_system<T: PluginManager>(
+    mut commands: Commands,
+    channels: Query<(
+        Option<&ChannelPluginInstance<T::Plugin>>,
+        Option<(&ChannelAutomation, &ChannelPluginParams)>,
+        Option<&ChannelInsertInstances<T::Plugin>>,
+    )>,
+    plugin_factory: NonSend<T>,
+    audio_graph: NonSend<GraphController>,
+) {
+    let mut restart = |plugin: &T::Plugin, node: Entity| {
+        let processor = plugin_factory.restart_audio_graph_node(
+            plugin,
+            audio_graph.sample_rate(),
+            audio_graph.max_block_frames(),
+        );
+        let restarted = processor.is_some();
+        if let Some(processor) = processor {
             commands.queue(move |world: &mut World| {
-                audio_graph::graph_disconnect_event_input(world, input_node_id, midi_input)
-                    .unwrap();
+                audio_graph::graph_set_processor(world, node, processor);
             });
         }
+        restarted
+    };
+
+    for (plugin, automation, inserts) in &channels {
+        if let Some(plugin) = plugin
+            && restart(&plugin.plugin, plugin.plugin_node)
+            && let Some((automation, params)) = automation
+        {
+            // The automation went with the old processor
+            T::set_param_automation(&plugin.plugin, automation.param_automation(&params.params));
+        }
+
+        for instance in inserts.iter().flat_map(|inserts| &inserts.0) {
+            restart(&instance.plugin, instance.plugin_node);
+        }
+    }
+}
+
+/// Collects the notes recorded on each channel, turning them into a clip when
+/// recording stops.
+fn record_system(
+    mut channels: Query<(&StableId, &mut ChannelRecorder, &mut ChannelClips)>,
+    record_mode: Res<RecordMode>,
+    mut edit_history: NonSendMut<EditHistory>,
+) {
+    for (channel, mut recorder, mut clips) in &mut channels {
+        let ChannelRecorder { recorder, take } = recorder.as_mut();
+
+        for event in recorder.receive() {
+            match event {
+                MidiRecorderEvent::Note {
+                    key,
+                    velocity,
+                    start,
+                    end,
+                } => take.push(MidiClipNote {
+                    key,
+                    velocity,
+                    start,
+                    length: end - start,
+                }),
+                MidiRecorderEvent::Stopped => {
+                    if let Some(clip) = MidiClip::from_recording(std::mem::take(take)) {
+                        let undo = SetChannelClipsEdit::new(*channel, clips.0.clone());
+                        record_mode.record(&mut clips.0, clip);
+                        edit_history.add_undo(Box::new(undo));
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -2,10 +2,12 @@ use bevy_ecs::{name::Name, prelude::*};
 use bevy_reflect::Reflect;
 use serde::{Deserialize, Serialize};
 
-use engine::builtin::GainNodeOwner;
-use engine::plugins::{ClapId, ClapProxy, PluginGuiHandle, PluginManager};
+use engine::builtin::{GainNodeOwner, MidiRecorderOwner, PanLaw, SequencerOwner, SummerOwner};
+use engine::plugins::{ClapId, ClapParam, ClapProxy, PluginGuiHandle, PluginManager};
 
-use crate::StableId;
+use crate::{
+    ChannelAutomation, ChannelClips, ChannelKind, ChannelRouting, MidiClip, MidiClipNote, StableId,
+};
 
 #[derive(Component, Reflect)]
 pub(crate) struct ChannelSourceNode(pub Entity);
@@ -19,12 +21,25 @@ pub struct ChannelPluginBinding {
 }
 
 #[derive(Component, Debug, Clone, Serialize, Deserialize, Reflect)]
-#[require(StableId=StableId::new(), Name)]
+#[require(
+    StableId=StableId::new(),
+    Name,
+    ChannelKind,
+    ChannelClips,
+    ChannelAutomation,
+    ChannelInserts,
+    ChannelRouting
+)]
 pub struct ChannelMixerState {
     pub gain_value: f32,
     pub muted: bool,
     pub soloed: bool,
     pub armed: bool,
+    /// -1 is hard left, 1 is hard right.
+    #[serde(default)]
+    pub pan: f32,
+    #[serde(default)]
+    pub pan_law: ChannelPanLaw,
 }
 
 impl Default for ChannelMixerState {
@@ -34,6 +49,8 @@ impl Default for ChannelMixerState {
             muted: false,
             soloed: false,
             armed: false,
+            pan: 0.0,
+            pan_law: ChannelPanLaw::default(),
         }
     }
 }
@@ -56,6 +73,45 @@ impl ChannelMixerState {
     }
 }
 
+/// How a channel's pan splits it between the left and right outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum ChannelPanLaw {
+    #[default]
+    Balance,
+    ConstantPower,
+    Compromise,
+    Linear,
+}
+
+impl ChannelPanLaw {
+    pub const ALL: [ChannelPanLaw; 4] = [
+        ChannelPanLaw::Balance,
+        ChannelPanLaw::ConstantPower,
+        ChannelPanLaw::Compromise,
+        ChannelPanLaw::Linear,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ChannelPanLaw::Balance => "Balance",
+            ChannelPanLaw::ConstantPower => "-3 dB",
+            ChannelPanLaw::Compromise => "-4.5 dB",
+            ChannelPanLaw::Linear => "-6 dB",
+        }
+    }
+}
+
+impl From<ChannelPanLaw> for PanLaw {
+    fn from(law: ChannelPanLaw) -> Self {
+        match law {
+            ChannelPanLaw::Balance => PanLaw::Balance,
+            ChannelPanLaw::ConstantPower => PanLaw::ConstantPower,
+            ChannelPanLaw::Compromise => PanLaw::Compromise,
+            ChannelPanLaw::Linear => PanLaw::Linear,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct ChannelPluginInstance<P: Component = ClapProxy> {
     pub(crate) plugin: P,
@@ -63,11 +119,169 @@ pub struct ChannelPluginInstance<P: Component = ClapProxy> {
     pub(crate) gui_handle: Option<PluginGuiHandle>,
 }
 
+/// An effect plugin that the channel's audio runs through, after the
+/// channel's own plugin and before its gain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct ChannelInsertSlot {
+    pub id: StableId,
+    pub plugin_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin_state: Option<String>,
+    #[serde(default)]
+    pub bypassed: bool,
+}
+
+impl ChannelInsertSlot {
+    pub fn new(plugin_id: impl Into<String>) -> Self {
+        Self {
+            id: StableId::new(),
+            plugin_id: plugin_id.into(),
+            plugin_state: None,
+            bypassed: false,
+        }
+    }
+}
+
+/// The channel's insert slots, in processing order.
+#[derive(Component, Debug, Clone, Default, PartialEq, Serialize, Deserialize, Reflect)]
+#[require(ChannelMixerState)]
+pub struct ChannelInserts(pub Vec<ChannelInsertSlot>);
+
+impl ChannelInserts {
+    pub fn slot(&self, slot: StableId) -> Option<&ChannelInsertSlot> {
+        self.0.iter().find(|s| s.id == slot)
+    }
+
+    pub(crate) fn index_of(&self, slot: StableId) -> Option<usize> {
+        self.0.iter().position(|s| s.id == slot)
+    }
+}
+
+/// The plugins created for a channel's insert slots, in slot order.
+#[derive(Component)]
+pub struct ChannelInsertInstances<P: Component = ClapProxy>(
+    pub(crate) Vec<ChannelInsertInstance<P>>,
+);
+
+impl<P: Component> Default for ChannelInsertInstances<P> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<P: Component> ChannelInsertInstances<P> {
+    pub fn get(&self, slot: StableId) -> Option<&ChannelInsertInstance<P>> {
+        self.0.iter().find(|instance| instance.slot == slot)
+    }
+
+    pub fn get_mut(&mut self, slot: StableId) -> Option<&mut ChannelInsertInstance<P>> {
+        self.0.iter_mut().find(|instance| instance.slot == slot)
+    }
+}
+
+pub struct ChannelInsertInstance<P> {
+    pub(crate) slot: StableId,
+    /// The plugin the instance was created for, so a slot whose plugin is
+    /// replaced gets a new instance.
+    pub(crate) plugin_id: String,
+    pub(crate) plugin: P,
+    pub(crate) plugin_node: Entity,
+    pub(crate) gui_handle: Option<PluginGuiHandle>,
+}
+
+impl<P> ChannelInsertInstance<P> {
+    pub fn has_gui(&self) -> bool {
+        self.gui_handle
+            .as_ref()
+            .map(|h| h.is_visible())
+            .unwrap_or(false)
+    }
+
+    pub fn plugin_id<T: PluginManager<Plugin = P>>(&self) -> ClapId {
+        T::plugin_id(&self.plugin)
+    }
+
+    pub fn window_title<T: PluginManager<Plugin = P>>(&self, channel_name: &str) -> String {
+        format!("{}: {channel_name}", T::plugin_name(&self.plugin))
+    }
+
+    pub fn set_gui_handle(&mut self, gui_handle: PluginGuiHandle) {
+        self.gui_handle = Some(gui_handle);
+    }
+}
+
 #[derive(Component, Debug, Reflect)]
 #[reflect(from_reflect = false)]
 #[require(ChannelMixerState)]
 pub struct ChannelGain(#[reflect(ignore)] pub GainNodeOwner);
 
+/// The parameters of a channel's plugin, as last reported by the plugin.
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(from_reflect = false)]
+#[require(ChannelMixerState)]
+pub struct ChannelPluginParams {
+    #[reflect(ignore)]
+    pub params: Vec<ClapParam>,
+    /// Values set by the host that haven't been sent to the plugin yet.
+    #[reflect(ignore)]
+    pub(crate) pending: Vec<(u32, f64)>,
+}
+
+impl ChannelPluginParams {
+    pub fn param(&self, param_id: u32) -> Option<&ClapParam> {
+        self.params.iter().find(|p| p.id == param_id)
+    }
+
+    /// Sets a parameter to a value in its own range and queues the change to
+    /// be sent to the plugin. Returns the old value.
+    pub fn set_value(&mut self, param_id: u32, value: f64) -> Option<f64> {
+        let param = self.params.iter_mut().find(|p| p.id == param_id)?;
+        let value = value.clamp(param.min, param.max);
+        let old_value = std::mem::replace(&mut param.value, value);
+        self.pending.push((param_id, value));
+        Some(old_value)
+    }
+}
+
+/// Sums the audio routed and sent to a bus or group channel.
+#[derive(Component, Debug, Reflect)]
+#[reflect(from_reflect = false)]
+#[require(ChannelMixerState)]
+pub struct ChannelBusInput(#[reflect(ignore)] pub SummerOwner);
+
+/// The gain nodes that set the level of each of a channel's sends.
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(from_reflect = false)]
+#[require(ChannelMixerState)]
+pub struct ChannelSendNodes(#[reflect(ignore)] pub(crate) Vec<(StableId, GainNodeOwner)>);
+
+/// Plays the channel's clips into its plugin.
+#[derive(Component, Debug, Reflect)]
+#[reflect(from_reflect = false)]
+#[require(ChannelMixerState)]
+pub struct ChannelSequencer(#[reflect(ignore)] pub SequencerOwner);
+
+/// Records live MIDI into a new clip while the channel is armed.
+#[derive(Component, Debug, Reflect)]
+#[reflect(from_reflect = false)]
+#[require(ChannelMixerState)]
+pub struct ChannelRecorder {
+    #[reflect(ignore)]
+    pub recorder: MidiRecorderOwner,
+    /// Notes recorded so far, positioned on the timeline.
+    #[reflect(ignore)]
+    pub(crate) take: Vec<MidiClipNote>,
+}
+
+impl ChannelRecorder {
+    pub fn new(recorder: MidiRecorderOwner) -> Self {
+        Self {
+            recorder,
+            take: Vec::new(),
+        }
+    }
+}
+
 impl<P: Component> ChannelPluginInstance<P> {
     pub fn has_gui(&self) -> bool {
         self.gui_handle
@@ -99,18 +313,28 @@ pub enum ChannelButton {
 #[derive(Debug, Clone)]
 pub struct ChannelSnapshot {
     pub name: Name,
+    pub kind: ChannelKind,
     pub state: ChannelMixerState,
     pub data: Option<ChannelPluginBinding>,
     pub id: StableId,
+    pub clips: Vec<MidiClip>,
+    pub automation: ChannelAutomation,
+    pub inserts: ChannelInserts,
+    pub routing: ChannelRouting,
 }
 
 impl Default for ChannelSnapshot {
     fn default() -> Self {
         Self {
             name: Name::new("unnamed channel"),
+            kind: ChannelKind::default(),
             state: ChannelMixerState::default(),
             data: None,
             id: StableId::new(),
+            clips: Vec::new(),
+            automation: ChannelAutomation::default(),
+            inserts: ChannelInserts::default(),
+            routing: ChannelRouting::default(),
         }
     }
 }
@@ -1,9 +1,12 @@
 use bevy_ecs::{name::Name, prelude::*};
 
 use crate::commands::EditCommand;
-use crate::{ChannelOrder, StableId};
+use crate::{ChannelAutomation, ChannelClips, ChannelKind, ChannelOrder, ChannelRouting, StableId};
 
-use super::components::{ChannelButton, ChannelMixerState, ChannelPluginBinding, ChannelSnapshot};
+use super::components::{
+    ChannelButton, ChannelInsertSlot, ChannelInserts, ChannelMixerState, ChannelPanLaw,
+    ChannelPluginBinding, ChannelPluginParams, ChannelSnapshot,
+};
 
 #[derive(Debug)]
 pub struct RenameChannelEdit {
@@ -75,11 +78,16 @@ impl EditCommand for AddChannelEdit {
         let mut entity = world.spawn((
             self.snapshot.state.clone(),
             self.snapshot.name.clone(),
+            self.snapshot.kind,
             self.snapshot.id,
         ));
         if let Some(data) = &self.snapshot.data {
             entity.insert(data.clone());
         }
+        entity.insert(ChannelClips(self.snapshot.clips.clone()));
+        entity.insert(self.snapshot.automation.clone());
+        entity.insert(self.snapshot.inserts.clone());
+        entity.insert(self.snapshot.routing.clone());
         let entity_id = entity.id();
 
         let mut query = world.query::<&mut ChannelOrder>();
@@ -110,9 +118,14 @@ impl EditCommand for DeleteChannelEdit {
         let entity = self.channel.find_entity(world)?;
 
         let name = world.get::<Name>(entity)?.clone();
+        let kind = *world.get::<ChannelKind>(entity)?;
         let state = world.get::<ChannelMixerState>(entity)?.clone();
         let data = world.get::<ChannelPluginBinding>(entity).cloned();
         let id = *world.get::<StableId>(entity)?;
+        let clips = world.get::<ChannelClips>(entity)?.0.clone();
+        let automation = world.get::<ChannelAutomation>(entity)?.clone();
+        let inserts = world.get::<ChannelInserts>(entity)?.clone();
+        let routing = world.get::<ChannelRouting>(entity)?.clone();
 
         let mut query = world.query::<&mut ChannelOrder>();
         let mut channel_order = query.single_mut(world).ok()?;
@@ -122,9 +135,14 @@ impl EditCommand for DeleteChannelEdit {
 
         let snapshot = ChannelSnapshot {
             name,
+            kind,
             state,
             data,
             id,
+            clips,
+            automation,
+            inserts,
+            routing,
         };
 
         Some(Box::new(AddChannelEdit::new(self.index, snapshot)))
@@ -211,3 +229,180 @@ impl EditCommand for SetGainEdit {
         Some(Box::new(SetGainEdit::new(self.channel, old_value)))
     }
 }
+
+#[derive(Debug)]
+pub struct SetPanEdit {
+    channel: StableId,
+    pan: f32,
+}
+
+impl SetPanEdit {
+    pub fn new(channel: StableId, pan: f32) -> Self {
+        Self { channel, pan }
+    }
+}
+
+impl EditCommand for SetPanEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        let mut state = world.get_mut::<ChannelMixerState>(entity)?;
+        let old_value = state.pan;
+        state.pan = self.pan.clamp(-1.0, 1.0);
+        Some(Box::new(SetPanEdit::new(self.channel, old_value)))
+    }
+}
+
+#[derive(Debug)]
+pub struct SetPanLawEdit {
+    channel: StableId,
+    pan_law: ChannelPanLaw,
+}
+
+impl SetPanLawEdit {
+    pub fn new(channel: StableId, pan_law: ChannelPanLaw) -> Self {
+        Self { channel, pan_law }
+    }
+}
+
+impl EditCommand for SetPanLawEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        let mut state = world.get_mut::<ChannelMixerState>(entity)?;
+        let old_value = state.pan_law;
+        state.pan_law = self.pan_law;
+        Some(Box::new(SetPanLawEdit::new(self.channel, old_value)))
+    }
+}
+
+/// Sets one of the channel's plugin parameters, in the parameter's own range.
+#[derive(Debug)]
+pub struct SetPluginParamEdit {
+    channel: StableId,
+    param_id: u32,
+    value: f64,
+}
+
+impl SetPluginParamEdit {
+    pub fn new(channel: StableId, param_id: u32, value: f64) -> Self {
+        Self {
+            channel,
+            param_id,
+            value,
+        }
+    }
+}
+
+impl EditCommand for SetPluginParamEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        let old_value = world
+            .get_mut::<ChannelPluginParams>(entity)?
+            .set_value(self.param_id, self.value)?;
+        Some(Box::new(SetPluginParamEdit::new(
+            self.channel,
+            self.param_id,
+            old_value,
+        )))
+    }
+}
+
+/// Finds the channel that owns an insert slot, and the slot's index within its
+/// `ChannelInserts`.
+fn find_insert(world: &mut World, slot: StableId) -> Option<(Entity, usize)> {
+    let mut query = world.query::<(Entity, &ChannelInserts)>();
+    query
+        .iter(world)
+        .find_map(|(entity, inserts)| inserts.index_of(slot).map(|index| (entity, index)))
+}
+
+#[derive(Debug)]
+pub struct AddInsertEdit {
+    channel: StableId,
+    index: usize,
+    slot: ChannelInsertSlot,
+}
+
+impl AddInsertEdit {
+    pub fn new(channel: StableId, index: usize, slot: ChannelInsertSlot) -> Self {
+        Self {
+            channel,
+            index,
+            slot,
+        }
+    }
+}
+
+impl EditCommand for AddInsertEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        let mut inserts = world.get_mut::<ChannelInserts>(entity)?;
+        let index = self.index.min(inserts.0.len());
+        inserts.0.insert(index, self.slot.clone());
+        Some(Box::new(RemoveInsertEdit::new(self.slot.id)))
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoveInsertEdit {
+    slot: StableId,
+}
+
+impl RemoveInsertEdit {
+    pub fn new(slot: StableId) -> Self {
+        Self { slot }
+    }
+}
+
+impl EditCommand for RemoveInsertEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_insert(world, self.slot)?;
+        let channel = *world.get::<StableId>(entity)?;
+        let slot = world.get_mut::<ChannelInserts>(entity)?.0.remove(index);
+        Some(Box::new(AddInsertEdit::new(channel, index, slot)))
+    }
+}
+
+/// Moves an insert slot so that it ends up at `index` in the channel's chain.
+#[derive(Debug)]
+pub struct MoveInsertEdit {
+    slot: StableId,
+    index: usize,
+}
+
+impl MoveInsertEdit {
+    pub fn new(slot: StableId, index: usize) -> Self {
+        Self { slot, index }
+    }
+}
+
+impl EditCommand for MoveInsertEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, old_index) = find_insert(world, self.slot)?;
+        let mut inserts = world.get_mut::<ChannelInserts>(entity)?;
+        let slot = inserts.0.remove(old_index);
+        let index = self.index.min(inserts.0.len());
+        inserts.0.insert(index, slot);
+        Some(Box::new(MoveInsertEdit::new(self.slot, old_index)))
+    }
+}
+
+#[derive(Debug)]
+pub struct SetInsertBypassEdit {
+    slot: StableId,
+    bypassed: bool,
+}
+
+impl SetInsertBypassEdit {
+    pub fn new(slot: StableId, bypassed: bool) -> Self {
+        Self { slot, bypassed }
+    }
+}
+
+impl EditCommand for SetInsertBypassEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_insert(world, self.slot)?;
+        let mut inserts = world.get_mut::<ChannelInserts>(entity)?;
+        let old_value = std::mem::replace(&mut inserts.0[index].bypassed, self.bypassed);
+        Some(Box::new(SetInsertBypassEdit::new(self.slot, old_value)))
+    }
+}
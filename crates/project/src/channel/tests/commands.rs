@@ -1,5 +1,7 @@
 use super::*;
 
+use engine::builtin::PanLaw;
+
 fn setup_world_with_4_channels() -> (World, [StableId; 4]) {
     let mut world = setup_world();
     let ids: [StableId; 4] = std::array::from_fn(|_| StableId::new());
@@ -388,3 +390,151 @@ fn set_gain_roundtrip() {
         1.0
     );
 }
+
+#[test]
+fn set_pan_and_undo() {
+    let mut world = setup_world();
+    let snapshot = ChannelSnapshot::default();
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    let entity = id.find_entity(&mut world).unwrap();
+
+    let undo = SetPanEdit::new(id, -0.5).execute(&mut world).unwrap();
+    assert_eq!(world.get::<ChannelMixerState>(entity).unwrap().pan, -0.5);
+
+    undo.execute(&mut world);
+    assert_eq!(world.get::<ChannelMixerState>(entity).unwrap().pan, 0.0);
+}
+
+#[test]
+fn set_pan_is_clamped() {
+    let mut world = setup_world();
+    let snapshot = ChannelSnapshot::default();
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    SetPanEdit::new(id, 3.0).execute(&mut world);
+
+    let entity = id.find_entity(&mut world).unwrap();
+    assert_eq!(world.get::<ChannelMixerState>(entity).unwrap().pan, 1.0);
+}
+
+#[test]
+fn set_pan_law_and_undo() {
+    let mut world = setup_world();
+    let snapshot = ChannelSnapshot::default();
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    let entity = id.find_entity(&mut world).unwrap();
+
+    let undo = SetPanLawEdit::new(id, ChannelPanLaw::ConstantPower)
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(
+        world.get::<ChannelMixerState>(entity).unwrap().pan_law,
+        ChannelPanLaw::ConstantPower
+    );
+
+    undo.execute(&mut world);
+    assert_eq!(
+        world.get::<ChannelMixerState>(entity).unwrap().pan_law,
+        ChannelPanLaw::Balance
+    );
+}
+
+#[test]
+fn pan_laws_at_centre() {
+    let centre = |law: ChannelPanLaw| PanLaw::from(law).gains(0.0)[0];
+
+    assert_eq!(centre(ChannelPanLaw::Balance), 1.0);
+    assert!((centre(ChannelPanLaw::ConstantPower) - 0.5f32.sqrt()).abs() < 1e-6);
+    assert!((centre(ChannelPanLaw::Compromise) - 0.5f32.powf(0.75)).abs() < 1e-6);
+    assert_eq!(centre(ChannelPanLaw::Linear), 0.5);
+}
+
+fn get_insert_ids(world: &mut World, channel: StableId) -> Vec<StableId> {
+    let entity = channel.find_entity(world).unwrap();
+    world
+        .get::<ChannelInserts>(entity)
+        .unwrap()
+        .0
+        .iter()
+        .map(|slot| slot.id)
+        .collect()
+}
+
+#[test]
+fn add_and_remove_inserts() {
+    let mut world = setup_world();
+    let snapshot = ChannelSnapshot::default();
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    let a = ChannelInsertSlot::new("com.test.fx-a");
+    let b = ChannelInsertSlot::new("com.test.fx-b");
+    AddInsertEdit::new(id, 0, a.clone()).execute(&mut world);
+    let undo = AddInsertEdit::new(id, 0, b.clone())
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_insert_ids(&mut world, id), vec![b.id, a.id]);
+
+    undo.execute(&mut world);
+    assert_eq!(get_insert_ids(&mut world, id), vec![a.id]);
+
+    // Removing a slot keeps its state and position for undo
+    let mut c = ChannelInsertSlot::new("com.test.fx-c");
+    c.plugin_state = Some("state".to_owned());
+    AddInsertEdit::new(id, 0, c.clone()).execute(&mut world);
+    let undo = RemoveInsertEdit::new(c.id).execute(&mut world).unwrap();
+    assert_eq!(get_insert_ids(&mut world, id), vec![a.id]);
+    undo.execute(&mut world);
+
+    let entity = id.find_entity(&mut world).unwrap();
+    assert_eq!(world.get::<ChannelInserts>(entity).unwrap().0, vec![c, a]);
+}
+
+#[test]
+fn move_and_bypass_inserts() {
+    let mut world = setup_world();
+    let slots: Vec<_> = ["a", "b", "c"].map(ChannelInsertSlot::new).into();
+    let ids: Vec<_> = slots.iter().map(|slot| slot.id).collect();
+    let snapshot = ChannelSnapshot {
+        inserts: ChannelInserts(slots),
+        ..Default::default()
+    };
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    let undo = MoveInsertEdit::new(ids[0], 2).execute(&mut world).unwrap();
+    assert_eq!(get_insert_ids(&mut world, id), vec![ids[1], ids[2], ids[0]]);
+    undo.execute(&mut world);
+    assert_eq!(get_insert_ids(&mut world, id), ids);
+
+    let undo = SetInsertBypassEdit::new(ids[1], true)
+        .execute(&mut world)
+        .unwrap();
+    let entity = id.find_entity(&mut world).unwrap();
+    assert!(world.get::<ChannelInserts>(entity).unwrap().0[1].bypassed);
+    undo.execute(&mut world);
+    assert!(!world.get::<ChannelInserts>(entity).unwrap().0[1].bypassed);
+}
+
+#[test]
+fn delete_channel_keeps_inserts() {
+    let mut world = setup_world();
+    let snapshot = ChannelSnapshot {
+        inserts: ChannelInserts(vec![ChannelInsertSlot::new("com.test.fx")]),
+        ..Default::default()
+    };
+    let id = snapshot.id;
+    let inserts = snapshot.inserts.clone();
+    AddChannelEdit::new(0, snapshot).execute(&mut world);
+
+    let undo = DeleteChannelEdit::new(id, 0).execute(&mut world).unwrap();
+    undo.execute(&mut world);
+
+    let entity = id.find_entity(&mut world).unwrap();
+    assert_eq!(*world.get::<ChannelInserts>(entity).unwrap(), inserts);
+}
@@ -1,34 +1,108 @@
 use std::cell::Cell;
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-use audio_graph::GraphProcessor;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use audio_blocks::AudioBlockMut;
+use audio_graph::{
+    GraphChannelLayout, GraphOutputMapping, GraphOutputRoute, GraphProcessor, GraphSeekEvent,
+    GraphTransport, GraphWorker,
+};
 use bevy_app::prelude::*;
-use engine::plugins::ClapId;
+use engine::{
+    automation::AutomationEnvelope,
+    plugins::{ClapId, ClapParam, ClapParamEvent},
+    render::{OfflineRender, OfflineRenderSettings},
+};
 
 use super::*;
+use crate::{
+    AddAutomationLaneEdit, AddClipEdit, AddSendEdit, AutomationLane, ChannelSend, MidiClip,
+    MidiClipNote, RemoveSendEdit, SetChannelDeviceOutputEdit, SetChannelOutputEdit, SetSendEdit,
+};
 
 static NEXT_MOCK_PLUGIN_ID: AtomicUsize = AtomicUsize::new(1);
 
+/// Counts how many plugin processors are alive. Outputs silence until it's
+/// sent an event, and a constant 1 from then on.
 #[derive(Debug)]
-struct NoOpProcessor;
-impl GraphProcessor for NoOpProcessor {
-    fn process(&mut self, _ctx: audio_graph::GraphProcessContext) {}
+struct MockProcessor {
+    live: Arc<AtomicUsize>,
+    sounding: bool,
+}
+
+impl MockProcessor {
+    fn new(live: Arc<AtomicUsize>) -> Self {
+        Self {
+            live,
+            sounding: false,
+        }
+    }
+}
+
+impl GraphProcessor for MockProcessor {
+    fn process(&mut self, ctx: audio_graph::GraphProcessContext) {
+        for GraphConnection {
+            src, src_channel, ..
+        } in &ctx.node.desc.event_channels.connections
+        {
+            if let Some(node) = ctx.graph.get_node(*src) {
+                self.sounding |= !node.output_event_buffers.get()[*src_channel as usize].is_empty();
+            }
+        }
+
+        let value = if self.sounding { 1.0 } else { 0.0 };
+        for sample in ctx.out_audio_buffers.raw_data_mut() {
+            *sample = value;
+        }
+    }
+}
+
+impl Drop for MockProcessor {
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 #[derive(Component)]
 pub(super) struct MockPlugin {
     plugin_id: ClapId,
     plugin_name: String,
+    /// Events for `receive_param_events` to return.
+    param_events: Mutex<Vec<ClapParamEvent>>,
+    /// Values passed to `set_param_value`.
+    param_values: Mutex<Vec<(u32, f64)>>,
+    restart_requested: AtomicBool,
+}
+
+fn mock_param(id: u32) -> ClapParam {
+    ClapParam {
+        id,
+        name: format!("Param {id}"),
+        module: String::new(),
+        min: 0.0,
+        max: 10.0,
+        default: 5.0,
+        value: 5.0,
+        automatable: true,
+        stepped: false,
+        hidden: false,
+        read_only: false,
+    }
 }
 
 struct MockPluginManager {
     plugins_created: Cell<usize>,
+    live_processors: Arc<AtomicUsize>,
+    restarts: Cell<usize>,
 }
 
 impl MockPluginManager {
     fn new() -> Self {
         Self {
             plugins_created: Cell::new(0),
+            live_processors: Arc::default(),
+            restarts: Cell::new(0),
         }
     }
 }
@@ -42,6 +116,12 @@ impl PluginManager for MockPluginManager {
         MockPlugin {
             plugin_id: ClapId::from_raw(id),
             plugin_name: plugin.name,
+            param_events: Mutex::new(vec![ClapParamEvent::Rescanned(vec![
+                mock_param(1),
+                mock_param(2),
+            ])]),
+            param_values: Mutex::new(Vec::new()),
+            restart_requested: AtomicBool::new(false),
         }
     }
 
@@ -53,6 +133,16 @@ impl PluginManager for MockPluginManager {
         &plugin.plugin_name
     }
 
+    fn set_param_automation(_plugin: &MockPlugin, _automation: Vec<(u32, AutomationEnvelope)>) {}
+
+    fn set_param_value(plugin: &MockPlugin, param_id: u32, value: f64) {
+        plugin.param_values.lock().unwrap().push((param_id, value));
+    }
+
+    fn receive_param_events(plugin: &MockPlugin) -> Vec<ClapParamEvent> {
+        std::mem::take(&mut plugin.param_events.lock().unwrap())
+    }
+
     fn load_plugin_state(
         &self,
         _clap_plugin_id: ClapId,
@@ -85,19 +175,43 @@ impl PluginManager for MockPluginManager {
     fn create_audio_graph_node(
         &self,
         _plugin: &MockPlugin,
+        _sample_rate: u32,
+        _max_block_frames: usize,
     ) -> (audio_graph::GraphNodeDesc, Box<dyn GraphProcessor>) {
         let node = audio_graph::GraphNodeDesc::default()
-            .audio(0, 2)
+            .audio(2, 2)
             .event(1, 0);
-        (node, Box::new(NoOpProcessor))
+        self.live_processors.fetch_add(1, Ordering::Relaxed);
+        (
+            node,
+            Box::new(MockProcessor::new(self.live_processors.clone())),
+        )
+    }
+
+    fn restart_audio_graph_node(
+        &self,
+        plugin: &MockPlugin,
+        _sample_rate: u32,
+        _max_block_frames: usize,
+    ) -> Option<Box<dyn GraphProcessor>> {
+        if !plugin.restart_requested.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        self.restarts.set(self.restarts.get() + 1);
+        self.live_processors.fetch_add(1, Ordering::Relaxed);
+        Some(Box::new(MockProcessor::new(self.live_processors.clone())))
     }
 }
 
 fn setup_test_app() -> App {
+    setup_test_app_with_layout(GraphChannelLayout::Stereo)
+}
+
+fn setup_test_app_with_layout(layout: GraphChannelLayout) -> App {
     let mut app = App::new();
     app.add_plugins(audio_graph::GraphPlugin);
 
-    let summer = SummerOwner::new(app.world_mut(), 2);
+    let summer = SummerOwner::new(app.world_mut(), layout);
     let midi_input = MidiInputOwner::new(app.world_mut());
     app.insert_non_send(summer);
     app.insert_non_send(midi_input);
@@ -108,7 +222,14 @@ fn setup_test_app() -> App {
         (
             remove_plugins_system::<MockPluginManager>,
             set_plugins_system::<MockPluginManager>,
+            update_buses_system,
+            update_inserts_system::<MockPluginManager>,
+            update_routing_system,
             update_channels_system,
+            update_sequencers_system,
+            update_plugin_params_system::<MockPluginManager>,
+            restart_plugins_system::<MockPluginManager>,
+            update_automation_system::<MockPluginManager>,
             sync_channel_order_system,
             sync_plugin_window_titles_system::<MockPluginManager>,
         )
@@ -146,6 +267,43 @@ fn get_entity(app: &mut App, id: StableId) -> Entity {
     id.find_entity(app.world_mut()).unwrap()
 }
 
+fn get_inputs(app: &App, node: Entity) -> Vec<Entity> {
+    app.world()
+        .get::<GraphNodeDesc>(node)
+        .unwrap()
+        .inputs
+        .clone()
+}
+
+fn get_insert_nodes(app: &App, entity: Entity) -> Vec<Entity> {
+    app.world()
+        .get::<ChannelInsertInstances<MockPlugin>>(entity)
+        .unwrap()
+        .0
+        .iter()
+        .map(|instance| instance.plugin_node)
+        .collect()
+}
+
+/// Lets the audio graph catch up with changes to the world, and the main
+/// thread free whatever the graph hands back.
+fn run_audio_graph(app: &mut App) {
+    app.update();
+    for _ in 0..2 {
+        let mut worker = app.world_mut().non_send_mut::<GraphWorker>();
+        worker.configure(2, 48_000, 64);
+        worker.tick(&mut [0.0; 128], Duration::default());
+        app.update();
+    }
+}
+
+fn live_processors(app: &App) -> usize {
+    app.world()
+        .non_send::<MockPluginManager>()
+        .live_processors
+        .load(Ordering::Relaxed)
+}
+
 #[test]
 fn set_plugin_creates_components() {
     let mut app = setup_test_app();
@@ -371,6 +529,81 @@ fn undo_redo_plugin_set_reconnects_fresh() {
     );
 }
 
+#[test]
+fn replacing_plugin_frees_old_processor() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data_a = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data_a)).execute(app.world_mut());
+    run_audio_graph(&mut app);
+    assert_eq!(1, live_processors(&app));
+
+    for plugin in ["com.test.synth-b", "com.test.synth-a", "com.test.synth-b"] {
+        let data = make_channel_data(plugin);
+        SetPluginEdit::new(id, Some(data)).execute(app.world_mut());
+        run_audio_graph(&mut app);
+        assert_eq!(1, live_processors(&app));
+    }
+}
+
+#[test]
+fn restarted_plugin_gets_a_new_processor() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data)).execute(app.world_mut());
+    run_audio_graph(&mut app);
+
+    let entity = get_entity(&mut app, id);
+    let instance = app
+        .world()
+        .get::<ChannelPluginInstance<MockPlugin>>(entity)
+        .unwrap();
+    let plugin_node = instance.plugin_node;
+    instance
+        .plugin
+        .restart_requested
+        .store(true, Ordering::Relaxed);
+
+    run_audio_graph(&mut app);
+
+    // The old processor has been retired and freed, and the node kept
+    assert_eq!(
+        1,
+        app.world().non_send::<MockPluginManager>().restarts.get()
+    );
+    assert_eq!(1, live_processors(&app));
+    let instance = app
+        .world()
+        .get::<ChannelPluginInstance<MockPlugin>>(entity)
+        .unwrap();
+    assert_eq!(plugin_node, instance.plugin_node);
+}
+
+#[test]
+fn undo_redo_plugin_set_frees_processors() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data_a = make_channel_data("com.test.synth-a");
+    let mut undo = SetPluginEdit::new(id, Some(data_a))
+        .execute(app.world_mut())
+        .unwrap();
+    run_audio_graph(&mut app);
+
+    for _ in 0..3 {
+        let redo = undo.execute(app.world_mut()).unwrap();
+        run_audio_graph(&mut app);
+        assert_eq!(0, live_processors(&app));
+
+        undo = redo.execute(app.world_mut()).unwrap();
+        run_audio_graph(&mut app);
+        assert_eq!(1, live_processors(&app));
+    }
+}
+
 #[test]
 fn set_plugin_wires_audio_graph() {
     let mut app = setup_test_app();
@@ -395,3 +628,467 @@ fn set_plugin_wires_audio_graph() {
     let summer_node = world.get::<GraphNodeDesc>(summer_entity).unwrap();
     assert!(summer_node.inputs.contains(&gain_entity));
 }
+
+#[test]
+fn set_plugin_connects_sequencer() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data)).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let world = app.world();
+
+    let input_node_entity = world.get::<ChannelSourceNode>(entity).unwrap().0;
+    let sequencer_entity = world.get::<ChannelSequencer>(entity).unwrap().0.entity;
+
+    let input_node = world.get::<GraphNodeDesc>(input_node_entity).unwrap();
+    assert!(input_node.has_event_connected(sequencer_entity));
+}
+
+#[test]
+fn replace_plugin_reuses_sequencer() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data_a = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data_a)).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let sequencer_before = app
+        .world()
+        .get::<ChannelSequencer>(entity)
+        .unwrap()
+        .0
+        .entity;
+
+    let data_b = make_channel_data("com.test.synth-b");
+    SetPluginEdit::new(id, Some(data_b)).execute(app.world_mut());
+    app.update();
+
+    let sequencer_after = app
+        .world()
+        .get::<ChannelSequencer>(entity)
+        .unwrap()
+        .0
+        .entity;
+    assert_eq!(sequencer_before, sequencer_after);
+
+    let input_node_entity = app.world().get::<ChannelSourceNode>(entity).unwrap().0;
+    let input_node = app.world().get::<GraphNodeDesc>(input_node_entity).unwrap();
+    assert!(input_node.has_event_connected(sequencer_after));
+}
+
+#[test]
+fn clip_is_rendered_once_the_transport_plays() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data)).execute(app.world_mut());
+    let mut clip = MidiClip::new(0.0, 4.0);
+    clip.notes.push(MidiClipNote {
+        key: 60,
+        velocity: 100,
+        start: 0.0,
+        length: 1.0,
+    });
+    AddClipEdit::new(id, clip).execute(app.world_mut());
+    app.update();
+
+    // As `render_project` does once the project has loaded
+    app.world_mut().resource_mut::<GraphTransport>().playing = true;
+    app.world_mut().trigger(GraphSeekEvent(0.0));
+    app.update();
+
+    let worker: GraphWorker = app.world_mut().remove_non_send().unwrap();
+    let settings = OfflineRenderSettings {
+        length: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let mut render = OfflineRender::new(worker, settings);
+
+    let mut loudest = 0.0f32;
+    render.render(|block| {
+        loudest = block
+            .iter()
+            .fold(loudest, |loudest, sample| loudest.max(sample.abs()));
+    });
+    assert!(loudest > 0.0);
+}
+
+#[test]
+fn arming_channel_connects_recorder() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    let data = make_channel_data("com.test.synth-a");
+    SetPluginEdit::new(id, Some(data)).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let recorder = app.world().get::<ChannelRecorder>(entity).unwrap();
+    let recorder_entity = recorder.recorder.entity;
+    let midi_input = app.world().non_send::<MidiInputOwner>().entity;
+
+    ChannelButtonEdit::new(id, ChannelButton::Arm, true).execute(app.world_mut());
+    app.update();
+    app.update();
+
+    let recorder_node = app.world().get::<GraphNodeDesc>(recorder_entity).unwrap();
+    assert!(recorder_node.has_event_connected(midi_input));
+
+    ChannelButtonEdit::new(id, ChannelButton::Arm, false).execute(app.world_mut());
+    app.update();
+    app.update();
+
+    let recorder_node = app.world().get::<GraphNodeDesc>(recorder_entity).unwrap();
+    assert!(!recorder_node.has_event_connected(midi_input));
+}
+
+#[test]
+fn plugin_params_follow_the_plugin() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    app.update();
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let params = app.world().get::<ChannelPluginParams>(entity).unwrap();
+    assert_eq!(params.params, vec![mock_param(1), mock_param(2)]);
+
+    let lane = AutomationLane::new(AutomationTarget::ClapParam(1));
+    AddAutomationLaneEdit::new(id, lane)
+        .execute(app.world_mut())
+        .unwrap();
+    app.update();
+
+    // Values changed by the plugin are read back, and clearing a parameter
+    // only removes its automation
+    let plugin = &app
+        .world()
+        .get::<ChannelPluginInstance<MockPlugin>>(entity)
+        .unwrap()
+        .plugin;
+    plugin
+        .param_events
+        .lock()
+        .unwrap()
+        .extend([ClapParamEvent::Value(2, 7.0), ClapParamEvent::Cleared(1)]);
+    app.update();
+
+    let params = app.world().get::<ChannelPluginParams>(entity).unwrap();
+    assert!(params.param(1).is_some());
+    assert_eq!(params.param(2).unwrap().value, 7.0);
+    let automation = app.world().get::<ChannelAutomation>(entity).unwrap();
+    assert!(automation.lanes.is_empty());
+}
+
+#[test]
+fn set_plugin_param_sends_value_and_undoes() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    app.update();
+    app.update();
+
+    // Values are clamped to the parameter's range
+    let undo = SetPluginParamEdit::new(id, 1, 20.0)
+        .execute(app.world_mut())
+        .unwrap();
+    app.update();
+    undo.execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let world = app.world();
+    assert_eq!(
+        world
+            .get::<ChannelPluginParams>(entity)
+            .unwrap()
+            .param(1)
+            .unwrap()
+            .value,
+        5.0
+    );
+    let plugin = &world
+        .get::<ChannelPluginInstance<MockPlugin>>(entity)
+        .unwrap()
+        .plugin;
+    assert_eq!(
+        *plugin.param_values.lock().unwrap(),
+        vec![(1, 10.0), (1, 5.0)]
+    );
+
+    assert!(
+        SetPluginParamEdit::new(id, 99, 1.0)
+            .execute(app.world_mut())
+            .is_none()
+    );
+}
+
+#[test]
+fn inserts_are_wired_in_series() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    let a = ChannelInsertSlot::new("com.test.synth-b");
+    let b = ChannelInsertSlot::new("com.test.synth-b");
+    AddInsertEdit::new(id, 0, a.clone()).execute(app.world_mut());
+    AddInsertEdit::new(id, 1, b.clone()).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let source = app.world().get::<ChannelSourceNode>(entity).unwrap().0;
+    let gain = app.world().get::<ChannelGain>(entity).unwrap().0.entity;
+    let [node_a, node_b] = get_insert_nodes(&app, entity).try_into().unwrap();
+
+    assert_eq!(get_inputs(&app, node_a), vec![source]);
+    assert_eq!(get_inputs(&app, node_b), vec![node_a]);
+    assert_eq!(get_inputs(&app, gain), vec![node_b]);
+
+    // Bypassed inserts are taken out of the chain
+    SetInsertBypassEdit::new(a.id, true).execute(app.world_mut());
+    app.update();
+    assert!(get_inputs(&app, node_a).is_empty());
+    assert_eq!(get_inputs(&app, node_b), vec![source]);
+    assert_eq!(get_inputs(&app, gain), vec![node_b]);
+
+    // Reordering keeps the plugins that are already running
+    SetInsertBypassEdit::new(a.id, false).execute(app.world_mut());
+    MoveInsertEdit::new(b.id, 0).execute(app.world_mut());
+    app.update();
+    assert_eq!(get_insert_nodes(&app, entity), vec![node_b, node_a]);
+    assert_eq!(get_inputs(&app, node_b), vec![source]);
+    assert_eq!(get_inputs(&app, node_a), vec![node_b]);
+    assert_eq!(get_inputs(&app, gain), vec![node_a]);
+}
+
+#[test]
+fn removing_insert_despawns_its_node() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    let slot = ChannelInsertSlot::new("com.test.synth-b");
+    AddInsertEdit::new(id, 0, slot.clone()).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let [node] = get_insert_nodes(&app, entity).try_into().unwrap();
+
+    let undo = RemoveInsertEdit::new(slot.id)
+        .execute(app.world_mut())
+        .unwrap();
+    app.update();
+
+    let source = app.world().get::<ChannelSourceNode>(entity).unwrap().0;
+    let gain = app.world().get::<ChannelGain>(entity).unwrap().0.entity;
+    assert!(app.world().get_entity(node).is_err());
+    assert!(get_insert_nodes(&app, entity).is_empty());
+    assert_eq!(get_inputs(&app, gain), vec![source]);
+
+    // Undo creates a fresh plugin for the slot
+    undo.execute(app.world_mut());
+    app.update();
+    let [node] = get_insert_nodes(&app, entity).try_into().unwrap();
+    assert_eq!(get_inputs(&app, gain), vec![node]);
+
+    // Removing the channel's plugin removes its inserts too
+    app.world_mut()
+        .entity_mut(entity)
+        .remove::<ChannelPluginBinding>();
+    app.update();
+    assert!(app.world().get_entity(node).is_err());
+    assert!(
+        app.world()
+            .get::<ChannelInsertInstances<MockPlugin>>(entity)
+            .is_none()
+    );
+}
+
+fn spawn_bus(app: &mut App, kind: ChannelKind) -> StableId {
+    let id = StableId::new();
+    let snapshot = ChannelSnapshot {
+        id,
+        kind,
+        ..Default::default()
+    };
+    AddChannelEdit::new(0, snapshot).execute(app.world_mut());
+    id
+}
+
+fn get_bus_input(app: &mut App, id: StableId) -> Entity {
+    let entity = get_entity(app, id);
+    app.world().get::<ChannelBusInput>(entity).unwrap().0.entity
+}
+
+fn get_gain(app: &mut App, id: StableId) -> Entity {
+    let entity = get_entity(app, id);
+    app.world().get::<ChannelGain>(entity).unwrap().0.entity
+}
+
+#[test]
+fn channels_output_to_groups() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+    let group = spawn_bus(&mut app, ChannelKind::Group);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    SetChannelOutputEdit::new(id, Some(group)).execute(app.world_mut());
+    app.update();
+
+    let summer = app.world().non_send::<SummerOwner>().entity;
+    let gain = get_gain(&mut app, id);
+    let group_input = get_bus_input(&mut app, group);
+    let group_gain = get_gain(&mut app, group);
+
+    assert_eq!(get_inputs(&app, group_input), vec![gain]);
+    assert_eq!(get_inputs(&app, group_gain), vec![group_input]);
+    assert_eq!(get_inputs(&app, summer), vec![group_gain]);
+
+    // Deleting the group sends the channel to the master output
+    let undo = DeleteChannelEdit::new(group, 0)
+        .execute(app.world_mut())
+        .unwrap();
+    app.update();
+    assert!(get_inputs(&app, summer).contains(&gain));
+
+    undo.execute(app.world_mut());
+    app.update();
+    app.update();
+    let group_input = get_bus_input(&mut app, group);
+    let group_gain = get_gain(&mut app, group);
+    assert_eq!(get_inputs(&app, group_input), vec![gain]);
+    assert_eq!(get_inputs(&app, summer), vec![group_gain]);
+}
+
+#[test]
+fn sends_feed_buses() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+    let bus = spawn_bus(&mut app, ChannelKind::Bus);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    let send = ChannelSend::new(bus);
+    AddSendEdit::new(id, 0, send.clone()).execute(app.world_mut());
+    app.update();
+
+    let entity = get_entity(&mut app, id);
+    let summer = app.world().non_send::<SummerOwner>().entity;
+    let source = app.world().get::<ChannelSourceNode>(entity).unwrap().0;
+    let gain = get_gain(&mut app, id);
+    let bus_input = get_bus_input(&mut app, bus);
+    let send_node = app.world().get::<ChannelSendNodes>(entity).unwrap().0[0]
+        .1
+        .entity;
+
+    // Post-fader sends take the gain's output
+    assert_eq!(get_inputs(&app, send_node), vec![gain]);
+    assert_eq!(get_inputs(&app, bus_input), vec![send_node]);
+    assert!(get_inputs(&app, summer).contains(&gain));
+
+    // Pre-fader sends take the gain's input
+    SetSendEdit::new(ChannelSend {
+        pre_fader: true,
+        ..send.clone()
+    })
+    .execute(app.world_mut());
+    app.update();
+    assert_eq!(get_inputs(&app, send_node), vec![source]);
+    assert_eq!(get_inputs(&app, bus_input), vec![send_node]);
+
+    RemoveSendEdit::new(send.id).execute(app.world_mut());
+    app.update();
+    assert!(app.world().get_entity(send_node).is_err());
+    assert!(get_inputs(&app, bus_input).is_empty());
+}
+
+#[test]
+fn channels_can_output_to_device_channels() {
+    let mut app = setup_test_app();
+    let id = spawn_channel(&mut app);
+    let bus = spawn_bus(&mut app, ChannelKind::Bus);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    SetChannelOutputEdit::new(id, Some(bus)).execute(app.world_mut());
+    let undo = SetChannelDeviceOutputEdit::new(id, Some(2))
+        .execute(app.world_mut())
+        .unwrap();
+    app.update();
+
+    let summer = app.world().non_send::<SummerOwner>().entity;
+    let gain = get_gain(&mut app, id);
+    let bus_input = get_bus_input(&mut app, bus);
+    assert!(!get_inputs(&app, summer).contains(&gain));
+    assert!(get_inputs(&app, bus_input).is_empty());
+    assert_eq!(
+        app.world().resource::<GraphOutputMapping>().routes,
+        vec![
+            GraphOutputRoute::new(gain, 0, 2),
+            GraphOutputRoute::new(gain, 1, 3)
+        ]
+    );
+
+    undo.execute(app.world_mut());
+    app.update();
+    assert_eq!(get_inputs(&app, bus_input), vec![gain]);
+    assert!(
+        app.world()
+            .resource::<GraphOutputMapping>()
+            .routes
+            .is_empty()
+    );
+}
+
+#[test]
+fn channels_use_the_master_layout() {
+    let mut app = setup_test_app_with_layout(GraphChannelLayout::Surround51);
+    let id = spawn_channel(&mut app);
+    let bus = spawn_bus(&mut app, ChannelKind::Bus);
+
+    SetPluginEdit::new(id, Some(make_channel_data("com.test.synth-a"))).execute(app.world_mut());
+    SetChannelOutputEdit::new(id, Some(bus)).execute(app.world_mut());
+    app.update();
+
+    let gain = get_gain(&mut app, id);
+    let bus_input = get_bus_input(&mut app, bus);
+    let ports = |entity| {
+        app.world()
+            .get::<GraphNodeDesc>(entity)
+            .unwrap()
+            .audio_channels
+            .clone()
+    };
+
+    // The stereo plugin only feeds the front speakers
+    let gain_ports = ports(gain);
+    assert_eq!(GraphChannelLayout::Surround51, gain_ports.input_layout);
+    assert_eq!(
+        vec![(0, 0), (1, 1)],
+        gain_ports
+            .connections
+            .iter()
+            .map(|connection| (connection.channel, connection.src_channel))
+            .collect::<Vec<_>>()
+    );
+
+    // Every channel of the gain reaches the bus
+    let bus_ports = ports(bus_input);
+    assert_eq!(GraphChannelLayout::Surround51, bus_ports.input_layout);
+    assert_eq!(
+        6,
+        bus_ports
+            .connections
+            .iter()
+            .filter(|connection| connection.src == gain)
+            .count()
+    );
+}
@@ -0,0 +1,25 @@
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+
+mod components;
+mod edits;
+
+pub use components::*;
+pub use edits::*;
+
+/// Finds the channel that owns a clip, and the clip's index within its
+/// `ChannelClips`.
+pub(crate) fn find_clip(world: &mut World, clip: StableId) -> Option<(Entity, usize)> {
+    let mut query = world.query::<(Entity, &ChannelClips)>();
+    query.iter(world).find_map(|(entity, clips)| {
+        clips
+            .0
+            .iter()
+            .position(|c| c.id == clip)
+            .map(|index| (entity, index))
+    })
+}
+
+#[cfg(test)]
+mod tests;
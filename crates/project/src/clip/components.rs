@@ -0,0 +1,137 @@
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+use serde::{Deserialize, Serialize};
+
+use crate::StableId;
+
+/// All positions and lengths are in beats.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct MidiClipNote {
+    pub key: u8,
+    pub velocity: u8,
+    /// Relative to the start of the clip.
+    pub start: f64,
+    pub length: f64,
+}
+
+/// A region of the timeline containing MIDI notes. All positions and lengths
+/// are in beats.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct MidiClip {
+    pub id: StableId,
+    pub name: String,
+    pub start: f64,
+    pub length: f64,
+    /// When set, the notes in the first `loop_length` beats of the clip repeat
+    /// until the end of the clip.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_length: Option<f64>,
+    #[serde(default)]
+    pub notes: Vec<MidiClipNote>,
+}
+
+impl MidiClip {
+    pub fn new(start: f64, length: f64) -> Self {
+        Self {
+            id: StableId::new(),
+            name: String::new(),
+            start,
+            length,
+            loop_length: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Makes a clip from notes positioned on the timeline, covering whole
+    /// beats from the start of the first note to the end of the last.
+    pub fn from_recording(notes: Vec<MidiClipNote>) -> Option<Self> {
+        let start = notes
+            .iter()
+            .map(|note| note.start)
+            .min_by(f64::total_cmp)?
+            .floor();
+        let end = notes
+            .iter()
+            .map(|note| note.start + note.length)
+            .max_by(f64::total_cmp)?
+            .ceil()
+            .max(start + 1.0);
+
+        let mut clip = MidiClip::new(start, end - start);
+        clip.notes = notes
+            .into_iter()
+            .map(|note| MidiClipNote {
+                start: note.start - start,
+                ..note
+            })
+            .collect();
+        Some(clip)
+    }
+
+    pub fn end(&self) -> f64 {
+        self.start + self.length
+    }
+
+    pub fn overlaps(&self, start: f64, end: f64) -> bool {
+        self.start < end && start < self.end()
+    }
+
+    /// The notes as they'll be played, with loops unrolled, positioned on the
+    /// timeline and cut off at the end of the clip.
+    pub fn timeline_notes(&self) -> impl Iterator<Item = MidiClipNote> + '_ {
+        let (period, repeats) = match self.loop_length {
+            Some(loop_length) if loop_length > 0.0 => {
+                (loop_length, (self.length / loop_length).ceil() as usize)
+            }
+            _ => (self.length, 1),
+        };
+
+        (0..repeats).flat_map(move |repeat| {
+            let offset = self.start + repeat as f64 * period;
+            self.notes
+                .iter()
+                .filter(move |note| note.start < period)
+                .filter_map(move |note| {
+                    let start = offset + note.start;
+                    (start < self.end()).then(|| MidiClipNote {
+                        start,
+                        length: note.length.min(self.end() - start),
+                        ..*note
+                    })
+                })
+        })
+    }
+}
+
+/// How a recorded clip is combined with the clips already on its channel.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum RecordMode {
+    /// The new clip is layered on top of the existing ones.
+    #[default]
+    Overdub,
+    /// Clips overlapping the new clip are removed.
+    Replace,
+}
+
+impl RecordMode {
+    pub fn record(self, clips: &mut Vec<MidiClip>, clip: MidiClip) {
+        if self == RecordMode::Replace {
+            clips.retain(|c| !c.overlaps(clip.start, clip.end()));
+        }
+        clips.push(clip);
+    }
+}
+
+/// The clips on a channel, in no particular order. Every channel has one.
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize, Reflect)]
+pub struct ChannelClips(pub Vec<MidiClip>);
+
+impl ChannelClips {
+    pub fn clip(&self, clip: StableId) -> Option<&MidiClip> {
+        self.0.iter().find(|c| c.id == clip)
+    }
+
+    pub fn clip_mut(&mut self, clip: StableId) -> Option<&mut MidiClip> {
+        self.0.iter_mut().find(|c| c.id == clip)
+    }
+}
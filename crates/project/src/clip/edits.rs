@@ -0,0 +1,137 @@
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+use crate::commands::EditCommand;
+
+use super::components::{ChannelClips, MidiClip};
+use super::find_clip;
+
+#[derive(Debug)]
+pub struct AddClipEdit {
+    channel: StableId,
+    clip: MidiClip,
+}
+
+impl AddClipEdit {
+    pub fn new(channel: StableId, clip: MidiClip) -> Self {
+        Self { channel, clip }
+    }
+}
+
+impl EditCommand for AddClipEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        world
+            .get_mut::<ChannelClips>(entity)?
+            .0
+            .push(self.clip.clone());
+        Some(Box::new(DeleteClipEdit::new(self.clip.id)))
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteClipEdit {
+    clip: StableId,
+}
+
+impl DeleteClipEdit {
+    pub fn new(clip: StableId) -> Self {
+        Self { clip }
+    }
+}
+
+impl EditCommand for DeleteClipEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_clip(world, self.clip)?;
+        let channel = *world.get::<StableId>(entity)?;
+        let clip = world.get_mut::<ChannelClips>(entity)?.0.remove(index);
+        Some(Box::new(AddClipEdit::new(channel, clip)))
+    }
+}
+
+/// Moves a clip to a new start position, possibly on a different channel.
+#[derive(Debug)]
+pub struct MoveClipEdit {
+    clip: StableId,
+    channel: StableId,
+    start: f64,
+}
+
+impl MoveClipEdit {
+    pub fn new(clip: StableId, channel: StableId, start: f64) -> Self {
+        Self {
+            clip,
+            channel,
+            start,
+        }
+    }
+}
+
+impl EditCommand for MoveClipEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (old_entity, index) = find_clip(world, self.clip)?;
+        let old_channel = *world.get::<StableId>(old_entity)?;
+        let new_entity = self.channel.find_entity(world)?;
+
+        let mut clips = world.get_mut::<ChannelClips>(old_entity)?;
+        let old_start = clips.0[index].start;
+
+        if old_entity == new_entity {
+            clips.0[index].start = self.start;
+        } else {
+            let mut clip = clips.0.remove(index);
+            clip.start = self.start;
+            world.get_mut::<ChannelClips>(new_entity)?.0.push(clip);
+        }
+
+        Some(Box::new(MoveClipEdit::new(
+            self.clip,
+            old_channel,
+            old_start,
+        )))
+    }
+}
+
+/// Replaces all of a channel's clips.
+#[derive(Debug)]
+pub struct SetChannelClipsEdit {
+    channel: StableId,
+    clips: Vec<MidiClip>,
+}
+
+impl SetChannelClipsEdit {
+    pub fn new(channel: StableId, clips: Vec<MidiClip>) -> Self {
+        Self { channel, clips }
+    }
+}
+
+impl EditCommand for SetChannelClipsEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        let mut clips = world.get_mut::<ChannelClips>(entity)?;
+        let old_clips = std::mem::replace(&mut clips.0, self.clips.clone());
+        Some(Box::new(SetChannelClipsEdit::new(self.channel, old_clips)))
+    }
+}
+
+#[derive(Debug)]
+pub struct ResizeClipEdit {
+    clip: StableId,
+    length: f64,
+}
+
+impl ResizeClipEdit {
+    pub fn new(clip: StableId, length: f64) -> Self {
+        Self { clip, length }
+    }
+}
+
+impl EditCommand for ResizeClipEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_clip(world, self.clip)?;
+        let mut clips = world.get_mut::<ChannelClips>(entity)?;
+        let old_length = clips.0[index].length;
+        clips.0[index].length = self.length;
+        Some(Box::new(ResizeClipEdit::new(self.clip, old_length)))
+    }
+}
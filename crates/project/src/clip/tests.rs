@@ -0,0 +1,158 @@
+use super::*;
+use crate::{AddChannelEdit, ChannelOrder, ChannelSnapshot, DeleteChannelEdit, EditCommand};
+
+fn setup_world_with_channels<const N: usize>() -> (World, [StableId; N]) {
+    let mut world = World::new();
+    world.spawn(ChannelOrder::default());
+
+    let ids: [StableId; N] = std::array::from_fn(|_| StableId::new());
+    for (index, id) in ids.iter().enumerate() {
+        let snapshot = ChannelSnapshot {
+            id: *id,
+            ..Default::default()
+        };
+        AddChannelEdit::new(index, snapshot).execute(&mut world);
+    }
+    (world, ids)
+}
+
+fn get_clips(world: &mut World, channel: StableId) -> Vec<MidiClip> {
+    let entity = channel.find_entity(world).unwrap();
+    world
+        .get::<ChannelClips>(entity)
+        .map(|clips| clips.0.clone())
+        .unwrap_or_default()
+}
+
+fn note(start: f64, length: f64) -> MidiClipNote {
+    MidiClipNote {
+        key: 60,
+        velocity: 100,
+        start,
+        length,
+    }
+}
+
+#[test]
+fn add_clip_and_undo() {
+    let (mut world, [channel]) = setup_world_with_channels();
+    let clip = MidiClip::new(4.0, 8.0);
+
+    let undo = AddClipEdit::new(channel, clip.clone())
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_clips(&mut world, channel), vec![clip.clone()]);
+
+    let redo = undo.execute(&mut world).unwrap();
+    assert!(get_clips(&mut world, channel).is_empty());
+
+    redo.execute(&mut world);
+    assert_eq!(get_clips(&mut world, channel), vec![clip]);
+}
+
+#[test]
+fn move_clip_between_channels() {
+    let (mut world, [a, b]) = setup_world_with_channels();
+    let clip = MidiClip::new(0.0, 4.0);
+    AddClipEdit::new(a, clip.clone()).execute(&mut world);
+
+    let undo = MoveClipEdit::new(clip.id, b, 8.0)
+        .execute(&mut world)
+        .unwrap();
+    assert!(get_clips(&mut world, a).is_empty());
+    assert_eq!(get_clips(&mut world, b)[0].start, 8.0);
+
+    undo.execute(&mut world);
+    assert!(get_clips(&mut world, b).is_empty());
+    assert_eq!(get_clips(&mut world, a), vec![clip]);
+}
+
+#[test]
+fn resize_clip_and_undo() {
+    let (mut world, [channel]) = setup_world_with_channels();
+    let clip = MidiClip::new(0.0, 4.0);
+    AddClipEdit::new(channel, clip.clone()).execute(&mut world);
+
+    let undo = ResizeClipEdit::new(clip.id, 2.0)
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_clips(&mut world, channel)[0].length, 2.0);
+
+    undo.execute(&mut world);
+    assert_eq!(get_clips(&mut world, channel)[0].length, 4.0);
+}
+
+#[test]
+fn delete_channel_restores_clips_on_undo() {
+    let (mut world, [channel]) = setup_world_with_channels();
+    let clip = MidiClip::new(0.0, 4.0);
+    AddClipEdit::new(channel, clip.clone()).execute(&mut world);
+
+    let undo = DeleteChannelEdit::new(channel, 0)
+        .execute(&mut world)
+        .unwrap();
+    assert!(channel.find_entity(&mut world).is_none());
+
+    undo.execute(&mut world);
+    assert_eq!(get_clips(&mut world, channel), vec![clip]);
+}
+
+#[test]
+fn timeline_notes_are_cut_off_at_clip_end() {
+    let mut clip = MidiClip::new(4.0, 2.0);
+    clip.notes = vec![note(0.0, 1.0), note(1.5, 1.0), note(3.0, 1.0)];
+
+    let notes: Vec<_> = clip.timeline_notes().collect();
+    assert_eq!(notes, vec![note(4.0, 1.0), note(5.5, 0.5)]);
+}
+
+#[test]
+fn timeline_notes_repeat_when_looped() {
+    let mut clip = MidiClip::new(0.0, 5.0);
+    clip.loop_length = Some(2.0);
+    clip.notes = vec![note(0.0, 1.0), note(2.5, 1.0)];
+
+    let notes: Vec<_> = clip.timeline_notes().collect();
+    assert_eq!(notes, vec![note(0.0, 1.0), note(2.0, 1.0), note(4.0, 1.0)]);
+}
+
+#[test]
+fn clip_from_recording_covers_whole_beats() {
+    let clip = MidiClip::from_recording(vec![note(4.5, 1.0), note(6.25, 0.5)]).unwrap();
+
+    assert_eq!(clip.start, 4.0);
+    assert_eq!(clip.length, 3.0);
+    assert_eq!(clip.notes, vec![note(0.5, 1.0), note(2.25, 0.5)]);
+
+    assert!(MidiClip::from_recording(Vec::new()).is_none());
+}
+
+#[test]
+fn record_modes() {
+    let existing = vec![MidiClip::new(0.0, 4.0), MidiClip::new(8.0, 4.0)];
+    let take = MidiClip::new(2.0, 4.0);
+
+    let mut clips = existing.clone();
+    RecordMode::Overdub.record(&mut clips, take.clone());
+    assert_eq!(clips.len(), 3);
+
+    let mut clips = existing.clone();
+    RecordMode::Replace.record(&mut clips, take.clone());
+    assert_eq!(clips, vec![existing[1].clone(), take]);
+}
+
+#[test]
+fn set_channel_clips_and_undo() {
+    let (mut world, [channel]) = setup_world_with_channels();
+    let clip = MidiClip::new(0.0, 4.0);
+    AddClipEdit::new(channel, clip.clone()).execute(&mut world);
+
+    let take = MidiClip::new(4.0, 4.0);
+    let undo = SetChannelClipsEdit::new(channel, vec![take.clone()])
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_clips(&mut world, channel), vec![take]);
+
+    undo.execute(&mut world);
+    assert_eq!(get_clips(&mut world, channel), vec![clip]);
+}
@@ -3,15 +3,21 @@ use bevy_reflect::Reflect;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod automation;
 mod channel;
+mod clip;
 mod commands;
 mod found_plugin;
 mod project;
+mod routing;
 
+pub use automation::*;
 pub use channel::*;
+pub use clip::*;
 pub use commands::*;
 pub use found_plugin::{AvailablePlugin, add_available_plugins};
 pub use project::{ChannelOrder, LoadEvent, ProjectInfo, ProjectPlugin, SaveEvent};
+pub use routing::*;
 
 #[derive(Component, Serialize, Deserialize, Hash, Eq, PartialEq, Clone, Copy, Debug, Reflect)]
 #[reflect(opaque)]
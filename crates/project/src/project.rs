@@ -8,11 +8,12 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
-    ChannelMixerState, ChannelPluginBinding, ChannelPluginInstance, EditHistory, StableId,
-    channel_bundle,
+    ChannelAutomation, ChannelClips, ChannelInsertInstances, ChannelInserts, ChannelKind,
+    ChannelMixerState, ChannelPluginBinding, ChannelPluginInstance, ChannelRouting, EditHistory,
+    MidiClip, StableId, channel_bundle,
 };
 
-use engine::plugins::{ClapManager, PluginManager};
+use engine::plugins::{ClapId, ClapManager, PluginManager};
 
 #[derive(Component, Default, Reflect)]
 pub struct ProjectInfo {
@@ -113,9 +114,19 @@ struct Document {
 #[derive(Deserialize)]
 struct ChannelDocument {
     name: Name,
+    #[serde(default)]
+    kind: ChannelKind,
     data: Option<ChannelPluginBinding>,
     state: ChannelMixerState,
     id: StableId,
+    #[serde(default)]
+    clips: Vec<MidiClip>,
+    #[serde(default)]
+    automation: ChannelAutomation,
+    #[serde(default)]
+    inserts: ChannelInserts,
+    #[serde(default)]
+    routing: ChannelRouting,
 }
 
 fn on_load_event(
@@ -143,10 +154,16 @@ fn on_load_event(
             let state = channel.state;
 
             let id = channel.id;
-            let mut entity = commands.spawn((state, channel.name, channel.id));
+            let mut entity = commands.spawn((state, channel.name, channel.kind, channel.id));
             if let Some(data) = channel.data {
                 entity.insert(data);
             }
+            entity.insert((
+                ChannelClips(channel.clips),
+                channel.automation,
+                channel.inserts,
+                channel.routing,
+            ));
             (id, entity.id())
         })
         .collect();
@@ -178,10 +195,16 @@ fn on_save_event<T: PluginManager>(
     project_query: Single<(&mut ProjectInfo, &ChannelOrder)>,
     channels_query: Query<(
         &Name,
+        &ChannelKind,
         Option<&ChannelPluginBinding>,
         &ChannelMixerState,
         &StableId,
         Option<&ChannelPluginInstance<T::Plugin>>,
+        &ChannelClips,
+        &ChannelAutomation,
+        &ChannelInserts,
+        Option<&ChannelInsertInstances<T::Plugin>>,
+        &ChannelRouting,
     )>,
     plugin_factory: NonSend<T>,
 ) {
@@ -191,31 +214,55 @@ fn on_save_event<T: PluginManager>(
 
     let channels: Vec<_> = channels_query
         .iter()
-        .map(|(name, data, state, id, view)| {
-            let data = match (data, view) {
-                (Some(data), Some(view)) => {
-                    let plugin_state = futures::executor::block_on(async {
-                        plugin_factory
-                            .save_plugin_state(view.plugin_id::<T>())
-                            .await
-                            .ok()
-                            .flatten()
-                    });
-                    let mut data = data.clone();
-                    data.plugin_state =
-                        plugin_state.map(|bytes| general_purpose::STANDARD.encode(&bytes));
-                    Some(data)
+        .map(
+            |(
+                name,
+                kind,
+                data,
+                state,
+                id,
+                view,
+                clips,
+                automation,
+                inserts,
+                instances,
+                routing,
+            )| {
+                let data = match (data, view) {
+                    (Some(data), Some(view)) => {
+                        let mut data = data.clone();
+                        data.plugin_state =
+                            save_plugin_state(&*plugin_factory, view.plugin_id::<T>());
+                        Some(data)
+                    }
+                    (data, _) => data.cloned(),
+                };
+                let mut inserts = inserts.clone();
+                for slot in &mut inserts.0 {
+                    if let Some(instance) = instances.and_then(|instances| instances.get(slot.id)) {
+                        slot.plugin_state =
+                            save_plugin_state(&*plugin_factory, instance.plugin_id::<T>());
+                    }
                 }
-                (data, _) => data.cloned(),
-            };
-            json!({"name": name, "data": data, "state": state, "id": id})
-        })
+                json!({
+                    "name": name,
+                    "kind": kind,
+                    "data": data,
+                    "state": state,
+                    "id": id,
+                    "clips": clips.0,
+                    "automation": automation,
+                    "inserts": inserts,
+                    "routing": routing,
+                })
+            },
+        )
         .collect();
 
     let channel_order: Vec<_> = channel_order
         .channel_order
         .iter()
-        .map(|e| channels_query.get(*e).ok().map(|r| r.3))
+        .map(|e| channels_query.get(*e).ok().map(|r| r.4))
         .collect();
 
     let document = &json!(
@@ -227,3 +274,15 @@ fn on_save_event<T: PluginManager>(
 
     project.path = Some(save_event.path.clone());
 }
+
+/// Saves a plugin's state, encoded so it can be stored in the project file.
+fn save_plugin_state<T: PluginManager>(plugin_factory: &T, plugin_id: ClapId) -> Option<String> {
+    let plugin_state = futures::executor::block_on(async {
+        plugin_factory
+            .save_plugin_state(plugin_id)
+            .await
+            .ok()
+            .flatten()
+    });
+    plugin_state.map(|bytes| general_purpose::STANDARD.encode(&bytes))
+}
@@ -0,0 +1,75 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+
+mod components;
+mod edits;
+
+pub use components::*;
+pub use edits::*;
+
+/// Finds the channel that owns a send, and the send's index within its
+/// `ChannelRouting`.
+pub(crate) fn find_send(world: &mut World, send: StableId) -> Option<(Entity, usize)> {
+    let mut query = world.query::<(Entity, &ChannelRouting)>();
+    query.iter(world).find_map(|(entity, routing)| {
+        routing
+            .sends
+            .iter()
+            .position(|s| s.id == send)
+            .map(|index| (entity, index))
+    })
+}
+
+/// The channels that each channel's audio goes to, used to stop routing from
+/// feeding a channel's audio back into itself.
+#[derive(Debug, Default)]
+pub struct ChannelRoutes(HashMap<StableId, Vec<StableId>>);
+
+impl ChannelRoutes {
+    pub fn new<'a>(channels: impl IntoIterator<Item = (&'a StableId, &'a ChannelRouting)>) -> Self {
+        let mut routes = Self::default();
+        for (channel, routing) in channels {
+            for target in routing.targets() {
+                routes.add(*channel, target);
+            }
+        }
+        routes
+    }
+
+    pub(crate) fn from_world(world: &mut World) -> Self {
+        let mut query = world.query::<(&StableId, &ChannelRouting)>();
+        Self::new(query.iter(world))
+    }
+
+    pub fn add(&mut self, channel: StableId, target: StableId) {
+        self.0.entry(channel).or_default().push(target);
+    }
+
+    /// Whether audio from `from` ends up in `to`, directly or through other
+    /// channels.
+    pub fn reaches(&self, from: StableId, to: StableId) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![from];
+        while let Some(channel) = pending.pop() {
+            if channel == to {
+                return true;
+            }
+            if visited.insert(channel) {
+                pending.extend(self.0.get(&channel).into_iter().flatten().copied());
+            }
+        }
+        false
+    }
+
+    /// Whether routing `channel` into `target` would feed the channel's audio
+    /// back into itself.
+    pub fn would_cycle(&self, channel: StableId, target: StableId) -> bool {
+        self.reaches(target, channel)
+    }
+}
+
+#[cfg(test)]
+mod tests;
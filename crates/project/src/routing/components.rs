@@ -0,0 +1,86 @@
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChannelMixerState, StableId};
+
+/// What a channel does with its audio input.
+#[derive(
+    Component, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect,
+)]
+#[require(ChannelMixerState)]
+pub enum ChannelKind {
+    /// Plays its clips through its plugin.
+    #[default]
+    Instrument,
+    /// Sums the audio routed or sent to it by other channels.
+    Bus,
+    /// A bus that the channels in it output to.
+    Group,
+}
+
+impl ChannelKind {
+    /// Whether other channels can route audio into this kind of channel.
+    pub fn is_bus(self) -> bool {
+        self != ChannelKind::Instrument
+    }
+}
+
+/// Sends a copy of the channel's audio to a bus at its own level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct ChannelSend {
+    pub id: StableId,
+    /// The bus channel that receives the audio.
+    pub target: StableId,
+    pub level: f32,
+    /// Taps the audio before the channel's gain rather than after it.
+    #[serde(default)]
+    pub pre_fader: bool,
+}
+
+impl ChannelSend {
+    pub fn new(target: StableId) -> Self {
+        Self {
+            id: StableId::new(),
+            target,
+            level: 1.0,
+            pre_fader: false,
+        }
+    }
+}
+
+/// Where a channel's audio goes.
+#[derive(Component, Debug, Clone, Default, PartialEq, Serialize, Deserialize, Reflect)]
+#[require(ChannelMixerState)]
+pub struct ChannelRouting {
+    /// The bus or group channel that the channel outputs to, or `None` for
+    /// the master output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<StableId>,
+    /// The first of a pair of device channels that the channel outputs to
+    /// directly. While it's set, `output` is ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_output: Option<u16>,
+    #[serde(default)]
+    pub sends: Vec<ChannelSend>,
+}
+
+impl ChannelRouting {
+    pub fn send(&self, send: StableId) -> Option<&ChannelSend> {
+        self.sends.iter().find(|s| s.id == send)
+    }
+
+    /// The bus or group channel that the channel outputs to, unless it
+    /// outputs to device channels of its own.
+    pub fn bus_output(&self) -> Option<StableId> {
+        self.output.filter(|_| self.device_output.is_none())
+    }
+
+    /// The channels this channel's audio goes to, through its output or its
+    /// sends.
+    pub fn targets(&self) -> impl Iterator<Item = StableId> + '_ {
+        self.bus_output()
+            .into_iter()
+            .chain(self.sends.iter().map(|send| send.target))
+    }
+}
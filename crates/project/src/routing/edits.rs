@@ -0,0 +1,168 @@
+use bevy_ecs::prelude::*;
+
+use crate::StableId;
+use crate::commands::EditCommand;
+
+use super::components::{ChannelKind, ChannelRouting, ChannelSend};
+use super::{ChannelRoutes, find_send};
+
+/// Whether `channel` can route audio to `target` without the audio feeding
+/// back into `channel`.
+fn can_route(world: &mut World, channel: StableId, target: StableId) -> bool {
+    let Some(target_entity) = target.find_entity(world) else {
+        return false;
+    };
+    let is_bus = world
+        .get::<ChannelKind>(target_entity)
+        .is_some_and(|kind| kind.is_bus());
+    is_bus && !ChannelRoutes::from_world(world).would_cycle(channel, target)
+}
+
+/// Sets the bus or group that a channel outputs to, or the master output when
+/// `output` is `None`. Does nothing if it would create a feedback loop.
+#[derive(Debug)]
+pub struct SetChannelOutputEdit {
+    channel: StableId,
+    output: Option<StableId>,
+}
+
+impl SetChannelOutputEdit {
+    pub fn new(channel: StableId, output: Option<StableId>) -> Self {
+        Self { channel, output }
+    }
+}
+
+impl EditCommand for SetChannelOutputEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        if let Some(output) = self.output
+            && !can_route(world, self.channel, output)
+        {
+            return None;
+        }
+
+        let entity = self.channel.find_entity(world)?;
+        let mut routing = world.get_mut::<ChannelRouting>(entity)?;
+        let old_output = std::mem::replace(&mut routing.output, self.output);
+        Some(Box::new(SetChannelOutputEdit::new(
+            self.channel,
+            old_output,
+        )))
+    }
+}
+
+/// Sends a channel's audio straight to a pair of device channels starting at
+/// `device_output`, or back to its usual output when it's `None`. Does nothing
+/// if going back to its usual output would create a feedback loop.
+#[derive(Debug)]
+pub struct SetChannelDeviceOutputEdit {
+    channel: StableId,
+    device_output: Option<u16>,
+}
+
+impl SetChannelDeviceOutputEdit {
+    pub fn new(channel: StableId, device_output: Option<u16>) -> Self {
+        Self {
+            channel,
+            device_output,
+        }
+    }
+}
+
+impl EditCommand for SetChannelDeviceOutputEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let entity = self.channel.find_entity(world)?;
+        if self.device_output.is_none()
+            && let Some(output) = world.get::<ChannelRouting>(entity)?.output
+            && !can_route(world, self.channel, output)
+        {
+            return None;
+        }
+
+        let mut routing = world.get_mut::<ChannelRouting>(entity)?;
+        let old_device_output = std::mem::replace(&mut routing.device_output, self.device_output);
+        Some(Box::new(SetChannelDeviceOutputEdit::new(
+            self.channel,
+            old_device_output,
+        )))
+    }
+}
+
+/// Adds a send to a channel. Does nothing if it would create a feedback loop.
+#[derive(Debug)]
+pub struct AddSendEdit {
+    channel: StableId,
+    index: usize,
+    send: ChannelSend,
+}
+
+impl AddSendEdit {
+    pub fn new(channel: StableId, index: usize, send: ChannelSend) -> Self {
+        Self {
+            channel,
+            index,
+            send,
+        }
+    }
+}
+
+impl EditCommand for AddSendEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        if !can_route(world, self.channel, self.send.target) {
+            return None;
+        }
+
+        let entity = self.channel.find_entity(world)?;
+        let mut routing = world.get_mut::<ChannelRouting>(entity)?;
+        let index = self.index.min(routing.sends.len());
+        routing.sends.insert(index, self.send.clone());
+        Some(Box::new(RemoveSendEdit::new(self.send.id)))
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoveSendEdit {
+    send: StableId,
+}
+
+impl RemoveSendEdit {
+    pub fn new(send: StableId) -> Self {
+        Self { send }
+    }
+}
+
+impl EditCommand for RemoveSendEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_send(world, self.send)?;
+        let channel = *world.get::<StableId>(entity)?;
+        let send = world.get_mut::<ChannelRouting>(entity)?.sends.remove(index);
+        Some(Box::new(AddSendEdit::new(channel, index, send)))
+    }
+}
+
+/// Replaces a send's target, level or pre/post fader setting. Does nothing if
+/// it would create a feedback loop.
+#[derive(Debug)]
+pub struct SetSendEdit {
+    send: ChannelSend,
+}
+
+impl SetSendEdit {
+    pub fn new(send: ChannelSend) -> Self {
+        Self { send }
+    }
+}
+
+impl EditCommand for SetSendEdit {
+    fn execute(&self, world: &mut World) -> Option<Box<dyn EditCommand>> {
+        let (entity, index) = find_send(world, self.send.id)?;
+        let channel = *world.get::<StableId>(entity)?;
+        let old_target = world.get::<ChannelRouting>(entity)?.sends[index].target;
+        if self.send.target != old_target && !can_route(world, channel, self.send.target) {
+            return None;
+        }
+
+        let mut routing = world.get_mut::<ChannelRouting>(entity)?;
+        let old_send = std::mem::replace(&mut routing.sends[index], self.send.clone());
+        Some(Box::new(SetSendEdit::new(old_send)))
+    }
+}
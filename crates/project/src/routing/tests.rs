@@ -0,0 +1,184 @@
+use super::*;
+use crate::{AddChannelEdit, ChannelOrder, ChannelSnapshot, DeleteChannelEdit, EditCommand};
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.spawn(ChannelOrder::default());
+    world
+}
+
+fn add_channel(world: &mut World, kind: ChannelKind) -> StableId {
+    let snapshot = ChannelSnapshot {
+        kind,
+        ..Default::default()
+    };
+    let id = snapshot.id;
+    AddChannelEdit::new(0, snapshot).execute(world);
+    id
+}
+
+fn get_routing(world: &mut World, channel: StableId) -> ChannelRouting {
+    let entity = channel.find_entity(world).unwrap();
+    world.get::<ChannelRouting>(entity).unwrap().clone()
+}
+
+#[test]
+fn routes_reach_through_buses() {
+    let [a, b, c, d] = std::array::from_fn(|_| StableId::new());
+    let mut routes = ChannelRoutes::default();
+    routes.add(a, b);
+    routes.add(b, c);
+
+    assert!(routes.reaches(a, c));
+    assert!(!routes.reaches(c, a));
+    assert!(!routes.reaches(a, d));
+    assert!(routes.would_cycle(c, a));
+    assert!(routes.would_cycle(a, a));
+    assert!(!routes.would_cycle(a, d));
+}
+
+#[test]
+fn set_output_and_undo() {
+    let mut world = setup_world();
+    let channel = add_channel(&mut world, ChannelKind::Instrument);
+    let group = add_channel(&mut world, ChannelKind::Group);
+
+    let undo = SetChannelOutputEdit::new(channel, Some(group))
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_routing(&mut world, channel).output, Some(group));
+
+    undo.execute(&mut world);
+    assert_eq!(get_routing(&mut world, channel).output, None);
+
+    // Only buses and groups can be routed to
+    let other = add_channel(&mut world, ChannelKind::Instrument);
+    assert!(
+        SetChannelOutputEdit::new(channel, Some(other))
+            .execute(&mut world)
+            .is_none()
+    );
+}
+
+#[test]
+fn routing_cannot_loop() {
+    let mut world = setup_world();
+    let a = add_channel(&mut world, ChannelKind::Bus);
+    let b = add_channel(&mut world, ChannelKind::Bus);
+
+    SetChannelOutputEdit::new(a, Some(b)).execute(&mut world);
+    assert!(
+        SetChannelOutputEdit::new(b, Some(a))
+            .execute(&mut world)
+            .is_none()
+    );
+    assert!(
+        SetChannelOutputEdit::new(a, Some(a))
+            .execute(&mut world)
+            .is_none()
+    );
+    assert!(
+        AddSendEdit::new(b, 0, ChannelSend::new(a))
+            .execute(&mut world)
+            .is_none()
+    );
+
+    // A send can't be pointed back at its own channel either
+    let c = add_channel(&mut world, ChannelKind::Bus);
+    let send = ChannelSend::new(c);
+    AddSendEdit::new(b, 0, send.clone()).execute(&mut world);
+    assert!(
+        SetSendEdit::new(ChannelSend { target: a, ..send })
+            .execute(&mut world)
+            .is_none()
+    );
+    assert_eq!(get_routing(&mut world, a).output, Some(b));
+    assert_eq!(get_routing(&mut world, b).output, None);
+    assert_eq!(get_routing(&mut world, b).sends[0].target, c);
+}
+
+#[test]
+fn add_set_and_remove_sends() {
+    let mut world = setup_world();
+    let channel = add_channel(&mut world, ChannelKind::Instrument);
+    let bus = add_channel(&mut world, ChannelKind::Bus);
+
+    let send = ChannelSend::new(bus);
+    let undo_add = AddSendEdit::new(channel, 0, send.clone())
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(get_routing(&mut world, channel).sends, vec![send.clone()]);
+
+    let changed = ChannelSend {
+        level: 0.5,
+        pre_fader: true,
+        ..send.clone()
+    };
+    let undo_set = SetSendEdit::new(changed.clone())
+        .execute(&mut world)
+        .unwrap();
+    assert_eq!(
+        get_routing(&mut world, channel).sends,
+        vec![changed.clone()]
+    );
+    undo_set.execute(&mut world);
+    assert_eq!(get_routing(&mut world, channel).sends, vec![send.clone()]);
+
+    let undo_remove = RemoveSendEdit::new(send.id).execute(&mut world).unwrap();
+    assert!(get_routing(&mut world, channel).sends.is_empty());
+    undo_remove.execute(&mut world);
+    assert_eq!(get_routing(&mut world, channel).sends, vec![send]);
+
+    undo_add.execute(&mut world);
+    assert!(get_routing(&mut world, channel).sends.is_empty());
+}
+
+#[test]
+fn delete_channel_keeps_routing() {
+    let mut world = setup_world();
+    let bus = add_channel(&mut world, ChannelKind::Bus);
+    let channel = add_channel(&mut world, ChannelKind::Instrument);
+    SetChannelOutputEdit::new(channel, Some(bus)).execute(&mut world);
+    AddSendEdit::new(channel, 0, ChannelSend::new(bus)).execute(&mut world);
+    let routing = get_routing(&mut world, channel);
+
+    let undo = DeleteChannelEdit::new(channel, 0)
+        .execute(&mut world)
+        .unwrap();
+    undo.execute(&mut world);
+    assert_eq!(get_routing(&mut world, channel), routing);
+
+    let undo = DeleteChannelEdit::new(bus, 1).execute(&mut world).unwrap();
+    undo.execute(&mut world);
+    let entity = bus.find_entity(&mut world).unwrap();
+    assert_eq!(*world.get::<ChannelKind>(entity).unwrap(), ChannelKind::Bus);
+}
+
+#[test]
+fn device_output_replaces_bus_output() {
+    let mut world = setup_world();
+    let a = add_channel(&mut world, ChannelKind::Bus);
+    let b = add_channel(&mut world, ChannelKind::Bus);
+    SetChannelOutputEdit::new(a, Some(b)).execute(&mut world);
+
+    let undo = SetChannelDeviceOutputEdit::new(a, Some(2))
+        .execute(&mut world)
+        .unwrap();
+    let routing = get_routing(&mut world, a);
+    assert_eq!(routing.device_output, Some(2));
+    assert_eq!(routing.bus_output(), None);
+
+    // With a's audio going to the device, b can output to a
+    SetChannelOutputEdit::new(b, Some(a)).execute(&mut world);
+    assert_eq!(get_routing(&mut world, b).output, Some(a));
+
+    // So a can't go back to outputting to b
+    assert!(undo.execute(&mut world).is_none());
+    assert_eq!(get_routing(&mut world, a).device_output, Some(2));
+
+    SetChannelOutputEdit::new(b, None).execute(&mut world);
+    undo.execute(&mut world);
+    let routing = get_routing(&mut world, a);
+    assert_eq!(routing.device_output, None);
+    assert_eq!(routing.bus_output(), Some(b));
+}